@@ -0,0 +1,35 @@
+/* ab-av1 C api, build with the "ffi" feature:
+ * `cargo rustc --lib --release --features ffi --crate-type cdylib` */
+#ifndef AB_AV1_H
+#define AB_AV1_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Search AbAv1Search;
+
+/* Start a background crf search with crf-search cli args,
+ * e.g. "-i vid.mkv --preset 8 --min-vmaf 95". Null if args is null or not utf8. */
+AbAv1Search *ab_av1_start_search(const char *args);
+
+/* Progress fraction 0-1, 1 once done. */
+double ab_av1_poll_progress(const AbAv1Search *search);
+
+/* Result json once done, otherwise null. Free with ab_av1_free_string.
+ * {"result": {"crf", "vmaf", ...}} or {"error", "exit_code"} */
+char *ab_av1_get_result_json(const AbAv1Search *search);
+
+/* Cancel a running search, which then finishes with exit_code 130. */
+void ab_av1_cancel(const AbAv1Search *search);
+
+/* Free a search, cancelling it if still running. */
+void ab_av1_free_search(AbAv1Search *search);
+
+void ab_av1_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
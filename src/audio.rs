@@ -0,0 +1,110 @@
+//! Audio sample encoding & spectral bandwidth scoring logic.
+use crate::process::{ensure_success, CommandExt};
+use anyhow::Context;
+use std::{path::Path, process::Stdio, time::Duration};
+use tokio::process::Command;
+
+/// Ffmpeg audio encoders supported by audio-search.
+pub const ENCODERS: &[&str] = &["libopus", "aac"];
+
+/// Encode the first audio stream of `input` from `offset` for `duration` at `kbps`.
+pub async fn encode_sample(
+    input: &Path,
+    offset: Duration,
+    duration: Duration,
+    acodec: &str,
+    kbps: u32,
+    dest: &Path,
+) -> anyhow::Result<()> {
+    let out = Command::new("ffmpeg")
+        .arg("-y")
+        .arg2("-ss", offset.as_secs_f32())
+        .arg2("-t", duration.as_secs_f32())
+        .arg2("-i", input)
+        .arg2("-map", "0:a:0")
+        .arg2("-c:a", acodec)
+        .arg2("-b:a", format!("{kbps}k"))
+        .arg(dest)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg audio encode")?;
+    ensure_success("ffmpeg audio encode", &out)
+}
+
+/// Mean spectral rolloff frequency of the first audio stream of `input`,
+/// optionally only from `offset` for `duration`.
+pub async fn spectral_rolloff(
+    input: &Path,
+    range: Option<(Duration, Duration)>,
+) -> anyhow::Result<f64> {
+    let mut cmd = Command::new("ffmpeg");
+    if let Some((offset, duration)) = range {
+        cmd.arg2("-ss", offset.as_secs_f32())
+            .arg2("-t", duration.as_secs_f32());
+    }
+    let out = cmd
+        .arg2("-i", input)
+        .arg2("-map", "0:a:0")
+        .arg2(
+            "-af",
+            "aspectralstats=measure=rolloff,ametadata=mode=print:file=-",
+        )
+        .arg2("-f", "null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg aspectralstats")?;
+    ensure_success("ffmpeg aspectralstats", &out)?;
+    parse_mean_rolloff(&String::from_utf8_lossy(&out.stdout))
+        .context("ffmpeg aspectralstats: no rolloff values")
+}
+
+/// Score `distorted` vs `reference` mean spectral rolloff as the percentage
+/// of reference bandwidth retained, capped at 100.
+///
+/// Lossy audio encoders mostly degrade by low-passing, so this is a cheap
+/// perceptual proxy for comparing bitrates of the same encoder.
+pub fn bandwidth_score(reference: f64, distorted: f64) -> f32 {
+    if reference <= 0.0 {
+        return 100.0;
+    }
+    (distorted / reference * 100.0).min(100.0) as f32
+}
+
+/// Parse the mean of all `ametadata` printed per-channel rolloff values, e.g.
+/// "lavfi.aspectralstats.1.rolloff=15340.869141".
+fn parse_mean_rolloff(out: &str) -> Option<f64> {
+    let (sum, count) = out
+        .lines()
+        .filter_map(|line| {
+            let (key, val) = line.trim().split_once('=')?;
+            if !key.starts_with("lavfi.aspectralstats.") || !key.ends_with(".rolloff") {
+                return None;
+            }
+            val.parse::<f64>().ok().filter(|v| v.is_finite())
+        })
+        .fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+#[test]
+fn parse_rolloff_metadata() {
+    let out = "frame:0    pts:0       pts_time:0
+lavfi.aspectralstats.1.rolloff=16000.000000
+lavfi.aspectralstats.2.rolloff=15000.000000
+frame:1    pts:1024    pts_time:0.0213333
+lavfi.aspectralstats.1.rolloff=nan
+lavfi.aspectralstats.2.rolloff=14000.000000
+";
+    assert_eq!(parse_mean_rolloff(out), Some(15000.0));
+    assert_eq!(parse_mean_rolloff("frame:0    pts:0"), None);
+}
+
+#[test]
+fn bandwidth_scores() {
+    assert_eq!(bandwidth_score(16000.0, 12000.0), 75.0);
+    assert_eq!(bandwidth_score(16000.0, 17000.0), 100.0);
+    assert_eq!(bandwidth_score(0.0, 100.0), 100.0);
+}
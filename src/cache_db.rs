@@ -0,0 +1,21 @@
+//! Persistent sled databases stored in the user cache dir.
+use anyhow::Context;
+use std::time::{Duration, Instant};
+
+/// Open the `name` database in the ab-av1 cache dir.
+///
+/// Retries for a short time if the database is locked by another ab-av1 process.
+pub fn open(name: &str) -> anyhow::Result<sled::Db> {
+    const LOCK_MAX_WAIT: Duration = Duration::from_secs(2);
+
+    let mut path = dirs::cache_dir().context("no cache dir found")?;
+    path.push("ab-av1");
+    path.push(name);
+    let a = Instant::now();
+    let mut db = sled::open(&path);
+    while db.is_err() && a.elapsed() < LOCK_MAX_WAIT {
+        std::thread::yield_now();
+        db = sled::open(&path);
+    }
+    Ok(db?)
+}
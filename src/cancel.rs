@@ -0,0 +1,46 @@
+//! Cancellation of command pipelines, e.g. by ctrl-c or an embedder.
+//!
+//! Cancelled futures are dropped, killing their ffmpeg child processes which are all
+//! spawned with `kill_on_drop`, and fail with [`Interrupted`]. Pipelines that spawn
+//! tasks take a [`CancellationToken`] so those tasks are dropped too, instead of running
+//! on detached. Temp files are removed by the caller with [`crate::temporary::clean`].
+use crate::machine::Interrupted;
+use std::future::Future;
+use tokio::task::JoinHandle;
+pub use tokio_util::sync::CancellationToken;
+
+/// Run `fut` to completion, or until `cancel`led failing with [`Interrupted`].
+pub async fn until<T>(
+    cancel: &CancellationToken,
+    fut: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    match cancel.run_until_cancelled(fut).await {
+        Some(out) => out,
+        None => Err(Interrupted.into()),
+    }
+}
+
+/// Spawn a local task that is dropped once `cancel`led, returning `None` in that case.
+pub fn spawn_local<F>(cancel: &CancellationToken, fut: F) -> JoinHandle<Option<F::Output>>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    tokio::task::spawn_local(cancel.clone().run_until_cancelled_owned(fut))
+}
+
+#[test]
+fn cancel_spawned_task() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let cancel = CancellationToken::new();
+    tokio::task::LocalSet::new().block_on(&runtime, async {
+        let task = spawn_local(&cancel, std::future::pending::<()>());
+        cancel.cancel();
+        assert_eq!(task.await.unwrap(), None);
+
+        let err = until(&cancel, async { Ok(()) }).await.unwrap_err();
+        assert!(err.downcast_ref::<Interrupted>().is_some());
+    });
+}
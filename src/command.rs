@@ -1,16 +1,41 @@
 pub mod args;
+pub mod audio_search;
 pub mod auto_encode;
+pub mod cache;
+pub mod compare;
+pub mod config;
 pub mod crf_search;
+pub mod debug;
+pub mod detect;
+pub mod doctor;
 pub mod encode;
 pub mod print_completions;
+pub mod progress;
+pub mod proxy;
+pub mod psnr;
+pub mod quality_search;
 pub mod sample_encode;
+pub mod scene_encode;
+pub mod ssimulacra2;
 pub mod vmaf;
 
+pub use audio_search::audio_search;
 pub use auto_encode::auto_encode;
+pub use cache::cache;
+pub use compare::compare;
+pub use config::config;
 pub use crf_search::crf_search;
+pub use debug::debug;
+pub use detect::detect;
+pub use doctor::doctor;
 pub use encode::encode;
 pub use print_completions::print_completions;
+pub use proxy::proxy;
+pub use psnr::psnr;
+pub use quality_search::quality_search;
 pub use sample_encode::sample_encode;
+pub use scene_encode::scene_encode;
+pub use ssimulacra2::ssimulacra2;
 pub use vmaf::vmaf;
 
 const PROGRESS_CHARS: &str = "##-";
@@ -1,15 +1,21 @@
 //! Shared argument logic.
 mod encode;
+mod sidecar;
 mod vmaf;
 
 pub use encode::*;
+pub use sidecar::*;
 pub use vmaf::*;
 
-use crate::{command::encode::default_output_ext, ffprobe::Ffprobe};
+use crate::{command::progress, console_ext::style, ffprobe::Ffprobe, naming, process::Runner};
+use anyhow::{ensure, Context};
 use clap::{Parser, ValueHint};
+use indicatif::ProgressBar;
 use std::{
+    collections::HashMap,
+    net::IpAddr,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex, OnceLock},
     time::Duration,
 };
 
@@ -36,11 +42,258 @@ pub struct EncodeToOutput {
     #[arg(long)]
     pub downmix_to_stereo: bool,
 
+    /// Audio bitrate per output channel when re-encoding audio with libopus,
+    /// e.g. "48k". Scales the `-b:a` bitrate by the output channel count,
+    /// so 7.1 audio gets 384k while downmixed stereo gets 96k.
+    ///
+    /// By default 48k per channel with a minimum of 128k is used.
+    /// Not used with --acodec copy or if `-b:a` is passed via --enc.
+    #[arg(long, value_parser = parse_bitrate)]
+    pub abitrate_per_channel: Option<u32>,
+
     /// Only process the main video stream, drop all other streams.
     ///
     /// The output will be a single video stream.
     #[arg(long)]
     pub video_only: bool,
+
+    /// Output container muxer flag(s). E.g. `--mux-flag movflags=+faststart+frag_keyframe`.
+    /// Validated against the output extension, overriding container defaults.
+    ///
+    /// Supported:
+    /// * mp4,mov,m4v: movflags [default: +faststart], frag_duration
+    /// * mkv,webm: cues_to_front [default: y], reserve_index_space, live
+    /// * webm: dash, dash_track_number
+    #[arg(long = "mux-flag", value_parser = parse_mux_flag)]
+    pub mux_flags: Vec<(Arc<str>, Arc<str>)>,
+
+    /// Serve a live preview of the encode over http on this port, e.g. `--preview-port 8080`.
+    ///
+    /// Shows progress & a periodically updated jpeg of a recently encoded frame.
+    /// Frames are decoded from the partially written output so may be unavailable
+    /// for some containers, e.g. mp4.
+    ///
+    /// Only served on localhost unless --preview-bind is set.
+    #[arg(long)]
+    pub preview_port: Option<u16>,
+
+    /// Address the --preview-port server listens on, e.g. `0.0.0.0` to serve the
+    /// preview to other machines.
+    #[arg(long, default_value = "127.0.0.1", requires = "preview_port")]
+    pub preview_bind: IpAddr,
+
+    #[clap(flatten)]
+    pub existing: ExistingOutput,
+
+    /// Also encode a second output with another encoder during the same ffmpeg run,
+    /// decoding the input once. Ffmpeg output options follow the encoder, e.g.
+    /// `--also-encode libx265:crf=24:preset=medium`.
+    ///
+    /// The second output is named as if encoding with that encoder, e.g. vid.x265.mkv.
+    /// Audio & vfilter settings are shared with the main output. --skip-existing &
+    /// --rename-existing apply to the second output too, skipping only the second encode.
+    #[arg(long, value_parser = parse_also_encode)]
+    pub also_encode: Option<AlsoEncode>,
+
+    /// Estimate encode energy usage by sampling cpu package power, RAPL on Linux
+    /// or powermetrics on macOS, both usually requiring root.
+    ///
+    /// Prints kWh used & per GB saved, recording kWh per input hour to compare with
+    /// previous encodes with the same encoder, preset & resolution.
+    #[arg(long)]
+    pub energy: bool,
+
+    /// Score already encoded parts of the output during the encode, showing a rolling
+    /// VMAF estimate. A 5s window is scored every minute of output by a single threaded
+    /// vmaf process. Requires mkv or webm output.
+    ///
+    /// auto-encode warns if the estimate drops below the search --min-vmaf.
+    #[arg(long)]
+    pub live_vmaf: bool,
+
+    /// Don't preserve Dolby Vision & HDR10+ dynamic metadata.
+    ///
+    /// By default, with hevc input & an hevc encoder, metadata is extracted from the input
+    /// with dovi_tool & hdr10plus_tool and re-injected into the output, converting Dolby
+    /// Vision profile 7 to 8.1. With libsvtav1 & libaom-av1 Dolby Vision is instead coded
+    /// by the encoder, requiring ffmpeg >= 7.1.
+    #[arg(long)]
+    pub no_hdr_passthrough: bool,
+}
+
+/// A second encoder output, see `--also-encode`.
+#[derive(Debug, Clone)]
+pub struct AlsoEncode {
+    pub vcodec: Arc<str>,
+    /// Ffmpeg output (key, value) options, e.g. ("crf", "24") for `-crf 24`.
+    pub args: Vec<(Arc<str>, Arc<str>)>,
+}
+
+impl AlsoEncode {
+    pub fn output(&self, input: &Path, is_image: bool) -> PathBuf {
+        naming::default_output_name(input, &self.vcodec, is_image)
+    }
+}
+
+/// Existing output file policy.
+#[derive(Parser, Clone)]
+pub struct ExistingOutput {
+    /// Overwrite an existing output file. This is the default.
+    #[arg(long, conflicts_with_all = ["skip_existing", "rename_existing"])]
+    pub overwrite: bool,
+
+    /// Skip encoding, successfully, if the output file already exists.
+    #[arg(long, conflicts_with = "rename_existing")]
+    pub skip_existing: bool,
+
+    /// Rename an existing output file before writing the output, e.g. "vid.av1.mkv" -> "vid.av1.1.mkv".
+    #[arg(long)]
+    pub rename_existing: bool,
+}
+
+impl ExistingOutput {
+    /// Check the `output` can be written, applying the existing output policy.
+    /// Called before any work starts.
+    ///
+    /// With --rename-existing the new name is decided here, but the existing output
+    /// is only renamed by [`rename_existing`] just before the output is written.
+    ///
+    /// Returns `false` if encoding should be skipped, i.e. --skip-existing.
+    pub fn check_output(&self, output: &Path) -> anyhow::Result<bool> {
+        let dir = match output.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        ensure!(dir.is_dir(), "output directory {} not found", dir.display());
+        // permission bits don't reflect e.g. ownership, acls or read-only mounts,
+        // so check by creating & removing a file
+        let probe = dir.join(format!(".ab-av1-write-check-{}", fastrand::u64(..)));
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+            .with_context(|| format!("output directory {} is not writable", dir.display()))?;
+        _ = std::fs::remove_file(&probe);
+
+        if !output.exists() {
+            return Ok(true);
+        }
+        ensure!(
+            !output.is_dir(),
+            "output {} is a directory",
+            output.display()
+        );
+        if self.skip_existing {
+            eprintln!(
+                "{}",
+                style!("Skipping, {} already exists", output.display()).dim()
+            );
+            return Ok(false);
+        }
+        if self.rename_existing {
+            let renamed = (1..)
+                .map(|n| naming::numbered_name(output, n))
+                .find(|p| !p.exists())
+                .expect("free name");
+            eprintln!(
+                "{}",
+                style!(
+                    "Existing {} will be renamed -> {}",
+                    output.display(),
+                    renamed.display()
+                )
+                .dim()
+            );
+            pending_renames()
+                .lock()
+                .unwrap()
+                .insert(output.to_owned(), renamed);
+        }
+        Ok(true)
+    }
+}
+
+fn pending_renames() -> &'static Mutex<HashMap<PathBuf, PathBuf>> {
+    static RENAMES: OnceLock<Mutex<HashMap<PathBuf, PathBuf>>> = OnceLock::new();
+    RENAMES.get_or_init(<_>::default)
+}
+
+/// Rename an existing `output` to the name decided by [`ExistingOutput::check_output`]
+/// with --rename-existing, if any. Called just before the output is written.
+pub fn rename_existing(output: &Path, bar: &ProgressBar) -> anyhow::Result<()> {
+    let Some(renamed) = pending_renames().lock().unwrap().remove(output) else {
+        return Ok(());
+    };
+    if !output.exists() {
+        return Ok(());
+    }
+    ensure!(
+        !renamed.exists(),
+        "rename {} -> {}: already exists",
+        output.display(),
+        renamed.display()
+    );
+    std::fs::rename(output, &renamed)
+        .with_context(|| format!("rename {} -> {}", output.display(), renamed.display()))?;
+    progress::println(
+        bar,
+        style!(
+            "Renamed existing {} -> {}",
+            output.display(),
+            renamed.display()
+        )
+        .dim()
+        .to_string(),
+    );
+    Ok(())
+}
+
+fn parse_mux_flag(arg: &str) -> anyhow::Result<(Arc<str>, Arc<str>)> {
+    let (key, val) = arg
+        .trim_start_matches('-')
+        .split_once('=')
+        .context("expected key=value")?;
+    ensure!(
+        crate::ffmpeg::MUX_FLAGS.iter().any(|(k, ..)| *k == key),
+        "unsupported muxer flag `{key}`"
+    );
+    Ok((key.into(), val.into()))
+}
+
+fn parse_also_encode(arg: &str) -> anyhow::Result<AlsoEncode> {
+    let mut parts = arg.split(':');
+    let vcodec = parts
+        .next()
+        .filter(|v| !v.is_empty())
+        .context("expected encoder")?;
+    let args = parts
+        .map(|opt| {
+            let (key, val) = opt
+                .trim_start_matches('-')
+                .split_once('=')
+                .with_context(|| format!("expected key=value, got `{opt}`"))?;
+            Ok((key.into(), val.into()))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(AlsoEncode {
+        vcodec: vcodec.into(),
+        args,
+    })
+}
+
+/// Parse a bitrate like "48k", "1M" or "48000" into bits/s.
+fn parse_bitrate(arg: &str) -> anyhow::Result<u32> {
+    let (num, multiplier) = match arg.trim() {
+        a if a.ends_with(['k', 'K']) => (&a[..a.len() - 1], 1_000),
+        a if a.ends_with(['m', 'M']) => (&a[..a.len() - 1], 1_000_000),
+        a => (a, 1),
+    };
+    let bitrate = num.parse::<f64>().context("invalid bitrate")? * f64::from(multiplier);
+    ensure!(
+        bitrate >= 1.0 && bitrate <= u32::MAX.into(),
+        "bitrate out of range"
+    );
+    Ok(bitrate.round() as _)
 }
 
 /// Sampling arguments.
@@ -63,6 +316,20 @@ pub struct Sample {
     #[arg(long)]
     pub min_samples: Option<u64>,
 
+    /// Encode & score each sample this many times, using the mean score or the min
+    /// with `--vmaf-statistic min`. Useful with non-deterministic hardware encoders,
+    /// also reports the run-to-run VMAF standard deviation (not with --reuse-encodes).
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub samples_per_point: u64,
+
+    /// Sample the whole input, including leading studio logos & trailing credits.
+    ///
+    /// By default inputs longer than 24 minutes are scanned for leading & trailing
+    /// mostly black or low-motion sections, which are excluded from sample selection
+    /// as they skew results relative to the main body.
+    #[arg(long)]
+    pub include_credits: bool,
+
     /// Keep temporary files after exiting.
     #[arg(long)]
     pub keep: bool,
@@ -72,9 +339,69 @@ pub struct Sample {
     #[arg(long, env = "AB_AV1_TEMP_DIR", value_hint = ValueHint::DirPath)]
     pub temp_dir: Option<PathBuf>,
 
+    /// Where to store temporary sample data.
+    ///
+    /// * dir: --temp-dir or the input's directory.
+    /// * tmpfs: in-memory /dev/shm, avoiding slow disk io. Falls back to "dir"
+    ///   if unavailable or lacking space for the samples & encodes.
+    #[arg(long, value_enum, default_value_t = TempBackend::Dir)]
+    pub temp_backend: TempBackend,
+
+    /// Encode & score each sample in a single ffmpeg process using a loopback decoder,
+    /// avoiding writing encoded samples to disk. Requires ffmpeg >= 7.1.
+    ///
+    /// Not used with --vfilter, --ffmpeg-template or SSIM. Predicted encode times
+    /// include vmaf analysis time.
+    #[arg(long)]
+    pub loopback_vmaf: bool,
+
+    /// Remux the input video to a temporary mkv before sampling, for frame accurate
+    /// sample seeking in containers with poor or broken indexes.
+    ///
+    /// * never: sample the input directly.
+    /// * auto: reindex .avi, .ts & other mpeg-ts/ps inputs.
+    /// * always: reindex any input.
+    ///
+    /// Reindexing copies the whole input each run, so only use it when samples of
+    /// these inputs have seeking issues.
+    #[arg(long, value_enum, default_value_t = Reindex::Never)]
+    pub reindex: Reindex,
+
     /// Extension preference for encoded samples (ffmpeg encoder only).
     #[arg(skip)]
     pub extension: Option<Arc<str>>,
+
+    /// Runs sample probes, encodes & scoring, replaced in tests.
+    #[arg(skip)]
+    pub runner: Runner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TempBackend {
+    Dir,
+    Tmpfs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Reindex {
+    Never,
+    Auto,
+    Always,
+}
+
+impl Reindex {
+    /// Returns true if `input` should be reindexed before sampling.
+    pub fn is_needed(self, input: &Path) -> bool {
+        const POOR_INDEX_EXTS: &[&str] = &["avi", "ts", "m2ts", "mts", "mpg", "mpeg", "vob"];
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => input
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| POOR_INDEX_EXTS.iter().any(|x| x.eq_ignore_ascii_case(e))),
+        }
+    }
 }
 
 impl Sample {
@@ -91,11 +418,83 @@ impl Sample {
         .max(1)
     }
 
-    pub fn set_extension_from_input(&mut self, input: &Path, probe: &Ffprobe) {
-        self.extension = Some(default_output_ext(input, probe.is_image).into());
+    pub fn set_extension_from_input(&mut self, input: &Path, encoder: &Encoder, probe: &Ffprobe) {
+        self.extension =
+            Some(naming::default_output_ext(input, encoder.as_str(), probe.is_image).into());
     }
 
     pub fn set_extension_from_output(&mut self, output: &Path) {
         self.extension = output.extension().and_then(|e| e.to_str().map(Into::into));
     }
 }
+
+#[test]
+fn reindex_auto_extensions() {
+    assert!(Reindex::Auto.is_needed(Path::new("vid.avi")));
+    assert!(Reindex::Auto.is_needed(Path::new("/tmp/rec.TS")));
+    assert!(!Reindex::Auto.is_needed(Path::new("vid.mkv")));
+    assert!(!Reindex::Auto.is_needed(Path::new("vid")));
+    assert!(Reindex::Always.is_needed(Path::new("vid.mkv")));
+    assert!(!Reindex::Never.is_needed(Path::new("vid.avi")));
+}
+
+#[test]
+fn also_encode_parse() {
+    let also = parse_also_encode("libx265:crf=24:-preset=medium").unwrap();
+    assert_eq!(&*also.vcodec, "libx265");
+    let args: Vec<_> = also.args.iter().map(|(k, v)| (&**k, &**v)).collect();
+    assert_eq!(args, [("crf", "24"), ("preset", "medium")]);
+    assert_eq!(
+        also.output(Path::new("vid.mkv"), false),
+        Path::new("vid.x265.mkv")
+    );
+
+    assert!(parse_also_encode("hevc_videotoolbox")
+        .unwrap()
+        .args
+        .is_empty());
+    assert!(parse_also_encode("libx265:crf").is_err());
+    assert!(parse_also_encode(":crf=24").is_err());
+}
+
+#[test]
+fn check_output_writable_dir() {
+    let dir = std::env::temp_dir().join(format!("ab-av1-check-output-{}", fastrand::u64(..)));
+    std::fs::create_dir(&dir).unwrap();
+    let existing = ExistingOutput::parse_from(["ab-av1"]);
+
+    assert!(existing.check_output(&dir.join("vid.av1.mkv")).unwrap());
+    // probe file removed
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    assert!(existing
+        .check_output(&dir.join("missing/vid.av1.mkv"))
+        .is_err());
+
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn rename_existing_deferred() {
+    let dir = std::env::temp_dir().join(format!("ab-av1-rename-existing-{}", fastrand::u64(..)));
+    std::fs::create_dir(&dir).unwrap();
+    let output = dir.join("vid.av1.mkv");
+    std::fs::write(&output, "old").unwrap();
+    let existing = ExistingOutput::parse_from(["ab-av1", "--rename-existing"]);
+
+    assert!(existing.check_output(&output).unwrap());
+    // not renamed until just before writing
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "old");
+
+    rename_existing(&output, &ProgressBar::hidden()).unwrap();
+    assert!(!output.exists());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("vid.av1.1.mkv")).unwrap(),
+        "old"
+    );
+    // only renamed once
+    std::fs::write(&output, "new").unwrap();
+    rename_existing(&output, &ProgressBar::hidden()).unwrap();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "new");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
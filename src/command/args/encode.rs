@@ -1,24 +1,46 @@
+mod amf;
+mod nvenc;
+mod overlay;
+mod qsv;
+mod vaapi;
+pub mod vfilter;
+mod videotoolbox;
+mod x26x;
+
 use crate::{
-    ffmpeg::FfmpegEncodeArgs,
-    ffprobe::{Ffprobe, ProbeError},
+    ffmpeg::{FfmpegEncodeArgs, VCodecSpecific},
+    ffprobe::{self, Ffprobe, ProbeError},
     float::TerseF32,
+    sample,
 };
-use anyhow::ensure;
+use amf::Amf;
+use anyhow::{ensure, Context};
 use clap::{Parser, ValueHint};
+use nvenc::Nvenc;
+pub use overlay::Overlay;
+use qsv::Qsv;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
     fmt::{self, Write},
+    ops::RangeInclusive,
     path::PathBuf,
     sync::Arc,
     time::Duration,
 };
+use vaapi::Vaapi;
+pub use vaapi::VAAPI_DEVICE;
+pub use videotoolbox::RateControl;
+use videotoolbox::Videotoolbox;
+use x26x::X26x;
 
 /// Common svt-av1/ffmpeg input encoding arguments.
 #[derive(Parser, Clone)]
 pub struct Encode {
     /// Encoder override. See https://ffmpeg.org/ffmpeg-all.html#toc-Video-Encoders.
     ///
-    /// [possible values: libsvtav1, libx264, libx265, libvpx-vp9, ...]
+    /// "auto" uses the best usable hardware or software encoder, see `ab-av1 detect`.
+    ///
+    /// [possible values: libsvtav1, libx264, libx265, libvpx-vp9, auto, ...]
     #[arg(value_enum, short, long, default_value = "libsvtav1")]
     pub encoder: Encoder,
 
@@ -33,6 +55,38 @@ pub struct Encode {
     #[arg(long)]
     pub vfilter: Option<String>,
 
+    /// Image overlaid on the video after any --vfilter, e.g. a logo watermark.
+    /// `IMAGE[:pos=tl|tr|bl|br|center][:opacity=0-1]`, default position br & opacity 1.
+    /// E.g. --overlay "logo.png:pos=tr:opacity=0.6".
+    ///
+    /// Metric reference frames are not overlaid, so the overlay counts against scores,
+    /// unless --overlay-reference.
+    #[arg(long)]
+    pub overlay: Option<Overlay>,
+
+    /// Also overlay the --overlay image onto the metric reference, so scores compare
+    /// the encode fairly against a branded reference.
+    #[arg(long, requires = "overlay")]
+    pub overlay_reference: bool,
+
+    /// Anamorphic (non-square pixel) input handling.
+    ///
+    /// * preserve: keep the input sample aspect ratio.
+    /// * square: scale the width to square pixels, e.g. 720x576 SAR 64:45 -> 1024x576.
+    #[arg(long, value_enum, default_value_t = Sar::Preserve)]
+    pub sar: Sar,
+
+    /// Inverse telecine (3:2 pulldown removal), e.g. for film DVDs stored as 29.97fps
+    /// interlaced video, restoring the original 23.976fps progressive frames.
+    ///
+    /// * off: encode the input frames as is.
+    /// * on: always apply fieldmatch & decimate filters.
+    /// * auto: apply when the ffmpeg idet filter detects telecined samples.
+    ///
+    /// Filters are applied to both the encode & metric reference.
+    #[arg(long, value_enum, default_value_t = Detelecine::Off)]
+    pub detelecine: Detelecine,
+
     /// Pixel format. svt-av1 default yuv420p10le.
     #[arg(value_enum, long)]
     pub pix_format: Option<PixelFormat>,
@@ -42,6 +96,8 @@ pub struct Encode {
     ///
     /// For some ffmpeg encoders a word may be used, e.g. "fast".
     /// libaom-av1 preset is mapped to equivalent -cpu-used argument.
+    /// nvenc preset 1-7 is mapped to p1-p7, qsv accepts 1-7 (veryslow-veryfast).
+    /// amf preset is mapped to -quality, e.g. "balanced".
     ///
     /// [svt-av1 default: 8]
     #[arg(long)]
@@ -62,6 +118,30 @@ pub struct Encode {
     #[arg(long)]
     pub scd: Option<bool>,
 
+    /// Force keyframes in the final encode, so seeking lands on them even with long
+    /// keyint values. Sample encodes are unaffected.
+    ///
+    /// * chapters: at each chapter start.
+    /// * scenes: where the input has keyframes, typically at scene cuts.
+    /// * interval: exactly every --keyint frames, or the default keyint. For inputs
+    ///   under 3m without a default keyint, every `--enc g` frames or 10s.
+    ///
+    /// Encoders may ignore forced keyframes, e.g. svt-av1 requires `--svt force-key-frames=1`.
+    #[arg(long, value_enum)]
+    pub force_keyframes: Option<ForceKeyframes>,
+
+    /// Videotoolbox rate control.
+    ///
+    /// * cq: constant quality, --crf 0-99 is mapped to `-q:v` 100-1.
+    /// * abr: average bitrate set with `--enc b:v=BITRATE`, optionally with
+    ///   `--enc maxrate=..` & `--enc bufsize=..`.
+    /// * cbr: constant bitrate set with `--enc b:v=BITRATE`.
+    ///
+    /// --crf is unused by abr & cbr, so these cannot be used to search crf.
+    /// [default: cq]
+    #[arg(long, value_enum)]
+    pub rc: Option<RateControl>,
+
     /// Additional svt-av1 arg(s). E.g. --svt mbr=2000 --svt film-grain=8
     ///
     /// See https://gitlab.com/AOMediaCodec/SVT-AV1/-/blob/master/Docs/svt-av1_encoder_user_guide.md#options
@@ -82,6 +162,21 @@ pub struct Encode {
     /// See --enc docs.
     #[arg(long = "enc-input", allow_hyphen_values = true, value_parser = parse_enc_arg)]
     pub enc_input_args: Vec<String>,
+
+    /// Raw command template to run instead of the built-in ffmpeg encoding command.
+    /// Intended for exotic pipelines, e.g. piping through external filters.
+    ///
+    /// Placeholders are substituted in each argument:
+    /// * `{input}` input (or sample) file
+    /// * `{output}` output file
+    /// * `{quality}` the crf value
+    /// * `{vfilter}` --vfilter value, or "null" if unset
+    ///
+    /// E.g. --ffmpeg-template "ffmpeg -y -i {input} -vf {vfilter} -c:v libx265 -crf {quality} -an {output}"
+    ///
+    /// Arguments are split shell style. The command should output ffmpeg progress to stderr.
+    #[arg(long, value_parser = parse_ffmpeg_template)]
+    pub ffmpeg_template: Option<Arc<str>>,
 }
 
 fn parse_svt_arg(arg: &str) -> anyhow::Result<Arc<str>> {
@@ -108,7 +203,92 @@ fn parse_enc_arg(arg: &str) -> anyhow::Result<String> {
     Ok(arg)
 }
 
+fn parse_ffmpeg_template(template: &str) -> anyhow::Result<Arc<str>> {
+    let args = shlex::split(template).context("invalid shell syntax")?;
+    ensure!(!args.is_empty(), "empty template");
+    for placeholder in ["{input}", "{output}"] {
+        ensure!(
+            args.iter().any(|a| a.contains(placeholder)),
+            "missing {placeholder} placeholder"
+        );
+    }
+    Ok(template.into())
+}
+
+/// Anamorphic input handling.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sar {
+    Preserve,
+    Square,
+}
+
+/// Inverse telecine input handling.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detelecine {
+    Off,
+    On,
+    Auto,
+}
+
+/// Matches telecined fields into progressive frames, deinterlacing any left combed,
+/// & drops the duplicate frame of each 5.
+const DETELECINE_VFILTER: &str = "fieldmatch,yadif=deint=interlaced,decimate";
+
+/// Scales anamorphic video to square pixels, keeping the height.
+const SQUARE_PIXELS_VFILTER: &str = "scale=trunc(iw*sar/2)*2:ih:flags=bicubic,setsar=1";
+
 impl Encode {
+    /// Resolve `--encoder auto` to the best usable encoder, see `ab-av1 detect`.
+    ///
+    /// Done by commands after arg parsing as detection runs test encodes.
+    pub fn resolve_encoder(&mut self) -> anyhow::Result<()> {
+        if self.encoder.as_str() == "auto" {
+            let best = crate::command::detect::best_encoder()
+                .context("--encoder auto: no usable encoders found")?;
+            self.encoder = Encoder(best.into());
+        }
+        Ok(())
+    }
+
+    /// With `--sar square` & anamorphic input, prepend square pixel scaling to the vfilter.
+    ///
+    /// The filter is folded into `vfilter` so encode hints & nested commands don't reapply it.
+    pub fn apply_sar(&mut self, probe: &Ffprobe) {
+        if self.sar != Sar::Square {
+            return;
+        }
+        self.sar = Sar::Preserve;
+        if probe.sar.is_some() {
+            self.vfilter = Some(match self.vfilter.take() {
+                Some(vf) => format!("{SQUARE_PIXELS_VFILTER},{vf}"),
+                None => SQUARE_PIXELS_VFILTER.into(),
+            });
+        }
+    }
+
+    /// With `--detelecine on`, or `auto` & detected telecine, prepend inverse telecine
+    /// filters to the vfilter.
+    ///
+    /// Like [`Self::apply_sar`] the filters are folded into `vfilter`, so both encodes
+    /// & metric references use them. Should be called after `apply_sar`.
+    pub fn apply_detelecine(&mut self, probe: &Ffprobe) {
+        let detelecine = match self.detelecine {
+            Detelecine::Off => false,
+            Detelecine::On => true,
+            Detelecine::Auto => match &probe.duration {
+                Ok(duration) if !probe.is_image => sample::telecined(&self.input, *duration),
+                _ => false,
+            },
+        };
+        self.detelecine = Detelecine::Off;
+        if detelecine {
+            self.vfilter = Some(match self.vfilter.take() {
+                Some(vf) => format!("{DETELECINE_VFILTER},{vf}"),
+                None => DETELECINE_VFILTER.into(),
+            });
+        }
+    }
+
     pub fn to_encoder_args(
         &self,
         crf: f32,
@@ -122,13 +302,20 @@ impl Encode {
             encoder,
             input,
             vfilter,
+            overlay,
+            overlay_reference,
+            sar: _,
+            detelecine: _,
             preset,
             pix_format,
             keyint,
             scd,
+            force_keyframes,
+            rc,
             svt_args,
             enc_args,
             enc_input_args,
+            ffmpeg_template,
         } = self;
 
         let input = shell_escape::escape(input.display().to_string().into());
@@ -150,12 +337,24 @@ impl Encode {
         if let Some(scd) = scd {
             write!(hint, " --scd {scd}").unwrap();
         }
+        if let Some(force) = force_keyframes {
+            write!(hint, " --force-keyframes {force}").unwrap();
+        }
+        if let Some(rc) = rc {
+            write!(hint, " --rc {rc}").unwrap();
+        }
         if let Some(pix_fmt) = pix_format {
             write!(hint, " --pix-format {pix_fmt}").unwrap();
         }
         if let Some(filter) = vfilter {
             write!(hint, " --vfilter {filter:?}").unwrap();
         }
+        if let Some(overlay) = overlay {
+            write!(hint, " --overlay {:?}", overlay.to_string()).unwrap();
+        }
+        if *overlay_reference {
+            hint.push_str(" --overlay-reference");
+        }
         for arg in svt_args {
             write!(hint, " --svt {arg}").unwrap();
         }
@@ -167,6 +366,9 @@ impl Encode {
             let arg = arg.trim_start_matches('-');
             write!(hint, " --enc {arg}").unwrap();
         }
+        if let Some(template) = ffmpeg_template {
+            write!(hint, " --ffmpeg-template {template:?}").unwrap();
+        }
 
         hint
     }
@@ -183,7 +385,9 @@ impl Encode {
             "--svt may only be used with svt-av1"
         );
 
+        let nvenc = Nvenc::from_vcodec(&vcodec);
         let preset = match &self.preset {
+            Some(Preset::Number(n)) if nvenc.is_some() => Some(Nvenc::preset(*n)?),
             Some(Preset::Number(n)) => Some(n.to_string().into()),
             Some(Preset::Name(n)) => Some(n.clone()),
             None if svtav1 => Some("8".into()),
@@ -240,12 +444,9 @@ impl Encode {
             }
         }
 
-        let pix_fmt = self.pix_format.unwrap_or(match &*vcodec {
-            vc if vc.contains("av1") => PixelFormat::Yuv420p10le,
-            _ => PixelFormat::Yuv420p,
-        });
+        let pix_fmt = self.pix_fmt();
 
-        let input_args: Vec<Arc<String>> = self
+        let mut input_args: Vec<Arc<String>> = self
             .enc_input_args
             .iter()
             .flat_map(|arg| {
@@ -258,63 +459,293 @@ impl Encode {
             .collect();
 
         // ban usage of the bits we already set via other args & logic
-        let reserved = HashMap::from([
-            ("-c:a", " use --acodec"),
-            ("-codec:a", " use --acodec"),
-            ("-acodec", " use --acodec"),
-            ("-i", ""),
-            ("-y", ""),
-            ("-n", ""),
-            ("-c:v", " use --encoder"),
-            ("-codec:v", " use --encoder"),
-            ("-vcodec", " use --encoder"),
-            ("-pix_fmt", " use --pix-format"),
-            ("-crf", ""),
-            ("-preset", " use --preset"),
-            ("-vf", " use --vfilter"),
-            ("-filter:v", " use --vfilter"),
-        ]);
-        for arg in args.iter().chain(input_args.iter()) {
-            if let Some(hint) = reserved.get(arg.as_str()) {
-                anyhow::bail!("Encoder argument `{arg}` not allowed{hint}");
+        for arg in self.enc_args.iter().chain(&self.enc_input_args) {
+            let opt = arg.split_once('=').map_or(arg.as_str(), |(opt, _)| opt);
+            if let Some(hint) = self.managed_option_hint(opt, &vcodec) {
+                anyhow::bail!("Encoder argument `{opt}` not allowed{hint}");
+            }
+        }
+
+        match Videotoolbox::from_vcodec(&vcodec) {
+            Some(vt) => {
+                let rc = self.rc.unwrap_or_default();
+                vt.validate(rc, crf, &args)?;
+                args.extend(vt.rc_args(rc, crf));
             }
+            None => ensure!(
+                self.rc.is_none(),
+                "--rc may only be used with videotoolbox encoders"
+            ),
         }
 
+        if let Some(x26x) = X26x::from_vcodec(&vcodec) {
+            x26x.validate(crf, pix_fmt, self.preset.as_ref(), &args)?;
+        }
+        if let Some(nvenc) = nvenc {
+            nvenc.validate(crf, self.preset.as_ref(), &args)?;
+        }
+        if let Some(qsv) = Qsv::from_vcodec(&vcodec) {
+            qsv.validate(crf, self.preset.as_ref(), &args)?;
+        }
+        if let Some(amf) = Amf::from_vcodec(&vcodec) {
+            amf.validate(crf, self.preset.as_ref(), &args)?;
+            args.extend(amf.qp_args(crf));
+        }
+        let hw_upload = match Vaapi::from_vcodec(&vcodec) {
+            Some(vaapi) => {
+                let range = vaapi.crf_range();
+                ensure!(
+                    range.contains(&crf),
+                    "crf {crf} out of range {}-{}",
+                    range.start(),
+                    range.end()
+                );
+                Vaapi::add_device_arg(&mut input_args);
+                Some(Vaapi::upload_vfilter(pix_fmt)?)
+            }
+            None => None,
+        };
+
+        let vfilter: Option<Cow<_>> = match &self.overlay {
+            Some(overlay) => Some(overlay.compose(self.vfilter.as_deref()).into()),
+            None => self.vfilter.as_deref().map(Cow::from),
+        };
+        let ref_vfilter = match self.overlay_reference {
+            true => vfilter.clone(),
+            false => self.vfilter.as_deref().map(Cow::from),
+        };
+
         Ok(FfmpegEncodeArgs {
             input: &self.input,
             vcodec,
             pix_fmt,
-            vfilter: self.vfilter.as_deref(),
+            vfilter,
+            ref_vfilter,
             crf,
             preset,
             output_args: args,
             input_args,
+            hw_upload,
             video_only: false,
+            template: self.ffmpeg_template.clone(),
         })
     }
 
+    /// Returns a hint if ffmpeg option `opt`, or an alias of it, is set by other args.
+    fn managed_option_hint(&self, opt: &str, vcodec: &Arc<str>) -> Option<&'static str> {
+        const MANAGED: &[(&str, &str)] = &[
+            ("-c:v", " use --encoder"),
+            ("-c:a", " use --acodec"),
+            ("-filter:v", " use --vfilter"),
+            ("-filter_complex", " use --vfilter"),
+            ("-lavfi", " use --vfilter"),
+            ("-pix_fmt", " use --pix-format"),
+            ("-crf", " use --crf"),
+            ("-preset", " use --preset"),
+            ("-i", ""),
+            ("-y", ""),
+            ("-n", ""),
+        ];
+        let opt = FfmpegOption::parse(opt);
+        MANAGED
+            .iter()
+            .copied()
+            .chain([
+                (vcodec.crf_arg(), " use --crf"),
+                (vcodec.preset_arg(), " use --preset"),
+            ])
+            .chain(
+                self.keyint
+                    .is_some()
+                    .then_some(("-g", " conflicts with --keyint")),
+            )
+            .find(|(managed, _)| opt.overlaps(&FfmpegOption::parse(managed)))
+            .map(|(_, hint)| hint)
+    }
+
+    /// Ensure no bitrate rate control args are set that would conflict with crf.
+    ///
+    /// Searching crf with these would produce mixed rate control sample encodes
+    /// that don't match the final encode.
+    pub fn ensure_crf_rate_control(&self) -> anyhow::Result<()> {
+        if let Some(rc) = self.rc.filter(|rc| *rc != RateControl::Cq) {
+            anyhow::bail!("--rc {rc} cannot be used with crf, bitrate rate control overrides it");
+        }
+        for arg in &self.enc_args {
+            let opt = arg.split_once('=').map_or(arg.as_str(), |(opt, _)| opt);
+            ensure!(
+                !FfmpegOption::parse(opt).overlaps(&FfmpegOption::parse("-b:v")),
+                "--enc {} cannot be used with crf, bitrate rate control overrides it",
+                arg.trim_start_matches('-'),
+            );
+        }
+        for arg in &self.svt_args {
+            let (opt, val) = arg.split_once('=').unwrap_or((arg, ""));
+            ensure!(
+                opt != "tbr" && !(opt == "rc" && val != "0"),
+                "--svt {arg} cannot be used with crf, bitrate rate control overrides it",
+            );
+        }
+        Ok(())
+    }
+
+    /// Final encode `-force_key_frames` output args for --force-keyframes.
+    pub fn force_keyframes_args(&self, probe: &Ffprobe) -> anyhow::Result<Vec<Arc<String>>> {
+        let Some(force) = self.force_keyframes else {
+            return Ok(vec![]);
+        };
+        ensure!(
+            !self
+                .enc_args
+                .iter()
+                .any(|a| a.starts_with("-force_key_frames")),
+            "--enc force_key_frames cannot be used with --force-keyframes"
+        );
+        let expr = match force {
+            ForceKeyframes::Chapters => {
+                let starts: Vec<_> = ffprobe::probe_all_chapters(&self.input)?
+                    .into_iter()
+                    .map(|c| c.start)
+                    .collect();
+                chapters_force_keyframes(&starts)
+                    .context("--force-keyframes chapters: input has no chapters")?
+            }
+            ForceKeyframes::Scenes => "source".into(),
+            ForceKeyframes::Interval => {
+                // short inputs have no default keyint, so use the encoder's gop
+                let keyint = match self.keyint(probe)? {
+                    Some(keyint) => keyint,
+                    None => match self.enc_args.iter().find_map(|a| a.strip_prefix("-g=")) {
+                        Some(g) => g.parse().context("--enc g invalid")?,
+                        None => self.default_keyint(probe)?,
+                    },
+                };
+                format!("expr:gte(n,n_forced*{keyint})")
+            }
+        };
+        Ok(vec!["-force_key_frames".to_owned().into(), expr.into()])
+    }
+
+    /// Output duration of an input `duration` after --vfilter timestamp changes.
+    pub fn output_duration(&self, duration: Duration) -> Duration {
+        match self.vfilter.as_deref() {
+            Some(vf) => duration.mul_f64(vfilter::time_scale(vf)),
+            None => duration,
+        }
+    }
+
     fn keyint(&self, probe: &Ffprobe) -> anyhow::Result<Option<i32>> {
         const KEYINT_DEFAULT_INPUT_MIN: Duration = Duration::from_secs(60 * 3);
+
+        Ok(match (self.keyint, &probe.duration) {
+            (Some(ki), _) => Some(ki.keyint_number(self.output_fps(probe))?),
+            (None, Ok(duration))
+                if *duration >= KEYINT_DEFAULT_INPUT_MIN && self.output_fps(probe).is_ok() =>
+            {
+                Some(self.default_keyint(probe)?)
+            }
+            _ => None,
+        })
+    }
+
+    /// Output pixel format, --pix-format or the encoder default.
+    pub fn pix_fmt(&self) -> PixelFormat {
+        self.pix_format.unwrap_or(match self.encoder.as_str() {
+            vc if vc.contains("av1") => PixelFormat::Yuv420p10le,
+            _ => PixelFormat::Yuv420p,
+        })
+    }
+
+    /// Default 10s keyint in frames.
+    fn default_keyint(&self, probe: &Ffprobe) -> anyhow::Result<i32> {
         const KEYINT_DEFAULT: Duration = Duration::from_secs(10);
+        Ok(KeyInterval::Duration(KEYINT_DEFAULT).keyint_number(self.output_fps(probe))?)
+    }
 
-        let filter_fps = self.vfilter.as_deref().and_then(try_parse_fps_vfilter);
-        Ok(
-            match (self.keyint, &probe.duration, &probe.fps, filter_fps) {
-                // use the filter-fps if used, otherwise the input fps
-                (Some(ki), .., Some(fps)) => Some(ki.keyint_number(Ok(fps))?),
-                (Some(ki), _, fps, None) => Some(ki.keyint_number(fps.clone())?),
-                (None, Ok(duration), _, Some(fps)) if *duration >= KEYINT_DEFAULT_INPUT_MIN => {
-                    Some(KeyInterval::Duration(KEYINT_DEFAULT).keyint_number(Ok(fps))?)
-                }
-                (None, Ok(duration), Ok(fps), None) if *duration >= KEYINT_DEFAULT_INPUT_MIN => {
-                    Some(KeyInterval::Duration(KEYINT_DEFAULT).keyint_number(Ok(*fps))?)
-                }
-                _ => None,
-            },
-        )
+    /// Output fps, the --vfilter fps if changed, otherwise the input fps.
+    fn output_fps(&self, probe: &Ffprobe) -> Result<f64, ProbeError> {
+        let filter_fps = self
+            .vfilter
+            .as_deref()
+            .and_then(|vf| vfilter::output_fps(vf, probe.fps.as_ref().ok().copied()));
+        match filter_fps {
+            Some(fps) => Ok(fps),
+            None => probe.fps.clone(),
+        }
     }
 }
 
+/// An ffmpeg option with aliases & stream specifiers resolved,
+/// e.g. "-vcodec" & "-codec:v:0" are both `-c` for video streams.
+#[derive(Debug, PartialEq, Eq)]
+struct FfmpegOption<'a> {
+    name: &'a str,
+    /// Stream type 'v', 'a' or 's'. `None` if applying to all streams.
+    stream: Option<char>,
+}
+
+impl<'a> FfmpegOption<'a> {
+    fn parse(opt: &'a str) -> Self {
+        let (name, spec) = match opt.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (opt, None),
+        };
+        let (name, stream) = match name {
+            "-codec" => ("-c", None),
+            "-vcodec" => ("-c", Some('v')),
+            "-acodec" => ("-c", Some('a')),
+            "-scodec" => ("-c", Some('s')),
+            "-vf" => ("-filter", Some('v')),
+            "-af" => ("-filter", Some('a')),
+            "-qscale" => ("-q", None),
+            "-filter_complex_script" | "-/filter_complex" => ("-filter_complex", None),
+            name => (name, None),
+        };
+        let stream = stream.or_else(|| match spec?.chars().next()? {
+            // "V" is video excluding attached pictures
+            'v' | 'V' => Some('v'),
+            c @ ('a' | 's') => Some(c),
+            _ => None,
+        });
+        Self { name, stream }
+    }
+
+    /// Returns true if both options set the same thing for some stream.
+    fn overlaps(&self, other: &Self) -> bool {
+        self.name == other.name
+            && (self.stream.is_none() || other.stream.is_none() || self.stream == other.stream)
+    }
+}
+
+/// Keyframe placement forced by --force-keyframes.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForceKeyframes {
+    Chapters,
+    Scenes,
+    Interval,
+}
+
+impl fmt::Display for ForceKeyframes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chapters => "chapters".fmt(f),
+            Self::Scenes => "scenes".fmt(f),
+            Self::Interval => "interval".fmt(f),
+        }
+    }
+}
+
+/// `-force_key_frames` time list of chapter starts, excluding the start of the video
+/// which is always a keyframe. `None` if there are no such chapters.
+fn chapters_force_keyframes(starts: &[Duration]) -> Option<String> {
+    let times: Vec<_> = starts
+        .iter()
+        .filter(|s| !s.is_zero())
+        .map(|s| format!("{:.3}", s.as_secs_f64()))
+        .collect();
+    (!times.is_empty()).then(|| times.join(","))
+}
+
 /// Video codec for encoding.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Encoder(Arc<str>);
@@ -335,7 +766,38 @@ impl Encoder {
         }
     }
 
+    /// Valid crf range for the output pixel format, if known.
+    pub fn crf_range(&self, pix_fmt: PixelFormat) -> Option<RangeInclusive<f32>> {
+        X26x::from_vcodec(self.as_str())
+            .map(|x26x| x26x.crf_range(pix_fmt))
+            .or_else(|| Nvenc::from_vcodec(self.as_str()).map(Nvenc::crf_range))
+            .or_else(|| Qsv::from_vcodec(self.as_str()).map(Qsv::crf_range))
+            .or_else(|| Vaapi::from_vcodec(self.as_str()).map(Vaapi::crf_range))
+            .or_else(|| Amf::from_vcodec(self.as_str()).map(Amf::crf_range))
+            .or_else(|| Videotoolbox::from_vcodec(self.as_str()).map(Videotoolbox::crf_range))
+    }
+
+    /// Minimum (highest quality) crf a crf-search tries by default.
+    pub fn default_min_crf(&self) -> f32 {
+        10.0
+    }
+
     pub fn default_max_crf(&self) -> f32 {
+        if let Some(nvenc) = Nvenc::from_vcodec(self.as_str()) {
+            return nvenc.default_max_crf();
+        }
+        if let Some(qsv) = Qsv::from_vcodec(self.as_str()) {
+            return qsv.default_max_crf();
+        }
+        if let Some(vaapi) = Vaapi::from_vcodec(self.as_str()) {
+            return vaapi.default_max_crf();
+        }
+        if let Some(amf) = Amf::from_vcodec(self.as_str()) {
+            return amf.default_max_crf();
+        }
+        if let Some(vt) = Videotoolbox::from_vcodec(self.as_str()) {
+            return vt.default_max_crf();
+        }
         match self.as_str() {
             "libx264" | "libx265" => 46.0,
             // rav1e: use max -qp
@@ -347,15 +809,18 @@ impl Encoder {
 
     /// Additional encoder specific ffmpeg arg defaults.
     fn default_ffmpeg_args(&self) -> &[(&'static str, &'static str)] {
+        if let Some(nvenc) = Nvenc::from_vcodec(self.as_str()) {
+            return nvenc.default_ffmpeg_args();
+        }
+        if let Some(qsv) = Qsv::from_vcodec(self.as_str()) {
+            return qsv.default_ffmpeg_args();
+        }
+        if let Some(amf) = Amf::from_vcodec(self.as_str()) {
+            return amf.default_ffmpeg_args();
+        }
         match self.as_str() {
             // add `-b:v 0` for aom & vp9 to use "constant quality" mode
             "libaom-av1" | "libvpx-vp9" => &[("-b:v", "0")],
-            // enable lookahead mode for qsv encoders
-            "av1_qsv" | "hevc_qsv" | "h264_qsv" => &[
-                ("-look_ahead", "1"),
-                ("-extbrc", "1"),
-                ("-look_ahead_depth", "40"),
-            ],
             _ => &[],
         }
     }
@@ -448,6 +913,12 @@ pub enum PixelFormat {
     Yuv444p10le,
 }
 
+/// Encoder output args, as parsed from --enc, for encoder validation tests.
+#[cfg(test)]
+fn test_args(args: &[&str]) -> Vec<Arc<String>> {
+    args.iter().map(|a| Arc::new(a.to_string())).collect()
+}
+
 #[test]
 fn pixel_format_order() {
     use PixelFormat::*;
@@ -463,6 +934,13 @@ impl PixelFormat {
             Self::Yuv420p => "yuv420p",
         }
     }
+
+    pub fn bit_depth(self) -> u8 {
+        match self {
+            Self::Yuv420p => 8,
+            Self::Yuv420p10le | Self::Yuv444p10le => 10,
+        }
+    }
 }
 
 impl fmt::Display for PixelFormat {
@@ -484,28 +962,69 @@ impl TryFrom<&str> for PixelFormat {
     }
 }
 
-fn try_parse_fps_vfilter(vfilter: &str) -> Option<f64> {
-    let fps_filter = vfilter
-        .split(',')
-        .find_map(|vf| vf.trim().strip_prefix("fps="))?
-        .trim();
-
-    match fps_filter {
-        "ntsc" => Some(30000.0 / 1001.0),
-        "pal" => Some(25.0),
-        "film" => Some(24.0),
-        "ntsc_film" => Some(24000.0 / 1001.0),
-        _ => crate::ffprobe::parse_frame_rate(fps_filter),
-    }
+#[test]
+fn ffmpeg_template_placeholders() {
+    assert!(parse_ffmpeg_template("ffmpeg -i {input} -crf {quality} {output}").is_ok());
+    assert!(parse_ffmpeg_template("ffmpeg -i {input} -crf {quality} out.mkv").is_err());
+    assert!(parse_ffmpeg_template("ffmpeg -i \"{input}").is_err());
 }
 
 #[test]
-fn test_try_parse_fps_vfilter() {
-    let fps = try_parse_fps_vfilter("scale=1280:-1, fps=24, transpose=1").unwrap();
-    assert!((fps - 24.0).abs() < f64::EPSILON, "{fps:?}");
+fn videotoolbox_rate_control_args() {
+    let enc = Encode {
+        encoder: Encoder("hevc_videotoolbox".into()),
+        input: "vid.mp4".into(),
+        vfilter: None,
+        overlay: None,
+        overlay_reference: false,
+        sar: Sar::Preserve,
+        detelecine: Detelecine::Off,
+        preset: None,
+        pix_format: None,
+        keyint: None,
+        scd: None,
+        force_keyframes: None,
+        rc: None,
+        svt_args: vec![],
+        enc_args: vec!["-b:v=5M".into()],
+        enc_input_args: <_>::default(),
+        ffmpeg_template: None,
+    };
+    let probe = Ffprobe {
+        duration: Ok(Duration::from_secs(60)),
+        has_audio: false,
+        max_audio_channels: None,
+        fps: Ok(30.0),
+        resolution: Some((1920, 1080)),
+        sar: None,
+        is_image: false,
+        pix_fmt: None,
+        dynamic_hdr: <_>::default(),
+    };
 
-    let fps = try_parse_fps_vfilter("scale=1280:-1, fps=ntsc, transpose=1").unwrap();
-    assert!((fps - 30000.0 / 1001.0).abs() < f64::EPSILON, "{fps:?}");
+    let Err(err) = enc.to_encoder_args(60.0, &probe) else {
+        panic!("-b:v should error");
+    };
+    assert!(err.to_string().contains("`-b:v` not allowed"), "{err}");
+
+    let abr = Encode {
+        rc: Some(RateControl::Abr),
+        enc_args: vec!["-b:v=5M".into()],
+        ..enc.clone()
+    };
+    let args = abr.to_encoder_args(60.0, &probe).unwrap();
+    assert!(!args.output_args.iter().any(|a| a.as_str() == "-q:v"));
+    assert!(abr.ensure_crf_rate_control().is_err());
+
+    let enc = Encode {
+        enc_args: vec!["-realtime=1".into()],
+        ..enc
+    };
+    let args = enc.to_encoder_args(60.0, &probe).unwrap();
+    assert!(args
+        .output_args
+        .windows(2)
+        .any(|a| a[0].as_str() == "-q:v" && a[1].as_str() == "40"));
 }
 
 #[test]
@@ -529,13 +1048,20 @@ fn svtav1_to_ffmpeg_args_default_over_3m() {
         encoder: Encoder("libsvtav1".into()),
         input: "vid.mp4".into(),
         vfilter: Some("scale=320:-1,fps=film".into()),
+        overlay: None,
+        overlay_reference: false,
+        sar: Sar::Preserve,
+        detelecine: Detelecine::Off,
         preset: None,
         pix_format: None,
         keyint: None,
         scd: None,
+        force_keyframes: None,
+        rc: None,
         svt_args: vec!["film-grain=30".into()],
         enc_args: <_>::default(),
         enc_input_args: <_>::default(),
+        ffmpeg_template: None,
     };
 
     let probe = Ffprobe {
@@ -544,31 +1070,37 @@ fn svtav1_to_ffmpeg_args_default_over_3m() {
         max_audio_channels: None,
         fps: Ok(30.0),
         resolution: Some((1280, 720)),
+        sar: None,
         is_image: false,
         pix_fmt: None,
+        dynamic_hdr: <_>::default(),
     };
 
     let FfmpegEncodeArgs {
         input,
         vcodec,
         vfilter,
+        ref_vfilter: _,
         pix_fmt,
         crf,
         preset,
         output_args,
         input_args,
+        hw_upload: _,
         video_only,
+        template,
     } = enc
         .to_ffmpeg_args("libsvtav1".into(), 32.0, &probe)
         .expect("to_ffmpeg_args");
 
     assert_eq!(&*vcodec, "libsvtav1");
     assert_eq!(input, enc.input);
-    assert_eq!(vfilter, Some("scale=320:-1,fps=film"));
+    assert_eq!(vfilter.as_deref(), Some("scale=320:-1,fps=film"));
     assert_eq!(crf, 32.0);
     assert_eq!(preset, Some("8".into()));
     assert_eq!(pix_fmt, PixelFormat::Yuv420p10le);
     assert!(!video_only);
+    assert_eq!(template, None);
 
     assert!(
         output_args
@@ -594,13 +1126,20 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
         encoder: Encoder("libsvtav1".into()),
         input: "vid.mp4".into(),
         vfilter: None,
+        overlay: None,
+        overlay_reference: false,
+        sar: Sar::Preserve,
+        detelecine: Detelecine::Off,
         preset: Some(Preset::Number(7)),
         pix_format: Some(PixelFormat::Yuv420p),
         keyint: None,
         scd: None,
+        force_keyframes: None,
+        rc: None,
         svt_args: vec![],
         enc_args: <_>::default(),
         enc_input_args: <_>::default(),
+        ffmpeg_template: None,
     };
 
     let probe = Ffprobe {
@@ -609,20 +1148,25 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
         max_audio_channels: None,
         fps: Ok(24.0),
         resolution: Some((1280, 720)),
+        sar: None,
         is_image: false,
         pix_fmt: None,
+        dynamic_hdr: <_>::default(),
     };
 
     let FfmpegEncodeArgs {
         input,
         vcodec,
         vfilter,
+        ref_vfilter: _,
         pix_fmt,
         crf,
         preset,
         output_args,
         input_args,
+        hw_upload: _,
         video_only,
+        template,
     } = enc
         .to_ffmpeg_args("libsvtav1".into(), 32.0, &probe)
         .expect("to_ffmpeg_args");
@@ -634,6 +1178,7 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
     assert_eq!(preset, Some("7".into()));
     assert_eq!(pix_fmt, PixelFormat::Yuv420p);
     assert!(!video_only);
+    assert_eq!(template, None);
 
     assert!(
         !output_args.iter().any(|a| a.as_str() == "-g"),
@@ -650,3 +1195,141 @@ fn svtav1_to_ffmpeg_args_default_under_3m() {
     assert_eq!(svtargs, "scd=0");
     assert!(input_args.is_empty());
 }
+
+#[test]
+fn crf_rate_control_conflicts() {
+    let enc = |svt_args: &[&str], enc_args: &[&str]| Encode {
+        encoder: Encoder("libsvtav1".into()),
+        input: "vid.mp4".into(),
+        vfilter: None,
+        overlay: None,
+        overlay_reference: false,
+        sar: Sar::Preserve,
+        detelecine: Detelecine::Off,
+        preset: None,
+        pix_format: None,
+        keyint: None,
+        scd: None,
+        force_keyframes: None,
+        rc: None,
+        svt_args: svt_args.iter().map(|&a| a.into()).collect(),
+        enc_args: enc_args.iter().map(|&a| a.into()).collect(),
+        enc_input_args: <_>::default(),
+        ffmpeg_template: None,
+    };
+
+    assert!(enc(&["rc=0", "mbr=2000"], &["-maxrate=2M"])
+        .ensure_crf_rate_control()
+        .is_ok());
+    assert!(enc(&["rc=1"], &[]).ensure_crf_rate_control().is_err());
+    assert!(enc(&["tbr=2000"], &[]).ensure_crf_rate_control().is_err());
+    assert!(enc(&[], &["-b:v=2M"]).ensure_crf_rate_control().is_err());
+    assert!(enc(&[], &["-b=2M"]).ensure_crf_rate_control().is_err());
+    assert!(enc(&[], &["-b:v:0=2M"]).ensure_crf_rate_control().is_err());
+    assert!(enc(&[], &["-b:a=128k"]).ensure_crf_rate_control().is_ok());
+}
+
+#[test]
+fn managed_option_aliases() {
+    let enc = |encoder: &str, keyint, enc_args: &[&str]| Encode {
+        encoder: Encoder(encoder.into()),
+        input: "vid.mp4".into(),
+        vfilter: None,
+        overlay: None,
+        overlay_reference: false,
+        sar: Sar::Preserve,
+        detelecine: Detelecine::Off,
+        preset: None,
+        pix_format: None,
+        keyint,
+        scd: None,
+        force_keyframes: None,
+        rc: None,
+        svt_args: vec![],
+        enc_args: enc_args.iter().map(|&a| a.into()).collect(),
+        enc_input_args: <_>::default(),
+        ffmpeg_template: None,
+    };
+    let probe = Ffprobe {
+        duration: Ok(Duration::from_secs(60)),
+        has_audio: false,
+        max_audio_channels: None,
+        fps: Ok(30.0),
+        resolution: Some((1920, 1080)),
+        sar: None,
+        is_image: false,
+        pix_fmt: None,
+        dynamic_hdr: <_>::default(),
+    };
+    let err = |encoder, keyint, arg| {
+        enc(encoder, keyint, &[arg])
+            .to_encoder_args(30.0, &probe)
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default()
+    };
+
+    assert!(err("libsvtav1", None, "-codec:v:0=libx264").contains("use --encoder"));
+    assert!(err("libsvtav1", None, "-c=copy").contains("use --encoder"));
+    assert!(err("libsvtav1", None, "-acodec=aac").contains("use --acodec"));
+    assert!(err("libsvtav1", None, "-vf=scale=1280:-1").contains("use --vfilter"));
+    assert!(err("libsvtav1", None, "-filter_complex=null").contains("use --vfilter"));
+    assert!(err("libsvtav1", None, "-pix_fmt:v=yuv420p").contains("use --pix-format"));
+    assert!(err("libsvtav1", None, "-crf:v=30").contains("use --crf"));
+    assert!(err("libaom-av1", None, "-cpu-used=4").contains("use --preset"));
+    assert!(err("libx264", Some(KeyInterval::Frames(240)), "-g=120").contains("--keyint"));
+    // -g is fine without --keyint
+    assert_eq!(err("libx264", None, "-g=120"), "");
+    assert_eq!(err("libsvtav1", None, "-b:a=128k"), "");
+}
+
+#[test]
+fn chapter_force_keyframes() {
+    let ms = Duration::from_millis;
+    assert_eq!(
+        chapters_force_keyframes(&[ms(0), ms(301_500), ms(1_200_000)]).as_deref(),
+        Some("301.500,1200.000")
+    );
+    assert_eq!(chapters_force_keyframes(&[ms(0)]), None);
+}
+
+#[test]
+fn encoder_auto_parse() {
+    use clap::Parser;
+
+    // detection runs later in the command, not while parsing
+    let args = Encode::try_parse_from(["ab-av1", "-i", "vid.mkv", "-e", "auto"]).unwrap();
+    assert_eq!(args.encoder.as_str(), "auto");
+    let args = Encode::try_parse_from(["ab-av1", "-i", "vid.mkv", "-e", "svt-av1"]).unwrap();
+    assert_eq!(args.encoder.as_str(), "libsvtav1");
+}
+
+#[test]
+fn interval_force_keyframes() {
+    use clap::Parser;
+
+    let force = |extra: &[&str], secs| {
+        let args = ["ab-av1", "-i", "vid.mkv", "--force-keyframes", "interval"];
+        let probe = Ffprobe {
+            duration: Ok(Duration::from_secs(secs)),
+            has_audio: false,
+            max_audio_channels: None,
+            fps: Ok(24.0),
+            resolution: Some((1920, 1080)),
+            sar: None,
+            is_image: false,
+            pix_fmt: None,
+            dynamic_hdr: <_>::default(),
+        };
+        Encode::try_parse_from(args.iter().chain(extra))
+            .unwrap()
+            .force_keyframes_args(&probe)
+            .unwrap()[1]
+            .to_string()
+    };
+    assert_eq!(force(&["--keyint", "120"], 60), "expr:gte(n,n_forced*120)");
+    assert_eq!(force(&[], 600), "expr:gte(n,n_forced*240)");
+    // short inputs have no default keyint
+    assert_eq!(force(&["--enc", "g=96"], 60), "expr:gte(n,n_forced*96)");
+    assert_eq!(force(&[], 60), "expr:gte(n,n_forced*240)");
+}
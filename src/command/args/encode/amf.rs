@@ -0,0 +1,143 @@
+//! AMD amf specific defaults & arg validation.
+use super::Preset;
+use anyhow::ensure;
+use std::{ops::RangeInclusive, sync::Arc};
+
+/// Qp args set from --crf.
+const QP_ARGS: &[&str] = &["-qp_i", "-qp_p", "-qp_b"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Amf {
+    H264,
+    Hevc,
+    Av1,
+}
+
+impl Amf {
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        match vcodec {
+            "h264_amf" => Some(Self::H264),
+            "hevc_amf" => Some(Self::Hevc),
+            "av1_amf" => Some(Self::Av1),
+            _ => None,
+        }
+    }
+
+    /// Valid `-qp_i` & `-qp_p` values.
+    pub fn crf_range(self) -> RangeInclusive<f32> {
+        match self {
+            Self::H264 | Self::Hevc => 0.0..=51.0,
+            Self::Av1 => 0.0..=255.0,
+        }
+    }
+
+    pub fn default_max_crf(self) -> f32 {
+        match self {
+            Self::H264 | Self::Hevc => 42.0,
+            Self::Av1 => 200.0,
+        }
+    }
+
+    /// Constant qp rate control.
+    pub fn default_ffmpeg_args(self) -> &'static [(&'static str, &'static str)] {
+        &[("-rc", "cqp")]
+    }
+
+    /// `-quality` presets.
+    fn presets(self) -> &'static [&'static str] {
+        match self {
+            Self::H264 | Self::Hevc => &["quality", "balanced", "speed"],
+            Self::Av1 => &["high_quality", "quality", "balanced", "speed"],
+        }
+    }
+
+    /// Output args using the `-qp_i` crf value for the remaining frame types.
+    pub fn qp_args(self, crf: f32) -> impl Iterator<Item = Arc<String>> {
+        let frame_types: &[_] = match self {
+            Self::H264 => &["-qp_p", "-qp_b"],
+            Self::Hevc | Self::Av1 => &["-qp_p"],
+        };
+        frame_types
+            .iter()
+            .flat_map(move |arg| [arg.to_string().into(), crf.to_string().into()])
+    }
+
+    /// Validate qp, quality preset & `--enc` rc values.
+    pub fn validate(
+        self,
+        crf: f32,
+        preset: Option<&Preset>,
+        output_args: &[Arc<String>],
+    ) -> anyhow::Result<()> {
+        let range = self.crf_range();
+        ensure!(
+            range.contains(&crf) && crf.fract() == 0.0,
+            "crf {crf} invalid, expected an integer {}-{}",
+            range.start(),
+            range.end()
+        );
+        let presets = self.presets();
+        match preset {
+            Some(Preset::Number(n)) => {
+                anyhow::bail!("Invalid preset {n}, expected one of {}", presets.join(", "))
+            }
+            Some(Preset::Name(name)) => ensure!(
+                presets.contains(&&**name),
+                "Invalid preset {name}, expected one of {}",
+                presets.join(", ")
+            ),
+            None => {}
+        }
+
+        if let Some(arg) = output_args.iter().find(|a| QP_ARGS.contains(&a.as_str())) {
+            anyhow::bail!("Encoder argument `{arg}` not allowed, qp is set via --crf");
+        }
+        for arg in output_args.windows(2) {
+            if arg[0].as_str() == "-rc" {
+                ensure!(
+                    arg[1].as_str() == "cqp",
+                    "Invalid rc {}, --crf requires cqp",
+                    arg[1]
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn amf_validate() {
+    assert!(Amf::Hevc
+        .validate(
+            28.0,
+            Some(&Preset::Name("balanced".into())),
+            &super::test_args(&["-rc", "cqp"])
+        )
+        .is_ok());
+    assert!(Amf::Av1
+        .validate(120.0, Some(&Preset::Name("high_quality".into())), &[])
+        .is_ok());
+
+    assert!(Amf::Hevc.validate(52.0, None, &[]).is_err());
+    assert!(Amf::Hevc.validate(28.5, None, &[]).is_err());
+    assert!(Amf::Hevc
+        .validate(28.0, Some(&Preset::Name("high_quality".into())), &[])
+        .is_err());
+    assert!(Amf::H264
+        .validate(28.0, Some(&Preset::Number(2)), &[])
+        .is_err());
+    assert!(Amf::H264
+        .validate(28.0, None, &super::test_args(&["-rc", "vbr_peak"]))
+        .is_err());
+    assert!(Amf::Av1
+        .validate(120.0, None, &super::test_args(&["-qp_p", "100"]))
+        .is_err());
+}
+
+#[test]
+fn amf_qp_args() {
+    let qp: Vec<_> = Amf::H264.qp_args(24.0).map(|a| a.to_string()).collect();
+    assert_eq!(qp, ["-qp_p", "24", "-qp_b", "24"]);
+    let qp: Vec<_> = Amf::Av1.qp_args(100.0).map(|a| a.to_string()).collect();
+    assert_eq!(qp, ["-qp_p", "100"]);
+}
@@ -0,0 +1,141 @@
+//! NVIDIA nvenc specific defaults & arg validation.
+use super::Preset;
+use anyhow::ensure;
+use std::{ops::RangeInclusive, sync::Arc};
+
+/// p1-p7 presets followed by legacy presets.
+const PRESETS: &[&str] = &[
+    "p1",
+    "p2",
+    "p3",
+    "p4",
+    "p5",
+    "p6",
+    "p7",
+    "default",
+    "slow",
+    "medium",
+    "fast",
+    "hp",
+    "hq",
+    "bd",
+    "ll",
+    "llhq",
+    "llhp",
+    "lossless",
+    "losslesshp",
+];
+
+/// Rate control modes supporting `-cq` constant quality.
+const CQ_RC: &[&str] = &["vbr", "vbr_hq"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nvenc {
+    H264,
+    Hevc,
+    Av1,
+}
+
+impl Nvenc {
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        match vcodec {
+            "h264_nvenc" => Some(Self::H264),
+            "hevc_nvenc" => Some(Self::Hevc),
+            "av1_nvenc" => Some(Self::Av1),
+            _ => None,
+        }
+    }
+
+    /// Valid `-cq` values, 0 meaning automatic is excluded.
+    pub fn crf_range(self) -> RangeInclusive<f32> {
+        match self {
+            Self::H264 | Self::Hevc => 1.0..=51.0,
+            Self::Av1 => 1.0..=63.0,
+        }
+    }
+
+    pub fn default_max_crf(self) -> f32 {
+        match self {
+            Self::H264 | Self::Hevc => 42.0,
+            Self::Av1 => 55.0,
+        }
+    }
+
+    /// Constant quality vbr with spatial & temporal adaptive quantization.
+    ///
+    /// `-b:v 0` avoids the default bitrate capping `-cq` quality.
+    pub fn default_ffmpeg_args(self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("-rc", "vbr"),
+            ("-b:v", "0"),
+            ("-spatial-aq", "1"),
+            ("-temporal-aq", "1"),
+        ]
+    }
+
+    /// Map numeric presets 1-7 to p1-p7.
+    pub fn preset(n: u8) -> anyhow::Result<Arc<str>> {
+        ensure!(
+            (1..=7).contains(&n),
+            "Invalid nvenc preset {n}, expected 1-7 or one of {}",
+            PRESETS.join(", ")
+        );
+        Ok(format!("p{n}").into())
+    }
+
+    /// Validate cq, preset word & `--enc` rc values.
+    pub fn validate(
+        self,
+        crf: f32,
+        preset: Option<&Preset>,
+        output_args: &[Arc<String>],
+    ) -> anyhow::Result<()> {
+        let range = self.crf_range();
+        ensure!(
+            range.contains(&crf),
+            "crf {crf} out of range {}-{}",
+            range.start(),
+            range.end()
+        );
+        if let Some(Preset::Name(name)) = preset {
+            ensure!(
+                PRESETS.contains(&&**name),
+                "Invalid preset {name}, expected 1-7 or one of {}",
+                PRESETS.join(", ")
+            );
+        }
+        for arg in output_args.windows(2) {
+            if arg[0].as_str() == "-rc" {
+                ensure!(
+                    CQ_RC.contains(&arg[1].as_str()),
+                    "Invalid rc {}, --crf constant quality requires one of {}",
+                    arg[1],
+                    CQ_RC.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn nvenc_validate() {
+    let p5 = Preset::Name("p5".into());
+
+    assert!(Nvenc::Hevc
+        .validate(30.0, Some(&p5), &super::test_args(&["-rc", "vbr"]))
+        .is_ok());
+    assert!(Nvenc::Av1.validate(60.0, None, &[]).is_ok());
+
+    assert!(Nvenc::Hevc.validate(60.0, None, &[]).is_err());
+    assert!(Nvenc::Hevc.validate(0.0, None, &[]).is_err());
+    assert!(Nvenc::Av1
+        .validate(30.0, Some(&Preset::Name("p8".into())), &[])
+        .is_err());
+    assert!(Nvenc::Av1
+        .validate(30.0, None, &super::test_args(&["-rc", "cbr"]))
+        .is_err());
+
+    assert_eq!(&*Nvenc::preset(7).unwrap(), "p7");
+    assert!(Nvenc::preset(8).is_err());
+}
@@ -0,0 +1,163 @@
+//! --overlay watermark vfilter composition.
+use anyhow::{ensure, Context};
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// Margin in pixels between an overlay & the frame edges.
+const MARGIN: u32 = 10;
+
+/// An image overlaid on the video, e.g. "logo.png:pos=tr:opacity=0.6".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub image: PathBuf,
+    pub pos: OverlayPos,
+    /// 0-1.
+    pub opacity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayPos {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Overlay {
+    /// Returns `vfilter` followed by the overlay.
+    ///
+    /// The image is read with a `movie` source so the result works as a `-vf` & as a
+    /// segment of metric filter graphs.
+    pub fn compose(&self, vfilter: Option<&str>) -> String {
+        // escape for a filter option within a filter graph
+        let image = self
+            .image
+            .to_string_lossy()
+            .replace('\\', "/")
+            .replace(':', "\\\\:");
+        let alpha = match self.opacity < 1.0 {
+            true => format!(",colorchannelmixer=aa={}", self.opacity),
+            false => String::new(),
+        };
+        format!(
+            "{}[ab_av1_main];movie={image},format=rgba{alpha}[ab_av1_overlay];\
+             [ab_av1_main][ab_av1_overlay]overlay={}",
+            vfilter.unwrap_or("null"),
+            self.pos.xy(),
+        )
+    }
+}
+
+impl OverlayPos {
+    fn xy(self) -> String {
+        let (x, y) = match self {
+            Self::TopLeft => (format!("{MARGIN}"), format!("{MARGIN}")),
+            Self::TopRight => (format!("W-w-{MARGIN}"), format!("{MARGIN}")),
+            Self::BottomLeft => (format!("{MARGIN}"), format!("H-h-{MARGIN}")),
+            Self::BottomRight => (format!("W-w-{MARGIN}"), format!("H-h-{MARGIN}")),
+            Self::Center => ("(W-w)/2".into(), "(H-h)/2".into()),
+        };
+        format!("x={x}:y={y}")
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::TopLeft => "tl",
+            Self::TopRight => "tr",
+            Self::BottomLeft => "bl",
+            Self::BottomRight => "br",
+            Self::Center => "center",
+        }
+    }
+}
+
+impl FromStr for Overlay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut image = s;
+        let mut pos = OverlayPos::BottomRight;
+        let mut opacity = 1.0;
+        // options are trailing, the image path may itself contain ':'
+        while let Some((rest, opt)) = image.rsplit_once(':') {
+            match opt.split_once('=') {
+                Some(("pos", p)) => {
+                    pos = match p {
+                        "tl" => OverlayPos::TopLeft,
+                        "tr" => OverlayPos::TopRight,
+                        "bl" => OverlayPos::BottomLeft,
+                        "br" => OverlayPos::BottomRight,
+                        "center" => OverlayPos::Center,
+                        _ => anyhow::bail!("invalid pos '{p}', expected tl, tr, bl, br or center"),
+                    }
+                }
+                Some(("opacity", o)) => {
+                    opacity = o.parse().context("invalid opacity")?;
+                    ensure!(
+                        (0.0..=1.0).contains(&opacity),
+                        "invalid opacity, expected 0-1"
+                    );
+                }
+                _ => break,
+            }
+            image = rest;
+        }
+        ensure!(!image.is_empty(), "missing overlay image");
+        Ok(Self {
+            image: image.into(),
+            pos,
+            opacity,
+        })
+    }
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:pos={}:opacity={}",
+            self.image.display(),
+            self.pos.as_str(),
+            self.opacity
+        )
+    }
+}
+
+#[test]
+fn parse_overlay() {
+    let overlay: Overlay = "logo.png:pos=tr:opacity=0.6".parse().unwrap();
+    assert_eq!(
+        overlay,
+        Overlay {
+            image: "logo.png".into(),
+            pos: OverlayPos::TopRight,
+            opacity: 0.6,
+        }
+    );
+    assert_eq!(overlay.to_string(), "logo.png:pos=tr:opacity=0.6");
+
+    let overlay: Overlay = "C:/logos/logo.png".parse().unwrap();
+    assert_eq!(overlay.image, PathBuf::from("C:/logos/logo.png"));
+    assert_eq!(overlay.pos, OverlayPos::BottomRight);
+
+    assert!("logo.png:pos=middle".parse::<Overlay>().is_err());
+    assert!("logo.png:opacity=2".parse::<Overlay>().is_err());
+}
+
+#[test]
+fn overlay_compose() {
+    let overlay: Overlay = "C:/logo.png:pos=tr:opacity=0.6".parse().unwrap();
+    assert_eq!(
+        overlay.compose(Some("scale=1280:-2")),
+        "scale=1280:-2[ab_av1_main];\
+         movie=C\\\\:/logo.png,format=rgba,colorchannelmixer=aa=0.6[ab_av1_overlay];\
+         [ab_av1_main][ab_av1_overlay]overlay=x=W-w-10:y=10"
+    );
+
+    let overlay: Overlay = "logo.png:pos=center".parse().unwrap();
+    assert_eq!(
+        overlay.compose(None),
+        "null[ab_av1_main];movie=logo.png,format=rgba[ab_av1_overlay];\
+         [ab_av1_main][ab_av1_overlay]overlay=x=(W-w)/2:y=(H-h)/2"
+    );
+}
@@ -0,0 +1,121 @@
+//! Intel qsv specific defaults & arg validation.
+use super::Preset;
+use anyhow::ensure;
+use std::{ops::RangeInclusive, sync::Arc};
+
+/// Named presets, equivalent to numeric presets 1-7.
+const PRESETS: &[&str] = &[
+    "veryslow", "slower", "slow", "medium", "fast", "faster", "veryfast",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Qsv {
+    H264,
+    Hevc,
+    Av1,
+}
+
+impl Qsv {
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        match vcodec {
+            "h264_qsv" => Some(Self::H264),
+            "hevc_qsv" => Some(Self::Hevc),
+            "av1_qsv" => Some(Self::Av1),
+            _ => None,
+        }
+    }
+
+    /// Valid `-global_quality` (ICQ) values.
+    pub fn crf_range(self) -> RangeInclusive<f32> {
+        1.0..=51.0
+    }
+
+    pub fn default_max_crf(self) -> f32 {
+        match self {
+            Self::H264 | Self::Hevc => 40.0,
+            Self::Av1 => 45.0,
+        }
+    }
+
+    /// Lookahead mode defaults.
+    pub fn default_ffmpeg_args(self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("-look_ahead", "1"),
+            ("-extbrc", "1"),
+            ("-look_ahead_depth", "40"),
+        ]
+    }
+
+    /// Validate global_quality, preset & `--enc` lookahead & low-power values.
+    pub fn validate(
+        self,
+        crf: f32,
+        preset: Option<&Preset>,
+        output_args: &[Arc<String>],
+    ) -> anyhow::Result<()> {
+        let range = self.crf_range();
+        ensure!(
+            range.contains(&crf),
+            "crf {crf} out of range {}-{}",
+            range.start(),
+            range.end()
+        );
+        match preset {
+            Some(Preset::Number(n)) => ensure!(
+                (1..=7).contains(n),
+                "Invalid preset {n}, expected 1-7 or one of {}",
+                PRESETS.join(", ")
+            ),
+            Some(Preset::Name(name)) => ensure!(
+                PRESETS.contains(&&**name),
+                "Invalid preset {name}, expected 1-7 or one of {}",
+                PRESETS.join(", ")
+            ),
+            None => {}
+        }
+
+        for arg in output_args.windows(2) {
+            match (arg[0].as_str(), arg[1].as_str()) {
+                ("-look_ahead_depth", v) => ensure!(
+                    v.parse::<u8>().is_ok_and(|d| d <= 100),
+                    "Invalid look_ahead_depth {v}, expected 0-100"
+                ),
+                (opt @ ("-low_power" | "-look_ahead" | "-extbrc"), v) => ensure!(
+                    matches!(v, "0" | "1" | "true" | "false"),
+                    "Invalid {} {v}, expected 0 or 1",
+                    opt.trim_start_matches('-')
+                ),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn qsv_validate() {
+    assert!(Qsv::Hevc
+        .validate(
+            25.0,
+            Some(&Preset::Name("slow".into())),
+            &super::test_args(&["-low_power", "1", "-look_ahead_depth", "60"])
+        )
+        .is_ok());
+    assert!(Qsv::Av1
+        .validate(30.0, Some(&Preset::Number(4)), &[])
+        .is_ok());
+
+    assert!(Qsv::Av1.validate(52.0, None, &[]).is_err());
+    assert!(Qsv::Hevc
+        .validate(25.0, Some(&Preset::Number(8)), &[])
+        .is_err());
+    assert!(Qsv::Hevc
+        .validate(25.0, Some(&Preset::Name("p4".into())), &[])
+        .is_err());
+    assert!(Qsv::H264
+        .validate(25.0, None, &super::test_args(&["-look_ahead_depth", "120"]))
+        .is_err());
+    assert!(Qsv::H264
+        .validate(25.0, None, &super::test_args(&["-low_power", "2"]))
+        .is_err());
+}
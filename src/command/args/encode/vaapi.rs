@@ -0,0 +1,89 @@
+//! vaapi specific defaults, device & hardware upload args.
+use super::PixelFormat;
+use std::{ops::RangeInclusive, sync::Arc};
+
+/// Default vaapi render device.
+pub const VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+/// Input args that already select a hardware device.
+const DEVICE_ARGS: &[&str] = &["-vaapi_device", "-init_hw_device", "-hwaccel_device"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vaapi {
+    H264,
+    Hevc,
+    Av1,
+}
+
+impl Vaapi {
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        match vcodec {
+            "h264_vaapi" => Some(Self::H264),
+            "hevc_vaapi" => Some(Self::Hevc),
+            "av1_vaapi" => Some(Self::Av1),
+            _ => None,
+        }
+    }
+
+    /// Valid `-qp` values.
+    pub fn crf_range(self) -> RangeInclusive<f32> {
+        match self {
+            Self::H264 | Self::Hevc => 0.0..=51.0,
+            Self::Av1 => 0.0..=255.0,
+        }
+    }
+
+    pub fn default_max_crf(self) -> f32 {
+        match self {
+            Self::H264 | Self::Hevc => 42.0,
+            Self::Av1 => 200.0,
+        }
+    }
+
+    /// Adds `-vaapi_device` to the input args, unless a device is already set.
+    pub fn add_device_arg(input_args: &mut Vec<Arc<String>>) {
+        if !input_args.iter().any(|a| DEVICE_ARGS.contains(&a.as_str())) {
+            input_args.insert(0, "-vaapi_device".to_owned().into());
+            input_args.insert(1, VAAPI_DEVICE.to_owned().into());
+        }
+    }
+
+    /// Vfilter converting software frames to `pix_fmt` vaapi surfaces.
+    pub fn upload_vfilter(pix_fmt: PixelFormat) -> anyhow::Result<&'static str> {
+        match pix_fmt {
+            PixelFormat::Yuv420p => Ok("format=nv12,hwupload"),
+            PixelFormat::Yuv420p10le => Ok("format=p010,hwupload"),
+            _ => anyhow::bail!(
+                "--pix-format {pix_fmt} not supported by vaapi, use yuv420p or yuv420p10le"
+            ),
+        }
+    }
+}
+
+#[test]
+fn vaapi_device_arg() {
+    let mut args = vec![];
+    Vaapi::add_device_arg(&mut args);
+    assert_eq!(args.len(), 2);
+    assert_eq!(args[1].as_str(), VAAPI_DEVICE);
+
+    let mut args: Vec<Arc<String>> = vec![
+        Arc::new("-init_hw_device".into()),
+        Arc::new("vaapi=va:/dev/dri/renderD129".into()),
+    ];
+    Vaapi::add_device_arg(&mut args);
+    assert_eq!(args.len(), 2);
+}
+
+#[test]
+fn vaapi_upload_vfilter() {
+    assert_eq!(
+        Vaapi::upload_vfilter(PixelFormat::Yuv420p).unwrap(),
+        "format=nv12,hwupload"
+    );
+    assert_eq!(
+        Vaapi::upload_vfilter(PixelFormat::Yuv420p10le).unwrap(),
+        "format=p010,hwupload"
+    );
+    assert!(Vaapi::upload_vfilter(PixelFormat::Yuv444p10le).is_err());
+}
@@ -0,0 +1,214 @@
+//! --vfilter chain analysis of frame rate & timestamp changing filters.
+use crate::ffprobe;
+
+/// A single filter of a chain, e.g. "fps=fps=30000/1001".
+#[derive(Debug, PartialEq)]
+struct Filter<'a> {
+    name: &'a str,
+    /// `(key, value)` args, key is `None` for positional args.
+    args: Vec<(Option<&'a str>, &'a str)>,
+}
+
+impl<'a> Filter<'a> {
+    fn parse(filter: &'a str) -> Option<Self> {
+        let filter = strip_labels(filter.trim());
+        let (name, args) = filter.split_once('=').unwrap_or((filter, ""));
+        // ignore instance names, e.g. "fps@out"
+        let name = name.split('@').next()?.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let args = split_unquoted(unquote(args.trim()), ':')
+            .into_iter()
+            .filter(|a| !a.is_empty())
+            .map(|arg| match arg.split_once('=') {
+                Some((k, v)) => (Some(k.trim()), unquote(v.trim())),
+                None => (None, unquote(arg.trim())),
+            })
+            .collect();
+        Some(Self { name, args })
+    }
+
+    /// Value of the option named `key`, or the `position`th positional arg.
+    fn arg(&self, key: &str, position: usize) -> Option<&'a str> {
+        self.args
+            .iter()
+            .find(|(k, _)| *k == Some(key))
+            .or_else(|| self.args.iter().filter(|(k, _)| k.is_none()).nth(position))
+            .map(|(_, v)| *v)
+    }
+}
+
+/// Returns the output frame rate of `vfilter` if it contains rate changing filters,
+/// e.g. `fps`, `framerate`, `minterpolate`, `framestep`, `decimate` or field rate
+/// `yadif`/`bwdif`.
+///
+/// Relative changes, e.g. `framestep=2`, require the `input_fps`.
+pub fn output_fps(vfilter: &str, input_fps: Option<f64>) -> Option<f64> {
+    let mut fps = input_fps;
+    let mut changed = false;
+    for filter in filters(vfilter) {
+        let rate = |default: f64| filter.arg("fps", 0).map_or(Some(default), parse_rate);
+        fps = match filter.name {
+            "fps" => rate(25.0),
+            "framerate" => rate(50.0),
+            "minterpolate" => rate(60.0),
+            "framestep" => {
+                let step: f64 = filter.arg("step", 0).unwrap_or("1").parse().ok()?;
+                fps.map(|fps| fps / step)
+            }
+            "decimate" => {
+                let cycle: f64 = filter.arg("cycle", 0).unwrap_or("5").parse().ok()?;
+                fps.map(|fps| fps * (cycle - 1.0) / cycle)
+            }
+            "yadif" | "bwdif" => {
+                let default_mode = if filter.name == "bwdif" { "1" } else { "0" };
+                match filter.arg("mode", 0).unwrap_or(default_mode) {
+                    "1" | "3" | "send_field" | "send_field_nospatial" => fps.map(|fps| fps * 2.0),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        changed = true;
+    }
+    fps.filter(|_| changed)
+}
+
+/// Returns the output/input duration ratio of `vfilter` `setpts` timestamp changes,
+/// e.g. 0.25 for "setpts=PTS/4".
+pub fn time_scale(vfilter: &str) -> f64 {
+    filters(vfilter)
+        .filter(|f| f.name == "setpts")
+        .filter_map(|f| f.arg("expr", 0))
+        .map(setpts_scale)
+        .product()
+}
+
+/// Parse simple linear setpts expressions, e.g. "PTS/4", "0.5*PTS", "(PTS-STARTPTS)*2".
+fn setpts_scale(expr: &str) -> f64 {
+    let expr: String = expr
+        .replace("-STARTPTS", "")
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '(' | ')'))
+        .collect();
+    let parse = |n: &str| ffprobe::parse_frame_rate(n).unwrap_or(1.0);
+    if let Some(n) = expr.strip_prefix("PTS/") {
+        1.0 / parse(n)
+    } else if let Some(n) = expr.strip_prefix("PTS*") {
+        parse(n)
+    } else if let Some(n) = expr.strip_suffix("*PTS") {
+        parse(n)
+    } else {
+        1.0
+    }
+}
+
+/// Parse a frame rate value or abbreviation.
+fn parse_rate(rate: &str) -> Option<f64> {
+    match rate {
+        "ntsc" | "qntsc" | "sntsc" => Some(30000.0 / 1001.0),
+        "pal" | "qpal" | "spal" => Some(25.0),
+        "film" => Some(24.0),
+        "ntsc_film" => Some(24000.0 / 1001.0),
+        _ => ffprobe::parse_frame_rate(rate),
+    }
+}
+
+fn filters(vfilter: &str) -> impl Iterator<Item = Filter<'_>> {
+    split_unquoted(vfilter, ',')
+        .into_iter()
+        .filter_map(Filter::parse)
+}
+
+/// Strip leading & trailing link labels, e.g. "[in]fps=24[out]".
+fn strip_labels(mut filter: &str) -> &str {
+    while let Some(rest) = filter
+        .strip_prefix('[')
+        .and_then(|f| Some(f.split_once(']')?.1))
+    {
+        filter = rest.trim_start();
+    }
+    while filter.ends_with(']') {
+        match filter.rfind('[') {
+            Some(idx) => filter = filter[..idx].trim_end(),
+            None => break,
+        }
+    }
+    filter
+}
+
+/// Split on `sep` outside of single quotes, ignoring backslash escaped chars.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (idx, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unquote(v: &str) -> &str {
+    v.strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(v)
+}
+
+#[test]
+fn vfilter_output_fps() {
+    let fps = |vf| output_fps(vf, Some(25.0));
+    let ntsc = 30000.0 / 1001.0;
+
+    assert_eq!(fps("scale=1280:-1, fps=24, transpose=1"), Some(24.0));
+    assert_eq!(fps("scale=1280:-1, fps=ntsc, transpose=1"), Some(ntsc));
+    assert_eq!(fps("fps=fps=30000/1001"), Some(ntsc));
+    assert_eq!(fps("fps=fps=ntsc_film:round=near"), Some(24000.0 / 1001.0));
+    assert_eq!(fps("[in]fps@a=24[out]"), Some(24.0));
+    assert_eq!(fps("fps"), Some(25.0));
+    assert_eq!(fps("minterpolate=fps=60:mi_mode=mci"), Some(60.0));
+    assert_eq!(fps("minterpolate='mi_mode=mci:fps=50'"), Some(50.0));
+    assert_eq!(fps("minterpolate"), Some(60.0));
+    assert_eq!(fps("framerate=fps=30"), Some(30.0));
+    // later filters change the rate again
+    assert_eq!(fps("fps=30,framestep=2"), Some(15.0));
+    assert_eq!(fps("fps=24,fps=30"), Some(30.0));
+    assert_eq!(fps("framestep=step=5"), Some(5.0));
+    assert_eq!(fps("decimate"), Some(20.0));
+    assert_eq!(fps("yadif=1"), Some(50.0));
+    assert_eq!(fps("yadif=mode=send_frame"), None);
+    let ivtc = output_fps("fieldmatch,yadif=deint=interlaced,decimate", Some(29.97));
+    assert!((ivtc.unwrap() - 23.976).abs() < 0.001, "{ivtc:?}");
+    assert_eq!(fps("bwdif"), Some(50.0));
+    // commas in quotes & escapes aren't filter separators
+    assert_eq!(
+        fps("drawtext=text='a, fps=10':x=8,drawtext=text=b\\,fps=5,fps=12"),
+        Some(12.0)
+    );
+
+    assert_eq!(fps("scale=1280:-1,setpts=PTS/2"), None);
+    assert_eq!(fps(""), None);
+    assert_eq!(output_fps("framestep=2", None), None);
+    assert_eq!(output_fps("fps=24,framestep=2", None), Some(12.0));
+    assert_eq!(output_fps("framestep=x", Some(25.0)), None);
+}
+
+#[test]
+fn vfilter_time_scale() {
+    assert_eq!(time_scale("scale=1280:-1"), 1.0);
+    assert_eq!(time_scale("framestep=4,setpts=PTS/4"), 0.25);
+    assert_eq!(time_scale("setpts=expr=0.5*PTS"), 0.5);
+    assert_eq!(time_scale("setpts='(PTS-STARTPTS)*2'"), 2.0);
+    assert_eq!(time_scale("setpts=PTS-STARTPTS"), 1.0);
+    assert_eq!(time_scale("setpts=PTS/2,setpts=PTS/2"), 0.25);
+    assert_eq!(time_scale("setpts=N/FRAME_RATE/TB"), 1.0);
+}
@@ -0,0 +1,160 @@
+//! Apple videotoolbox specific defaults & arg validation.
+use anyhow::ensure;
+use std::{ops::RangeInclusive, sync::Arc};
+
+/// Videotoolbox rate control mode, see `--rc`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RateControl {
+    /// Constant quality `-q:v` set from --crf.
+    #[default]
+    Cq,
+    /// Average bitrate, requires `--enc b:v=..` & optionally `-maxrate`/`-bufsize`.
+    Abr,
+    /// Constant bitrate, requires `--enc b:v=..`.
+    Cbr,
+}
+
+impl std::fmt::Display for RateControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cq => "cq".fmt(f),
+            Self::Abr => "abr".fmt(f),
+            Self::Cbr => "cbr".fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Videotoolbox;
+
+impl Videotoolbox {
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        vcodec.ends_with("_videotoolbox").then_some(Self)
+    }
+
+    /// Valid crf values, mapped to `-q:v` 100-1.
+    ///
+    /// `-q:v` is higher for better quality, so is inverted to match the crf
+    /// convention of higher values for lower quality.
+    pub fn crf_range(self) -> RangeInclusive<f32> {
+        0.0..=99.0
+    }
+
+    pub fn default_max_crf(self) -> f32 {
+        // -q:v 40
+        60.0
+    }
+
+    /// `-q:v` value for `crf`.
+    pub fn quality(crf: f32) -> f32 {
+        100.0 - crf
+    }
+
+    /// Rate control output args. Only constant quality uses `crf`.
+    pub fn rc_args(self, rc: RateControl, crf: f32) -> Vec<Arc<String>> {
+        match rc {
+            RateControl::Cq => vec![
+                "-q:v".to_owned().into(),
+                Self::quality(crf).to_string().into(),
+            ],
+            RateControl::Abr => vec![],
+            RateControl::Cbr => vec![
+                "-constant_bit_rate".to_owned().into(),
+                "1".to_owned().into(),
+            ],
+        }
+    }
+
+    /// Validate crf & `--enc` bitrate args for the rate control mode.
+    pub fn validate(
+        self,
+        rc: RateControl,
+        crf: f32,
+        output_args: &[Arc<String>],
+    ) -> anyhow::Result<()> {
+        let has = |name: &str| output_args.iter().any(|a| a.as_str() == name);
+        if has("-constant_bit_rate") {
+            anyhow::bail!("Encoder argument `-constant_bit_rate` not allowed, use --rc cbr");
+        }
+        match rc {
+            RateControl::Cq => {
+                let range = self.crf_range();
+                ensure!(
+                    range.contains(&crf) && crf.fract() == 0.0,
+                    "crf {crf} invalid, expected an integer {}-{}",
+                    range.start(),
+                    range.end()
+                );
+                for arg in ["-b:v", "-maxrate", "-bufsize"] {
+                    ensure!(
+                        !has(arg),
+                        "Encoder argument `{arg}` not allowed with --rc cq, use --rc abr or cbr"
+                    );
+                }
+            }
+            RateControl::Abr => ensure!(has("-b:v"), "--rc abr requires --enc b:v=BITRATE"),
+            RateControl::Cbr => {
+                ensure!(has("-b:v"), "--rc cbr requires --enc b:v=BITRATE");
+                for arg in ["-maxrate", "-bufsize"] {
+                    ensure!(
+                        !has(arg),
+                        "Encoder argument `{arg}` not allowed with --rc cbr"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn videotoolbox_validate() {
+    let vt = Videotoolbox;
+
+    assert!(vt.validate(RateControl::Cq, 35.0, &[]).is_ok());
+    assert!(vt.validate(RateControl::Cq, 35.5, &[]).is_err());
+    assert!(vt.validate(RateControl::Cq, 100.0, &[]).is_err());
+    assert!(vt
+        .validate(RateControl::Cq, 35.0, &super::test_args(&["-b:v", "5M"]))
+        .is_err());
+
+    assert!(vt.validate(RateControl::Abr, 35.0, &[]).is_err());
+    assert!(vt
+        .validate(
+            RateControl::Abr,
+            35.0,
+            &super::test_args(&["-b:v", "5M", "-maxrate", "8M"])
+        )
+        .is_ok());
+
+    assert!(vt
+        .validate(RateControl::Cbr, 35.0, &super::test_args(&["-b:v", "5M"]))
+        .is_ok());
+    assert!(vt
+        .validate(
+            RateControl::Cbr,
+            35.0,
+            &super::test_args(&["-b:v", "5M", "-maxrate", "8M"])
+        )
+        .is_err());
+    assert!(vt
+        .validate(
+            RateControl::Abr,
+            35.0,
+            &super::test_args(&["-b:v", "5M", "-constant_bit_rate", "1"])
+        )
+        .is_err());
+}
+
+#[test]
+fn videotoolbox_quality_inverted() {
+    // higher crf -> lower quality
+    assert!(Videotoolbox::quality(40.0) < Videotoolbox::quality(20.0));
+    let q: Vec<_> = Videotoolbox
+        .rc_args(RateControl::Cq, 30.0)
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    assert_eq!(q, ["-q:v", "70"]);
+    assert!(Videotoolbox.rc_args(RateControl::Abr, 30.0).is_empty());
+}
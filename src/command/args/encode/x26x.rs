@@ -0,0 +1,193 @@
+//! libx264 & libx265 specific arg validation.
+use super::{PixelFormat, Preset};
+use anyhow::ensure;
+use std::{ops::RangeInclusive, sync::Arc};
+
+const PRESETS: &[&str] = &[
+    "ultrafast",
+    "superfast",
+    "veryfast",
+    "faster",
+    "fast",
+    "medium",
+    "slow",
+    "slower",
+    "veryslow",
+    "placebo",
+];
+
+const LEVELS: &[&str] = &[
+    "1", "1b", "1.1", "1.2", "1.3", "2", "2.1", "2.2", "3", "3.1", "3.2", "4", "4.1", "4.2", "5",
+    "5.1", "5.2", "6", "6.1", "6.2",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X26x {
+    X264,
+    X265,
+}
+
+impl X26x {
+    pub fn from_vcodec(vcodec: &str) -> Option<Self> {
+        match vcodec {
+            "libx264" => Some(Self::X264),
+            "libx265" => Some(Self::X265),
+            _ => None,
+        }
+    }
+
+    /// Valid crf values. High bit depth x264 extends the range below 0 by 6 per extra bit.
+    pub fn crf_range(self, pix_fmt: PixelFormat) -> RangeInclusive<f32> {
+        match self {
+            Self::X264 => -6.0 * f32::from(pix_fmt.bit_depth() - 8)..=51.0,
+            Self::X265 => 0.0..=51.0,
+        }
+    }
+
+    fn tunes(self) -> &'static [&'static str] {
+        match self {
+            Self::X264 => &[
+                "film",
+                "animation",
+                "grain",
+                "stillimage",
+                "psnr",
+                "ssim",
+                "fastdecode",
+                "zerolatency",
+            ],
+            Self::X265 => &[
+                "psnr",
+                "ssim",
+                "grain",
+                "zerolatency",
+                "fastdecode",
+                "animation",
+            ],
+        }
+    }
+
+    fn profiles(self) -> &'static [&'static str] {
+        match self {
+            Self::X264 => &["baseline", "main", "high", "high10", "high422", "high444p"],
+            Self::X265 => &[
+                "main",
+                "main10",
+                "main12",
+                "mainstillpicture",
+                "main422-10",
+                "main422-12",
+                "main444-8",
+                "main444-10",
+                "main444-12",
+            ],
+        }
+    }
+
+    /// Validate crf, preset word & `--enc` tune, profile & level values.
+    pub fn validate(
+        self,
+        crf: f32,
+        pix_fmt: PixelFormat,
+        preset: Option<&Preset>,
+        output_args: &[Arc<String>],
+    ) -> anyhow::Result<()> {
+        let range = self.crf_range(pix_fmt);
+        ensure!(
+            range.contains(&crf),
+            "crf {crf} out of range {}-{}",
+            range.start(),
+            range.end()
+        );
+        match preset {
+            Some(Preset::Number(n)) => {
+                anyhow::bail!("Invalid preset {n}, expected one of {}", PRESETS.join(", "))
+            }
+            Some(Preset::Name(name)) => ensure!(
+                PRESETS.contains(&&**name),
+                "Invalid preset {name}, expected one of {}",
+                PRESETS.join(", ")
+            ),
+            None => {}
+        }
+
+        for arg in output_args.windows(2) {
+            let (valid, value) = match (arg[0].as_str(), arg[1].as_str()) {
+                ("-tune", v) => (self.tunes(), v),
+                ("-profile:v" | "-profile", v) => (self.profiles(), v),
+                ("-level" | "-level:v", v) if self == Self::X264 => (LEVELS, v),
+                _ => continue,
+            };
+            ensure!(
+                // multiple tunes are comma separated, e.g. "film,fastdecode"
+                value.split(',').all(|v| valid.contains(&v)),
+                "Invalid {} {value}, expected one of {}",
+                arg[0].trim_start_matches('-'),
+                valid.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn x26x_validate() {
+    let slow = Preset::Name("slow".into());
+
+    assert!(X26x::X264
+        .validate(
+            23.0,
+            PixelFormat::Yuv420p,
+            Some(&slow),
+            &super::test_args(&["-tune", "film", "-level", "4.1"])
+        )
+        .is_ok());
+    assert!(X26x::X265
+        .validate(
+            28.0,
+            PixelFormat::Yuv420p10le,
+            None,
+            &super::test_args(&["-profile:v", "main10"])
+        )
+        .is_ok());
+
+    assert!(X26x::X264
+        .validate(52.0, PixelFormat::Yuv420p, None, &[])
+        .is_err());
+    assert!(X26x::X264
+        .validate(-6.0, PixelFormat::Yuv420p, None, &[])
+        .is_err());
+    assert!(X26x::X264
+        .validate(-12.0, PixelFormat::Yuv420p10le, None, &[])
+        .is_ok());
+    assert!(X26x::X265
+        .validate(-1.0, PixelFormat::Yuv420p10le, None, &[])
+        .is_err());
+    assert!(X26x::X264
+        .validate(23.0, PixelFormat::Yuv420p, Some(&Preset::Number(8)), &[])
+        .is_err());
+    assert!(X26x::X265
+        .validate(
+            23.0,
+            PixelFormat::Yuv420p,
+            Some(&Preset::Name("snail".into())),
+            &[]
+        )
+        .is_err());
+    assert!(X26x::X265
+        .validate(
+            23.0,
+            PixelFormat::Yuv420p,
+            None,
+            &super::test_args(&["-tune", "film"])
+        )
+        .is_err());
+    assert!(X26x::X264
+        .validate(
+            23.0,
+            PixelFormat::Yuv420p,
+            None,
+            &super::test_args(&["-profile:v", "main10"])
+        )
+        .is_err());
+}
@@ -0,0 +1,177 @@
+//! Per-input sidecar argument overrides.
+use anyhow::{bail, Context};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// Sidecar file extension appended to the input file name,
+/// e.g. "movie.mkv" -> "movie.mkv.abav1.toml".
+pub const SIDECAR_EXT: &str = "abav1.toml";
+
+/// Sidecar override file path for `input`.
+pub fn sidecar_path(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".");
+    path.push(SIDECAR_EXT);
+    path.into()
+}
+
+/// Parsed sidecar argument overrides.
+#[derive(Debug, Default, PartialEq)]
+pub struct Sidecar {
+    /// Valued args, e.g. `--min-vmaf 96`.
+    args: Vec<OsString>,
+    /// Boolean flags, e.g. `keep = false` -> ("--keep", false).
+    flags: Vec<(String, bool)>,
+}
+
+impl Sidecar {
+    /// Merge into full command line `cli_args`, with the sidecar taking precedence.
+    ///
+    /// Sidecar flags replace any command line occurrence, so `false` unsets a flag.
+    /// Valued args are appended, so must be parsed with `args_override_self`.
+    pub fn merge(&self, cli_args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+        let mut args: Vec<_> = cli_args
+            .into_iter()
+            .filter(|arg| !self.flags.iter().any(|(flag, _)| arg == flag.as_str()))
+            .collect();
+        for (flag, set) in &self.flags {
+            if *set {
+                args.push(flag.into());
+            }
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+/// Read the `input` sidecar file, if any.
+///
+/// Keys are long argument names, e.g.
+/// ```toml
+/// min-vmaf = 96
+/// vfilter = "crop=1920:800"
+/// enc = ["tune=0", "enable-overlays=1"]
+/// ```
+pub fn sidecar_args(input: &Path) -> anyhow::Result<Option<(PathBuf, Sidecar)>> {
+    let path = sidecar_path(input);
+    let toml = match std::fs::read_to_string(&path) {
+        Ok(toml) => toml,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("read {}", path.display())),
+    };
+    let sidecar = parse_sidecar(&toml).with_context(|| format!("invalid {}", path.display()))?;
+    Ok(Some((path, sidecar)))
+}
+
+fn parse_sidecar(toml: &str) -> anyhow::Result<Sidecar> {
+    let table: toml::Table = toml.parse()?;
+    let mut sidecar = Sidecar::default();
+    let args = &mut sidecar.args;
+    for (key, value) in table {
+        if matches!(key.as_str(), "input" | "i") {
+            bail!("`{key}` cannot be overridden");
+        }
+        let flag = format!("--{key}");
+        match value {
+            toml::Value::Boolean(set) => sidecar.flags.push((flag, set)),
+            toml::Value::Array(values) => {
+                for value in values {
+                    args.push(flag.clone().into());
+                    args.push(arg_value(&key, value)?);
+                }
+            }
+            value => {
+                args.push(flag.into());
+                args.push(arg_value(&key, value)?);
+            }
+        }
+    }
+    Ok(sidecar)
+}
+
+fn arg_value(key: &str, value: toml::Value) -> anyhow::Result<OsString> {
+    Ok(match value {
+        toml::Value::String(s) => s.into(),
+        toml::Value::Integer(n) => n.to_string().into(),
+        toml::Value::Float(n) => n.to_string().into(),
+        toml::Value::Boolean(b) => b.to_string().into(),
+        _ => bail!("unsupported `{key}` value"),
+    })
+}
+
+#[test]
+fn parse_sidecar_args() {
+    let sidecar = parse_sidecar(
+        r#"
+        min-vmaf = 96.5
+        preset = 6
+        vfilter = "crop=1920:800"
+        enc = ["tune=0", "enable-overlays=1"]
+        keep = true
+        loopback-vmaf = false
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        sidecar.args,
+        [
+            "--enc",
+            "tune=0",
+            "--enc",
+            "enable-overlays=1",
+            "--min-vmaf",
+            "96.5",
+            "--preset",
+            "6",
+            "--vfilter",
+            "crop=1920:800",
+        ]
+        .map(OsString::from)
+    );
+    assert_eq!(
+        sidecar.flags,
+        [("--keep".into(), true), ("--loopback-vmaf".into(), false)]
+    );
+
+    assert!(parse_sidecar("input = \"other.mkv\"").is_err());
+    assert!(parse_sidecar("enc = { tune = 0 }").is_err());
+}
+
+#[test]
+fn sidecar_path_appends_ext() {
+    assert_eq!(
+        sidecar_path(Path::new("/films/movie.mkv")),
+        Path::new("/films/movie.mkv.abav1.toml")
+    );
+}
+
+#[test]
+fn merge_sidecar_args() {
+    let sidecar = parse_sidecar("keep = false\nloopback-vmaf = true\nmin-vmaf = 96").unwrap();
+    let cli = [
+        "ab-av1",
+        "crf-search",
+        "-i",
+        "vid.mkv",
+        "--keep",
+        "--min-vmaf",
+        "95",
+    ];
+    assert_eq!(
+        sidecar.merge(cli.map(OsString::from)),
+        [
+            "ab-av1",
+            "crf-search",
+            "-i",
+            "vid.mkv",
+            "--min-vmaf",
+            "95",
+            "--loopback-vmaf",
+            "--min-vmaf",
+            "96",
+        ]
+        .map(OsString::from)
+    );
+}
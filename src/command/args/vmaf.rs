@@ -1,10 +1,19 @@
-use crate::command::args::PixelFormat;
+use crate::{
+    command::args::PixelFormat,
+    stats::{percentile, Stats},
+};
 use anyhow::Context;
 use clap::Parser;
-use std::{borrow::Cow, fmt::Display, sync::Arc, thread};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
 
 /// Common vmaf options.
-#[derive(Parser, Clone, Hash)]
+#[derive(Parser, Clone)]
 pub struct Vmaf {
     /// Additional vmaf arg(s). E.g. --vmaf n_threads=8 --vmaf n_subsample=4
     ///
@@ -28,6 +37,87 @@ pub struct Vmaf {
     /// Scaling happens after any input/reference vfilters.
     #[arg(long, default_value_t = VmafScale::Auto, value_parser = parse_vmaf_scale)]
     pub vmaf_scale: VmafScale,
+
+    /// Pixel format to convert both distorted & reference to for VMAF analysis.
+    ///
+    /// By default the reference pixel format is used, if known, to avoid
+    /// upconverting the reference (e.g. 8-bit to 10-bit) which can alter scores.
+    #[arg(long, value_enum)]
+    pub metric_pix_fmt: Option<PixelFormat>,
+
+    /// Statistic of per-frame VMAF scores used as the score.
+    /// `mean`, `harmonic-mean`, `min` or a percentile e.g. `p5` or `percentile:5`.
+    ///
+    /// harmonic-mean, min & percentiles penalize bad frames more than the mean,
+    /// so a crf-search can guarantee worst-frame quality rather than average.
+    /// These are calculated over the frames of all samples, `min` being the lowest frame.
+    #[arg(long, visible_alias = "vmaf-target", default_value_t = VmafStatistic::Mean)]
+    pub vmaf_statistic: VmafStatistic,
+
+    /// Viewing conditions to assume, choosing the VMAF model & the crf-search
+    /// default --min-vmaf.
+    ///
+    /// * 1080p-tv: 1k model, min-vmaf 95.
+    /// * 4k-tv: 4k model, min-vmaf 95.
+    /// * phone: 1k phone model, min-vmaf 93.
+    ///
+    /// By default the 4k model is used for resolutions > 2560x1440, otherwise the 1k model,
+    /// with min-vmaf 95. `--vmaf-model` & `--vmaf model=...` args take precedence.
+    #[arg(long, value_enum)]
+    pub target_viewing: Option<TargetViewing>,
+
+    /// VMAF model to use instead of the resolution/--target-viewing default.
+    /// `4k`, `neg` (the 1k no-enhancement-gain model) or a model json file path.
+    ///
+    /// --vmaf-scale auto upscales for the 4k model, or for the 1k model with `neg` &
+    /// model files named like "vmaf_v0.6.1*.json". Other model files are not scaled.
+    /// A `--vmaf model=...` arg takes precedence.
+    #[arg(long, value_parser = parse_vmaf_model)]
+    pub vmaf_model: Option<VmafModelArg>,
+
+    /// Threads used by each vmaf process. Overridden by `--vmaf n_threads=N`.
+    ///
+    /// By default system threads are divided among concurrently running
+    /// vmaf processes.
+    #[arg(long)]
+    pub vmaf_threads: Option<usize>,
+
+    /// Number of vmaf processes that run concurrently, sharing system threads.
+    #[arg(skip = 1_usize)]
+    pub concurrent_processes: usize,
+}
+
+/// Same as the command line defaults.
+impl Default for Vmaf {
+    fn default() -> Self {
+        Self {
+            vmaf_args: vec![],
+            vmaf_scale: VmafScale::Auto,
+            metric_pix_fmt: None,
+            vmaf_statistic: VmafStatistic::Mean,
+            target_viewing: None,
+            vmaf_model: None,
+            vmaf_threads: None,
+            concurrent_processes: 1,
+        }
+    }
+}
+
+/// Thread settings don't affect scores so are not hashed.
+impl std::hash::Hash for Vmaf {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.vmaf_args.hash(state);
+        self.vmaf_scale.hash(state);
+        self.metric_pix_fmt.hash(state);
+        self.vmaf_statistic.hash(state);
+        // only hashed if set for back compat
+        if let Some(viewing) = self.target_viewing {
+            viewing.hash(state);
+        }
+        if let Some(model) = &self.vmaf_model {
+            model.hash(state);
+        }
+    }
 }
 
 fn parse_vmaf_arg(arg: &str) -> anyhow::Result<Arc<str>> {
@@ -36,7 +126,107 @@ fn parse_vmaf_arg(arg: &str) -> anyhow::Result<Arc<str>> {
 
 impl Vmaf {
     pub fn is_default(&self) -> bool {
-        self.vmaf_args.is_empty() && self.vmaf_scale == VmafScale::Auto
+        self.vmaf_args.is_empty()
+            && self.vmaf_scale == VmafScale::Auto
+            && self.metric_pix_fmt.is_none()
+            && self.vmaf_statistic == VmafStatistic::Mean
+            && self.target_viewing.is_none()
+            && self.vmaf_model.is_none()
+    }
+
+    /// Default crf-search --min-vmaf for the --target-viewing.
+    pub fn default_min_vmaf(&self) -> f32 {
+        self.target_viewing
+            .map_or(95.0, TargetViewing::default_min_vmaf)
+    }
+
+    /// Threads to use per vmaf process, `--vmaf-threads` or system threads divided
+    /// among concurrent processes.
+    pub fn threads(&self) -> usize {
+        self.vmaf_threads
+            .unwrap_or_else(|| {
+                thread::available_parallelism().map_or(1, |p| p.get())
+                    / self.concurrent_processes.max(1)
+            })
+            .max(1)
+    }
+
+    /// Returns a copy logging per-frame scores as json to `log_path`.
+    pub fn with_frame_log(&self, log_path: &Path) -> Self {
+        let log_path = log_path
+            .to_string_lossy()
+            .replace('\\', "/")
+            .replace(':', "\\\\:");
+        let mut vmaf = self.clone();
+        vmaf.vmaf_args
+            .retain(|a| !a.starts_with("log_path=") && !a.starts_with("log_fmt="));
+        vmaf.vmaf_args.push(format!("log_path={log_path}").into());
+        vmaf.vmaf_args.push("log_fmt=json".into());
+        vmaf
+    }
+
+    /// Returns a copy also calculating the libvmaf MS-SSIM feature,
+    /// logged per-frame if a frame log is used.
+    pub fn with_ms_ssim(&self) -> Self {
+        let feature = format!("name={}", crate::vmaf::MS_SSIM_FEATURE);
+        let mut vmaf = self.clone();
+        match vmaf
+            .vmaf_args
+            .iter_mut()
+            .find(|a| a.starts_with("feature="))
+        {
+            // multiple features are '|' separated
+            Some(features) => *features = format!("{features}|{feature}").into(),
+            None => vmaf.vmaf_args.push(format!("feature={feature}").into()),
+        }
+        vmaf
+    }
+
+    /// Returns the pixel format to convert distorted & reference to for analysis.
+    ///
+    /// Uses `--metric-pix-fmt` if set, otherwise preserves the reference format if known.
+    pub fn pix_fmt(
+        &self,
+        distorted: Option<PixelFormat>,
+        reference: Option<PixelFormat>,
+    ) -> PixelFormat {
+        self.metric_pix_fmt
+            .or(reference)
+            .or(distorted)
+            .unwrap_or(PixelFormat::Yuv444p10le)
+    }
+
+    /// Returns a copy with halved `n_threads` & at least `n_subsample=2`, to use less memory.
+    ///
+    /// Returns `None` if already single threaded.
+    pub fn reduced_threads(&self) -> Option<Self> {
+        let threads = self
+            .vmaf_args
+            .iter()
+            .find_map(|a| a.strip_prefix("n_threads=")?.parse::<usize>().ok())
+            .unwrap_or_else(|| self.threads());
+        if threads <= 1 {
+            return None;
+        }
+        let subsample = self
+            .vmaf_args
+            .iter()
+            .find_map(|a| a.strip_prefix("n_subsample=")?.parse::<usize>().ok())
+            .unwrap_or(1)
+            .max(2);
+
+        let mut vmaf_args: Vec<_> = self
+            .vmaf_args
+            .iter()
+            .filter(|a| !a.starts_with("n_threads=") && !a.starts_with("n_subsample="))
+            .cloned()
+            .collect();
+        vmaf_args.push(format!("n_threads={}", threads / 2).into());
+        vmaf_args.push(format!("n_subsample={subsample}").into());
+        Some(Self {
+            vmaf_args,
+            ..self.clone()
+        })
     }
 
     /// Returns ffmpeg `filter_complex`/`lavfi` value for calculating vmaf.
@@ -48,24 +238,17 @@ impl Vmaf {
     ) -> String {
         let mut args = self.vmaf_args.clone();
         if !args.iter().any(|a| a.contains("n_threads")) {
-            // default n_threads to all cores
-            args.push(
-                format!(
-                    "n_threads={}",
-                    thread::available_parallelism().map_or(1, |p| p.get())
-                )
-                .into(),
-            );
+            args.push(format!("n_threads={}", self.threads()).into());
         }
         let mut lavfi = args.join(":");
         lavfi.insert_str(0, "libvmaf=");
 
         let mut model = VmafModel::from_args(&args);
-        if let (None, Some((w, h))) = (model, distorted_res) {
-            if w > 2560 && h > 1440 {
-                // for >2k resoultions use 4k model
-                lavfi.push_str(":model=version=vmaf_4k_v0.6.1");
-                model = Some(VmafModel::Vmaf4K);
+        if model.is_none() {
+            if let Some((arg, auto)) = self.auto_model(distorted_res) {
+                lavfi.push_str(":model=");
+                lavfi.push_str(&arg);
+                model = Some(auto);
             }
         }
 
@@ -96,6 +279,35 @@ impl Vmaf {
         lavfi
     }
 
+    /// Model to use when not specified in --vmaf args, --vmaf-model, per --target-viewing
+    /// or the 4k model for >2k resolutions.
+    fn auto_model(&self, distorted_res: Option<(u32, u32)>) -> Option<(Cow<'_, str>, VmafModel)> {
+        match (&self.vmaf_model, self.target_viewing) {
+            (Some(model), _) => return Some((model.model_arg(), model.model())),
+            (_, Some(viewing)) => return Some((viewing.model_arg().into(), viewing.model())),
+            _ => {}
+        }
+        let (w, h) = distorted_res?;
+        (w > 2560 && h > 1440).then_some(("version=vmaf_4k_v0.6.1".into(), VmafModel::Vmaf4K))
+    }
+
+    /// Describes the libvmaf model that will be used, for display.
+    pub fn model_label(&self, distorted_res: Option<(u32, u32)>) -> String {
+        let custom: Vec<_> = self
+            .vmaf_args
+            .iter()
+            .filter(|a| a.contains("model"))
+            .map(|a| a.as_ref())
+            .collect();
+        if !custom.is_empty() {
+            return custom.join(":");
+        }
+        match self.auto_model(distorted_res) {
+            Some((arg, _)) => format!("model={arg}"),
+            None => "model=version=vmaf_v0.6.1 (libvmaf default)".into(),
+        }
+    }
+
     fn vf_scale(&self, model: VmafModel, distorted_res: Option<(u32, u32)>) -> Option<(i32, i32)> {
         match (self.vmaf_scale, distorted_res) {
             (VmafScale::Auto, Some((w, h))) => match model {
@@ -120,13 +332,164 @@ impl Vmaf {
 
 /// Return the smallest ffmpeg vf `(w, h)` scale values so that at least one of the
 /// `target_w` or `target_h` bounds are met.
+///
+/// Both values are explicit, keeping the `from` aspect, as `-1` would keep the
+/// storage aspect of anamorphic inputs rather than the display aspect.
 fn minimally_scale((from_w, from_h): (u32, u32), (target_w, target_h): (u32, u32)) -> (i32, i32) {
     let w_factor = from_w as f64 / target_w as f64;
     let h_factor = from_h as f64 / target_h as f64;
+    let even = |v: f64| (v / 2.0).round() as i32 * 2;
     if h_factor > w_factor {
-        (-1, target_h as _) // scale vertically
+        (even(from_w as f64 / h_factor), target_h as _) // scale vertically
     } else {
-        (target_w as _, -1) // scale horizontally
+        (target_w as _, even(from_h as f64 / w_factor)) // scale horizontally
+    }
+}
+
+/// Statistic of per-frame VMAF scores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum VmafStatistic {
+    #[default]
+    Mean,
+    HarmonicMean,
+    Min,
+    /// Percentile `[0, 100]`.
+    Percentile(u8),
+}
+
+impl VmafStatistic {
+    /// Calculate the statistic from per-frame scores.
+    pub fn calculate(self, scores: &[f32]) -> Option<f32> {
+        match self {
+            Self::Mean => Stats::from_scores(scores).map(|s| s.mean),
+            Self::HarmonicMean => Stats::from_scores(scores).map(|s| s.harmonic_mean),
+            Self::Min => Stats::from_scores(scores).map(|s| s.min),
+            Self::Percentile(p) => percentile(scores, p.into()),
+        }
+    }
+}
+
+impl std::str::FromStr for VmafStatistic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "mean" => Self::Mean,
+            "harmonic-mean" => Self::HarmonicMean,
+            "min" => Self::Min,
+            _ => {
+                const ERR: &str = "expected mean, harmonic-mean, min or pN e.g. p5";
+                let p = s
+                    .strip_prefix("percentile:")
+                    .or_else(|| s.strip_prefix('p'));
+                let p: u8 = p.context(ERR)?.parse().context(ERR)?;
+                anyhow::ensure!(p <= 100, "percentile must be 0-100");
+                Self::Percentile(p)
+            }
+        })
+    }
+}
+
+impl Display for VmafStatistic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mean => "mean".fmt(f),
+            Self::HarmonicMean => "harmonic-mean".fmt(f),
+            Self::Min => "min".fmt(f),
+            Self::Percentile(p) => write!(f, "p{p}"),
+        }
+    }
+}
+
+/// `--vmaf-model` value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VmafModelArg {
+    Vmaf4K,
+    /// 1k no-enhancement-gain model.
+    Neg,
+    /// Model json file.
+    Path(PathBuf),
+}
+
+fn parse_vmaf_model(v: &str) -> anyhow::Result<VmafModelArg> {
+    match v {
+        "4k" => Ok(VmafModelArg::Vmaf4K),
+        "neg" => Ok(VmafModelArg::Neg),
+        "" => Err(anyhow::anyhow!(
+            "vmaf-model must be '4k', 'neg' or a model file path"
+        )),
+        path => Ok(VmafModelArg::Path(path.into())),
+    }
+}
+
+impl VmafModelArg {
+    /// libvmaf `model` option value.
+    fn model_arg(&self) -> Cow<'_, str> {
+        match self {
+            Self::Vmaf4K => "version=vmaf_4k_v0.6.1".into(),
+            Self::Neg => "version=vmaf_v0.6.1neg".into(),
+            Self::Path(path) => {
+                let path = path
+                    .to_string_lossy()
+                    .replace('\\', "/")
+                    .replace(':', "\\\\:");
+                format!("path={path}").into()
+            }
+        }
+    }
+
+    fn model(&self) -> VmafModel {
+        match self {
+            Self::Vmaf4K => VmafModel::Vmaf4K,
+            Self::Neg => VmafModel::Vmaf1K,
+            Self::Path(path) => VmafModel::from_path(&path.to_string_lossy()),
+        }
+    }
+}
+
+/// Viewing conditions, see `--target-viewing`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum TargetViewing {
+    #[value(name = "1080p-tv")]
+    Tv1080p,
+    #[value(name = "4k-tv")]
+    Tv4k,
+    Phone,
+}
+
+impl TargetViewing {
+    /// Default crf-search --min-vmaf, phone viewing tolerates lower scores.
+    pub fn default_min_vmaf(self) -> f32 {
+        match self {
+            Self::Tv1080p | Self::Tv4k => 95.0,
+            Self::Phone => 93.0,
+        }
+    }
+
+    /// libvmaf `model` option value.
+    pub fn model_arg(self) -> &'static str {
+        match self {
+            Self::Tv1080p => "version=vmaf_v0.6.1",
+            Self::Tv4k => "version=vmaf_4k_v0.6.1",
+            Self::Phone => r"'version=vmaf_v0.6.1\:enable_transform=true'",
+        }
+    }
+
+    fn model(self) -> VmafModel {
+        match self {
+            Self::Tv1080p | Self::Phone => VmafModel::Vmaf1K,
+            Self::Tv4k => VmafModel::Vmaf4K,
+        }
+    }
+}
+
+impl Display for TargetViewing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tv1080p => "1080p-tv".fmt(f),
+            Self::Tv4k => "4k-tv".fmt(f),
+            Self::Phone => "phone".fmt(f),
+        }
     }
 }
 
@@ -160,9 +523,10 @@ impl Display for VmafScale {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 enum VmafModel {
     /// Default 1080p model.
+    #[default]
     Vmaf1K,
     /// 4k model.
     Vmaf4K,
@@ -170,12 +534,6 @@ enum VmafModel {
     Custom,
 }
 
-impl Default for VmafModel {
-    fn default() -> Self {
-        Self::Vmaf1K
-    }
-}
-
 impl VmafModel {
     fn from_args(args: &[Arc<str>]) -> Option<Self> {
         let mut using_custom_model: Vec<_> = args.iter().filter(|v| v.contains("model")).collect();
@@ -184,12 +542,27 @@ impl VmafModel {
             0 => None,
             1 => Some(match using_custom_model.remove(0) {
                 v if v.ends_with("version=vmaf_v0.6.1") => Self::Vmaf1K,
+                v if v.ends_with("version=vmaf_v0.6.1neg") => Self::Vmaf1K,
                 v if v.ends_with("version=vmaf_4k_v0.6.1") => Self::Vmaf4K,
-                _ => Self::Custom,
+                v => match v.split_once("path=") {
+                    Some((_, path)) => Self::from_path(path),
+                    None => Self::Custom,
+                },
             }),
             _ => Some(Self::Custom),
         }
     }
+
+    /// Model kind of a model file, by the standard libvmaf model names
+    /// e.g. "vmaf_4k_v0.6.1.json", "vmaf_v0.6.1neg.json".
+    fn from_path(path: &str) -> Self {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        match name {
+            n if n.starts_with("vmaf_4k_v") => Self::Vmaf4K,
+            n if n.starts_with("vmaf_v") => Self::Vmaf1K,
+            _ => Self::Custom,
+        }
+    }
 }
 
 #[test]
@@ -197,6 +570,12 @@ fn vmaf_lavfi() {
     let vmaf = Vmaf {
         vmaf_args: vec!["n_threads=5".into(), "n_subsample=4".into()],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(None, PixelFormat::Yuv420p, Some("scale=1280:-1,fps=24")),
@@ -211,6 +590,12 @@ fn vmaf_lavfi_default() {
     let vmaf = Vmaf {
         vmaf_args: vec![],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     let expected = format!(
         "[0:v]format=yuv420p10le,setpts=PTS-STARTPTS[dis];\
@@ -229,6 +614,12 @@ fn vmaf_lavfi_include_n_threads() {
     let vmaf = Vmaf {
         vmaf_args: vec!["log_path=output.xml".into()],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     let expected = format!(
         "[0:v]format=yuv420p,setpts=PTS-STARTPTS[dis];\
@@ -248,21 +639,62 @@ fn vmaf_lavfi_small_width() {
     let vmaf = Vmaf {
         vmaf_args: vec!["n_threads=5".into(), "n_subsample=4".into()],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(Some((1280, 720)), PixelFormat::Yuv420p, None),
-        "[0:v]format=yuv420p,scale=1920:-1:flags=bicubic,setpts=PTS-STARTPTS[dis];\
-         [1:v]format=yuv420p,scale=1920:-1:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+        "[0:v]format=yuv420p,scale=1920:1080:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=1920:1080:flags=bicubic,setpts=PTS-STARTPTS[ref];\
          [dis][ref]libvmaf=n_threads=5:n_subsample=4"
     );
 }
 
+/// Anamorphic videos should be upscaled by display aspect, e.g. 720x576 SAR 64:45
+/// displayed at 1024x576, not the 5:4 storage aspect.
+#[test]
+fn vmaf_lavfi_anamorphic() {
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1024, 576)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,scale=1920:1080:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=1920:1080:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5"
+    );
+    // 4:3 display of 720x480 SAR 8:9
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((640, 480)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,scale=1440:1080:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=1440:1080:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5"
+    );
+}
+
 /// 4k videos should use 4k model
 #[test]
 fn vmaf_lavfi_4k() {
     let vmaf = Vmaf {
         vmaf_args: vec!["n_threads=5".into(), "n_subsample=4".into()],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(Some((3840, 2160)), PixelFormat::Yuv420p, None),
@@ -278,11 +710,17 @@ fn vmaf_lavfi_3k_upscale_to_4k() {
     let vmaf = Vmaf {
         vmaf_args: vec!["n_threads=5".into()],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(Some((3008, 1692)), PixelFormat::Yuv420p, None),
-        "[0:v]format=yuv420p,scale=3840:-1:flags=bicubic,setpts=PTS-STARTPTS[dis];\
-         [1:v]format=yuv420p,scale=3840:-1:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+        "[0:v]format=yuv420p,scale=3840:2160:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=3840:2160:flags=bicubic,setpts=PTS-STARTPTS[ref];\
          [dis][ref]libvmaf=n_threads=5:model=version=vmaf_4k_v0.6.1"
     );
 }
@@ -297,6 +735,12 @@ fn vmaf_lavfi_small_width_custom_model() {
             "n_subsample=4".into(),
         ],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(Some((1280, 720)), PixelFormat::Yuv420p, None),
@@ -319,11 +763,17 @@ fn vmaf_lavfi_custom_model_and_width() {
             width: 123,
             height: 720,
         },
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(Some((1280, 720)), PixelFormat::Yuv420p, None),
-        "[0:v]format=yuv420p,scale=123:-1:flags=bicubic,setpts=PTS-STARTPTS[dis];\
-         [1:v]format=yuv420p,scale=123:-1:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+        "[0:v]format=yuv420p,scale=123:70:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=123:70:flags=bicubic,setpts=PTS-STARTPTS[ref];\
          [dis][ref]libvmaf=model=version=foo:n_threads=5:n_subsample=4"
     );
 }
@@ -333,6 +783,12 @@ fn vmaf_lavfi_1080p() {
     let vmaf = Vmaf {
         vmaf_args: vec!["n_threads=5".into(), "n_subsample=4".into()],
         vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
     };
     assert_eq!(
         vmaf.ffmpeg_lavfi(Some((1920, 1080)), PixelFormat::Yuv420p, None),
@@ -341,3 +797,217 @@ fn vmaf_lavfi_1080p() {
          [dis][ref]libvmaf=n_threads=5:n_subsample=4"
     );
 }
+
+#[test]
+fn vmaf_pix_fmt_default_reference() {
+    use PixelFormat::*;
+    let mut vmaf = Vmaf {
+        vmaf_args: vec![],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    assert_eq!(vmaf.pix_fmt(Some(Yuv420p10le), Some(Yuv420p)), Yuv420p);
+    assert_eq!(vmaf.pix_fmt(Some(Yuv420p10le), None), Yuv420p10le);
+    assert_eq!(vmaf.pix_fmt(None, None), Yuv444p10le);
+
+    vmaf.metric_pix_fmt = Some(Yuv444p10le);
+    assert_eq!(vmaf.pix_fmt(Some(Yuv420p10le), Some(Yuv420p)), Yuv444p10le);
+}
+
+#[test]
+fn vmaf_reduced_threads() {
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=64".into(), "log_path=out.xml".into()],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    let reduced = vmaf.reduced_threads().unwrap();
+    assert_eq!(
+        reduced.vmaf_args,
+        vec![
+            Arc::from("log_path=out.xml"),
+            "n_threads=32".into(),
+            "n_subsample=2".into()
+        ]
+    );
+    assert!(crate::vmaf::oom_retry_warning(&vmaf, &reduced)
+        .ends_with("n_subsample changed from 1 to 2 which may affect the score"));
+
+    let single = Vmaf {
+        vmaf_args: vec!["n_threads=1".into()],
+        ..vmaf
+    };
+    assert!(single.reduced_threads().is_none());
+}
+
+#[test]
+fn vmaf_statistic_from_str() {
+    use std::str::FromStr;
+    assert_eq!(
+        VmafStatistic::from_str("mean").unwrap(),
+        VmafStatistic::Mean
+    );
+    assert_eq!(
+        VmafStatistic::from_str("harmonic-mean").unwrap(),
+        VmafStatistic::HarmonicMean
+    );
+    assert_eq!(
+        VmafStatistic::from_str("p5").unwrap(),
+        VmafStatistic::Percentile(5)
+    );
+    assert_eq!(
+        VmafStatistic::from_str("percentile:1").unwrap(),
+        VmafStatistic::Percentile(1)
+    );
+    assert!(VmafStatistic::from_str("p101").is_err());
+    assert!(VmafStatistic::from_str("median").is_err());
+}
+
+#[test]
+fn vmaf_with_frame_log() {
+    let vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into(), "log_path=foo.xml".into()],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    let vmaf = vmaf.with_frame_log(Path::new("C:\\tmp\\vid.vmaf.json"));
+    assert_eq!(
+        vmaf.vmaf_args,
+        vec![
+            Arc::from("n_threads=5"),
+            "log_path=C\\\\:/tmp/vid.vmaf.json".into(),
+            "log_fmt=json".into(),
+        ]
+    );
+}
+
+#[test]
+fn vmaf_threads_divided() {
+    let mut vmaf = Vmaf {
+        vmaf_args: vec![],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: Some(6),
+        concurrent_processes: 3,
+    };
+    assert_eq!(vmaf.threads(), 6);
+    assert!(vmaf
+        .ffmpeg_lavfi(None, PixelFormat::Yuv420p, None)
+        .ends_with("n_threads=6"));
+
+    vmaf.vmaf_threads = None;
+    let all = thread::available_parallelism().map_or(1, |p| p.get());
+    assert_eq!(vmaf.threads(), (all / 3).max(1));
+}
+
+#[test]
+fn vmaf_with_ms_ssim() {
+    let mut vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    assert_eq!(
+        vmaf.with_ms_ssim().vmaf_args,
+        vec![
+            Arc::from("n_threads=5"),
+            "feature=name=float_ms_ssim".into()
+        ]
+    );
+
+    vmaf.vmaf_args = vec!["feature=name=psnr".into()];
+    assert_eq!(
+        vmaf.with_ms_ssim().vmaf_args,
+        vec![Arc::from("feature=name=psnr|name=float_ms_ssim")]
+    );
+}
+
+#[test]
+fn vmaf_lavfi_target_viewing() {
+    let mut vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: Some(TargetViewing::Tv4k),
+        vmaf_model: None,
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    // 4k model upscales 1080p
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1920, 1080)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,scale=3840:2160:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=3840:2160:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5:model=version=vmaf_4k_v0.6.1"
+    );
+
+    vmaf.target_viewing = Some(TargetViewing::Phone);
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((3840, 2160)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5:model='version=vmaf_v0.6.1\\:enable_transform=true'"
+    );
+}
+
+#[test]
+fn vmaf_lavfi_model_arg() {
+    let mut vmaf = Vmaf {
+        vmaf_args: vec!["n_threads=5".into()],
+        vmaf_scale: VmafScale::Auto,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: Some(TargetViewing::Tv4k),
+        vmaf_model: Some(VmafModelArg::Neg),
+        vmaf_threads: None,
+        concurrent_processes: 1,
+    };
+    // neg uses 1k scaling
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1280, 720)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,scale=1920:1080:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=1920:1080:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5:model=version=vmaf_v0.6.1neg"
+    );
+
+    vmaf.vmaf_model = Some(parse_vmaf_model("C:/models/vmaf_4k_v0.6.1.json").unwrap());
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1920, 1080)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,scale=3840:2160:flags=bicubic,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,scale=3840:2160:flags=bicubic,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5:model=path=C\\\\:/models/vmaf_4k_v0.6.1.json"
+    );
+
+    // unknown model files are not scaled
+    vmaf.vmaf_model = Some(parse_vmaf_model("my_model.json").unwrap());
+    assert_eq!(
+        vmaf.ffmpeg_lavfi(Some((1280, 720)), PixelFormat::Yuv420p, None),
+        "[0:v]format=yuv420p,setpts=PTS-STARTPTS[dis];\
+         [1:v]format=yuv420p,setpts=PTS-STARTPTS[ref];\
+         [dis][ref]libvmaf=n_threads=5:model=path=my_model.json"
+    );
+}
@@ -0,0 +1,150 @@
+use crate::{
+    audio,
+    cancel::{self, CancellationToken},
+    command::{progress, PROGRESS_CHARS},
+    console_ext::style,
+    ffprobe, machine, naming, sample,
+    temporary::{self, TempKind},
+};
+use anyhow::{ensure, Context};
+use clap::{Parser, ValueHint};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{path::PathBuf, time::Duration};
+
+/// Search for the lowest audio bitrate retaining the input's audio quality.
+///
+/// Encodes short samples of the first audio stream at candidate bitrates,
+/// binary searching for the lowest bitrate scoring at least --min-score.
+///
+/// Score is the percentage of the reference spectral bandwidth (mean ffmpeg
+/// aspectralstats rolloff) retained by the encoded audio.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Input video or audio file.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub input: PathBuf,
+
+    /// Audio encoder, libopus or aac.
+    #[arg(long, default_value = "libopus", value_parser = clap::builder::PossibleValuesParser::new(audio::ENCODERS))]
+    pub acodec: String,
+
+    /// Candidate bitrates in kbps.
+    #[arg(long, value_delimiter = ',', default_value = "48,64,96,128,160,192")]
+    pub bitrates: Vec<u32>,
+
+    /// Minimum percentage of the reference spectral bandwidth to retain.
+    #[arg(long, default_value_t = 95.0)]
+    pub min_score: f32,
+
+    /// Number of 20s samples to use.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    pub samples: u64,
+
+    /// Don't print per-bitrate attempt lines. Set by the global --quiet.
+    #[arg(skip)]
+    pub quiet: bool,
+
+    /// Print the result to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn audio_search(
+    Args {
+        input,
+        acodec,
+        mut bitrates,
+        min_score,
+        samples,
+        quiet,
+        json,
+    }: Args,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    bitrates.sort_unstable();
+    bitrates.dedup();
+    ensure!(!bitrates.is_empty(), "--bitrates must not be empty");
+
+    let probe = ffprobe::probe(&input);
+    ensure!(probe.has_audio, "input has no audio stream");
+    let duration = probe.duration.clone()?;
+    let ranges = sample::ranges(duration, samples);
+
+    let bar = ProgressBar::new(1).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
+            .progress_chars(PROGRESS_CHARS)
+    );
+    machine::watch(&bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    // reference analysis + a binary search of encodes
+    let max_attempts = bitrates.len().ilog2() as u64 + 1;
+    bar.set_length(ranges.len() as u64 * (1 + max_attempts));
+    bar.set_message("analysing reference, ");
+
+    let mut references = Vec::with_capacity(ranges.len());
+    for range in &ranges {
+        references
+            .push(cancel::until(&cancel, audio::spectral_rolloff(&input, Some(*range))).await?);
+        bar.inc(1);
+    }
+
+    let temp_dir = temporary::process_dir(None);
+    let mut attempts: Vec<(u32, f32)> = vec![];
+    let mut best = None;
+    let (mut lo, mut hi) = (0, bitrates.len());
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let kbps = bitrates[mid];
+        bar.set_message(format!("{acodec} {kbps}k, "));
+
+        let mut score = 0.0;
+        for (idx, (range, reference)) in ranges.iter().zip(&references).enumerate() {
+            let dest = temp_dir.join(naming::temp_file_name(
+                &input,
+                &format!("audio{idx}.{kbps}k.mka"),
+            ));
+            temporary::add(&dest, TempKind::NotKeepable);
+            let sample = audio::encode_sample(&input, range.0, range.1, &acodec, kbps, &dest);
+            cancel::until(&cancel, sample).await?;
+            let distorted = cancel::until(&cancel, audio::spectral_rolloff(&dest, None)).await?;
+            _ = tokio::fs::remove_file(&dest).await;
+            score += audio::bandwidth_score(*reference, distorted) / ranges.len() as f32;
+            bar.inc(1);
+        }
+        attempts.push((kbps, score));
+        if !quiet {
+            let mut line = style!("{acodec} {kbps}k bandwidth {score:.2}%");
+            if score < min_score {
+                line = line.red().bright();
+            }
+            progress::println(&bar, line.to_string());
+        }
+
+        if score >= min_score {
+            best = Some((kbps, score));
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    bar.finish();
+
+    let (kbps, score) =
+        best.with_context(|| format!("no --bitrates candidate reaches --min-score {min_score}"))?;
+    match json {
+        true => machine::print_json(&serde_json::json!({
+            "acodec": acodec,
+            "bitrate_kbps": kbps,
+            "score": score,
+            "attempts": attempts
+                .iter()
+                .map(|(kbps, score)| serde_json::json!({ "bitrate_kbps": kbps, "score": score }))
+                .collect::<Vec<_>>(),
+        })),
+        false => println!("{kbps}k"),
+    }
+    Ok(())
+}
@@ -1,17 +1,18 @@
+mod state;
+
 use crate::{
-    command::{
-        args, crf_search,
-        encode::{self, default_output_name},
-        PROGRESS_CHARS,
-    },
+    cancel::CancellationToken,
+    command::{args, crf_search, encode, progress, PROGRESS_CHARS},
     console_ext::style,
-    ffprobe,
+    ffmpeg, ffprobe,
     float::TerseF32,
-    temporary,
+    machine, naming, temporary,
 };
+use anyhow::ensure;
 use clap::Parser;
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use state::{Entry, State};
 use std::{sync::Arc, time::Duration};
 
 /// Automatically determine the best crf to deliver the min-vmaf and use it to encode a video or image.
@@ -28,9 +29,46 @@ pub struct Args {
 
     #[clap(flatten)]
     pub encode: args::EncodeToOutput,
+
+    /// After the search, display the predicted outcome & wait for confirmation
+    /// before starting the final encode.
+    #[arg(long)]
+    pub confirm: bool,
+
+    /// With --confirm, automatically continue encoding after this duration without input.
+    /// By default waits indefinitely.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub confirm_timeout: Option<Duration>,
+
+    /// Abort before encoding if the predicted video stream size saves less than this
+    /// percentage of the input size. E.g. "--min-savings 25".
+    #[arg(long)]
+    pub min_savings: Option<f32>,
+
+    /// Resume a previous run with the same settings, reusing its crf-search result
+    /// or skipping the input if already encoded. Useful for long batch jobs.
+    ///
+    /// Job state is stored in ".ab-av1-state.json" in --temp-dir or the input's directory.
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Print the encode result to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
 }
 
-pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()> {
+pub async fn auto_encode(
+    Args {
+        mut search,
+        encode,
+        confirm,
+        confirm_timeout,
+        min_savings,
+        resume,
+        json,
+    }: Args,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
     const SPINNER_RUNNING: &str =
         "{spinner:.cyan.bold} {prefix} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})";
     const SPINNER_FINISHED: &str =
@@ -39,54 +77,119 @@ pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()
     search.quiet = true;
     let defaulting_output = encode.output.is_none();
     let input_probe = Arc::new(ffprobe::probe(&search.args.input));
+    search.args.resolve_encoder()?;
+    search.args.apply_sar(&input_probe);
+    search.args.apply_detelecine(&input_probe);
 
-    let output = encode.output.unwrap_or_else(|| {
-        default_output_name(
+    let output = encode.output.clone().unwrap_or_else(|| {
+        naming::default_output_name(
             &search.args.input,
-            &search.args.encoder,
+            search.args.encoder.as_str(),
             input_probe.is_image,
         )
     });
+    let settings = resume_settings(&search);
+    let mut state = match resume {
+        true => Some(State::load(
+            search.sample.temp_dir.as_deref(),
+            &search.args.input,
+        )?),
+        false => None,
+    };
+    let resumed = state
+        .as_ref()
+        .and_then(|s| s.get(&search.args.input, &settings))
+        .filter(|e| e.output == output)
+        .cloned();
+    if resumed
+        .as_ref()
+        .is_some_and(|e| e.encoded && output.exists())
+    {
+        eprintln!(
+            "{}",
+            style!("Skipping, {} already encoded", output.display()).dim()
+        );
+        return Ok(());
+    }
+    if !encode.existing.check_output(&output)? {
+        return Ok(());
+    }
     search.sample.set_extension_from_output(&output);
+    let degraded = search.vmaf_fallback();
+    // validate muxer flags before searching
+    ffmpeg::mux_args(&output, &encode.mux_flags, &<_>::default())?;
 
     let bar = ProgressBar::new(12).with_style(
         ProgressStyle::default_bar()
             .template(SPINNER_RUNNING)?
             .progress_chars(PROGRESS_CHARS),
     );
+    machine::watch(&bar);
 
     bar.set_prefix("Searching");
+    if let Some(warning) = &degraded {
+        progress::println(&bar, warning.clone());
+    }
     if defaulting_output {
         let out = shell_escape::escape(output.display().to_string().into());
-        bar.println(style!("Encoding {out}").dim().to_string());
+        progress::println(&bar, style!("Encoding {out}").dim().to_string());
     }
 
-    let best = match crf_search::run(&search, input_probe.clone(), bar.clone()).await {
-        Ok(best) => best,
-        Err(err) => {
-            if let crf_search::Error::NoGoodCrf { last } = &err {
-                // show last sample attempt in progress bar
-                bar.set_style(
-                    ProgressStyle::default_bar()
-                        .template(SPINNER_FINISHED)?
-                        .progress_chars(PROGRESS_CHARS),
-                );
-                let mut vmaf = style(last.enc.vmaf);
-                if last.enc.vmaf < search.min_vmaf {
-                    vmaf = vmaf.red();
+    let best = match resumed {
+        Some(entry) => {
+            progress::println(
+                &bar,
+                style!(
+                    "Resuming previous search result crf {}",
+                    TerseF32(entry.crf)
+                )
+                .dim()
+                .to_string(),
+            );
+            entry
+        }
+        None => match crf_search::run(&search, input_probe.clone(), bar.clone(), &cancel).await {
+            Ok(best) => {
+                let entry = Entry {
+                    settings,
+                    output: output.clone(),
+                    crf: best.crf(),
+                    score: best.score_label(),
+                    predicted_encode_size: best.enc.predicted_encode_size,
+                    encode_percent: best.enc.encode_percent,
+                    predicted_encode_time: best.enc.predicted_encode_time,
+                    encoded: false,
+                };
+                if let Some(state) = &mut state {
+                    state.set(&search.args.input, entry.clone())?;
                 }
-                let mut percent = style!("{:.0}%", last.enc.encode_percent);
-                if last.enc.encode_percent > search.max_encoded_percent as _ {
-                    percent = percent.red();
+                entry
+            }
+            Err(err) => {
+                if let crf_search::Error::NoGoodCrf { last } = &err {
+                    // show last sample attempt in progress bar
+                    bar.set_style(
+                        ProgressStyle::default_bar()
+                            .template(SPINNER_FINISHED)?
+                            .progress_chars(PROGRESS_CHARS),
+                    );
+                    let mut score = style(last.score_label());
+                    if last.score() < search.min_score() {
+                        score = score.red();
+                    }
+                    let mut percent = style!("{:.0}%", last.enc.encode_percent);
+                    if last.capped_percent() > search.max_encoded_percent() as _ {
+                        percent = percent.red();
+                    }
+                    bar.finish_with_message(format!(
+                        "crf {}, {score}, size {percent}",
+                        style(TerseF32(last.crf())).red(),
+                    ));
                 }
-                bar.finish_with_message(format!(
-                    "crf {}, VMAF {vmaf:.2}, size {percent}",
-                    style(TerseF32(last.crf())).red(),
-                ));
+                bar.finish();
+                return Err(err.into());
             }
-            bar.finish();
-            return Err(err.into());
-        }
+        },
     };
     bar.set_style(
         ProgressStyle::default_bar()
@@ -94,32 +197,175 @@ pub async fn auto_encode(Args { mut search, encode }: Args) -> anyhow::Result<()
             .progress_chars(PROGRESS_CHARS),
     );
     bar.finish_with_message(format!(
-        "crf {}, VMAF {:.2}, size {}",
-        style(TerseF32(best.crf())).green(),
-        style(best.enc.vmaf).green(),
-        style(format!("{:.0}%", best.enc.encode_percent)).green(),
+        "crf {}, {}, size {}",
+        style(TerseF32(best.crf)).green(),
+        style(&best.score).green(),
+        style(format!("{:.0}%", best.encode_percent)).green(),
     ));
     temporary::clean_all().await;
+    if degraded.is_some() {
+        if let Some(note) =
+            crf_search::bits_per_pixel_note(best.predicted_encode_size, &input_probe)
+        {
+            eprintln!("{note}");
+        }
+    }
+
+    let input_size = tokio::fs::metadata(&search.args.input).await?.len();
+    let savings = 100.0 - 100.0 * best.predicted_encode_size as f64 / input_size as f64;
+    if let Some(min_savings) = min_savings {
+        ensure!(
+            savings >= min_savings.into(),
+            "Predicted savings {savings:.0}% less than --min-savings {min_savings}%, aborting"
+        );
+    }
+    if confirm {
+        eprint!(
+            "Predicted video stream size {} ({:.0}% savings), {}, taking {}. Encode? [Y/n] ",
+            style(HumanBytes(best.predicted_encode_size)).bold(),
+            savings,
+            best.score,
+            style(HumanDuration(best.predicted_encode_time)).bold(),
+        );
+        ensure!(
+            await_confirmation(confirm_timeout).await?,
+            "Encode cancelled"
+        );
+    }
 
     let bar = ProgressBar::new(12).with_style(
         ProgressStyle::default_bar()
             .template("{spinner:.cyan.bold} {prefix} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
             .progress_chars(PROGRESS_CHARS)
     );
+    machine::watch(&bar);
     bar.set_prefix("Encoding ");
     bar.enable_steady_tick(Duration::from_millis(100));
 
+    let input = search.args.input.clone();
+    let min_vmaf = search.targets().into_iter().find_map(|t| match t {
+        crf_search::Target::Vmaf(min) => Some(min),
+        _ => None,
+    });
     encode::run(
         encode::Args {
             args: search.args,
-            crf: best.crf(),
+            crf: best.crf,
             encode: args::EncodeToOutput {
                 output: Some(output),
                 ..encode
             },
+            chapters: vec![],
+            chunks: None,
+            chunk_jobs: None,
+            vmaf: Some(search.vmaf),
+            min_vmaf,
+            json,
+            quiet: false,
         },
         input_probe,
         &bar,
+        &cancel,
     )
-    .await
+    .await?;
+
+    if let Some(state) = &mut state {
+        state.set(
+            &input,
+            Entry {
+                encoded: true,
+                ..best
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Search, scoring, sampling & encoder settings a resumed result must match.
+fn resume_settings(search: &crf_search::Args) -> String {
+    let targets: Vec<_> = search.targets().iter().map(|t| format!("{t:?}")).collect();
+    let mut settings = format!(
+        "{} targets={} max-encoded-percent={}",
+        search.args.encode_hint(0.0),
+        targets.join(","),
+        search.max_encoded_percent(),
+    );
+    if let Some(objective) = &search.objective {
+        settings += &format!(" objective={objective}");
+    }
+
+    let vmaf = &search.vmaf;
+    settings += &format!(
+        " vmaf={} vmaf-scale={} vmaf-statistic={} {}",
+        vmaf.vmaf_args.join(":"),
+        vmaf.vmaf_scale,
+        vmaf.vmaf_statistic,
+        vmaf.model_label(None),
+    );
+    if let Some(pix_fmt) = vmaf.metric_pix_fmt {
+        settings += &format!(" metric-pix-fmt={pix_fmt}");
+    }
+
+    let sample = &search.sample;
+    settings += &format!(
+        " samples={:?} sample-every={:?} min-samples={:?} samples-per-point={}",
+        sample.samples, sample.sample_every, sample.min_samples, sample.samples_per_point,
+    );
+    if sample.include_credits {
+        settings += " include-credits";
+    }
+    settings
+}
+
+/// Read a y/n answer from stdin, empty means yes.
+///
+/// If `timeout` elapses without an answer returns `true`.
+async fn await_confirmation(timeout: Option<Duration>) -> anyhow::Result<bool> {
+    // read on a detached thread, as a blocking task abandoned after a timeout
+    // would block runtime shutdown until a line is entered
+    let (tx, read) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let mut answer = String::new();
+        _ = tx.send(std::io::stdin().read_line(&mut answer).map(|_| answer));
+    });
+    let answer = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, read).await {
+            Ok(answer) => answer??,
+            Err(_) => {
+                eprintln!("{}", style("timed out, continuing").dim());
+                return Ok(true);
+            }
+        },
+        None => read.await??,
+    };
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+#[test]
+fn resume_settings_invalidated() {
+    let settings = |args: &[&str]| {
+        let search =
+            crf_search::Args::try_parse_from(["auto-encode", "-i", "vid.mkv"].iter().chain(args))
+                .unwrap();
+        resume_settings(&search)
+    };
+    let default = settings(&[]);
+    assert_eq!(settings(&[]), default);
+    for changed in [
+        &["--min-ssim", "0.98"][..],
+        &["--min-xpsnr", "40"],
+        &["--min-ssimulacra2", "80"],
+        &["--vmaf", "n_subsample=4"],
+        &["--vmaf-model", "neg"],
+        &["--target-viewing", "phone"],
+        &["--samples", "5"],
+        &["--sample-every", "5m"],
+        &["--samples-per-point", "2"],
+        &["--include-credits"],
+    ] {
+        assert_ne!(settings(changed), default, "{changed:?}");
+    }
 }
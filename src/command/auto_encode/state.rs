@@ -0,0 +1,143 @@
+//! _auto-encode_ `--resume` job state, persisted so long batch runs can pick up
+//! where they left off after a crash.
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// State file name, stored in --temp-dir or the input's directory.
+const FILE_NAME: &str = ".ab-av1-state.json";
+
+/// Per-input search result & encode completion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Search & encoder settings the result applies to.
+    pub settings: String,
+    pub output: PathBuf,
+    pub crf: f32,
+    /// E.g. "VMAF 95.12".
+    pub score: String,
+    pub predicted_encode_size: u64,
+    pub encode_percent: f64,
+    pub predicted_encode_time: Duration,
+    /// The final encode completed.
+    pub encoded: bool,
+}
+
+/// Job state file of entries keyed by input path.
+#[derive(Debug)]
+pub struct State {
+    path: PathBuf,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl State {
+    /// Load the state file in `temp_dir`, or the `input` directory.
+    pub fn load(temp_dir: Option<&Path>, input: &Path) -> anyhow::Result<Self> {
+        let dir = match temp_dir {
+            Some(dir) => dir,
+            None => input
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+        };
+        let path = dir.join(FILE_NAME);
+        let entries = read_entries(&path)?;
+        Ok(Self { path, entries })
+    }
+
+    /// Returns the `input` entry if it was produced with the same `settings`.
+    pub fn get(&self, input: &Path, settings: &str) -> Option<&Entry> {
+        self.entries
+            .get(&key(input))
+            .filter(|e| e.settings == settings)
+    }
+
+    /// Store the `input` entry & write the state file.
+    ///
+    /// The file is re-read under an exclusive lock before writing, so entries of
+    /// concurrent runs sharing the state file are kept.
+    pub fn set(&mut self, input: &Path, entry: Entry) -> anyhow::Result<()> {
+        let lock_path = self.path.with_extension("json.lock");
+        let lock = std::fs::File::create(&lock_path)
+            .with_context(|| format!("create {}", lock_path.display()))?;
+        lock.lock()
+            .with_context(|| format!("lock {}", lock_path.display()))?;
+
+        self.entries = read_entries(&self.path)?;
+        self.entries.insert(key(input), entry);
+        // write & rename so a crash can't leave a partially written file
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&self.entries)?)
+            .with_context(|| format!("write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path).with_context(|| format!("write {}", self.path.display()))
+    }
+}
+
+fn read_entries(path: &Path) -> anyhow::Result<BTreeMap<String, Entry>> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("invalid state file {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(<_>::default()),
+        Err(err) => Err(err).context(format!("read {}", path.display())),
+    }
+}
+
+fn key(input: &Path) -> String {
+    std::fs::canonicalize(input)
+        .unwrap_or_else(|_| input.to_owned())
+        .display()
+        .to_string()
+}
+
+#[test]
+fn state_roundtrip() {
+    let dir = std::env::temp_dir().join(format!("ab-av1-state-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("vid.mkv");
+    let entry = Entry {
+        settings: "libsvtav1 --preset 6 min-vmaf=95".into(),
+        output: dir.join("vid.av1.mkv"),
+        crf: 32.0,
+        score: "VMAF 95.12".into(),
+        predicted_encode_size: 1000,
+        encode_percent: 40.0,
+        predicted_encode_time: Duration::from_secs(60),
+        encoded: false,
+    };
+
+    let mut state = State::load(Some(&dir), &input).unwrap();
+    assert_eq!(state.get(&input, &entry.settings), None);
+    state.set(&input, entry.clone()).unwrap();
+
+    let state = State::load(Some(&dir), &input).unwrap();
+    assert_eq!(state.get(&input, &entry.settings), Some(&entry));
+    // different settings are not resumed
+    assert_eq!(state.get(&input, "libsvtav1 --preset 4 min-vmaf=95"), None);
+
+    // concurrent runs loaded before each other's writes
+    let other_input = dir.join("other.mkv");
+    let mut other = State::load(Some(&dir), &other_input).unwrap();
+    let mut state = State::load(Some(&dir), &input).unwrap();
+    other.set(&other_input, entry.clone()).unwrap();
+    state
+        .set(
+            &input,
+            Entry {
+                crf: 30.0,
+                ..entry.clone()
+            },
+        )
+        .unwrap();
+    let state = State::load(Some(&dir), &input).unwrap();
+    assert_eq!(state.get(&other_input, &entry.settings), Some(&entry));
+    assert_eq!(
+        state.get(&input, &entry.settings).map(|e| e.crf),
+        Some(30.0)
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
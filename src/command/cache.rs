@@ -0,0 +1,43 @@
+use crate::command::sample_encode::cache;
+use clap::{Parser, Subcommand, ValueHint};
+use std::path::PathBuf;
+
+/// Export or import the sample-encode cache, e.g. to share results between machines.
+///
+/// Cache keys use the input file name, duration & size rather than full paths,
+/// so results computed on one machine apply to the same input file on another.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Write all cache entries to a json-lines file.
+    Export {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+    },
+    /// Merge cache entries from a previously exported file into the cache.
+    Import {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+    },
+}
+
+pub fn cache(Args { action }: Args) -> anyhow::Result<()> {
+    match action {
+        Action::Export { file } => {
+            let count = cache::export(&file)?;
+            eprintln!("Exported {count} cache entries to {}", file.display());
+        }
+        Action::Import { file } => {
+            let count = cache::import(&file)?;
+            eprintln!("Imported {count} cache entries from {}", file.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use crate::{
+    cancel::CancellationToken,
+    command::{args, progress, vmaf, PROGRESS_CHARS},
+    console_ext::style,
+    ffprobe, machine, naming,
+    plot::{self, Plot},
+    stats::{percentile, Stats},
+    temporary::{self, TempKind},
+};
+use clap::{ArgAction, Parser, ValueHint};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Per-frame VMAF comparison of two distorted files of the same reference,
+/// e.g. to A/B encoder settings.
+///
+/// * Scores both distorted files with the same vmaf settings.
+/// * Prints a table of per-frame score statistics for each & the difference (B - A).
+/// * Plots both score curves on one chart & the per-frame difference with --plot.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Reference video file.
+    #[arg(long)]
+    pub reference: PathBuf,
+
+    /// Ffmpeg video filter applied to the reference before analysis.
+    /// E.g. --reference-vfilter "scale=1280:-1,fps=24".
+    #[arg(long)]
+    pub reference_vfilter: Option<String>,
+
+    /// The two re-encoded/distorted video files "A" & "B" to compare.
+    #[arg(long, num_args = 2, required = true, action = ArgAction::Set, value_names = ["A", "B"])]
+    pub distorted: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    pub vmaf: args::Vmaf,
+
+    /// Also calculate & compare the libvmaf MS-SSIM feature.
+    #[arg(long)]
+    pub ms_ssim: bool,
+
+    /// Write a plot of both per-frame vmaf score curves to this ".svg" or ".png" file.
+    ///
+    /// The frame-aligned difference (B - A) is plotted to a ".diff.svg"/".diff.png"
+    /// file alongside.
+    #[arg(long, value_hint = ValueHint::FilePath, value_parser = plot::parse_path)]
+    pub plot: Option<PathBuf>,
+
+    /// Print the comparison to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn compare(
+    Args {
+        reference,
+        reference_vfilter,
+        distorted,
+        vmaf,
+        ms_ssim,
+        plot,
+        json,
+    }: Args,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let results = score_all(
+        &reference,
+        reference_vfilter.as_deref(),
+        &distorted,
+        vmaf,
+        ms_ssim,
+        &cancel,
+    )
+    .await?;
+    if let Some(path) = &plot {
+        write_plots(path, &results)?;
+    }
+
+    let [a, b] = &results[..] else { unreachable!() };
+    match json {
+        true => machine::print_json(&serde_json::json!({
+            "a": a.to_json(),
+            "b": b.to_json(),
+            "diff": { "vmaf": b.score - a.score },
+        })),
+        false => print!("{}", table(&results)),
+    }
+    Ok(())
+}
+
+/// Score each `distorted` file against the `reference` with the same vmaf settings.
+///
+/// Files are labelled A, B, C... in progress output, see [`label`].
+pub async fn score_all(
+    reference: &Path,
+    reference_vfilter: Option<&str>,
+    distorted: &[PathBuf],
+    mut vmaf: args::Vmaf,
+    ms_ssim: bool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<Scored>> {
+    let bar = ProgressBar::new(1).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
+            .progress_chars(PROGRESS_CHARS)
+    );
+    machine::watch(&bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+
+    if ms_ssim {
+        vmaf = vmaf.with_ms_ssim();
+    }
+    let rprobe = ffprobe::probe(reference);
+
+    let mut results = Vec::with_capacity(distorted.len());
+    for (idx, distorted) in distorted.iter().enumerate() {
+        let name = label(idx);
+        bar.set_message(format!("vmaf {name} running, "));
+        bar.set_position(0);
+        let dprobe = ffprobe::probe(distorted);
+        let pix_fmt = vmaf.pix_fmt(dprobe.pixel_format(), rprobe.pixel_format());
+        let nframes = dprobe.nframes().or_else(|_| rprobe.nframes());
+        if let Ok(nframes) = nframes {
+            bar.set_length(nframes);
+        }
+        progress::println(
+            &bar,
+            style!(
+                "{name}: {}, vmaf pixel format {pix_fmt}",
+                distorted.display()
+            )
+            .dim()
+            .to_string(),
+        );
+
+        let mut log = temporary::process_dir(None);
+        log.push(naming::temp_file_name(
+            distorted,
+            &format!("compare{name}.vmaf.json"),
+        ));
+        temporary::add(&log, TempKind::NotKeepable);
+
+        let lavfi = |vmaf: &args::Vmaf| {
+            vmaf.with_frame_log(&log).ffmpeg_lavfi(
+                dprobe.display_resolution(),
+                pix_fmt,
+                reference_vfilter,
+            )
+        };
+        let mean = vmaf::run(
+            &bar,
+            &mut vmaf,
+            (reference, distorted),
+            lavfi,
+            None,
+            nframes.is_ok(),
+            cancel,
+        )
+        .await?;
+        let (score, scores) = crate::vmaf::score_statistic_frames(&vmaf, Some(&log), mean)?;
+        let ms_ssim_scores = match ms_ssim {
+            true => crate::vmaf::read_frame_metric(&log, crate::vmaf::MS_SSIM_FEATURE)?,
+            false => vec![],
+        };
+        results.push(Scored {
+            score,
+            stats: Stats::from_scores(&scores),
+            p5: percentile(&scores, 5.0),
+            ms_ssim: Stats::from_scores(&ms_ssim_scores).map(|s| s.mean),
+            size: std::fs::metadata(distorted).map(|m| m.len()).ok(),
+            scores,
+        });
+    }
+    bar.finish();
+    Ok(results)
+}
+
+/// Label of the `idx`th distorted file, "A", "B", "C"...
+pub fn label(idx: usize) -> String {
+    match u8::try_from(idx).ok().filter(|i| *i < 26) {
+        Some(i) => char::from(b'A' + i).into(),
+        None => format!("#{}", idx + 1),
+    }
+}
+
+/// Plot per-frame score curves to `path` & the frame-aligned differences to the
+/// first file (e.g. B - A) to a ".diff.svg"/".diff.png" file alongside.
+pub fn write_plots(path: &Path, results: &[Scored]) -> anyhow::Result<()> {
+    let mut plot = Plot::new("VMAF");
+    for (idx, scored) in results.iter().enumerate() {
+        plot.series(&label(idx), scored.scores.clone());
+    }
+    plot.write(path)?;
+
+    let mut diff = Plot::new("VMAF difference");
+    for (idx, scored) in results.iter().enumerate().skip(1) {
+        diff.series(
+            &format!("{} - A", label(idx)),
+            frame_diff(&results[0].scores, &scored.scores),
+        );
+    }
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("svg");
+    diff.write(&path.with_extension(format!("diff.{ext}")))
+}
+
+/// Per-frame `b - a` score differences, over the frames both have.
+fn frame_diff(a: &[f32], b: &[f32]) -> Vec<f32> {
+    a.iter().zip(b).map(|(a, b)| b - a).collect()
+}
+
+/// Vmaf result of one distorted file.
+#[derive(Debug, Default)]
+pub struct Scored {
+    /// Configured --vmaf-statistic score.
+    score: f32,
+    stats: Option<Stats>,
+    p5: Option<f32>,
+    ms_ssim: Option<f32>,
+    /// File size in bytes.
+    size: Option<u64>,
+    scores: Vec<f32>,
+}
+
+impl Scored {
+    /// Configured --vmaf-statistic score.
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({ "vmaf": self.score, "frames": self.scores.len() });
+        if let (Some(stats), Some(p5)) = (self.stats, self.p5) {
+            json["stats"] = serde_json::json!({
+                "mean": stats.mean,
+                "harmonic_mean": stats.harmonic_mean,
+                "min": stats.min,
+                "max": stats.max,
+                "p5": p5,
+            });
+        }
+        if let Some(ms_ssim) = self.ms_ssim {
+            json["ms_ssim"] = ms_ssim.into();
+        }
+        if let Some(size) = self.size {
+            json["size"] = size.into();
+        }
+        json
+    }
+}
+
+/// Table row label, value & decimal places.
+type Row = (&'static str, fn(&Scored) -> Option<f32>, usize);
+
+/// Stats table of each result (A, B, ...) & the differences to the first (B - A, ...).
+pub fn table(results: &[Scored]) -> String {
+    let rows: [Row; 5] = [
+        ("vmaf", |s| Some(s.score), 2),
+        ("mean", |s| s.stats.map(|s| s.mean), 2),
+        ("harmonic mean", |s| s.stats.map(|s| s.harmonic_mean), 2),
+        ("min", |s| s.stats.map(|s| s.min), 2),
+        ("p5", |s| s.p5, 2),
+    ];
+    let ms_ssim: Row = ("ms-ssim", |s| s.ms_ssim, 4);
+    let rows = rows.into_iter().chain(
+        results
+            .iter()
+            .any(|s| s.ms_ssim.is_some())
+            .then_some(ms_ssim),
+    );
+
+    let mut table = format!("{:<14}", "");
+    for idx in 0..results.len() {
+        let _ = write!(table, "{:>12}", label(idx));
+    }
+    for idx in 1..results.len() {
+        let _ = write!(table, "{:>12}", format!("{} - A", label(idx)));
+    }
+    table.push('\n');
+
+    let row = |table: &mut String, name: &str, cells: Vec<String>, diffs: Vec<String>| {
+        let _ = write!(table, "{name:<14}");
+        for cell in cells.iter().chain(&diffs) {
+            let _ = write!(table, "{cell:>12}");
+        }
+        table.push('\n');
+    };
+    for (name, value, decimals) in rows {
+        let values: Vec<_> = results.iter().map(value).collect();
+        let cells = values
+            .iter()
+            .map(|v| v.map(|v| format!("{v:.decimals$}")).unwrap_or_default())
+            .collect();
+        let diffs = values[1..]
+            .iter()
+            .map(|v| match (values[0], v) {
+                (Some(a), Some(b)) => format!("{:+.decimals$}", b - a),
+                _ => String::new(),
+            })
+            .collect();
+        row(&mut table, name, cells, diffs);
+    }
+    if let Some(sizes) = results.iter().map(|s| s.size).collect::<Option<Vec<_>>>() {
+        let cells = sizes.iter().map(|s| HumanBytes(*s).to_string()).collect();
+        let diffs = sizes[1..]
+            .iter()
+            .map(|b| match sizes[0] {
+                0 => String::new(),
+                a => format!("{:+.1}%", (*b as f64 / a as f64 - 1.0) * 100.0),
+            })
+            .collect();
+        row(&mut table, "size", cells, diffs);
+    }
+    table
+}
+
+#[test]
+fn compare_table() {
+    let scored = |scores: &[f32], size| Scored {
+        score: Stats::from_scores(scores).unwrap().mean,
+        stats: Stats::from_scores(scores),
+        p5: percentile(scores, 5.0),
+        size: Some(size),
+        ..<_>::default()
+    };
+    let a = scored(&[94.0, 96.0], 1000);
+    let b = scored(&[95.0, 96.0], 800);
+    assert_eq!(
+        table(&[a, b]),
+        "                         A           B       B - A\n\
+         vmaf                 95.00       95.50       +0.50\n\
+         mean                 95.00       95.50       +0.50\n\
+         harmonic mean        94.99       95.50       +0.51\n\
+         min                  94.00       95.00       +1.00\n\
+         p5                   94.00       95.00       +1.00\n\
+         size                1000 B       800 B      -20.0%\n"
+    );
+}
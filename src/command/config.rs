@@ -0,0 +1,164 @@
+use crate::{command::args, ffmpeg, ffprobe, float::TerseF32, naming};
+use clap::{Parser, Subcommand};
+use std::{ffi::OsStr, sync::Arc};
+
+/// Inspect the effective configuration.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    #[command(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Print the effective encoding parameters, after applying any input sidecar
+    /// overrides & encoder defaults, and the resulting ffmpeg command.
+    ///
+    /// Useful to debug why an encode behaved unexpectedly.
+    Show(ShowArgs),
+}
+
+#[derive(Parser)]
+#[group(skip)]
+pub struct ShowArgs {
+    #[clap(flatten)]
+    pub args: args::Encode,
+
+    /// Encoder constant rate factor. Defaults to the first crf a crf-search would try.
+    #[arg(long)]
+    pub crf: Option<f32>,
+
+    /// Desired min VMAF score, as used by crf-search.
+    ///
+    /// [default: 95, 93 with --target-viewing phone]
+    #[arg(long)]
+    pub min_vmaf: Option<f32>,
+
+    #[clap(flatten)]
+    pub encode: args::EncodeToOutput,
+
+    #[clap(flatten)]
+    pub vmaf: args::Vmaf,
+}
+
+pub fn config(Args { action }: Args) -> anyhow::Result<()> {
+    match action {
+        Action::Show(args) => show(args),
+    }
+}
+
+fn show(
+    ShowArgs {
+        mut args,
+        crf,
+        min_vmaf,
+        encode,
+        vmaf,
+    }: ShowArgs,
+) -> anyhow::Result<()> {
+    let probe = ffprobe::probe(&args.input);
+    args.resolve_encoder()?;
+    args.apply_sar(&probe);
+    args.apply_detelecine(&probe);
+    let crf = crf.unwrap_or_else(|| {
+        // crf-search starts in the middle of the default q range
+        let increment = args.encoder.default_crf_increment();
+        let q = |crf: f32| (crf / increment).round() as u64;
+        let (min, max) = (
+            args.encoder.default_min_crf(),
+            args.encoder.default_max_crf(),
+        );
+        ((q(min) + q(max)) / 2) as f32 * increment
+    });
+    let output = encode.output.clone().unwrap_or_else(|| {
+        naming::default_output_name(&args.input, args.encoder.as_str(), probe.is_image)
+    });
+
+    let stereo_downmix =
+        encode.downmix_to_stereo && probe.max_audio_channels.is_some_and(|c| c > 3);
+    let audio_channels = match stereo_downmix {
+        true => Some(2),
+        false => probe.max_audio_channels,
+    };
+    let opus_bitrate = ffmpeg::opus_bitrate(audio_channels, encode.abitrate_per_channel);
+
+    let mut enc_args = args.to_encoder_args(crf, &probe)?;
+    enc_args.video_only = encode.video_only;
+    enc_args
+        .output_args
+        .extend(args.force_keyframes_args(&probe)?);
+
+    let also = encode
+        .also_encode
+        .as_ref()
+        .map(|also| (also, also.output(&args.input, probe.is_image)));
+
+    let sidecar = args::sidecar_path(&args.input);
+    let row = |name: &str, value: &dyn std::fmt::Display| println!("{name:<12} {value}");
+    row("input", &args.input.display());
+    if sidecar.exists() {
+        row("sidecar", &sidecar.display());
+    }
+    row("output", &output.display());
+    row("encoder", &enc_args.vcodec);
+    row("crf", &TerseF32(crf));
+    row(
+        "preset",
+        &enc_args.preset.as_deref().unwrap_or("encoder default"),
+    );
+    row("pix-format", &enc_args.pix_fmt);
+    row("vfilter", &enc_args.vfilter.as_deref().unwrap_or("none"));
+    row("enc-input", &join(&enc_args.input_args));
+    row("enc", &join(&enc_args.output_args));
+    let audio = match encode.audio_codec.as_deref() {
+        Some(codec) => codec,
+        None if stereo_downmix && probe.has_audio => "libopus",
+        None => "copy",
+    };
+    match audio {
+        "libopus" => row("audio", &format!("libopus {}k", opus_bitrate / 1000)),
+        _ => row("audio", &audio),
+    }
+
+    if let Some((also, out)) = &also {
+        row("also-encode", &format!("{} {}", also.vcodec, out.display()));
+    }
+    if let Some(viewing) = vmaf.target_viewing {
+        row("viewing", &viewing);
+    }
+    let min_vmaf = min_vmaf.unwrap_or_else(|| vmaf.default_min_vmaf());
+    row("min-vmaf", &TerseF32(min_vmaf));
+    row("vmaf-model", &vmaf.model_label(probe.display_resolution()));
+
+    let cmd = ffmpeg::encode_command(
+        enc_args,
+        &output,
+        probe.has_audio,
+        encode.audio_codec.as_deref(),
+        stereo_downmix,
+        opus_bitrate,
+        &encode.mux_flags,
+        also.as_ref().map(|(also, out)| (*also, out.as_path())),
+    )?;
+    let cmd = cmd.as_std();
+    let argv: Vec<_> = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(shell_arg)
+        .collect();
+    row("ffmpeg", &argv.join(" "));
+    Ok(())
+}
+
+fn join(args: &[Arc<String>]) -> String {
+    let args: Vec<_> = args
+        .iter()
+        .map(|a| shell_arg(a.as_ref().as_ref()))
+        .collect();
+    args.join(" ")
+}
+
+fn shell_arg(arg: &OsStr) -> String {
+    shell_escape::escape(arg.to_string_lossy()).into_owned()
+}
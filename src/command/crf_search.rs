@@ -1,20 +1,33 @@
 mod err;
+pub mod history;
+mod objective;
 
 pub use err::Error;
+pub use objective::Objective;
 
 use crate::{
-    command::{args, crf_search::err::ensure_or_no_good_crf, sample_encode, PROGRESS_CHARS},
+    cancel::{self, CancellationToken},
+    command::{
+        args,
+        crf_search::err::ensure_or_no_good_crf,
+        progress::{self, ProgressTree},
+        sample_encode, PROGRESS_CHARS,
+    },
     console_ext::style,
-    ffprobe,
+    ffmpeg, ffprobe,
     ffprobe::Ffprobe,
     float::TerseF32,
+    machine,
+    rendition::Rendition,
 };
-use clap::{ArgAction, Parser};
+use anyhow::Context;
+use clap::{ArgAction, Parser, ValueHint};
 use console::style;
 use err::ensure_other;
 use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
 use std::{
-    io::{self, IsTerminal},
+    fmt::Write,
+    path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
@@ -24,12 +37,15 @@ const BAR_LEN: u64 = 1_000_000_000;
 /// Interpolated binary search using sample-encode to find the best crf
 /// value delivering min-vmaf & max-encoded-percent.
 ///
+/// Multiple score targets may be combined, e.g. `--min-vmaf 95 --min-xpsnr 38`,
+/// requiring all to be met.
+///
 /// Outputs:
 /// * Best crf value
 /// * Mean sample VMAF score
 /// * Predicted full encode size
 /// * Predicted full encode time
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[clap(verbatim_doc_comment)]
 #[group(skip)]
 pub struct Args {
@@ -37,16 +53,61 @@ pub struct Args {
     pub args: args::Encode,
 
     /// Desired min VMAF score to deliver.
-    #[arg(long, default_value_t = 95.0)]
-    pub min_vmaf: f32,
+    ///
+    /// [default: 95, 93 with --target-viewing phone]
+    #[arg(long)]
+    pub min_vmaf: Option<f32>,
+
+    /// Desired min SSIM "All" score to deliver, e.g. 0.98.
+    ///
+    /// When set, samples are analysed with SSIM instead of VMAF, unless --min-vmaf is also set.
+    #[arg(long)]
+    pub min_ssim_all: Option<f32>,
+
+    /// Desired min SSIM "All" score to deliver in addition to --min-vmaf, e.g. 0.98.
+    ///
+    /// Samples are analysed with both VMAF & SSIM and must meet both targets.
+    #[arg(long, conflicts_with_all = ["min_ssim_all", "objective"])]
+    pub min_ssim: Option<f32>,
+
+    /// Desired min luma XPSNR score to deliver, e.g. 40.
+    ///
+    /// When set, samples are analysed with XPSNR instead of VMAF, unless --min-vmaf is also set.
+    /// Requires ffmpeg >= 7.1 with the xpsnr filter.
+    #[arg(long)]
+    pub min_xpsnr: Option<f32>,
+
+    /// Desired min SSIMULACRA2 score to deliver, e.g. 80.
+    ///
+    /// When set, samples are analysed with SSIMULACRA2 instead of VMAF, unless --min-vmaf
+    /// is also set. Requires `ssimulacra2_rs` in PATH.
+    #[arg(long)]
+    pub min_ssimulacra2: Option<f32>,
+
+    /// Composite search score of weighted VMAF & SSIM "All" (scaled x100) scores
+    /// targeting --min-vmaf, with an optional size constraint that overrides
+    /// --max-encoded-percent. E.g. `--objective "0.8*vmaf+0.2*ssim; size<=60%"`.
+    ///
+    /// Metrics: vmaf, vmaf_min, ssim.
+    #[arg(long, conflicts_with_all = ["min_ssim_all", "min_xpsnr", "min_ssimulacra2"])]
+    pub objective: Option<Objective>,
 
     /// Maximum desired encoded size percentage of the input size.
     #[arg(long, default_value_t = 80.0)]
     pub max_encoded_percent: f32,
 
+    /// Compare --max-encoded-percent against the input video stream size only,
+    /// excluding audio & other streams, e.g. for inputs with large lossless audio.
+    ///
+    /// Uses the whole input size if the video stream size is unknown.
+    #[arg(long)]
+    pub max_encoded_percent_video: bool,
+
     /// Minimum (highest quality) crf value to try.
-    #[arg(long, default_value_t = 10.0)]
-    pub min_crf: f32,
+    ///
+    /// [default: 10]
+    #[arg(long)]
+    pub min_crf: Option<f32>,
 
     /// Maximum (lowest quality) crf value to try.
     ///
@@ -67,6 +128,24 @@ pub struct Args {
     #[arg(long)]
     pub crf_increment: Option<f32>,
 
+    /// Interpolation used to pick the next crf to try between attempts.
+    ///
+    /// * linear: linear interpolation between the closest good & bad attempts.
+    /// * pchip: monotone cubic interpolation over all attempts, often converging in
+    ///   fewer attempts on content with a smooth crf->score curve.
+    ///   Falls back to linear if attempt scores aren't monotonic.
+    #[arg(long, value_enum, default_value_t = Interpolation::Linear)]
+    pub interpolation: Interpolation,
+
+    /// Warm-start the search by first searching with this fast proxy preset,
+    /// e.g. `--proxy-preset 12`.
+    ///
+    /// The proxy crf, adjusted by the crf offset learned from previous searches,
+    /// seeds a short search with the target --preset. Can significantly reduce
+    /// search time with slow presets.
+    #[arg(long)]
+    pub proxy_preset: Option<args::Preset>,
+
     /// Enable sample-encode caching.
     #[arg(
         long,
@@ -82,25 +161,294 @@ pub struct Args {
     #[clap(flatten)]
     pub vmaf: args::Vmaf,
 
+    /// Write each crf attempt as a csv row to this file, e.g. `--export-attempts attempts.csv`.
+    ///
+    /// Columns: crf, vmaf, vmaf_min_sample, ssim, xpsnr, ssimulacra2, encode_percent, predicted_encode_size, encode_fps, cache
+    ///
+    /// vmaf_min_sample is the lowest VMAF of any single sample, not the lowest frame score.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub export_attempts: Option<PathBuf>,
+
+    /// Don't print per-crf attempts. Set by the global --quiet.
     #[arg(skip)]
     pub quiet: bool,
+
+    /// Don't record the result in crf-search history, e.g. for scene chunks that
+    /// aren't representative of whole inputs. Set by scene-encode.
+    #[arg(skip)]
+    pub no_history_record: bool,
+}
+
+// crf-search command args, not a doc comment so the `Args` doc is used as the about.
+#[derive(Parser)]
+#[group(skip)]
+pub struct CommandArgs {
+    #[clap(flatten)]
+    pub search: Args,
+
+    /// Stdout result format `human`, `json` or `csv`. Set to json by the global --json.
+    ///
+    /// The csv first row is the result, any following rows are runner-up candidates.
+    ///
+    /// json includes a `rendition` of the predicted encode for packaging scripts:
+    /// RFC6381 `codecs`, `bandwidth` bits per second, `resolution` & `frame_rate`.
+    #[arg(long, value_enum, default_value_t = StdoutFormat::Human)]
+    pub stdout_format: StdoutFormat,
+}
+
+impl Args {
+    /// --min-vmaf or the --target-viewing default.
+    pub fn min_vmaf(&self) -> f32 {
+        self.min_vmaf
+            .unwrap_or_else(|| self.vmaf.default_min_vmaf())
+    }
+
+    /// Score targets that must all be met, the first being the primary target.
+    ///
+    /// VMAF is targeted if --min-vmaf or --min-ssim is set, or no other metric is.
+    pub fn targets(&self) -> Vec<Target> {
+        targets(
+            self.min_vmaf,
+            self.vmaf.default_min_vmaf(),
+            self.min_ssim_all,
+            self.min_ssim,
+            self.min_xpsnr,
+            self.min_ssimulacra2,
+        )
+    }
+
+    /// Min score the search targets, see [`Sample::score`].
+    pub fn min_score(&self) -> f32 {
+        self.targets()[0].min_score()
+    }
+
+    /// Max encoded percent the search targets, --objective size or --max-encoded-percent.
+    pub fn max_encoded_percent(&self) -> f32 {
+        self.objective
+            .as_ref()
+            .and_then(|o| o.max_encoded_percent)
+            .unwrap_or(self.max_encoded_percent)
+    }
 }
 
-pub async fn crf_search(mut args: Args) -> anyhow::Result<()> {
+impl Args {
+    /// If ffmpeg lacks libvmaf, fall back to a "degraded mode" SSIM target estimated
+    /// from --min-vmaf. Returns the degraded mode warning if used.
+    pub fn vmaf_fallback(&mut self) -> Option<String> {
+        if self.min_ssim_all.is_some()
+            || self.min_ssim.is_some()
+            || self.min_xpsnr.is_some()
+            || self.min_ssimulacra2.is_some()
+            || self.objective.is_some()
+            || ffmpeg::has_filter("libvmaf") != Some(false)
+        {
+            return None;
+        }
+        let min_vmaf = self.min_vmaf();
+        let min_ssim = vmaf_to_ssim_estimate(min_vmaf);
+        self.min_ssim_all = Some(min_ssim);
+        // replace the vmaf target
+        self.min_vmaf = None;
+        Some(vmaf_fallback_warning(min_ssim, min_vmaf))
+    }
+}
+
+/// Rough SSIM "All" equivalent of a VMAF score, used when libvmaf is unavailable.
+fn vmaf_to_ssim_estimate(vmaf: f32) -> f32 {
+    const POINTS: &[(f32, f32)] = &[
+        (80.0, 0.95),
+        (90.0, 0.97),
+        (95.0, 0.98),
+        (97.0, 0.985),
+        (99.0, 0.99),
+    ];
+    let (first, last) = (POINTS[0], POINTS[POINTS.len() - 1]);
+    if vmaf <= first.0 {
+        return first.1;
+    }
+    POINTS
+        .windows(2)
+        .find(|w| vmaf <= w[1].0)
+        .map(|w| {
+            let ((v0, s0), (v1, s1)) = (w[0], w[1]);
+            s0 + (s1 - s0) * (vmaf - v0) / (v1 - v0)
+        })
+        .unwrap_or(last.1)
+}
+
+/// Degraded mode warning & SSIM target message.
+fn vmaf_fallback_warning(min_ssim: f32, min_vmaf: f32) -> String {
+    format!(
+        "{} libvmaf unavailable, degraded mode: targeting SSIM All {min_ssim:.3} \
+         estimated from --min-vmaf {min_vmaf} with bits-per-pixel checks",
+        style("Warning:").yellow().bold(),
+    )
+}
+
+/// Predicted bits per pixel of the encoded video stream.
+fn bits_per_pixel(predicted_encode_size: u64, probe: &Ffprobe) -> Option<f64> {
+    let (w, h) = probe.resolution?;
+    let frames = probe.nframes().ok()?;
+    Some(predicted_encode_size as f64 * 8.0 / (f64::from(w) * f64::from(h) * frames as f64))
+}
+
+/// Packaging rendition of the predicted encode. `None` for images or unknown durations.
+pub fn rendition(args: &args::Encode, best: &Sample, probe: &Ffprobe) -> Option<Rendition> {
+    let duration = probe.duration.clone().ok().filter(|_| !probe.is_image)?;
+    let pix_fmt = args.to_encoder_args(best.crf(), probe).ok()?.pix_fmt;
+    Some(Rendition::new(
+        args.encoder.as_str(),
+        pix_fmt,
+        best.enc.encoded_resolution.or(probe.resolution),
+        probe.fps.clone().ok(),
+        best.enc.predicted_encode_size,
+        duration,
+    ))
+}
+
+/// Degraded mode bits-per-pixel note, warning if likely too low quality.
+pub fn bits_per_pixel_note(predicted_encode_size: u64, probe: &Ffprobe) -> Option<String> {
+    /// Below this video streams are likely visibly low quality.
+    const LOW_BPP: f64 = 0.02;
+
+    let bpp = bits_per_pixel(predicted_encode_size, probe)?;
+    Some(match bpp < LOW_BPP {
+        true => format!(
+            "{} degraded mode: {bpp:.3} bits per pixel is very low, quality may be poor",
+            style("Warning:").yellow().bold(),
+        ),
+        false => style!("degraded mode: {bpp:.3} bits per pixel")
+            .dim()
+            .to_string(),
+    })
+}
+
+fn targets(
+    min_vmaf: Option<f32>,
+    default_min_vmaf: f32,
+    min_ssim_all: Option<f32>,
+    min_ssim: Option<f32>,
+    min_xpsnr: Option<f32>,
+    min_ssimulacra2: Option<f32>,
+) -> Vec<Target> {
+    let others = [
+        min_ssim_all.or(min_ssim).map(Target::Ssim),
+        min_xpsnr.map(Target::Xpsnr),
+        min_ssimulacra2.map(Target::Ssimulacra2),
+    ];
+    let vmaf = (min_vmaf.is_some() || min_ssim.is_some() || others.iter().all(Option::is_none))
+        .then(|| Target::Vmaf(min_vmaf.unwrap_or(default_min_vmaf)));
+    vmaf.into_iter()
+        .chain(others.into_iter().flatten())
+        .collect()
+}
+
+/// A min score search target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Vmaf(f32),
+    /// SSIM "All".
+    Ssim(f32),
+    /// Luma XPSNR.
+    Xpsnr(f32),
+    Ssimulacra2(f32),
+}
+
+impl Target {
+    /// Ssim scores are scaled x100 towards the vmaf range.
+    /// Xpsnr (dB) & ssimulacra2 scores are used as is.
+    fn scale(self) -> f32 {
+        match self {
+            Self::Ssim(_) => 100.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Scaled "higher score" search tolerance for the vmaf `tolerance`.
+    ///
+    /// Ssim x100 scores vary much less with crf than vmaf, e.g. 98.8 -> 97.5 where
+    /// vmaf drops 97 -> 90, so use a tenth of the vmaf tolerance.
+    fn tolerance(self, tolerance: f32) -> f32 {
+        match self {
+            Self::Ssim(_) => tolerance / 10.0,
+            _ => tolerance,
+        }
+    }
+
+    fn min(self) -> f32 {
+        match self {
+            Self::Vmaf(min) | Self::Ssim(min) | Self::Xpsnr(min) | Self::Ssimulacra2(min) => min,
+        }
+    }
+
+    /// Scaled min score, see [`Sample::score`].
+    fn min_score(self) -> f32 {
+        self.min() * self.scale()
+    }
+
+    /// The sample's unscaled score for this target's metric.
+    fn value(self, enc: &sample_encode::Output) -> f32 {
+        match self {
+            Self::Vmaf(_) => enc.vmaf,
+            Self::Ssim(_) => enc.ssim.or(enc.vmaf_ssim).unwrap_or(0.0),
+            Self::Xpsnr(_) => enc.xpsnr.unwrap_or(0.0),
+            Self::Ssimulacra2(_) => enc.ssimulacra2.unwrap_or(0.0),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Vmaf(_) => "VMAF",
+            Self::Ssim(_) => "SSIM All",
+            Self::Xpsnr(_) => "XPSNR",
+            Self::Ssimulacra2(_) => "SSIMULACRA2",
+        }
+    }
+
+    /// Formatted sample score, e.g. "95.12" or ssim "0.9812".
+    fn format_value(self, enc: &sample_encode::Output) -> String {
+        match self {
+            Self::Ssim(_) => format!("{:.4}", self.value(enc)),
+            _ => format!("{:.2}", self.value(enc)),
+        }
+    }
+}
+
+pub async fn crf_search(
+    CommandArgs {
+        search: mut args,
+        stdout_format,
+    }: CommandArgs,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
     let bar = ProgressBar::new(12).with_style(
         ProgressStyle::default_bar()
             .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
             .progress_chars(PROGRESS_CHARS)
     );
+    machine::watch(&bar);
 
-    let probe = ffprobe::probe(&args.args.input);
+    let probe = Arc::new(ffprobe::probe(&args.args.input));
+    args.args.resolve_encoder()?;
+    args.args.apply_sar(&probe);
+    args.args.apply_detelecine(&probe);
     let input_is_image = probe.is_image;
     args.sample
-        .set_extension_from_input(&args.args.input, &probe);
+        .set_extension_from_input(&args.args.input, &args.args.encoder, &probe);
+    let degraded = args.vmaf_fallback();
+    if let Some(warning) = &degraded {
+        progress::println(&bar, warning.clone());
+    }
 
-    let best = run(&args, probe.into(), bar.clone()).await;
+    let mut attempts = vec![];
+    let best = run_attempts(&args, probe.clone(), bar.clone(), &cancel, &mut attempts).await;
     bar.finish();
     let best = best?;
+    if degraded.is_some() {
+        if let Some(note) = bits_per_pixel_note(best.enc.predicted_encode_size, &probe) {
+            eprintln!("{note}");
+        }
+    }
 
     // encode how-to hint + predictions
     eprintln!(
@@ -109,38 +457,229 @@ pub async fn crf_search(mut args: Args) -> anyhow::Result<()> {
         style(args.args.encode_hint(best.crf())).dim().italic(),
     );
 
-    StdoutFormat::Human.print_result(&best, input_is_image);
+    let candidates = candidates(
+        &best,
+        &attempts,
+        args.min_score(),
+        args.max_encoded_percent(),
+    );
+    let rendition = rendition(&args.args, &best, &probe);
+    stdout_format.print_result(&best, &candidates, rendition.as_ref(), input_is_image);
 
     Ok(())
 }
 
 pub async fn run(
+    args: &Args,
+    input_probe: Arc<Ffprobe>,
+    bar: ProgressBar,
+    cancel: &CancellationToken,
+) -> Result<Sample, Error> {
+    run_attempts(args, input_probe, bar, cancel, &mut vec![]).await
+}
+
+/// Run the search collecting all `attempts`.
+async fn run_attempts(
+    args: &Args,
+    input_probe: Arc<Ffprobe>,
+    bar: ProgressBar,
+    cancel: &CancellationToken,
+    attempts: &mut Vec<Sample>,
+) -> Result<Sample, Error> {
+    args.args.ensure_crf_rate_control()?;
+
+    let history_key =
+        (args.cache && matches!(args.targets()[..], [Target::Vmaf(_)]) && args.objective.is_none())
+            .then(|| history::Key::new(&args.args, &args.vmaf, args.min_vmaf(), &input_probe));
+    let prior_crf = match &history_key {
+        Some(key) => history::prior_crf(key.clone()).await,
+        None => None,
+    };
+
+    let sample_bar = ProgressTree::new(bar.clone()).add_task();
+
+    let proxy = match &args.proxy_preset {
+        Some(proxy) if args.args.preset.as_ref() != Some(proxy) => {
+            let crf =
+                proxy_search(args, proxy, input_probe.clone(), &bar, &sample_bar, cancel).await?;
+            Some((proxy, crf))
+        }
+        _ => None,
+    };
+    let initial_crf = match (proxy, &history_key) {
+        (Some((proxy, crf)), Some(key)) => {
+            Some(crf + history::prior_proxy_offset(key, proxy).await.unwrap_or(0.0))
+        }
+        (Some((_, crf)), None) => Some(crf),
+        (None, _) => prior_crf,
+    };
+
+    let best = search(
+        args,
+        input_probe,
+        bar.clone(),
+        sample_bar.clone(),
+        cancel,
+        initial_crf,
+        attempts,
+    )
+    .await;
+    sample_bar.finish_and_clear();
+    if let Some(path) = &args.export_attempts {
+        write_attempts_csv(path, attempts).context("--export-attempts")?;
+    }
+    let best = best?;
+
+    if let Some(key) = history_key.filter(|_| !args.no_history_record) {
+        let encode_percent = best.enc.encode_percent as f32;
+        if let Some(median) = history::prior_encode_percent(key.clone()).await {
+            if history::is_size_regression(encode_percent, median) {
+                let warning = format!(
+                    "{} predicted size {encode_percent:.0}% is much larger than the {median:.0}% \
+                     median of previous similar searches, possible encoder regression",
+                    style("Warning:").yellow().bold(),
+                );
+                progress::println(&bar, warning);
+            }
+        }
+        if let Some((proxy, proxy_crf)) = proxy {
+            history::record_proxy_offset(&key, proxy, best.crf() - proxy_crf).await;
+        }
+        history::record(key, best.crf(), encode_percent).await;
+    }
+    Ok(best)
+}
+
+/// Search using the `proxy_preset` returning the crf found, or the last crf
+/// attempted if no crf satisfied the search constraints.
+async fn proxy_search(
+    args: &Args,
+    proxy_preset: &args::Preset,
+    input_probe: Arc<Ffprobe>,
+    bar: &ProgressBar,
+    sample_bar: &ProgressBar,
+    cancel: &CancellationToken,
+) -> Result<f32, Error> {
+    let mut proxy_args = args.clone();
+    proxy_args.args.preset = Some(proxy_preset.clone());
+    proxy_args.quiet = true;
+
+    let crf = match search(
+        &proxy_args,
+        input_probe,
+        bar.clone(),
+        sample_bar.clone(),
+        cancel,
+        None,
+        &mut vec![],
+    )
+    .await
+    {
+        Ok(sample) => sample.crf(),
+        Err(Error::NoGoodCrf { last }) => last.crf(),
+        Err(err) => return Err(err),
+    };
+    if !args.quiet {
+        progress::println(
+            bar,
+            style!("proxy preset {proxy_preset} crf {}", TerseF32(crf))
+                .dim()
+                .to_string(),
+        );
+    }
+    Ok(crf)
+}
+
+/// Search for the best crf. Starts at `initial_crf`, if provided, otherwise the middle crf.
+async fn search(
     Args {
         args,
         min_vmaf,
+        min_ssim_all,
+        min_ssim,
+        min_xpsnr,
+        min_ssimulacra2,
+        objective,
         max_encoded_percent,
+        max_encoded_percent_video,
         min_crf,
         max_crf,
         crf_increment,
+        interpolation,
+        proxy_preset: _,
         thorough,
         sample,
         quiet,
         cache,
         vmaf,
+        export_attempts: _,
+        no_history_record: _,
     }: &Args,
     input_probe: Arc<Ffprobe>,
     bar: ProgressBar,
+    sample_bar: ProgressBar,
+    cancel: &CancellationToken,
+    initial_crf: Option<f32>,
+    crf_attempts: &mut Vec<Sample>,
 ) -> Result<Sample, Error> {
+    let min_crf = min_crf.unwrap_or_else(|| args.encoder.default_min_crf());
     let max_crf = max_crf.unwrap_or_else(|| args.encoder.default_max_crf());
-    ensure_other!(*min_crf < max_crf, "Invalid --min-crf & --max-crf");
+    ensure_other!(min_crf < max_crf, "Invalid --min-crf & --max-crf");
+    if let Some(range) = args.encoder.crf_range(args.pix_fmt()) {
+        ensure_other!(
+            range.contains(&min_crf) && range.contains(&max_crf),
+            format!(
+                "--min-crf & --max-crf must be within {}-{} for {}",
+                range.start(),
+                range.end(),
+                args.encoder.as_str()
+            )
+        );
+    }
+    ensure_other!(
+        min_ssim_all.is_none_or(|s| (0.0..=1.0).contains(&s)),
+        "Invalid --min-ssim-all, expected 0-1"
+    );
+    ensure_other!(
+        min_ssim.is_none_or(|s| (0.0..=1.0).contains(&s)),
+        "Invalid --min-ssim, expected 0-1"
+    );
+    ensure_other!(
+        min_xpsnr.is_none_or(|s| (0.0..=100.0).contains(&s)),
+        "Invalid --min-xpsnr, expected 0-100"
+    );
+    ensure_other!(
+        min_ssimulacra2.is_none_or(|s| s <= 100.0),
+        "Invalid --min-ssimulacra2, expected at most 100"
+    );
+    let targets = targets(
+        *min_vmaf,
+        vmaf.default_min_vmaf(),
+        *min_ssim_all,
+        *min_ssim,
+        *min_xpsnr,
+        *min_ssimulacra2,
+    );
+    let min_score = targets[0].min_score();
+    let with_vmaf = matches!(targets[0], Target::Vmaf(_));
+    let max_encoded_percent = objective
+        .as_ref()
+        .and_then(|o| o.max_encoded_percent)
+        .unwrap_or(*max_encoded_percent);
 
     let crf_increment = crf_increment
         .unwrap_or_else(|| args.encoder.default_crf_increment())
         .max(0.001);
 
-    let min_q = q_from_crf(*min_crf, crf_increment);
+    let min_q = q_from_crf(min_crf, crf_increment);
     let max_q = q_from_crf(max_crf, crf_increment);
-    let mut q: u64 = (min_q + max_q) / 2;
+    let mut q: u64 = match initial_crf {
+        // use a previously learned crf to start closer to the answer
+        Some(crf) => q_from_crf(crf, crf_increment).clamp(min_q, max_q),
+        None => (min_q + max_q) / 2,
+    };
+    // A learned initial crf should be close, so take smaller first steps away from it
+    let first_step = initial_crf.map(|_| ((max_q - min_q) / 8).max(1));
 
     let mut args = sample_encode::Args {
         args: args.clone(),
@@ -149,33 +688,44 @@ pub async fn run(
         cache: *cache,
         stdout_format: sample_encode::StdoutFormat::Json,
         vmaf: vmaf.clone(),
+        ssim: min_ssim_all.is_some(),
+        xpsnr: min_xpsnr.is_some(),
+        ssimulacra2: min_ssimulacra2.is_some(),
+        with_ssim: min_ssim.is_some() || objective.as_ref().is_some_and(|o| o.uses_ssim()),
+        with_vmaf,
+        quiet: true,
+        chapters: vec![],
+        summary: None,
+        reuse_encodes: None,
+        report: None,
     };
 
     bar.set_length(BAR_LEN);
-    let sample_bar = ProgressBar::hidden();
-    let mut crf_attempts = Vec::new();
 
     for run in 1.. {
         // how much we're prepared to go higher than the min-vmaf
-        let higher_tolerance = match thorough {
+        let higher_tolerance = targets[0].tolerance(match thorough {
             true => 0.05,
             // increment 1.0 => +0.1, +0.2, +0.4, +0.8 ..
             // increment 0.1 => +0.1, +0.1, +0.1, +0.16 ..
             _ => (crf_increment * 2_f32.powi(run as i32 - 1) * 0.1).max(0.1),
-        };
+        });
         args.crf = q.to_crf(crf_increment);
         bar.set_message(format!("sampling crf {}, ", TerseF32(args.crf)));
-        let mut sample_task = tokio::task::spawn_local(sample_encode::run(
-            args.clone(),
-            input_probe.clone(),
-            sample_bar.clone(),
-        ));
+        let mut sample_task = cancel::spawn_local(
+            cancel,
+            sample_encode::run(
+                args.clone(),
+                input_probe.clone(),
+                sample_bar.clone(),
+                cancel.clone(),
+            ),
+        );
 
         let sample_task = loop {
             match tokio::time::timeout(Duration::from_millis(100), &mut sample_task).await {
                 Err(_) => {
-                    let sample_progress = sample_bar.position() as f64
-                        / sample_bar.length().unwrap_or(1).max(1) as f64;
+                    let sample_progress = ProgressTree::task_fraction(&sample_bar);
                     bar.set_position(guess_progress(run, sample_progress, *thorough) as _);
                 }
                 Ok(o) => {
@@ -185,18 +735,22 @@ pub async fn run(
             }
         };
 
+        let enc = sample_task?.ok_or_else(|| anyhow::Error::from(machine::Interrupted))??;
         let sample = Sample {
             crf_increment,
             q,
-            enc: sample_task??,
+            objective: objective.as_ref().map(|o| o.score(&enc)),
+            targets: targets.clone(),
+            video_only_percent: *max_encoded_percent_video,
+            enc,
         };
         let from_cache = sample.enc.from_cache;
         crf_attempts.push(sample.clone());
-        let sample_small_enough = sample.enc.encode_percent <= *max_encoded_percent as _;
+        let sample_small_enough = sample.capped_percent() <= max_encoded_percent as _;
 
-        if sample.enc.vmaf > *min_vmaf {
+        if sample.score() > min_score {
             // good
-            if sample_small_enough && sample.enc.vmaf < min_vmaf + higher_tolerance {
+            if sample_small_enough && sample.score() < min_score + higher_tolerance {
                 return Ok(sample);
             }
             let u_bound = crf_attempts
@@ -210,21 +764,24 @@ pub async fn run(
                     return Ok(sample);
                 }
                 Some(upper) => {
-                    q = vmaf_lerp_q(*min_vmaf, upper, &sample);
+                    q = interpolation.next_q(min_score, crf_attempts, upper, &sample);
                 }
                 None if sample.q == max_q => {
                     ensure_or_no_good_crf!(sample_small_enough, sample);
                     return Ok(sample);
                 }
                 None if run == 1 && sample.q + 1 < max_q => {
-                    q = (sample.q + max_q) / 2;
+                    q = match first_step {
+                        Some(step) => (sample.q + step).min(max_q),
+                        None => (sample.q + max_q) / 2,
+                    };
                 }
                 None => q = max_q,
             };
         } else {
             // not good enough
             if !sample_small_enough || sample.q == min_q {
-                sample.print_attempt(&bar, *min_vmaf, *max_encoded_percent, *quiet, from_cache);
+                sample.print_attempt(&bar, min_score, max_encoded_percent, *quiet, from_cache);
                 ensure_or_no_good_crf!(false, sample);
             }
 
@@ -235,30 +792,85 @@ pub async fn run(
 
             match l_bound {
                 Some(lower) if lower.q + 1 == sample.q => {
-                    sample.print_attempt(&bar, *min_vmaf, *max_encoded_percent, *quiet, from_cache);
-                    let lower_small_enough = lower.enc.encode_percent <= *max_encoded_percent as _;
+                    sample.print_attempt(&bar, min_score, max_encoded_percent, *quiet, from_cache);
+                    let lower_small_enough = lower.capped_percent() <= max_encoded_percent as _;
                     ensure_or_no_good_crf!(lower_small_enough, sample);
                     return Ok(lower.clone());
                 }
                 Some(lower) => {
-                    q = vmaf_lerp_q(*min_vmaf, &sample, lower);
+                    q = interpolation.next_q(min_score, crf_attempts, &sample, lower);
                 }
                 None if run == 1 && sample.q > min_q + 1 => {
-                    q = (min_q + sample.q) / 2;
+                    q = match first_step {
+                        Some(step) => sample.q.saturating_sub(step).max(min_q),
+                        None => (min_q + sample.q) / 2,
+                    };
                 }
                 None => q = min_q,
             };
         }
-        sample.print_attempt(&bar, *min_vmaf, *max_encoded_percent, *quiet, from_cache);
+        sample.print_attempt(&bar, min_score, max_encoded_percent, *quiet, from_cache);
     }
     unreachable!();
 }
 
+/// Runner-up attempts, other than `best`, that also satisfy the score & size
+/// constraints, ordered by crf (highest quality first).
+fn candidates(
+    best: &Sample,
+    attempts: &[Sample],
+    min_score: f32,
+    max_encoded_percent: f32,
+) -> Vec<Sample> {
+    let mut candidates: Vec<_> = attempts
+        .iter()
+        .filter(|s| s.q != best.q)
+        .filter(|s| s.score() >= min_score)
+        .filter(|s| s.capped_percent() <= max_encoded_percent as _)
+        .cloned()
+        .collect();
+    candidates.sort_by_key(|s| s.q);
+    candidates.dedup_by_key(|s| s.q);
+    candidates
+}
+
+fn write_attempts_csv(path: &Path, attempts: &[Sample]) -> anyhow::Result<()> {
+    let mut csv = String::from(
+        "crf,vmaf,vmaf_min_sample,ssim,xpsnr,ssimulacra2,encode_percent,predicted_encode_size,encode_fps,cache\n",
+    );
+    for sample in attempts {
+        let enc = &sample.enc;
+        let (vmaf, vmaf_min_sample) = match enc.has_vmaf() {
+            true => (enc.vmaf.to_string(), enc.vmaf_min.to_string()),
+            false => (String::new(), String::new()),
+        };
+        let ssim = enc.ssim.map(|s| s.to_string()).unwrap_or_default();
+        let xpsnr = enc.xpsnr.map(|s| s.to_string()).unwrap_or_default();
+        let ssimulacra2 = enc.ssimulacra2.map(|s| s.to_string()).unwrap_or_default();
+        writeln!(
+            csv,
+            "{},{vmaf},{vmaf_min_sample},{ssim},{xpsnr},{ssimulacra2},{:.2},{},{:.2},{}",
+            TerseF32(sample.crf()),
+            enc.encode_percent,
+            enc.predicted_encode_size,
+            enc.encode_fps,
+            enc.from_cache,
+        )?;
+    }
+    std::fs::write(path, csv).with_context(|| format!("write {}", path.display()))
+}
+
 #[derive(Debug, Clone)]
 pub struct Sample {
     pub enc: sample_encode::Output,
     pub crf_increment: f32,
     pub q: u64,
+    /// --objective score, if used.
+    pub objective: Option<f32>,
+    /// Score targets, see [`Args::targets`].
+    pub targets: Vec<Target>,
+    /// Compare the video stream only encode percent with --max-encoded-percent.
+    pub video_only_percent: bool,
 }
 
 impl Sample {
@@ -266,10 +878,66 @@ impl Sample {
         self.q.to_crf(self.crf_increment)
     }
 
+    /// Encode percent compared with --max-encoded-percent.
+    pub fn capped_percent(&self) -> f64 {
+        self.enc.capped_percent(self.video_only_percent)
+    }
+
+    /// Score used for the search, objective, vmaf, ssim scaled to the same 0-100 range,
+    /// xpsnr or ssimulacra2.
+    ///
+    /// With multiple targets, the primary target's min score plus the smallest scaled
+    /// margin above any target.
+    pub fn score(&self) -> f32 {
+        if let Some(objective) = self.objective {
+            return objective;
+        }
+        if let [primary, _, ..] = self.targets[..] {
+            let margin = self
+                .targets
+                .iter()
+                .map(|t| (t.value(&self.enc) - t.min()) * t.scale())
+                .fold(f32::INFINITY, f32::min);
+            return primary.min_score() + margin;
+        }
+        match (self.enc.ssim, self.enc.xpsnr, self.enc.ssimulacra2) {
+            (Some(ssim), ..) => ssim * 100.0,
+            (_, Some(xpsnr), _) => xpsnr,
+            (.., Some(ssimulacra2)) => ssimulacra2,
+            _ => self.enc.vmaf,
+        }
+    }
+
+    /// E.g. "VMAF 95.12", "SSIM All 0.9812", "XPSNR 40.25", "SSIMULACRA2 80.12"
+    /// or "Objective 95.34".
+    pub fn score_label(&self) -> String {
+        if let Some(objective) = self.objective {
+            return format!("Objective {objective:.2}");
+        }
+        if self.targets.len() > 1 {
+            return self.target_scores(|value| value);
+        }
+        match (self.enc.ssim, self.enc.xpsnr, self.enc.ssimulacra2) {
+            (Some(ssim), ..) => format!("SSIM All {ssim:.4}"),
+            (_, Some(xpsnr), _) => format!("XPSNR {xpsnr:.2}"),
+            (.., Some(s2)) => format!("SSIMULACRA2 {s2:.2}"),
+            _ => format!("VMAF {:.2}", self.enc.vmaf),
+        }
+    }
+
+    /// Each target's score, e.g. "VMAF 95.12 XPSNR 40.25", formatting values with `fmt`.
+    fn target_scores(&self, fmt: impl Fn(String) -> String) -> String {
+        self.targets
+            .iter()
+            .map(|t| format!("{} {}", t.name(), fmt(t.format_value(&self.enc))))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     fn print_attempt(
         &self,
         bar: &ProgressBar,
-        min_vmaf: f32,
+        min_score: f32,
         max_encoded_percent: f32,
         quiet: bool,
         from_cache: bool,
@@ -279,9 +947,32 @@ impl Sample {
         }
         let crf_label = style("- crf").dim();
         let mut crf = style(TerseF32(self.crf()));
-        let vmaf_label = style("VMAF").dim();
-        let mut vmaf = style(self.enc.vmaf);
+        let (vmaf_label, mut vmaf) = match (self.enc.ssim, self.enc.xpsnr, self.enc.ssimulacra2) {
+            _ if self.objective.is_some() => (
+                style("Objective").dim(),
+                style(format!("{:.2}", self.score())),
+            ),
+            _ if self.targets.len() > 1 => {
+                let (first, rest) = (self.targets[0], &self.targets[1..]);
+                let mut value = first.format_value(&self.enc);
+                for t in rest {
+                    value += &format!(" {} {}", t.name(), t.format_value(&self.enc));
+                }
+                (style(first.name()).dim(), style(value))
+            }
+            (Some(ssim), ..) => (style("SSIM All").dim(), style(format!("{ssim:.4}"))),
+            (_, Some(xpsnr), _) => (style("XPSNR").dim(), style(format!("{xpsnr:.2}"))),
+            (.., Some(s2)) => (style("SSIMULACRA2").dim(), style(format!("{s2:.2}"))),
+            _ => (style("VMAF").dim(), style(format!("{:.2}", self.enc.vmaf))),
+        };
         let mut percent = style!("{:.0}%", self.enc.encode_percent);
+        let mut normalized = match self.enc.resolution_normalized_percent() {
+            Some(p) => style!(", {p:.0}% per-pixel").dim().to_string(),
+            None => String::new(),
+        };
+        if let Some(p) = self.enc.video_only_percent() {
+            normalized += &style!(", {p:.0}% of input video").dim().to_string();
+        }
         let open = style("(").dim();
         let close = style(")").dim();
         let cache_msg = match from_cache {
@@ -289,52 +980,123 @@ impl Sample {
             false => style(""),
         };
 
-        if self.enc.vmaf < min_vmaf {
+        if self.score() < min_score {
             crf = crf.red().bright();
             vmaf = vmaf.red().bright();
         }
-        if self.enc.encode_percent > max_encoded_percent as _ {
+        if self.capped_percent() > max_encoded_percent as _ {
             crf = crf.red().bright();
             percent = percent.red().bright();
         }
 
-        let msg =
-            format!("{crf_label} {crf} {vmaf_label} {vmaf:.2} {open}{percent}{close}{cache_msg}");
-        if io::stderr().is_terminal() {
-            bar.println(msg);
-        } else {
-            eprintln!("{msg}");
-        }
+        let msg = format!(
+            "{crf_label} {crf} {vmaf_label} {vmaf} {open}{percent}{normalized}{close}{cache_msg}"
+        );
+        progress::println(bar, msg);
     }
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
 pub enum StdoutFormat {
     Human,
+    Json,
+    Csv,
 }
 
 impl StdoutFormat {
-    fn print_result(self, sample: &Sample, image: bool) {
+    fn print_result(
+        self,
+        sample: &Sample,
+        candidates: &[Sample],
+        rendition: Option<&Rendition>,
+        image: bool,
+    ) {
         match self {
             Self::Human => {
                 let crf = style(TerseF32(sample.crf())).bold().green();
                 let enc = &sample.enc;
-                let vmaf = style(enc.vmaf).bold().green();
+                let score = match (enc.ssim, enc.xpsnr, enc.ssimulacra2) {
+                    _ if sample.objective.is_some() => {
+                        format!("Objective {:.2}", style(sample.score()).bold().green())
+                    }
+                    _ if sample.targets.len() > 1 => {
+                        sample.target_scores(|value| style(value).bold().green().to_string())
+                    }
+                    (Some(ssim), ..) => format!("SSIM All {:.4}", style(ssim).bold().green()),
+                    (_, Some(xpsnr), _) => format!("XPSNR {:.2}", style(xpsnr).bold().green()),
+                    (.., Some(s2)) => format!("SSIMULACRA2 {:.2}", style(s2).bold().green()),
+                    _ => format!("VMAF {:.2}", style(enc.vmaf).bold().green()),
+                };
                 let size = style(HumanBytes(enc.predicted_encode_size)).bold().green();
                 let percent = style!("{}%", enc.encode_percent.round()).bold().green();
+                let mut normalized = match enc.resolution_normalized_percent() {
+                    Some(p) => style!(", {p:.0}% per-pixel").dim().to_string(),
+                    None => String::new(),
+                };
+                if let Some(p) = enc.video_only_percent() {
+                    normalized += &style!(", {p:.0}% of input video").dim().to_string();
+                }
                 let time = style(HumanDuration(enc.predicted_encode_time)).bold();
                 let enc_description = match image {
                     true => "image",
                     false => "video stream",
                 };
                 println!(
-                    "crf {crf} VMAF {vmaf:.2} predicted {enc_description} size {size} ({percent}) taking {time}"
+                    "crf {crf} {score} predicted {enc_description} size {size} ({percent}{normalized}) taking {time}"
                 );
+                if let Some(stddev) = enc.vmaf_stddev {
+                    eprintln!("{}", style!("run-to-run VMAF stddev {stddev:.2}").dim());
+                }
+            }
+            Self::Json => {
+                let to_json = |sample: &Sample| {
+                    let mut json = sample.enc.to_json();
+                    json["crf"] = sample.crf().into();
+                    if let Some(objective) = sample.objective {
+                        json["objective"] = objective.into();
+                    }
+                    json
+                };
+                let mut json = to_json(sample);
+                if let Some(rendition) = rendition {
+                    json["rendition"] = rendition.to_json();
+                }
+                json["candidates"] = candidates.iter().map(to_json).collect();
+                machine::print_json(&json);
             }
+            Self::Csv => print!("{}", csv(sample, candidates)),
         }
     }
 }
 
+/// Csv header, result row & candidate rows.
+fn csv(sample: &Sample, candidates: &[Sample]) -> String {
+    let mut csv = String::from(
+        "crf,vmaf,ssim,xpsnr,ssimulacra2,objective,predicted_encode_size,predicted_encode_percent,predicted_encode_seconds\n",
+    );
+    let opt = |v: Option<f32>| v.map(|v| v.to_string()).unwrap_or_default();
+    for sample in std::iter::once(sample).chain(candidates) {
+        let enc = &sample.enc;
+        let vmaf = match enc.has_vmaf() {
+            true => enc.vmaf.to_string(),
+            false => String::new(),
+        };
+        _ = writeln!(
+            csv,
+            "{},{vmaf},{},{},{},{},{},{:.2},{}",
+            TerseF32(sample.crf()),
+            opt(enc.ssim.or(enc.vmaf_ssim)),
+            opt(enc.xpsnr),
+            opt(enc.ssimulacra2),
+            opt(sample.objective),
+            enc.predicted_encode_size,
+            enc.encode_percent,
+            enc.predicted_encode_time.as_secs(),
+        );
+    }
+    csv
+}
+
 /// Produce a q value between given samples using vmaf score linear interpolation
 /// so the output q value should produce the `min_vmaf`.
 ///
@@ -348,20 +1110,93 @@ impl StdoutFormat {
 /// This would be helpful particularly for small crf-increments.
 fn vmaf_lerp_q(min_vmaf: f32, worse_q: &Sample, better_q: &Sample) -> u64 {
     assert!(
-        worse_q.enc.vmaf <= min_vmaf
-            && worse_q.enc.vmaf < better_q.enc.vmaf
-            && worse_q.q > better_q.q,
+        worse_q.score() <= min_vmaf && worse_q.score() < better_q.score() && worse_q.q > better_q.q,
         "invalid vmaf_lerp_crf usage: ({min_vmaf}, {worse_q:?}, {better_q:?})"
     );
 
-    let vmaf_diff = better_q.enc.vmaf - worse_q.enc.vmaf;
-    let vmaf_factor = (min_vmaf - worse_q.enc.vmaf) / vmaf_diff;
+    let vmaf_diff = better_q.score() - worse_q.score();
+    let vmaf_factor = (min_vmaf - worse_q.score()) / vmaf_diff;
 
     let q_diff = worse_q.q - better_q.q;
     let lerp = (worse_q.q as f32 - q_diff as f32 * vmaf_factor).round() as u64;
     lerp.clamp(better_q.q + 1, worse_q.q - 1)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Interpolation {
+    Linear,
+    Pchip,
+}
+
+impl Interpolation {
+    /// Produce a q value between the bounding samples that should produce the `min_score`.
+    fn next_q(
+        self,
+        min_score: f32,
+        attempts: &[Sample],
+        worse_q: &Sample,
+        better_q: &Sample,
+    ) -> u64 {
+        match self {
+            Self::Linear => vmaf_lerp_q(min_score, worse_q, better_q),
+            Self::Pchip => pchip_q(min_score, attempts)
+                .map(|q| q.clamp(better_q.q + 1, worse_q.q - 1))
+                .unwrap_or_else(|| vmaf_lerp_q(min_score, worse_q, better_q)),
+        }
+    }
+}
+
+/// Produce a q value using monotone cubic (PCHIP, Fritsch-Carlson) interpolation
+/// of q as a function of score over all attempts.
+///
+/// Returns `None` if there are fewer than 3 attempts or scores aren't strictly
+/// decreasing with increasing q.
+fn pchip_q(min_score: f32, attempts: &[Sample]) -> Option<u64> {
+    let mut points: Vec<(f64, f64)> = attempts
+        .iter()
+        .map(|s| (f64::from(s.score()), s.q as f64))
+        .collect();
+    points.sort_by(|a, b| a.1.total_cmp(&b.1));
+    points.dedup_by(|a, b| a.1 == b.1);
+    // x: score ascending, y: q descending
+    points.reverse();
+    if points.len() < 3 || points.windows(2).any(|w| w[1].0 <= w[0].0) {
+        return None;
+    }
+
+    let x = f64::from(min_score);
+    let k = points.windows(2).position(|w| w[0].0 <= x && x <= w[1].0)?;
+
+    let h: Vec<f64> = points.windows(2).map(|w| w[1].0 - w[0].0).collect();
+    let d: Vec<f64> = points
+        .windows(2)
+        .zip(&h)
+        .map(|(w, h)| (w[1].1 - w[0].1) / h)
+        .collect();
+    let slope = |i: usize| -> f64 {
+        if i == 0 {
+            return d[0];
+        }
+        if i == d.len() {
+            return d[i - 1];
+        }
+        if d[i - 1] * d[i] <= 0.0 {
+            return 0.0;
+        }
+        let (w1, w2) = (2.0 * h[i] + h[i - 1], h[i] + 2.0 * h[i - 1]);
+        (w1 + w2) / (w1 / d[i - 1] + w2 / d[i])
+    };
+
+    let ((x0, y0), (_, y1)) = (points[k], points[k + 1]);
+    let t = (x - x0) / h[k];
+    let (t2, t3) = (t * t, t * t * t);
+    let y = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+        + (t3 - 2.0 * t2 + t) * h[k] * slope(k)
+        + (-2.0 * t3 + 3.0 * t2) * y1
+        + (t3 - t2) * h[k] * slope(k + 1);
+    Some(y.round().max(0.0) as u64)
+}
+
 /// sample_progress: [0, 1]
 fn guess_progress(run: usize, sample_progress: f64, thorough: bool) -> f64 {
     let total_runs_guess = match () {
@@ -399,3 +1234,291 @@ fn q_crf_conversions() {
     assert_eq!(q_from_crf(33.5, 0.1), 335);
     assert_eq!(q_from_crf(27.0, 1.0), 27);
 }
+
+#[test]
+fn vmaf_to_ssim() {
+    assert_eq!(vmaf_to_ssim_estimate(50.0), 0.95);
+    assert_eq!(vmaf_to_ssim_estimate(95.0), 0.98);
+    assert!((vmaf_to_ssim_estimate(92.5) - 0.975).abs() < 1e-6);
+    assert_eq!(vmaf_to_ssim_estimate(100.0), 0.99);
+}
+
+#[cfg(test)]
+fn test_sample(q: u64, vmaf: f32, encode_percent: f64) -> Sample {
+    Sample {
+        enc: sample_encode::Output {
+            vmaf,
+            vmaf_min: vmaf,
+            vmaf_frames: vec![],
+            ssim: None,
+            xpsnr: None,
+            ssimulacra2: None,
+            vmaf_ssim: None,
+            with_vmaf: false,
+            predicted_encode_size: 0,
+            encode_percent,
+            encode_percent_normalized: None,
+            encoded_resolution: None,
+            video_encode_percent: None,
+            predicted_encode_time: Duration::ZERO,
+            encode_fps: 0.0,
+            vmaf_stddev: None,
+            from_cache: false,
+        },
+        crf_increment: 1.0,
+        q,
+        objective: None,
+        targets: vec![Target::Vmaf(95.0)],
+        video_only_percent: false,
+    }
+}
+
+#[test]
+fn pchip_interpolation() {
+    let sample = |q, vmaf| test_sample(q, vmaf, 0.0);
+    // vmaf = 100 - (q/10)^2, a smooth curve
+    let attempts = [sample(20, 96.0), sample(30, 91.0), sample(40, 84.0)];
+    assert_eq!(pchip_q(95.0, &attempts[..2]), None);
+    // exact answer is 22.4, linear gives 22
+    assert_eq!(pchip_q(95.0, &attempts), Some(22));
+    assert_eq!(pchip_q(88.0, &attempts), Some(35));
+    assert_eq!(
+        Interpolation::Pchip.next_q(88.0, &attempts, &attempts[2], &attempts[1]),
+        35
+    );
+
+    // steepening curve where the answer differs from linear interpolation
+    let steep = [sample(20, 99.0), sample(30, 97.0), sample(40, 84.0)];
+    assert_eq!(vmaf_lerp_q(90.0, &steep[2], &steep[1]), 35);
+    assert_eq!(
+        Interpolation::Pchip.next_q(90.0, &steep, &steep[2], &steep[1]),
+        37
+    );
+
+    // non-monotonic scores fall back to linear
+    let noisy = [sample(20, 96.0), sample(30, 97.0), sample(40, 84.0)];
+    assert_eq!(pchip_q(95.0, &noisy), None);
+    assert_eq!(
+        Interpolation::Pchip.next_q(90.0, &noisy, &noisy[2], &noisy[1]),
+        vmaf_lerp_q(90.0, &noisy[2], &noisy[1])
+    );
+}
+
+#[test]
+fn csv_output() {
+    let best = test_sample(30, 95.1, 35.0);
+    let mut ssim = test_sample(28, 0.0, 40.5);
+    ssim.enc.ssim = Some(0.985);
+    assert_eq!(
+        csv(&best, &[ssim]),
+        "crf,vmaf,ssim,xpsnr,ssimulacra2,objective,predicted_encode_size,predicted_encode_percent,predicted_encode_seconds\n\
+         30,95.1,,,,,0,35.00,0\n\
+         28,,0.985,,,,0,40.50,0\n"
+    );
+}
+
+#[test]
+fn runner_up_candidates() {
+    let attempts = [
+        test_sample(32, 94.0, 30.0),
+        test_sample(28, 95.6, 40.0),
+        test_sample(24, 96.9, 55.0),
+        test_sample(30, 95.1, 35.0),
+        test_sample(20, 98.0, 85.0),
+    ];
+    let best = &attempts[3];
+    let candidates = candidates(best, &attempts, 95.0, 80.0);
+    let qs: Vec<_> = candidates.iter().map(|s| s.q).collect();
+    assert_eq!(qs, [24, 28]);
+}
+
+#[test]
+fn video_only_capped_percent() {
+    let mut sample = test_sample(30, 95.0, 90.0);
+    sample.enc.video_encode_percent = Some(70.0);
+    assert_eq!(sample.capped_percent(), 90.0);
+    sample.video_only_percent = true;
+    assert_eq!(sample.capped_percent(), 70.0);
+    // unknown video size falls back to the whole input percent
+    sample.enc.video_encode_percent = None;
+    assert_eq!(sample.capped_percent(), 90.0);
+}
+
+#[test]
+fn vmaf_and_ssim_score() {
+    let mut sample = test_sample(30, 96.0, 50.0);
+    sample.targets = targets(None, 95.0, None, Some(0.98), None, None);
+    assert_eq!(sample.targets, [Target::Vmaf(95.0), Target::Ssim(0.98)]);
+    sample.enc.vmaf_ssim = Some(0.995);
+    // both targets met, vmaf is the limiting score
+    assert_eq!(sample.score(), 96.0);
+    // ssim below target
+    sample.enc.vmaf_ssim = Some(0.975);
+    assert!((sample.score() - 94.5).abs() < 1e-3, "{}", sample.score());
+}
+
+#[test]
+fn ssim_tolerance() {
+    assert_eq!(Target::Vmaf(95.0).tolerance(0.2), 0.2);
+    // ssim x100 0.02 == ssim 0.0002
+    assert!((Target::Ssim(0.98).tolerance(0.2) - 0.02).abs() < 1e-6);
+}
+
+#[test]
+fn multi_target_score() {
+    // other metrics replace vmaf unless --min-vmaf is set
+    assert_eq!(
+        targets(None, 95.0, None, None, Some(38.0), Some(80.0)),
+        [Target::Xpsnr(38.0), Target::Ssimulacra2(80.0)]
+    );
+    let targets = targets(Some(95.0), 95.0, Some(0.97), None, Some(38.0), None);
+    assert_eq!(
+        targets,
+        [Target::Vmaf(95.0), Target::Ssim(0.97), Target::Xpsnr(38.0)]
+    );
+
+    let mut sample = test_sample(30, 96.0, 50.0);
+    sample.targets = targets;
+    sample.enc.ssim = Some(0.985);
+    sample.enc.xpsnr = Some(38.5);
+    // xpsnr has the smallest margin, +0.5
+    assert_eq!(sample.score(), 95.5);
+    assert_eq!(
+        sample.score_label(),
+        "VMAF 96.00 SSIM All 0.9850 XPSNR 38.50"
+    );
+    // any target unmet fails
+    sample.enc.xpsnr = Some(37.0);
+    assert!(sample.score() < 95.0);
+}
+
+/// Fake ffprobe & ffmpeg for a 10s input, encoding samples sized by crf with
+/// vmaf falling linearly from 100 at crf 10 to 95 at crf 30.
+#[cfg(test)]
+struct FakeFfmpeg;
+
+#[cfg(test)]
+impl crate::process::ProcessRunner for FakeFfmpeg {
+    fn output(&self, cmd: &mut std::process::Command) -> std::io::Result<std::process::Output> {
+        if !cmd.get_args().any(|a| a == "-show_streams") {
+            return Err(std::io::ErrorKind::NotFound.into());
+        }
+        let video = ::ffprobe::Stream {
+            codec_type: Some("video".into()),
+            width: Some(1920),
+            height: Some(1080),
+            pix_fmt: Some("yuv420p".into()),
+            r_frame_rate: "24/1".into(),
+            avg_frame_rate: "24/1".into(),
+            ..<_>::default()
+        };
+        let probe = ::ffprobe::FfProbe {
+            streams: vec![video],
+            format: ::ffprobe::Format {
+                duration: Some("10".into()),
+                ..<_>::default()
+            },
+        };
+        Ok(std::process::Output {
+            status: <_>::default(),
+            stdout: serde_json::to_vec(&probe)?,
+            stderr: vec![],
+        })
+    }
+
+    fn spawn(
+        &self,
+        cmd: &mut tokio::process::Command,
+    ) -> std::io::Result<crate::process::ChunkStream> {
+        use tokio_process_stream::Item;
+
+        let args: Vec<_> = cmd
+            .as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        let crf_of = |name: &str| -> f32 {
+            let crf = name.split(".crf").nth(1).and_then(|c| c.split('.').next());
+            crf.unwrap().replace('_', ".").parse().unwrap()
+        };
+        let stderr = match args.iter().any(|a| a == "-c:v") {
+            // encode, writing the output sample
+            true => {
+                let dest = args.last().unwrap();
+                std::fs::write(dest, vec![0; (60.0 - crf_of(dest)) as usize * 1000])?;
+                String::new()
+            }
+            // vmaf of the first input, the distorted sample
+            false => {
+                let distorted = &args[args.iter().position(|a| a == "-i").unwrap() + 1];
+                format!("VMAF score: {}\n", 100.0 - (crf_of(distorted) - 10.0) / 4.0)
+            }
+        };
+        let chunks = [
+            Item::Stderr(stderr.into_bytes()),
+            Item::Done(Ok(<_>::default())),
+        ];
+        Ok(Box::pin(tokio_stream::iter(chunks)))
+    }
+}
+
+#[test]
+fn search_fake_ffmpeg() {
+    let dir = std::env::temp_dir().join(format!("ab-av1-crf-search-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.mkv");
+    std::fs::write(&input, vec![0; 100_000]).unwrap();
+
+    let mut args = Args::try_parse_from([
+        "crf-search".as_ref(),
+        "-i".as_ref(),
+        input.as_os_str(),
+        "--cache=false".as_ref(),
+        "--temp-dir".as_ref(),
+        dir.as_os_str(),
+    ])
+    .unwrap();
+    args.sample.runner = crate::process::Runner::new(FakeFfmpeg);
+    let probe = Arc::new(ffprobe::probe_with(&FakeFfmpeg, &input));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let mut attempts = vec![];
+    let best = tokio::task::LocalSet::new().block_on(&runtime, async {
+        let cancel = CancellationToken::new();
+        run_attempts(&args, probe, ProgressBar::hidden(), &cancel, &mut attempts).await
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let best = best.unwrap();
+    // crf 29 is the highest crf scoring above 95
+    assert_eq!(best.crf(), 29.0);
+    assert_eq!(best.enc.vmaf, 95.25);
+    assert_eq!(best.enc.encode_percent, 31.0);
+    assert!(attempts.len() > 1);
+}
+
+#[test]
+fn attempts_csv() {
+    let mut vmaf = test_sample(30, 95.25, 35.0);
+    vmaf.enc.with_vmaf = true;
+    vmaf.enc.vmaf_min = 94.5;
+    vmaf.enc.predicted_encode_size = 1234;
+    vmaf.enc.encode_fps = 42.0;
+    let mut ssim = test_sample(28, 0.0, 40.5);
+    ssim.enc.ssim = Some(0.985);
+    ssim.enc.from_cache = true;
+
+    let path = std::env::temp_dir().join(format!("ab-av1-attempts-{}.csv", fastrand::u64(..)));
+    write_attempts_csv(&path, &[vmaf, ssim]).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        csv,
+        "crf,vmaf,vmaf_min_sample,ssim,xpsnr,ssimulacra2,encode_percent,predicted_encode_size,encode_fps,cache\n\
+         30,95.25,94.5,,,,35.00,1234,42.00,false\n\
+         28,,,0.985,,,40.50,0,0.00,true\n"
+    );
+}
@@ -0,0 +1,224 @@
+//! _crf-search_ history logic, used to seed new searches with previously found crf values
+//! & to flag results that deviate from previous results for similar inputs.
+use crate::{cache_db, command::args, ffprobe::Ffprobe, float::TerseF32};
+use anyhow::Context;
+
+/// Maximum number of previous results stored per key.
+const MAX_ENTRIES: usize = 20;
+/// Minimum number of previous results required before a prior is used.
+const MIN_ENTRIES: usize = 3;
+/// Encode percent, relative to the historical median, above which a result is flagged.
+const SIZE_REGRESSION_FACTOR: f32 = 1.2;
+
+/// History key describing the kind of search, e.g.
+/// "libsvtav1 preset=8 vmaf=95 res=1080p model=version=vmaf_v0.6.1".
+#[derive(Debug, Clone)]
+pub struct Key(String);
+
+impl Key {
+    /// Key storing predicted encode percents for the same kind of search.
+    fn encode_percent(&self) -> Self {
+        Self(format!("{} encode-percent", self.0))
+    }
+
+    /// Key storing encode energy usage per input hour.
+    pub fn energy(args: &args::Encode, probe: &Ffprobe) -> Self {
+        let (preset, res) = preset_res(args, probe);
+        Self(format!(
+            "{} preset={preset} res={res} encode-kwh-per-hour",
+            args.encoder.as_str()
+        ))
+    }
+
+    /// Key storing crf offsets of this search relative to a proxy preset search.
+    fn proxy_offset(&self, proxy_preset: &args::Preset) -> Self {
+        Self(format!("{} proxy-preset={proxy_preset} crf-offset", self.0))
+    }
+}
+
+impl Key {
+    pub fn new(args: &args::Encode, vmaf: &args::Vmaf, min_vmaf: f32, probe: &Ffprobe) -> Self {
+        let (preset, res) = preset_res(args, probe);
+        let mut key = format!(
+            "{} preset={preset} vmaf={} res={res} {}",
+            args.encoder.as_str(),
+            TerseF32(min_vmaf),
+            vmaf.model_label(probe.resolution),
+        );
+        if let Some(vfilter) = &args.vfilter {
+            key.push_str(" vfilter=");
+            key.push_str(vfilter);
+        }
+        Self(key)
+    }
+}
+
+fn preset_res(args: &args::Encode, probe: &Ffprobe) -> (String, &'static str) {
+    let preset = args
+        .preset
+        .as_ref()
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "default".into());
+    let res = match probe.resolution {
+        Some((_, h)) if h <= 576 => "sd",
+        Some((_, h)) if h <= 720 => "720p",
+        Some((_, h)) if h <= 1080 => "1080p",
+        Some((_, h)) if h <= 1440 => "1440p",
+        Some(_) => "2160p",
+        None => "unknown",
+    };
+    (preset, res)
+}
+
+/// Returns the median crf of previous searches with the same key,
+/// if enough history is available.
+pub async fn prior_crf(key: Key) -> Option<f32> {
+    prior(key).await
+}
+
+/// Returns the median predicted encode percent of previous searches with the same key,
+/// if enough history is available.
+pub async fn prior_encode_percent(key: Key) -> Option<f32> {
+    prior(key.encode_percent()).await
+}
+
+/// Returns the median crf offset of previous searches relative to a `proxy_preset`
+/// search, if enough history is available.
+pub async fn prior_proxy_offset(key: &Key, proxy_preset: &args::Preset) -> Option<f32> {
+    prior(key.proxy_offset(proxy_preset)).await
+}
+
+/// Returns the median encode kWh per input hour of previous encodes with the same key,
+/// if enough history is available.
+pub async fn prior_energy(key: Key) -> Option<f32> {
+    prior(key).await
+}
+
+/// Returns the median of previously recorded values for `key`, if enough are available.
+async fn prior(key: Key) -> Option<f32> {
+    let values =
+        tokio::task::spawn_blocking(move || read(&cache_db::open("crf-search-history")?, &key))
+            .await
+            .context("history read task failed")
+            .and_then(|r| r);
+
+    match values {
+        Ok(values) => median(values),
+        Err(err) => {
+            eprintln!("crf history error: {err}");
+            None
+        }
+    }
+}
+
+/// Returns true if `encode_percent` is significantly larger than the historical `median`,
+/// which may indicate an encoder regression, e.g. after an ffmpeg update.
+pub fn is_size_regression(encode_percent: f32, median: f32) -> bool {
+    encode_percent > median * SIZE_REGRESSION_FACTOR
+}
+
+/// Store a successful search result.
+pub async fn record(key: Key, crf: f32, encode_percent: f32) {
+    insert(vec![(key.encode_percent(), encode_percent), (key, crf)]).await;
+}
+
+/// Store the crf offset of a search result relative to its `proxy_preset` search result.
+pub async fn record_proxy_offset(key: &Key, proxy_preset: &args::Preset, offset: f32) {
+    insert(vec![(key.proxy_offset(proxy_preset), offset)]).await;
+}
+
+/// Store an encode's kWh per input hour.
+pub async fn record_energy(key: Key, kwh_per_hour: f32) {
+    insert(vec![(key, kwh_per_hour)]).await;
+}
+
+async fn insert(entries: Vec<(Key, f32)>) {
+    let insert = tokio::task::spawn_blocking(move || {
+        let db = cache_db::open("crf-search-history")?;
+        for (key, value) in entries {
+            let mut values = read(&db, &key)?;
+            values.push(value);
+            if values.len() > MAX_ENTRIES {
+                values.drain(..values.len() - MAX_ENTRIES);
+            }
+            db.insert(key.0.as_bytes(), serde_json::to_vec(&values)?)?;
+        }
+        db.flush()?;
+        Ok::<_, anyhow::Error>(())
+    })
+    .await
+    .context("history insert task failed")
+    .and_then(|r| r);
+
+    if let Err(err) = insert {
+        eprintln!("crf history error: {err}");
+    }
+}
+
+fn read(db: &sled::Db, key: &Key) -> anyhow::Result<Vec<f32>> {
+    Ok(match db.get(key.0.as_bytes())? {
+        Some(data) => serde_json::from_slice(&data)?,
+        None => vec![],
+    })
+}
+
+fn median(mut crfs: Vec<f32>) -> Option<f32> {
+    crfs.retain(|c| c.is_finite());
+    if crfs.len() < MIN_ENTRIES {
+        return None;
+    }
+    crfs.sort_by(f32::total_cmp);
+    let mid = crfs.len() / 2;
+    Some(match crfs.len() % 2 {
+        0 => (crfs[mid - 1] + crfs[mid]) / 2.0,
+        _ => crfs[mid],
+    })
+}
+
+#[test]
+fn median_prior() {
+    assert_eq!(median(vec![30.0, 32.0]), None);
+    assert_eq!(median(vec![34.0, 30.0, 32.0]), Some(32.0));
+    assert_eq!(median(vec![34.0, 30.0, 32.0, 28.0]), Some(31.0));
+}
+
+#[test]
+fn size_regression() {
+    assert!(!is_size_regression(40.0, 40.0));
+    assert!(!is_size_regression(47.9, 40.0));
+    assert!(is_size_regression(48.1, 40.0));
+}
+
+#[test]
+fn search_key() {
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[clap(flatten)]
+        encode: args::Encode,
+        #[clap(flatten)]
+        vmaf: args::Vmaf,
+    }
+    let key = |args: &[&str]| {
+        let args = Args::try_parse_from(["ab-av1", "-i", "vid.mkv"].iter().chain(args)).unwrap();
+        let probe = Ffprobe {
+            duration: Ok(std::time::Duration::from_secs(600)),
+            has_audio: false,
+            max_audio_channels: None,
+            fps: Ok(24.0),
+            resolution: Some((1920, 1080)),
+            sar: None,
+            is_image: false,
+            pix_fmt: None,
+            dynamic_hdr: <_>::default(),
+        };
+        Key::new(&args.encode, &args.vmaf, 95.0, &probe).0
+    };
+    assert_eq!(
+        key(&[]),
+        "libsvtav1 preset=default vmaf=95 res=1080p model=version=vmaf_v0.6.1 (libvmaf default)"
+    );
+    assert!(key(&["--vfilter", "scale=1280:-1"]).ends_with(" vfilter=scale=1280:-1"));
+    assert_ne!(key(&[]), key(&["--vmaf-model", "neg"]));
+}
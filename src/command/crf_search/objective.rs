@@ -0,0 +1,135 @@
+//! `--objective` composite search score logic.
+use crate::command::sample_encode;
+use anyhow::{ensure, Context};
+use std::fmt;
+
+/// Weighted sum of sample metrics used as the search score, with an optional size limit.
+///
+/// E.g. "0.8*vmaf+0.2*ssim; size<=60%".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Objective {
+    pub terms: Vec<(f32, Metric)>,
+    /// Overrides --max-encoded-percent.
+    pub max_encoded_percent: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Mean VMAF.
+    Vmaf,
+    /// Lowest sample VMAF.
+    VmafMin,
+    /// Mean SSIM "All", scaled x100 to the vmaf range.
+    Ssim,
+}
+
+impl Objective {
+    /// Returns true if SSIM needs to be calculated, in addition to VMAF.
+    pub fn uses_ssim(&self) -> bool {
+        self.terms.iter().any(|(_, m)| *m == Metric::Ssim)
+    }
+
+    pub fn score(&self, enc: &sample_encode::Output) -> f32 {
+        self.terms
+            .iter()
+            .map(|(weight, metric)| {
+                weight
+                    * match metric {
+                        Metric::Vmaf => enc.vmaf,
+                        Metric::VmafMin => enc.vmaf_min,
+                        Metric::Ssim => enc.vmaf_ssim.unwrap_or(0.0) * 100.0,
+                    }
+            })
+            .sum()
+    }
+}
+
+impl std::str::FromStr for Objective {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut clauses = s.split(';').map(str::trim);
+        let expr = clauses.next().unwrap_or_default();
+
+        let mut terms = vec![];
+        for term in expr.split('+').map(str::trim) {
+            let (weight, metric) = match term.split_once('*') {
+                Some((w, m)) => (
+                    w.trim()
+                        .parse::<f32>()
+                        .with_context(|| format!("invalid weight in `{term}`"))?,
+                    m.trim(),
+                ),
+                None => (1.0, term),
+            };
+            let metric = match metric {
+                "vmaf" => Metric::Vmaf,
+                "vmaf_min" => Metric::VmafMin,
+                "ssim" => Metric::Ssim,
+                _ => anyhow::bail!("unknown metric `{metric}`, expected vmaf, vmaf_min or ssim"),
+            };
+            ensure!(weight.is_finite(), "invalid weight in `{term}`");
+            terms.push((weight, metric));
+        }
+
+        let mut max_encoded_percent = None;
+        for clause in clauses.filter(|c| !c.is_empty()) {
+            let size = clause
+                .strip_prefix("size")
+                .and_then(|c| c.trim_start().strip_prefix("<="))
+                .with_context(|| format!("unknown constraint `{clause}`, expected size<=N%"))?;
+            let size = size.trim().trim_end_matches('%');
+            max_encoded_percent = Some(
+                size.parse::<f32>()
+                    .with_context(|| format!("invalid size `{size}`"))?,
+            );
+        }
+
+        Ok(Self {
+            terms,
+            max_encoded_percent,
+        })
+    }
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, (weight, metric)) in self.terms.iter().enumerate() {
+            if idx > 0 {
+                f.write_str("+")?;
+            }
+            let metric = match metric {
+                Metric::Vmaf => "vmaf",
+                Metric::VmafMin => "vmaf_min",
+                Metric::Ssim => "ssim",
+            };
+            write!(f, "{weight}*{metric}")?;
+        }
+        if let Some(size) = self.max_encoded_percent {
+            write!(f, "; size<={size}%")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn parse_objective() {
+    let objective: Objective = "0.8*vmaf+0.2*ssim; size<=60%".parse().unwrap();
+    assert_eq!(
+        objective,
+        Objective {
+            terms: vec![(0.8, Metric::Vmaf), (0.2, Metric::Ssim)],
+            max_encoded_percent: Some(60.0),
+        }
+    );
+    assert!(objective.uses_ssim());
+    assert_eq!(objective.to_string(), "0.8*vmaf+0.2*ssim; size<=60%");
+
+    let objective: Objective = "vmaf_min".parse().unwrap();
+    assert_eq!(objective.terms, [(1.0, Metric::VmafMin)]);
+    assert_eq!(objective.max_encoded_percent, None);
+
+    assert!("0.5*psnr".parse::<Objective>().is_err());
+    assert!("vmaf; bitrate<=5M".parse::<Objective>().is_err());
+    assert!("x*vmaf".parse::<Objective>().is_err());
+}
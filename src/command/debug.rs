@@ -0,0 +1,186 @@
+use crate::{
+    command::args::{self, PixelFormat},
+    psnr, ssim, xpsnr,
+};
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use std::path::Path;
+
+/// Debugging utilities.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    #[command(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Print the ffmpeg `filter_complex` (lavfi) used to calculate a quality metric,
+    /// without running anything.
+    Lavfi(LavfiArgs),
+}
+
+#[derive(Parser)]
+#[group(skip)]
+pub struct LavfiArgs {
+    /// Metric to build the filter graph for.
+    #[arg(long, value_enum, default_value_t = Metric::Vmaf)]
+    pub metric: Metric,
+
+    /// Distorted video resolution, e.g. 1920x1080. Affects the vmaf model & scaling.
+    #[arg(long, value_parser = parse_resolution)]
+    pub resolution: Option<(u32, u32)>,
+
+    /// Ffmpeg video filter applied to the reference before analysis.
+    #[arg(long)]
+    pub reference_vfilter: Option<String>,
+
+    /// Vmaf args, --metric-pix-fmt sets the analysis pixel format for all metrics
+    /// [default: yuv420p].
+    #[clap(flatten)]
+    pub vmaf: args::Vmaf,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Metric {
+    Vmaf,
+    Ssim,
+    Psnr,
+    Xpsnr,
+}
+
+pub fn debug(Args { action }: Args) -> anyhow::Result<()> {
+    match action {
+        Action::Lavfi(LavfiArgs {
+            metric,
+            resolution,
+            reference_vfilter,
+            vmaf,
+        }) => {
+            let pix_fmt = vmaf.metric_pix_fmt.unwrap_or(PixelFormat::Yuv420p);
+            println!(
+                "{}",
+                lavfi(
+                    metric,
+                    &vmaf,
+                    resolution,
+                    pix_fmt,
+                    reference_vfilter.as_deref()
+                )
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Returns the `filter_complex` value the `metric` commands & sample-encode use.
+pub fn lavfi(
+    metric: Metric,
+    vmaf: &args::Vmaf,
+    resolution: Option<(u32, u32)>,
+    pix_fmt: PixelFormat,
+    ref_vfilter: Option<&str>,
+) -> String {
+    match metric {
+        Metric::Vmaf => vmaf.ffmpeg_lavfi(resolution, pix_fmt, ref_vfilter),
+        Metric::Ssim => ssim::ffmpeg_lavfi(pix_fmt, ref_vfilter),
+        Metric::Psnr => psnr::ffmpeg_lavfi(pix_fmt, ref_vfilter, Path::new("psnr.log")),
+        Metric::Xpsnr => xpsnr::ffmpeg_lavfi(pix_fmt, ref_vfilter),
+    }
+}
+
+fn parse_resolution(arg: &str) -> anyhow::Result<(u32, u32)> {
+    const ERR: &str = "expected WxH format e.g. '1920x1080'";
+    let (w, h) = arg.split_once('x').context(ERR)?;
+    Ok((w.parse().context(ERR)?, h.parse().context(ERR)?))
+}
+
+/// Snapshot of lavfi values across metric, resolution, scale, model, pixel format &
+/// reference vfilter combinations. Update with `UPDATE_EXPECT=1 cargo test`.
+#[test]
+fn lavfi_matrix_snapshot() {
+    use crate::command::args::{VmafScale, VmafStatistic};
+    use std::fmt::Write;
+
+    let resolutions = [
+        None,
+        // anamorphic 720x576 SAR 64:45 display resolution
+        Some((1024, 576)),
+        Some((1280, 720)),
+        Some((1920, 1080)),
+        Some((3840, 2160)),
+    ];
+    let scales = [
+        VmafScale::Auto,
+        VmafScale::None,
+        VmafScale::Custom {
+            width: 1280,
+            height: 720,
+        },
+    ];
+    let models: [&[&str]; 2] = [&[], &["model=version=vmaf_4k_v0.6.1"]];
+    let pix_fmts = [PixelFormat::Yuv420p, PixelFormat::Yuv420p10le];
+    let ref_vfilters = [None, Some("crop=1920:800")];
+
+    let vmaf = |scale, model: &[&str]| args::Vmaf {
+        vmaf_args: model.iter().map(|&a| a.into()).collect(),
+        vmaf_scale: scale,
+        metric_pix_fmt: None,
+        vmaf_statistic: VmafStatistic::Mean,
+        target_viewing: None,
+        vmaf_model: None,
+        // fixed so output doesn't depend on system threads
+        vmaf_threads: Some(4),
+        concurrent_processes: 1,
+    };
+    let res_label = |res: Option<(u32, u32)>| match res {
+        Some((w, h)) => format!("{w}x{h}"),
+        None => "unknown".into(),
+    };
+
+    let mut snapshot = String::new();
+    for res in resolutions {
+        for scale in scales {
+            for model in models {
+                for pix_fmt in pix_fmts {
+                    for ref_vf in ref_vfilters {
+                        let lavfi = lavfi(Metric::Vmaf, &vmaf(scale, model), res, pix_fmt, ref_vf);
+                        writeln!(
+                            snapshot,
+                            "vmaf res={} scale={scale} model={} pix_fmt={pix_fmt} ref_vf={}\n  {lavfi}",
+                            res_label(res),
+                            model
+                                .first()
+                                .map_or("default", |m| m.trim_start_matches("model=version=")),
+                            ref_vf.unwrap_or("none"),
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+    }
+    for metric in [Metric::Ssim, Metric::Psnr, Metric::Xpsnr] {
+        for pix_fmt in pix_fmts {
+            for ref_vf in ref_vfilters {
+                let lavfi = lavfi(metric, &vmaf(VmafScale::Auto, &[]), None, pix_fmt, ref_vf);
+                writeln!(
+                    snapshot,
+                    "{} pix_fmt={pix_fmt} ref_vf={}\n  {lavfi}",
+                    format!("{metric:?}").to_lowercase(),
+                    ref_vf.unwrap_or("none"),
+                )
+                .unwrap();
+            }
+        }
+    }
+    expect_test::expect_file!["debug/lavfi_matrix.snap"].assert_eq(&snapshot);
+}
+
+#[test]
+fn parse_resolution_arg() {
+    assert_eq!(parse_resolution("1920x1080").unwrap(), (1920, 1080));
+    assert!(parse_resolution("1920").is_err());
+}
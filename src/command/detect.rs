@@ -0,0 +1,160 @@
+use crate::{command::args::VAAPI_DEVICE, console_ext::style, ffmpeg::capabilities, machine};
+use clap::Parser;
+use console::style;
+use std::process::{Command, Stdio};
+
+/// Detect usable hardware & software encoders.
+///
+/// Probes the ffmpeg build for videotoolbox, nvenc, qsv, vaapi, amf & libsvtav1
+/// encoders then runs a tiny test encode with each available encoder.
+///
+/// `--encoder auto` uses the first usable encoder in the listed order.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Print the result to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+/// Detected encoders in `--encoder auto` preference order.
+///
+/// AV1 hardware encoders are preferred for speed, then libsvtav1 for AV1 output,
+/// then HEVC hardware encoders. videotoolbox is last as it only supports bitrate &
+/// coarse `-q:v` rate control.
+pub const CANDIDATES: &[&str] = &[
+    "av1_nvenc",
+    "av1_qsv",
+    "av1_vaapi",
+    "av1_amf",
+    "libsvtav1",
+    "hevc_nvenc",
+    "hevc_qsv",
+    "hevc_vaapi",
+    "hevc_amf",
+    "hevc_videotoolbox",
+];
+
+/// Encoder detection result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detected {
+    pub encoder: &'static str,
+    /// Included in the ffmpeg build.
+    pub available: bool,
+    /// Test encode succeeded.
+    pub usable: bool,
+}
+
+pub fn detect(Args { json }: Args) -> anyhow::Result<()> {
+    let detected = detect_encoders();
+    let best = detected.iter().find(|d| d.usable).map(|d| d.encoder);
+
+    if json {
+        let encoders: Vec<_> = detected
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "encoder": d.encoder,
+                    "available": d.available,
+                    "usable": d.usable,
+                })
+            })
+            .collect();
+        machine::print_json(&serde_json::json!({ "encoders": encoders, "best": best }));
+    } else {
+        for d in &detected {
+            let status = match (d.available, d.usable) {
+                (_, true) => style("ok").green(),
+                (true, false) => style("unusable").yellow(),
+                (false, _) => style("not found").red(),
+            };
+            println!("{:<18} {status}", d.encoder);
+        }
+    }
+    match best {
+        Some(best) => eprintln!("{}", style!("--encoder auto: {best}").dim()),
+        None => anyhow::bail!("no usable encoders found"),
+    }
+    Ok(())
+}
+
+/// Detect all [`CANDIDATES`], test encoding those included in the ffmpeg build.
+pub fn detect_encoders() -> Vec<Detected> {
+    let encoders = ffmpeg_encoders();
+    CANDIDATES
+        .iter()
+        .map(|&encoder| {
+            let available = encoders.iter().any(|e| e == encoder);
+            Detected {
+                encoder,
+                available,
+                usable: available && test_encode(encoder),
+            }
+        })
+        .collect()
+}
+
+/// Returns the first usable encoder of [`CANDIDATES`], used for `--encoder auto`.
+pub fn best_encoder() -> Option<&'static str> {
+    let encoders = ffmpeg_encoders();
+    CANDIDATES
+        .iter()
+        .copied()
+        .filter(|c| encoders.iter().any(|e| e == c))
+        .find(|c| test_encode(c))
+}
+
+/// Video encoder names included in the ffmpeg build.
+fn ffmpeg_encoders() -> Vec<String> {
+    capabilities::tool_output("ffmpeg", &["-hide_banner", "-encoders"])
+        .map(|out| parse_video_encoders(&String::from_utf8_lossy(&out)))
+        .unwrap_or_default()
+}
+
+/// Parse `ffmpeg -encoders` output, e.g. " V....D libsvtav1  SVT-AV1(...)".
+fn parse_video_encoders(out: &str) -> Vec<String> {
+    out.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let flags = words.next()?;
+            let name = words.next()?;
+            (flags.len() == 6 && flags.starts_with('V') && name != "=").then(|| name.into())
+        })
+        .collect()
+}
+
+/// Encode a few frames of a test source, returning true if successful.
+///
+/// Encodes as ab-av1 would, vaapi encoders use the default device & hardware upload.
+fn test_encode(encoder: &str) -> bool {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-v", "error"]);
+    let vaapi = encoder.ends_with("_vaapi");
+    if vaapi {
+        cmd.args(["-vaapi_device", VAAPI_DEVICE]);
+    }
+    cmd.args(["-f", "lavfi", "-i", "testsrc2=s=640x360:r=24"]);
+    if vaapi {
+        cmd.args(["-vf", "format=nv12,hwupload"]);
+    }
+    cmd.args(["-frames:v", "10", "-c:v", encoder, "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[test]
+fn parse_encoders_output() {
+    let out = "Encoders:
+ V..... = Video
+ A..... = Audio
+ ------
+ V....D libsvtav1            SVT-AV1(Scalable Video Technology for AV1) encoder (codec av1)
+ V....D av1_nvenc            NVIDIA NVENC av1 encoder (codec av1)
+ A....D libopus              libopus Opus (codec opus)
+";
+    assert_eq!(parse_video_encoders(out), ["libsvtav1", "av1_nvenc"]);
+}
@@ -0,0 +1,169 @@
+use crate::console_ext::style;
+use clap::Parser;
+use console::style;
+use std::process::Command;
+
+/// Check external tool availability & known issues.
+///
+/// Reports whether ffmpeg, libsvtav1, libvmaf & SvtAv1EncApp are usable
+/// and warns when known problematic versions are detected.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Print full version info of each external tool.
+    #[arg(long)]
+    pub versions: bool,
+}
+
+/// Known problematic versions. (tool, version below which the warning applies, warning)
+const COMPAT: &[(&str, (u32, u32, u32), &str)] = &[
+    (
+        "ffmpeg",
+        (5, 1, 0),
+        "ffmpeg older than 5.1 may lack required libsvtav1/libvmaf features",
+    ),
+    (
+        "SvtAv1EncApp",
+        (1, 0, 0),
+        "svt-av1 older than 1.0 is untested, results may differ from newer releases",
+    ),
+];
+
+pub fn doctor(Args { versions }: Args) -> anyhow::Result<()> {
+    let ffmpeg = tool_version("ffmpeg", "-version");
+    let svt = tool_version("SvtAv1EncApp", "--version");
+
+    let filters = ffmpeg_output(&["-hide_banner", "-filters"]);
+    let encoders = ffmpeg_output(&["-hide_banner", "-encoders"]);
+    // libvmaf has no version output of its own, show how ffmpeg was built with it
+    let buildconf = ffmpeg_output(&["-hide_banner", "-buildconf"]);
+    let libvmaf_conf = buildconf.as_deref().and_then(|c| build_flag(c, "libvmaf"));
+
+    let mut warnings = 0;
+    for (name, found, version) in [
+        ("ffmpeg", ffmpeg.is_some(), ffmpeg.as_deref()),
+        (
+            "libsvtav1",
+            encoders
+                .as_deref()
+                .is_some_and(|e| is_listed(e, "libsvtav1")),
+            None,
+        ),
+        (
+            "libvmaf",
+            filters.as_deref().is_some_and(|f| is_listed(f, "libvmaf")),
+            libvmaf_conf,
+        ),
+        ("SvtAv1EncApp", svt.is_some(), svt.as_deref()),
+    ] {
+        let status = match found {
+            true => style("ok").green(),
+            false => style("not found").red(),
+        };
+        match (versions, version) {
+            (true, Some(v)) => println!("{name:<13} {status:<9} {}", style(v).dim()),
+            _ => println!("{name:<13} {status}"),
+        }
+
+        if let Some(warning) = version.and_then(|v| compat_warning(name, v)) {
+            warnings += 1;
+            eprintln!("{} {warning}", style!("Warning:").yellow().bold());
+        }
+    }
+
+    if ffmpeg.is_none() {
+        anyhow::bail!("ffmpeg not found in $PATH");
+    }
+    if warnings > 0 {
+        eprintln!("{}", style!("{warnings} warning(s)").dim());
+    }
+    Ok(())
+}
+
+/// Returns the first line of version output of the tool, if available.
+fn tool_version(tool: &str, arg: &str) -> Option<String> {
+    let out = Command::new(tool).arg(arg).output().ok()?;
+    let out = String::from_utf8_lossy(&out.stdout);
+    Some(
+        out.lines()
+            .find(|l| !l.trim().is_empty())?
+            .trim()
+            .to_owned(),
+    )
+}
+
+fn ffmpeg_output(args: &[&str]) -> Option<String> {
+    let out = Command::new("ffmpeg").args(args).output().ok()?;
+    Some(String::from_utf8_lossy(&out.stdout).into())
+}
+
+/// Returns true if `name` is listed in `ffmpeg -filters` or `-encoders` output.
+fn is_listed(list: &str, name: &str) -> bool {
+    list.lines()
+        .any(|l| l.split_whitespace().nth(1) == Some(name))
+}
+
+/// Returns the `ffmpeg -buildconf` configure flag enabling `lib`, e.g. "--enable-libvmaf".
+fn build_flag<'a>(buildconf: &'a str, lib: &str) -> Option<&'a str> {
+    buildconf
+        .lines()
+        .map(str::trim)
+        .find(|l| l.starts_with("--enable-") && l.ends_with(lib))
+}
+
+fn compat_warning(tool: &str, version_line: &str) -> Option<&'static str> {
+    let version = parse_version(version_line)?;
+    COMPAT
+        .iter()
+        .find(|(t, below, _)| *t == tool && version < *below)
+        .map(|(.., warning)| *warning)
+}
+
+/// Parse the first "x.y(.z)" version from a version line, ignoring a "v" or "n" prefix.
+///
+/// E.g. "ffmpeg version n6.1.1-1 Copyright..." -> (6, 1, 1)
+fn parse_version(line: &str) -> Option<(u32, u32, u32)> {
+    line.split_whitespace().find_map(|word| {
+        let word = word.trim_start_matches(['v', 'n']);
+        let end = word
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(word.len());
+        let mut parts = word[..end].split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+        Some((major, minor, patch))
+    })
+}
+
+#[test]
+fn parse_tool_versions() {
+    assert_eq!(
+        parse_version("ffmpeg version n6.1.1-1 Copyright (c) 2000-2023"),
+        Some((6, 1, 1))
+    );
+    assert_eq!(
+        parse_version("ffmpeg version 5.1 Copyright"),
+        Some((5, 1, 0))
+    );
+    assert_eq!(parse_version("SVT-AV1 v1.8.0 (release)"), Some((1, 8, 0)));
+    assert_eq!(
+        parse_version("ffmpeg version N-113000-g0a1b2c3 Copyright (c) 2000-2023"),
+        None
+    );
+}
+
+#[test]
+fn ffmpeg_libvmaf() {
+    let filters =
+        " ... libvmaf          VV->V      Calculate the VMAF between two video streams.\n \
+                   ... vmafmotion       V->V       Calculate the VMAF Motion score.";
+    assert!(is_listed(filters, "libvmaf"));
+    assert!(!is_listed(filters, "vmaf"));
+
+    let buildconf =
+        "  configuration:\n    --enable-gpl\n    --enable-libvmaf\n    --enable-libx264";
+    assert_eq!(build_flag(buildconf, "libvmaf"), Some("--enable-libvmaf"));
+    assert_eq!(build_flag(buildconf, "libsvtav1"), None);
+}
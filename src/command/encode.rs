@@ -1,24 +1,35 @@
 use crate::{
+    cancel::{self, CancellationToken},
     command::{
-        args::{self, Encoder},
-        SmallDuration, PROGRESS_CHARS,
+        args::{self, vfilter},
+        crf_search::history,
+        progress, SmallDuration, PROGRESS_CHARS,
     },
     console_ext::style,
-    ffmpeg,
+    energy, ffmpeg,
     ffprobe::{self, Ffprobe},
-    process::FfmpegOut,
+    hdr,
+    live_vmaf::{self, LiveVmaf},
+    machine, naming,
+    preview::Preview,
+    process::{self, FfmpegOut},
+    sample,
     temporary::{self, TempKind},
+    timings::{self, Phase},
+    vmaf,
 };
+use anyhow::{anyhow, ensure, Context};
 use clap::Parser;
 use console::style;
 use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use std::{
+    net::SocketAddr,
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
 use tokio::fs;
-use tokio_stream::StreamExt;
+use tokio_stream::{StreamExt, StreamMap};
 
 /// Invoke ffmpeg to encode a video or image.
 #[derive(Parser)]
@@ -33,18 +44,138 @@ pub struct Args {
 
     #[clap(flatten)]
     pub encode: args::EncodeToOutput,
+
+    /// Only encode the specified 1-based chapters, e.g. `--chapters 3,7`.
+    ///
+    /// Each chapter is encoded to a separate output, e.g. "vid.av1.chapter3.mkv",
+    /// useful for spot checking tricky sections before a full encode.
+    #[arg(long, value_delimiter = ',')]
+    pub chapters: Vec<u32>,
+
+    /// Split the input into this many chunks at keyframes & encode them in parallel
+    /// ffmpeg processes, then losslessly concatenate the encoded chunks.
+    ///
+    /// Speeds up encoders that don't use all cores. Audio & subtitle streams, chapters
+    /// & metadata are copied from the input.
+    ///
+    /// The concatenated output is verified to have every encoded frame once with
+    /// increasing timestamps, & windows around each splice are vmaf scored to warn
+    /// about visible concat artifacts.
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = [
+            "chapters",
+            "preview_port",
+            "also_encode",
+            "energy",
+            "live_vmaf",
+            "video_only",
+            "audio_codec",
+            "downmix_to_stereo",
+            "abitrate_per_channel",
+        ],
+    )]
+    pub chunks: Option<u32>,
+
+    /// Max --chunks encoding at once, by default a quarter of the logical cpus.
+    #[arg(long, requires = "chunks", value_parser = clap::value_parser!(u32).range(1..))]
+    pub chunk_jobs: Option<u32>,
+
+    /// --live-vmaf settings, by default as with `ab-av1 vmaf`. Set by auto-encode.
+    #[arg(skip)]
+    pub vmaf: Option<args::Vmaf>,
+
+    /// --live-vmaf warning threshold. Set by auto-encode.
+    #[arg(skip)]
+    pub min_vmaf: Option<f32>,
+
+    /// Print the encode result to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+
+    /// Don't draw progress or print warnings. Set by the global --quiet.
+    #[arg(skip)]
+    pub quiet: bool,
 }
 
-pub async fn encode(args: Args) -> anyhow::Result<()> {
+pub async fn encode(mut args: Args, cancel: CancellationToken) -> anyhow::Result<()> {
+    if args.quiet {
+        progress::set_quiet();
+    }
     let bar = ProgressBar::new(1).with_style(
         ProgressStyle::default_bar()
             .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
             .progress_chars(PROGRESS_CHARS)
     );
+    machine::watch(&bar);
     bar.enable_steady_tick(Duration::from_millis(100));
 
     let probe = ffprobe::probe(&args.args.input);
-    run(args, probe.into(), &bar).await
+    args.args.resolve_encoder()?;
+    args.args.apply_sar(&probe);
+    args.args.apply_detelecine(&probe);
+    let output = args.encode.output.clone().unwrap_or_else(|| {
+        naming::default_output_name(&args.args.input, args.args.encoder.as_str(), probe.is_image)
+    });
+    if args.chapters.is_empty() {
+        if !args.encode.existing.check_output(&output)? {
+            return Ok(());
+        }
+        if let Some(chunks) = args.chunks.filter(|n| *n > 1) {
+            return run_chunked(args, &probe, output, chunks, &bar, &cancel).await;
+        }
+        return run(args, probe.into(), &bar, &cancel).await;
+    }
+
+    let ext = output.extension().and_then(|e| e.to_str()).unwrap_or("mkv");
+    for (n, chapter) in ffprobe::probe_chapters(&args.args.input, &args.chapters)? {
+        let chapter_output = output.with_extension(format!("chapter{n}.{ext}"));
+        if !args.encode.existing.check_output(&chapter_output)? {
+            continue;
+        }
+        let input = cancel::until(
+            &cancel,
+            sample::copy_chapter(
+                &args.args.input,
+                n,
+                chapter,
+                None,
+                probe.dynamic_hdr.any(),
+                Some(&chapter_output),
+            ),
+        )
+        .await?;
+        let chapter_probe = ffprobe::probe(&input);
+        let out = shell_escape::escape(chapter_output.display().to_string().into());
+        progress::println(&bar, style!("Encoding chapter {n} {out}").dim().to_string());
+        bar.reset();
+        run(
+            Args {
+                args: args::Encode {
+                    input,
+                    ..args.args.clone()
+                },
+                crf: args.crf,
+                encode: args::EncodeToOutput {
+                    output: Some(chapter_output),
+                    ..args.encode.clone()
+                },
+                chapters: vec![],
+                chunks: None,
+                chunk_jobs: None,
+                vmaf: args.vmaf.clone(),
+                min_vmaf: args.min_vmaf,
+                json: args.json,
+                quiet: args.quiet,
+            },
+            chapter_probe.into(),
+            &bar,
+            &cancel,
+        )
+        .await?;
+    }
+    Ok(())
 }
 
 pub async fn run(
@@ -56,68 +187,261 @@ pub async fn run(
                 output,
                 audio_codec,
                 downmix_to_stereo,
+                abitrate_per_channel,
                 video_only,
+                mux_flags,
+                preview_port,
+                preview_bind,
+                existing,
+                also_encode,
+                energy,
+                live_vmaf,
+                no_hdr_passthrough,
             },
+        chapters: _,
+        chunks: _,
+        chunk_jobs: _,
+        vmaf,
+        min_vmaf,
+        json,
+        quiet: _,
     }: Args,
     probe: Arc<Ffprobe>,
     bar: &ProgressBar,
+    cancel: &CancellationToken,
 ) -> anyhow::Result<()> {
     let defaulting_output = output.is_none();
     // let probe = ffprobe::probe(&args.input);
-    let output =
-        output.unwrap_or_else(|| default_output_name(&args.input, &args.encoder, probe.is_image));
-    // output is temporary until encoding has completed successfully
-    temporary::add(&output, TempKind::NotKeepable);
-
+    let output = output.unwrap_or_else(|| {
+        naming::default_output_name(&args.input, args.encoder.as_str(), probe.is_image)
+    });
     if defaulting_output {
         let out = shell_escape::escape(output.display().to_string().into());
-        bar.println(style!("Encoding {out}").dim().to_string());
+        progress::println(bar, style!("Encoding {out}").dim().to_string());
     }
     bar.set_message("encoding, ");
 
     let mut enc_args = args.to_encoder_args(crf, &probe)?;
     enc_args.video_only = video_only;
+    enc_args
+        .output_args
+        .extend(args.force_keyframes_args(&probe)?);
     let has_audio = probe.has_audio;
     if let Ok(d) = &probe.duration {
-        bar.set_length(d.as_micros_u64().max(1));
+        bar.set_length(args.output_duration(*d).as_micros_u64().max(1));
     }
 
     // only downmix if achannels > 3
-    let stereo_downmix = downmix_to_stereo && probe.max_audio_channels.map_or(false, |c| c > 3);
+    let stereo_downmix = downmix_to_stereo && probe.max_audio_channels.is_some_and(|c| c > 3);
     let audio_codec = audio_codec.as_deref();
-    if stereo_downmix && audio_codec == Some("copy") {
-        anyhow::bail!("--stereo-downmix cannot be used with --acodec copy");
+    if audio_codec == Some("copy") {
+        ensure!(
+            !stereo_downmix,
+            "--downmix-to-stereo cannot be used with --acodec copy"
+        );
+        ensure!(
+            abitrate_per_channel.is_none(),
+            "--abitrate-per-channel cannot be used with --acodec copy"
+        );
+    }
+    let audio_channels = match stereo_downmix {
+        true => Some(2),
+        false => probe.max_audio_channels,
+    };
+    let opus_bitrate = ffmpeg::opus_bitrate(audio_channels, abitrate_per_channel);
+
+    args::rename_existing(&output, bar)?;
+    // output is temporary until encoding has completed successfully
+    temporary::add(&output, TempKind::NotKeepable);
+
+    let preview = match preview_port {
+        Some(port) => {
+            let addr = SocketAddr::new(preview_bind, port);
+            let preview = Preview::serve(addr, &output, probe.duration.as_ref().ok().copied())?;
+            let url = match preview_bind.is_loopback() || preview_bind.is_unspecified() {
+                true => format!("http://localhost:{port}"),
+                false => format!("http://{addr}"),
+            };
+            progress::println(bar, style!("Serving preview at {url}").dim().to_string());
+            Some(preview)
+        }
+        None => None,
+    };
+
+    let encode_timer = timings::start(Phase::Encode);
+    let also_output = also_encode
+        .as_ref()
+        .map(|also| also.output(&args.input, probe.is_image));
+    if let Some(also) = &also_output {
+        ensure!(
+            *also != output,
+            "--also-encode output {} is the same as the main output",
+            also.display()
+        );
+    }
+    // same existing output policy as the main output, skipping only the also-encode
+    let also_output = match also_output {
+        Some(also) if !existing.check_output(&also)? => None,
+        also => also,
+    };
+    if let Some(also_output) = &also_output {
+        args::rename_existing(also_output, bar)?;
+        temporary::add(also_output, TempKind::NotKeepable);
+        let out = shell_escape::escape(also_output.display().to_string().into());
+        progress::println(bar, style!("Also encoding {out}").dim().to_string());
+    }
+
+    let meter = energy.then(|| energy::Meter::start(cancel));
+    if meter.as_ref().is_some_and(Option::is_none) {
+        progress::println(
+            bar,
+            format!(
+                "{} energy metering unavailable, requires RAPL or powermetrics access",
+                style("Warning:").yellow().bold(),
+            ),
+        );
     }
 
-    let mut enc = ffmpeg::encode(enc_args, &output, has_audio, audio_codec, stereo_downmix)?;
+    let live_vmaf = match live_vmaf::unsupported(&output, &args) {
+        _ if !live_vmaf || probe.is_image => None,
+        Some(reason) => {
+            progress::println(
+                bar,
+                format!(
+                    "{} --live-vmaf {reason}, ignoring",
+                    style("Warning:").yellow().bold()
+                ),
+            );
+            None
+        }
+        None => Some(LiveVmaf::start(
+            bar.clone(),
+            (args.input.clone(), output.clone()),
+            vmaf.unwrap_or_default(),
+            enc_args.ref_vfilter.as_deref().map(str::to_owned),
+            min_vmaf,
+            cancel,
+        )),
+    };
+
+    let hdr = match no_hdr_passthrough {
+        true => None,
+        false => extract_hdr(&args, &probe, bar).await,
+    };
+    if let Some(metadata) = &hdr {
+        enc_args.output_args.extend(metadata.encoder_args());
+    }
 
     let mut stream_sizes = None;
-    while let Some(progress) = enc.next().await {
-        match progress? {
-            FfmpegOut::Progress { fps, time, .. } => {
-                if fps > 0.0 {
-                    bar.set_message(format!("{fps} fps, "));
-                }
-                if probe.duration.is_ok() {
-                    bar.set_position(time.as_micros_u64());
+    let mut attempt = 0;
+    cancel::until(cancel, async {
+        'encode: loop {
+            let mut enc = ffmpeg::encode(
+                enc_args.clone(),
+                &output,
+                has_audio,
+                audio_codec,
+                stereo_downmix,
+                opus_bitrate,
+                &mux_flags,
+                also_encode.as_ref().zip(also_output.as_deref()),
+            )?;
+            while let Some(progress) = enc.next().await {
+                let progress = match progress {
+                    Err(err) if attempt < process::IO_RETRIES && process::is_io_error(&err) => {
+                        attempt += 1;
+                        let backoff = process::io_retry_backoff(attempt);
+                        progress::println(
+                            bar,
+                            format!(
+                                "{} encode i/o error, retrying in {}s ({attempt}/{})",
+                                style("Warning:").yellow().bold(),
+                                backoff.as_secs(),
+                                process::IO_RETRIES,
+                            ),
+                        );
+                        tokio::time::sleep(backoff).await;
+                        bar.set_position(0);
+                        continue 'encode;
+                    }
+                    progress => progress?,
+                };
+                match progress {
+                    FfmpegOut::Progress { fps, time, .. } => {
+                        if let Some(preview) = &preview {
+                            preview.progress(time, fps);
+                        }
+                        if let Some(live_vmaf) = &live_vmaf {
+                            live_vmaf.progress(time);
+                        }
+                        match live_vmaf.as_ref().and_then(LiveVmaf::estimate) {
+                            Some(vmaf) if fps > 0.0 => {
+                                bar.set_message(format!("{fps} fps, vmaf ~{vmaf:.1}, "))
+                            }
+                            _ if fps > 0.0 => bar.set_message(format!("{fps} fps, ")),
+                            _ => {}
+                        }
+                        if probe.duration.is_ok() {
+                            bar.set_position(time.as_micros_u64());
+                        }
+                    }
+                    FfmpegOut::StreamSizes {
+                        video,
+                        audio,
+                        subtitle,
+                        other,
+                    } => stream_sizes = Some((video, audio, subtitle, other)),
                 }
             }
-            FfmpegOut::StreamSizes {
-                video,
-                audio,
-                subtitle,
-                other,
-            } => stream_sizes = Some((video, audio, subtitle, other)),
+            break Ok(());
         }
+    })
+    .await?;
+    drop(encode_timer);
+    let live_vmaf = live_vmaf.and_then(|l| l.estimate());
+    let kwh = meter.flatten().and_then(energy::Meter::stop);
+    if let Some(metadata) = &hdr {
+        inject_hdr(&output, metadata, &probe, &mux_flags, bar).await;
     }
     bar.finish();
 
     // successful encode, so don't delete it!
     temporary::unadd(&output);
+    if let Some(also_output) = &also_output {
+        temporary::unadd(also_output);
+    }
 
     // print output info
     let output_size = fs::metadata(&output).await?.len();
-    let output_percent = 100.0 * output_size as f64 / fs::metadata(&args.input).await?.len() as f64;
+    let input_size = fs::metadata(&args.input).await?.len();
+    let output_percent = 100.0 * output_size as f64 / input_size as f64;
+    // kWh per GB saved, if smaller
+    let kwh_per_gb = kwh
+        .filter(|_| input_size > output_size)
+        .map(|kwh| kwh / ((input_size - output_size) as f64 / 1e9));
+    if json {
+        let mut json = serde_json::json!({
+            "output": output,
+            "size": output_size,
+            "encode_percent": output_percent,
+        });
+        if let Some(kwh) = kwh {
+            json["energy_kwh"] = kwh.into();
+            json["kwh_per_gb_saved"] = kwh_per_gb.into();
+        }
+        if let Some(vmaf) = live_vmaf {
+            json["live_vmaf"] = vmaf.into();
+        }
+        if let Some((video, audio, subtitle, other)) = stream_sizes {
+            json["stream_sizes"] = serde_json::json!({
+                "video": video,
+                "audio": audio,
+                "subtitle": subtitle,
+                "other": other,
+            });
+        }
+        machine::print_json(&json);
+    }
     let output_size = style(HumanBytes(output_size)).dim().bold();
     let output_percent = style!("{}%", output_percent.round()).dim().bold();
     eprint!(
@@ -142,25 +466,430 @@ pub async fn run(
     }
     eprintln!("{}", style(")").dim());
 
+    if let Some(kwh) = kwh {
+        let mut msg = format!("Energy {kwh:.3} kWh");
+        if let Some(per_gb) = kwh_per_gb {
+            msg += &format!(", {per_gb:.3} kWh per GB saved");
+        }
+        if let Some(duration) = probe.duration.as_ref().ok().filter(|d| !d.is_zero()) {
+            let key = history::Key::energy(&args, &probe);
+            let kwh_per_hour = (kwh / (duration.as_secs_f64() / 3600.0)) as f32;
+            if let Some(median) = history::prior_energy(key.clone()).await {
+                msg += &format!(
+                    ", {kwh_per_hour:.3} kWh per input hour vs {median:.3} median of similar encodes"
+                );
+            }
+            history::record_energy(key, kwh_per_hour).await;
+        }
+        eprintln!("{}", style(msg).dim());
+    }
+
+    Ok(())
+}
+
+/// Encode `chunks` keyframe-aligned parts of the input in parallel & concatenate them.
+async fn run_chunked(
+    Args {
+        args,
+        crf,
+        encode:
+            args::EncodeToOutput {
+                mux_flags,
+                no_hdr_passthrough,
+                ..
+            },
+        chunk_jobs,
+        vmaf,
+        json,
+        ..
+    }: Args,
+    probe: &Ffprobe,
+    output: PathBuf,
+    chunks: u32,
+    bar: &ProgressBar,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let duration = probe.duration.clone()?;
+    ensure!(!probe.is_image, "--chunks cannot be used with image input");
+
+    let hdr = match no_hdr_passthrough {
+        true => None,
+        false => extract_hdr(&args, probe, bar).await,
+    };
+
+    bar.set_message("splitting, ");
+    let (parts, input_frames) = cancel::until(
+        cancel,
+        sample::split_scenes(
+            &args.input,
+            &chunk_cuts(duration, chunks),
+            None,
+            probe.dynamic_hdr.any(),
+        ),
+    )
+    .await?;
+    let jobs = chunk_jobs.map_or_else(default_chunk_jobs, |j| j as usize);
+    progress::println(
+        bar,
+        style!("Encoding {} chunks, {jobs} at a time", parts.len())
+            .dim()
+            .to_string(),
+    );
+
+    let _encode_timer = timings::start(Phase::Encode);
+    let ext = naming::pre_extension_name(args.encoder.as_str()).to_owned();
+    let mut encoded = Vec::with_capacity(parts.len());
+    let mut encodes = StreamMap::new();
+    let mut pending = parts.iter().enumerate();
+
+    // aggregate progress of all chunk encodes
+    bar.set_message("encoding, ");
+    bar.set_length(args.output_duration(duration).as_micros_u64().max(1));
+    let mut progress = vec![(Duration::ZERO, 0.0); parts.len()];
+    loop {
+        while encodes.len() < jobs {
+            let Some((idx, part)) = pending.next() else {
+                break;
+            };
+            let part_probe = ffprobe::probe(&part.path);
+            let part_args = args::Encode {
+                input: part.path.clone(),
+                ..args.clone()
+            };
+            let mut enc_args = part_args.to_encoder_args(crf, &part_probe)?;
+            enc_args.video_only = true;
+            enc_args
+                .output_args
+                .extend(part_args.force_keyframes_args(&part_probe)?);
+            if let Some(metadata) = &hdr {
+                enc_args.output_args.extend(metadata.encoder_args());
+            }
+            let part_output = part
+                .path
+                .with_file_name(naming::temp_file_name(&part.path, &format!("{ext}.mkv")));
+            temporary::add(&part_output, TempKind::NotKeepable);
+            let enc = ffmpeg::encode(enc_args, &part_output, false, None, false, 0, &[], None)?;
+            // end with `None` so the next chunk can start
+            encodes.insert(idx, Box::pin(enc.map(Some).chain(tokio_stream::once(None))));
+            encoded.push(part_output);
+        }
+        let Some((idx, out)) = cancel::until(cancel, async { Ok(encodes.next().await) }).await?
+        else {
+            break;
+        };
+        match out {
+            Some(out) => {
+                if let FfmpegOut::Progress { fps, time, .. } = out? {
+                    progress[idx] = (time, fps);
+                }
+            }
+            None => {
+                encodes.remove(&idx);
+                progress[idx].1 = 0.0;
+            }
+        }
+        let fps: f32 = progress.iter().map(|(_, fps)| fps).sum();
+        if fps > 0.0 {
+            bar.set_message(format!("{fps:.1} fps, "));
+        }
+        let time: Duration = progress.iter().map(|(time, _)| *time).sum();
+        bar.set_position(time.as_micros_u64());
+    }
+
+    // output is temporary until encoding has completed successfully
+    args::rename_existing(&output, bar)?;
+    temporary::add(&output, TempKind::NotKeepable);
+    bar.set_message("concat, ");
+    cancel::until(
+        cancel,
+        ffmpeg::concat(&encoded, &args.input, &output, &mux_flags, None),
+    )
+    .await?;
+
+    bar.set_message("verifying, ");
+    let timing_preserved = timing_preserved(&args, probe);
+    verify_concat(&output, &encoded, input_frames.filter(|_| timing_preserved))?;
+    let splices = match timing_preserved {
+        true => {
+            let cuts: Vec<_> = parts[1..].iter().map(|p| p.start).collect();
+            let ref_vfilter = args.to_encoder_args(crf, probe)?.ref_vfilter;
+            score_splices(
+                (&args.input, &output),
+                &cuts,
+                &vmaf.unwrap_or_default(),
+                ref_vfilter.as_deref(),
+                bar,
+            )
+            .await
+        }
+        false => vec![],
+    };
+
+    if let Some(metadata) = &hdr {
+        inject_hdr(&output, metadata, probe, &mux_flags, bar).await;
+    }
+    bar.finish();
+    // successful encode, so don't delete it!
+    temporary::unadd(&output);
+
+    let output_size = fs::metadata(&output).await?.len();
+    let output_percent = 100.0 * output_size as f64 / fs::metadata(&args.input).await?.len() as f64;
+    if json {
+        machine::print_json(&serde_json::json!({
+            "output": output,
+            "size": output_size,
+            "encode_percent": output_percent,
+            "chunks": parts.len(),
+            "splices": splices.iter().map(Splice::to_json).collect::<Vec<_>>(),
+        }));
+    }
+    eprintln!(
+        "{} {} {}",
+        style("Encoded").dim(),
+        style(HumanBytes(output_size)).dim().bold(),
+        style!("({}%)", output_percent.round()).dim(),
+    );
+    Ok(())
+}
+
+/// Extract input Dolby Vision & HDR10+ metadata to re-inject with [`inject_hdr`],
+/// or to code with the encoder args [`hdr::Metadata::encoder_args`].
+///
+/// Prints a warning if the input has dynamic HDR metadata that won't be preserved.
+async fn extract_hdr(
+    args: &args::Encode,
+    probe: &Ffprobe,
+    bar: &ProgressBar,
+) -> Option<hdr::Metadata> {
+    if !probe.dynamic_hdr.any() {
+        return None;
+    }
+    let extracted = match hdr::passthrough(args, probe) {
+        Err(reason) => Err(anyhow!("passthrough {reason}")),
+        Ok(hdr::Passthrough::Encoder) => {
+            if probe.dynamic_hdr.hdr10_plus {
+                progress::println(
+                    bar,
+                    format!(
+                        "{} HDR10+ metadata will not be preserved, passthrough requires an hevc encoder",
+                        style("Warning:").yellow().bold(),
+                    ),
+                );
+            }
+            Ok(hdr::Metadata::encoder())
+        }
+        Ok(hdr::Passthrough::Tools) => {
+            bar.set_message("hdr metadata, ");
+            hdr::extract(&args.input, probe.dynamic_hdr, None).await
+        }
+    };
+    extracted
+        .map_err(|err| {
+            progress::println(
+                bar,
+                format!(
+                    "{} dynamic HDR metadata will not be preserved, {err:#}",
+                    style("Warning:").yellow().bold(),
+                ),
+            )
+        })
+        .ok()
+}
+
+/// Inject extracted dynamic HDR metadata into the encoded `output`.
+///
+/// Failure only prints a warning, keeping the encoded output without metadata.
+async fn inject_hdr(
+    output: &Path,
+    metadata: &hdr::Metadata,
+    probe: &Ffprobe,
+    mux_flags: &[(Arc<str>, Arc<str>)],
+    bar: &ProgressBar,
+) {
+    let Ok(fps) = probe.fps.clone() else {
+        return;
+    };
+    if !metadata.needs_inject() {
+        return;
+    }
+    bar.set_message("hdr metadata, ");
+    if let Err(err) = hdr::inject(output, metadata, fps, mux_flags, None).await {
+        progress::println(
+            bar,
+            format!(
+                "{} dynamic HDR metadata passthrough failed, {err:#}",
+                style("Warning:").yellow().bold(),
+            ),
+        );
+    }
+}
+
+/// Default max chunk encodes running at once, a quarter of the logical cpus.
+fn default_chunk_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| (n.get() / 4).max(1))
+}
+
+/// Returns `true` if output frames map 1:1 to input frames with the same timestamps.
+fn timing_preserved(args: &args::Encode, probe: &Ffprobe) -> bool {
+    let fps = probe.fps.as_ref().ok().copied();
+    match &args.vfilter {
+        Some(vf) => vfilter::output_fps(vf, fps).is_none() && vfilter::time_scale(vf) == 1.0,
+        None => true,
+    }
+}
+
+/// Verify the concatenated `output` has exactly the frames of the `encoded` chunks,
+/// & the `input_frames` copied when splitting if frames should map 1:1, with
+/// increasing timestamps.
+fn verify_concat(
+    output: &Path,
+    encoded: &[PathBuf],
+    input_frames: Option<u64>,
+) -> anyhow::Result<()> {
+    let dts = ffprobe::probe_packet_dts(output)?;
+    ensure_increasing(&dts).context("concat verification failed")?;
+
+    let mut chunk_frames = 0;
+    for chunk in encoded {
+        chunk_frames += ffprobe::probe_packet_dts(chunk)?.len();
+    }
+    ensure!(
+        dts.len() == chunk_frames,
+        "concat verification failed, output has {} frames but encoded chunks have {chunk_frames}",
+        dts.len(),
+    );
+    if let Some(input_frames) = input_frames {
+        ensure!(
+            dts.len() as u64 == input_frames,
+            "concat verification failed, output has {} frames but input has {input_frames}",
+            dts.len(),
+        );
+    }
     Ok(())
 }
 
-/// * vid.mp4 -> "mp4"
-/// * vid.??? -> "mkv"
-/// * image.??? -> "avif"
-pub fn default_output_ext(input: &Path, is_image: bool) -> &'static str {
-    if is_image {
-        return "avif";
+/// Ensure known packet timestamps strictly increase, i.e. there are no duplicate
+/// or out of order frames at chunk boundaries.
+fn ensure_increasing(dts: &[Option<f64>]) -> anyhow::Result<()> {
+    let mut last = None;
+    for (idx, dts) in dts.iter().enumerate().filter_map(|(i, d)| Some((i, (*d)?))) {
+        if let Some((last_idx, last)) = last.filter(|(_, last)| dts <= *last) {
+            anyhow::bail!("frame {idx} timestamp {dts}s is not after frame {last_idx} {last}s");
+        }
+        last = Some((idx, dts));
     }
-    match input.extension().and_then(|e| e.to_str()) {
-        Some("mp4") => "mp4",
-        _ => "mkv",
+    Ok(())
+}
+
+/// Duration of splice & preceding vmaf windows.
+const SPLICE_WINDOW: Duration = Duration::from_secs(2);
+/// Splice windows scoring more than this below the preceding window are warned about.
+const SPLICE_VMAF_TOLERANCE: f32 = 3.0;
+
+/// Vmaf scores of the window around a chunk splice & the window before it.
+struct Splice {
+    time: f64,
+    vmaf: f32,
+    prev_vmaf: f32,
+}
+
+impl Splice {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "time": self.time,
+            "vmaf": self.vmaf,
+            "prev_vmaf": self.prev_vmaf,
+        })
+    }
+}
+
+/// Score a window around each splice point `cuts` & the window before it, warning if
+/// a splice scores notably worse.
+///
+/// Scoring failures only print a warning.
+async fn score_splices(
+    (input, output): (&Path, &Path),
+    cuts: &[f64],
+    vmaf: &args::Vmaf,
+    ref_vfilter: Option<&str>,
+    bar: &ProgressBar,
+) -> Vec<Splice> {
+    let (rprobe, dprobe) = (ffprobe::probe(input), ffprobe::probe(output));
+    let lavfi = vmaf.ffmpeg_lavfi(
+        dprobe.display_resolution(),
+        vmaf.pix_fmt(dprobe.pixel_format(), rprobe.pixel_format()),
+        ref_vfilter,
+    );
+    let mut splices = vec![];
+    for &cut in cuts {
+        let time = Duration::from_secs_f64(cut);
+        let Some(prev_start) = time.checked_sub(SPLICE_WINDOW + SPLICE_WINDOW / 2) else {
+            continue;
+        };
+        let splice = (time - SPLICE_WINDOW / 2, SPLICE_WINDOW);
+        let scores = async {
+            let prev = vmaf::window_score(input, output, &lavfi, (prev_start, SPLICE_WINDOW));
+            Ok::<_, anyhow::Error>((
+                prev.await?,
+                vmaf::window_score(input, output, &lavfi, splice).await?,
+            ))
+        };
+        let (prev_vmaf, vmaf) = match scores.await {
+            Ok((Some(prev), Some(splice))) => (prev, splice),
+            Ok(_) => continue,
+            Err(err) => {
+                progress::println(
+                    bar,
+                    format!(
+                        "{} chunk splice vmaf failed, {err:#}",
+                        style("Warning:").yellow().bold(),
+                    ),
+                );
+                break;
+            }
+        };
+        if vmaf < prev_vmaf - SPLICE_VMAF_TOLERANCE {
+            progress::println(
+                bar,
+                format!(
+                    "{} chunk splice at {cut:.1}s vmaf {vmaf:.2} vs {prev_vmaf:.2} before, \
+                     possible concat artifact",
+                    style("Warning:").yellow().bold(),
+                ),
+            );
+        }
+        splices.push(Splice {
+            time: cut,
+            vmaf,
+            prev_vmaf,
+        });
     }
+    splices
+}
+
+/// Evenly spaced input times (seconds) splitting `duration` into `chunks`.
+fn chunk_cuts(duration: Duration, chunks: u32) -> Vec<f64> {
+    let len = duration.as_secs_f64() / f64::from(chunks);
+    (1..chunks).map(|n| len * f64::from(n)).collect()
 }
 
-/// E.g. vid.mkv -> "vid.av1.mkv"
-pub fn default_output_name(input: &Path, encoder: &Encoder, is_image: bool) -> PathBuf {
-    let pre = ffmpeg::pre_extension_name(encoder.as_str());
-    let ext = default_output_ext(input, is_image);
-    input.with_extension(format!("{pre}.{ext}"))
+#[test]
+fn chunk_cuts_even() {
+    assert_eq!(
+        chunk_cuts(Duration::from_secs(120), 4),
+        vec![30.0, 60.0, 90.0]
+    );
+    assert!(chunk_cuts(Duration::from_secs(120), 1).is_empty());
+}
+
+#[test]
+fn concat_timestamps_increasing() {
+    assert!(ensure_increasing(&[Some(0.0), None, Some(0.04), Some(0.08)]).is_ok());
+    // duplicate frame at a boundary
+    let err = ensure_increasing(&[Some(0.0), Some(0.04), Some(0.04)]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "frame 2 timestamp 0.04s is not after frame 1 0.04s"
+    );
+    assert!(ensure_increasing(&[Some(0.08), Some(0.04)]).is_err());
 }
@@ -1,4 +1,4 @@
-use clap::{CommandFactory, Parser};
+use clap::Parser;
 use clap_complete::Shell;
 
 /// Print shell completions.
@@ -10,12 +10,8 @@ pub struct Args {
     shell: Shell,
 }
 
-pub fn print_completions(Args { shell }: Args) -> anyhow::Result<()> {
-    clap_complete::generate(
-        shell,
-        &mut crate::Command::command(),
-        "ab-av1",
-        &mut std::io::stdout(),
-    );
+/// Print `cli` completions for `shell`.
+pub fn print_completions(Args { shell }: Args, mut cli: clap::Command) -> anyhow::Result<()> {
+    clap_complete::generate(shell, &mut cli, "ab-av1", &mut std::io::stdout());
     Ok(())
 }
@@ -0,0 +1,69 @@
+//! Hierarchical progress bar logic.
+use crate::{command::PROGRESS_CHARS, machine};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Hide progress bars & don't print [`println`] lines, including warnings.
+/// Set by the global --quiet for commands without per-sample/attempt lines.
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Print a line above `bar`, or to stderr if the bar is hidden as stderr isn't a terminal.
+///
+/// Lines are not printed in machine or quiet mode.
+pub fn println(bar: &ProgressBar, msg: impl Into<String>) {
+    if quiet() {
+        return;
+    }
+    match bar.is_hidden() {
+        true if !machine::enabled() => eprintln!("{}", msg.into()),
+        true => {}
+        false => bar.println(msg.into()),
+    }
+}
+
+/// An overall progress bar with per-task progress bars drawn beneath it.
+#[derive(Debug, Clone)]
+pub struct ProgressTree {
+    multi: MultiProgress,
+    overall: ProgressBar,
+}
+
+impl ProgressTree {
+    pub fn new(overall: ProgressBar) -> Self {
+        let multi = match overall.is_hidden() {
+            true => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            false => MultiProgress::new(),
+        };
+        let overall = multi.add(overall);
+        Self { multi, overall }
+    }
+
+    /// Add a new task bar beneath the overall bar.
+    ///
+    /// Returns a hidden bar if the overall bar is hidden.
+    pub fn add_task(&self) -> ProgressBar {
+        if self.overall.is_hidden() {
+            return ProgressBar::hidden();
+        }
+        let task = ProgressBar::new(1).with_style(
+            ProgressStyle::default_bar()
+                .template("  {prefix:.dim} {wide_bar:.cyan/blue} ({msg:13} eta {eta})")
+                .expect("valid template")
+                .progress_chars(PROGRESS_CHARS),
+        );
+        self.multi.add(task)
+    }
+
+    /// Fraction `[0, 1]` of the task bar completed.
+    pub fn task_fraction(task: &ProgressBar) -> f64 {
+        task.position() as f64 / task.length().unwrap_or(1).max(1) as f64
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    cancel::{self, CancellationToken},
+    command::{args, progress, SmallDuration, PROGRESS_CHARS},
+    console_ext::style,
+    ffmpeg, ffprobe, machine,
+    process::FfmpegOut,
+    temporary::{self, TempKind},
+};
+use clap::{Parser, ValueHint};
+use console::style;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio_stream::StreamExt;
+
+/// Fast low-res sped up proxy encode for quick content review, e.g. to decide
+/// crop or trim parameters before full processing.
+///
+/// * Keeps every nth frame at the input frame rate, e.g. 4x speed.
+/// * Burns in the original input timecode.
+/// * Audio is not included.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Input video file.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub input: PathBuf,
+
+    /// Output file, by default the same as input with `.proxy.mkv` as the extension.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub existing: args::ExistingOutput,
+
+    /// Output height, width is scaled to preserve the aspect ratio.
+    #[arg(long, default_value_t = 480)]
+    pub height: u32,
+
+    /// Playback speed multiplier.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    pub speed: u32,
+
+    /// Don't burn in the input timecode.
+    #[arg(long)]
+    pub no_timecode: bool,
+
+    /// Print the result to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn proxy(
+    Args {
+        input,
+        output,
+        existing,
+        height,
+        speed,
+        no_timecode,
+        json,
+    }: Args,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let output = output.unwrap_or_else(|| default_output(&input));
+    if !existing.check_output(&output)? {
+        return Ok(());
+    }
+    let bar = ProgressBar::new(1).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
+            .progress_chars(PROGRESS_CHARS)
+    );
+    machine::watch(&bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar.set_message("proxy encoding, ");
+
+    let probe = ffprobe::probe(&input);
+    // ffmpeg reports the sped up output time
+    if let Ok(d) = &probe.duration {
+        bar.set_length((*d / speed).as_micros_u64().max(1));
+    }
+    let timecode = !no_timecode && ffmpeg::has_filter("drawtext") != Some(false);
+    if !no_timecode && !timecode {
+        progress::println(
+            &bar,
+            format!(
+                "{} ffmpeg drawtext filter unavailable, not burning in timecode",
+                style("Warning:").yellow().bold(),
+            ),
+        );
+    }
+
+    // output is temporary until encoding has completed successfully
+    args::rename_existing(&output, &bar)?;
+    temporary::add(&output, TempKind::NotKeepable);
+    let mut enc = ffmpeg::proxy(&input, &output, height, speed, timecode)?;
+    cancel::until(&cancel, async {
+        while let Some(progress) = enc.next().await {
+            if let FfmpegOut::Progress { fps, time, .. } = progress? {
+                if fps > 0.0 {
+                    bar.set_message(format!("proxy {fps} fps, "));
+                }
+                bar.set_position(time.as_micros_u64());
+            }
+        }
+        Ok(())
+    })
+    .await?;
+    bar.finish();
+    // successful encode, so don't delete it!
+    temporary::unadd(&output);
+
+    let output_size = tokio::fs::metadata(&output).await?.len();
+    if json {
+        machine::print_json(&serde_json::json!({
+            "output": output,
+            "size": output_size,
+        }));
+    }
+    eprintln!(
+        "{} {} {}",
+        style("Proxy").dim(),
+        style(HumanBytes(output_size)).dim().bold(),
+        style!("{}", output.display()).dim(),
+    );
+    Ok(())
+}
+
+fn default_output(input: &Path) -> PathBuf {
+    input.with_extension("proxy.mkv")
+}
+
+#[test]
+fn proxy_default_output() {
+    assert_eq!(
+        default_output(Path::new("/videos/film.mp4")),
+        Path::new("/videos/film.proxy.mkv")
+    );
+}
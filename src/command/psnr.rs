@@ -0,0 +1,150 @@
+use crate::{
+    command::{args::PixelFormat, progress, PROGRESS_CHARS},
+    console_ext::style,
+    ffprobe, machine, naming,
+    plot::{self, Plot},
+    process::FfmpegOut,
+    psnr::{self, PsnrOut},
+    stats::{percentile, Stats},
+    temporary::{self, TempKind},
+};
+use anyhow::ensure;
+use clap::{Parser, ValueHint};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{path::PathBuf, time::Duration};
+use tokio_stream::StreamExt;
+
+/// Full PSNR score calculation, distorted file vs reference file.
+/// Works with videos and images.
+///
+/// * Scales distorted to the reference resolution, if different.
+/// * Prints the average PSNR & per-frame mean, min & 5th percentile values.
+///   Identical frames ("inf") count as 100.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Reference video file.
+    #[arg(long)]
+    pub reference: PathBuf,
+
+    /// Ffmpeg video filter applied to the reference before analysis.
+    /// E.g. --reference-vfilter "scale=1280:-1,fps=24".
+    #[arg(long)]
+    pub reference_vfilter: Option<String>,
+
+    /// Re-encoded/distorted video file.
+    #[arg(long)]
+    pub distorted: PathBuf,
+
+    /// Pixel format to convert distorted & reference to for analysis.
+    /// Defaults to the reference format, if known.
+    #[arg(long, value_enum)]
+    pub metric_pix_fmt: Option<PixelFormat>,
+
+    /// Verify the average PSNR is at least this value, exiting with an error otherwise.
+    #[arg(long)]
+    pub min_psnr: Option<f32>,
+
+    /// Write a plot of per-frame scores to this ".svg" or ".png" file.
+    #[arg(long, value_hint = ValueHint::FilePath, value_parser = plot::parse_path)]
+    pub plot: Option<PathBuf>,
+
+    /// Print the score to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn psnr(
+    Args {
+        reference,
+        reference_vfilter,
+        distorted,
+        metric_pix_fmt,
+        min_psnr,
+        plot,
+        json,
+    }: Args,
+) -> anyhow::Result<()> {
+    let bar = ProgressBar::new(1).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
+            .progress_chars(PROGRESS_CHARS)
+    );
+    machine::watch(&bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar.set_message("psnr running, ");
+
+    let dprobe = ffprobe::probe(&distorted);
+    let rprobe = ffprobe::probe(&reference);
+    let pix_fmt = metric_pix_fmt
+        .or(rprobe.pixel_format())
+        .or(dprobe.pixel_format())
+        .unwrap_or(PixelFormat::Yuv420p);
+    progress::println(
+        &bar,
+        style!("psnr pixel format {pix_fmt}").dim().to_string(),
+    );
+    let nframes = dprobe.nframes().or_else(|_| rprobe.nframes());
+    if let Ok(nframes) = nframes {
+        bar.set_length(nframes);
+    }
+
+    let mut stats_file = temporary::process_dir(None);
+    stats_file.push(naming::temp_file_name(&distorted, "psnr.log"));
+    temporary::add(&stats_file, TempKind::NotKeepable);
+
+    let mut psnr_out = psnr::run(
+        &reference,
+        &distorted,
+        &psnr::ffmpeg_lavfi(pix_fmt, reference_vfilter.as_deref(), &stats_file),
+    )?;
+    let mut psnr_score = None;
+    while let Some(next) = psnr_out.next().await {
+        match next {
+            PsnrOut::Done(score) => psnr_score = Some(score),
+            PsnrOut::Progress(FfmpegOut::Progress { frame, fps, .. }) => {
+                if fps > 0.0 {
+                    bar.set_message(format!("psnr {fps} fps, "));
+                }
+                if nframes.is_ok() {
+                    bar.set_position(frame);
+                }
+            }
+            PsnrOut::Progress(FfmpegOut::StreamSizes { .. }) => {}
+            PsnrOut::Err(e) => return Err(e),
+        }
+    }
+    bar.finish();
+    let psnr_score = psnr_score.ok_or_else(|| anyhow::anyhow!("ffmpeg psnr: no score"))?;
+
+    match json {
+        true => machine::print_json(&serde_json::json!({ "psnr": psnr_score })),
+        false => println!("{psnr_score}"),
+    }
+
+    let scores = psnr::read_frame_scores(&stats_file)?;
+    if let Some(path) = &plot {
+        let mut plot = Plot::new("PSNR (dB)");
+        plot.series("psnr", scores.clone());
+        plot.write(path)?;
+    }
+    if let (Some(stats), Some(p5)) = (Stats::from_scores(&scores), percentile(&scores, 5.0)) {
+        eprintln!(
+            "{}",
+            style!(
+                "frames mean {:.2}, min {:.2}, p5 {p5:.2}",
+                stats.mean,
+                stats.min
+            )
+            .dim()
+        );
+    }
+    if let Some(min_psnr) = min_psnr {
+        ensure!(
+            psnr_score >= min_psnr,
+            "PSNR {psnr_score:.2} is below --min-psnr {min_psnr}"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use crate::{
+    cancel::CancellationToken,
+    command::{args, crf_search, progress, PROGRESS_CHARS},
+    console_ext::style,
+    ffprobe,
+    float::TerseF32,
+    machine,
+};
+use anyhow::ensure;
+use clap::Parser;
+use console::style;
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use std::{sync::Arc, time::Duration};
+
+/// Search crf & preset combinations for the smallest predicted size delivering
+/// min-vmaf & max-encoded-percent.
+///
+/// Runs a crf-search for each of --presets, then prints the Pareto optimal
+/// results (predicted size vs encode time) & the smallest.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    #[clap(flatten)]
+    pub search: crf_search::Args,
+
+    /// Encoder presets to search, e.g. `--presets 4,6,8`.
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub presets: Vec<args::Preset>,
+
+    /// Print results to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn quality_search(
+    Args {
+        mut search,
+        presets,
+        json,
+    }: Args,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let bar = ProgressBar::new(12).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {prefix} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
+            .progress_chars(PROGRESS_CHARS)
+    );
+    machine::watch(&bar);
+
+    let probe = Arc::new(ffprobe::probe(&search.args.input));
+    search.args.resolve_encoder()?;
+    search.args.apply_sar(&probe);
+    search.args.apply_detelecine(&probe);
+    let input_is_image = probe.is_image;
+    search
+        .sample
+        .set_extension_from_input(&search.args.input, &search.args.encoder, &probe);
+    if let Some(warning) = search.vmaf_fallback() {
+        progress::println(&bar, warning);
+    }
+
+    let mut results = vec![];
+    for preset in presets {
+        bar.set_prefix(format!("preset {preset}"));
+        search.args.preset = Some(preset.clone());
+        match crf_search::run(&search, probe.clone(), bar.clone(), &cancel).await {
+            Ok(sample) => results.push((preset, sample)),
+            Err(crf_search::Error::NoGoodCrf { .. }) => progress::println(
+                &bar,
+                style!("preset {preset}: no suitable crf found")
+                    .dim()
+                    .to_string(),
+            ),
+            Err(crf_search::Error::Other(err)) => return Err(err),
+        }
+    }
+    bar.finish();
+
+    let front = pareto_front(results, |(_, s)| {
+        (s.enc.predicted_encode_size, s.enc.predicted_encode_time)
+    });
+    // front is sorted by size, so the first is the smallest
+    ensure!(
+        !front.is_empty(),
+        "Failed to find a suitable crf for any preset"
+    );
+    let (best_preset, best) = &front[0];
+
+    search.args.preset = Some(best_preset.clone());
+    eprintln!(
+        "\n{} {}\n",
+        style("Encode with:").dim(),
+        style(search.args.encode_hint(best.crf())).dim().italic(),
+    );
+
+    if json {
+        let to_json = |(preset, sample): &(args::Preset, crf_search::Sample)| {
+            let mut json = sample.enc.to_json();
+            json["preset"] = preset.to_string().into();
+            json["crf"] = sample.crf().into();
+            json
+        };
+        let pareto: Vec<_> = front.iter().map(to_json).collect();
+        machine::print_json(&serde_json::json!({ "best": to_json(&front[0]), "pareto": pareto }));
+        return Ok(());
+    }
+
+    let enc_description = match input_is_image {
+        true => "image",
+        false => "video stream",
+    };
+    for (idx, (preset, sample)) in front.iter().enumerate() {
+        let enc = &sample.enc;
+        let line = format!(
+            "preset {preset} crf {} {} predicted {enc_description} size {} ({}%) taking {}",
+            TerseF32(sample.crf()),
+            sample.score_label(),
+            HumanBytes(enc.predicted_encode_size),
+            enc.encode_percent.round(),
+            HumanDuration(enc.predicted_encode_time),
+        );
+        match idx {
+            0 => println!("{}", style(line).bold().green()),
+            _ => println!("{line}"),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the items not dominated by another item in both size & time, sorted by size.
+fn pareto_front<T>(mut items: Vec<T>, key: impl Fn(&T) -> (u64, Duration)) -> Vec<T> {
+    items.sort_by_key(|item| key(item));
+    let mut front: Vec<T> = Vec::with_capacity(items.len());
+    for item in items {
+        let (_, time) = key(&item);
+        // sorted by size, so only items faster than the previous are optimal
+        if front.last().is_none_or(|last| time < key(last).1) {
+            front.push(item);
+        }
+    }
+    front
+}
+
+#[test]
+fn pareto_front_size_vs_time() {
+    let secs = Duration::from_secs;
+    let items = vec![
+        ("p8", 120, secs(60)),
+        ("p4", 100, secs(300)),
+        ("p6", 105, secs(120)),
+        ("p7", 110, secs(150)), // dominated by p6
+        ("p10", 150, secs(60)), // dominated by p8
+    ];
+    let front = pareto_front(items, |(_, size, time)| (*size, *time));
+    let names: Vec<_> = front.iter().map(|(n, ..)| *n).collect();
+    assert_eq!(names, ["p4", "p6", "p8"]);
+}
@@ -1,23 +1,34 @@
-mod cache;
+pub mod cache;
 
 use crate::{
+    cancel::{self, CancellationToken},
     command::{
-        args::{self, PixelFormat},
-        SmallDuration, PROGRESS_CHARS,
+        args::{self, PixelFormat, TempBackend, VmafStatistic},
+        progress, SmallDuration, PROGRESS_CHARS,
     },
     console_ext::style,
-    ffmpeg::{self, FfmpegEncodeArgs},
+    ffmpeg::{self, FfmpegEncodeArgs, LoopbackOut},
     ffprobe::{self, Ffprobe},
-    process::FfmpegOut,
-    sample, temporary, vmaf,
+    float::TerseF32,
+    machine, naming,
+    process::{self, FfmpegOut},
+    report::Report,
+    sample,
+    ssim::{self, SsimOut},
+    ssimulacra2,
+    temporary::{self, TempKind},
+    timings::{self, Phase},
+    vmaf,
     vmaf::VmafOut,
+    xpsnr::{self, XpsnrOut},
     SAMPLE_SIZE, SAMPLE_SIZE_S,
 };
-use anyhow::ensure;
+use anyhow::{ensure, Context};
 use clap::{ArgAction, Parser};
 use console::style;
 use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
 use std::{
+    borrow::Cow,
     path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
@@ -61,45 +72,331 @@ pub struct Args {
 
     #[clap(flatten)]
     pub vmaf: args::Vmaf,
+
+    /// Calculate the SSIM "All" score of samples instead of VMAF.
+    #[arg(long)]
+    pub ssim: bool,
+
+    /// Calculate the luma XPSNR score of samples instead of VMAF.
+    /// Requires ffmpeg >= 7.1 with the xpsnr filter.
+    #[arg(long, conflicts_with = "ssim")]
+    pub xpsnr: bool,
+
+    /// Calculate the SSIMULACRA2 score of samples instead of VMAF.
+    /// Requires `ssimulacra2_rs` in PATH.
+    #[arg(long, conflicts_with_all = ["ssim", "xpsnr"])]
+    pub ssimulacra2: bool,
+
+    /// Also calculate the SSIM "All" score alongside VMAF. Set by crf-search --objective.
+    #[arg(skip)]
+    pub with_ssim: bool,
+
+    /// Also calculate VMAF alongside --ssim, --xpsnr or --ssimulacra2.
+    /// Set by crf-search when targeting multiple metrics.
+    #[arg(skip)]
+    pub with_vmaf: bool,
+
+    /// Don't print per-sample results. Set by the global --quiet.
+    #[arg(skip)]
+    pub quiet: bool,
+
+    /// Only sample the specified 1-based chapters, e.g. `--chapters 3,7`.
+    ///
+    /// Each chapter is sampled separately with results printed per chapter,
+    /// useful for spot checking tricky sections.
+    #[arg(long, value_delimiter = ',')]
+    pub chapters: Vec<u32>,
+
+    /// With --chapters, also write the per-chapter summary to this file as csv,
+    /// or json if the file extension is ".json".
+    #[arg(long, requires = "chapters", value_hint = clap::ValueHint::FilePath)]
+    pub summary: Option<PathBuf>,
+
+    /// Directory of existing sample encodes, e.g. kept from a previous run with --keep.
+    /// Samples with a matching encode file name, including an id of the encoder
+    /// settings, are not re-encoded, only scored.
+    ///
+    /// Useful for comparing score settings. Reused encodes are not cached and
+    /// don't contribute to the predicted encode time.
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pub reuse_encodes: Option<PathBuf>,
+
+    /// Write a self-contained html report to this file, with an interactive
+    /// per-frame VMAF graph of all samples, summary stats & encode settings.
+    ///
+    /// Per-frame scores are unavailable for samples cached without them.
+    #[arg(long, conflicts_with = "chapters", value_hint = clap::ValueHint::FilePath)]
+    pub report: Option<PathBuf>,
 }
 
-pub async fn sample_encode(mut args: Args) -> anyhow::Result<()> {
+pub async fn sample_encode(mut args: Args, cancel: CancellationToken) -> anyhow::Result<()> {
     let bar = ProgressBar::new(12).with_style(
         ProgressStyle::default_bar()
             .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {prefix} {wide_bar:.cyan/blue} ({msg:13} eta {eta})")?
             .progress_chars(PROGRESS_CHARS)
     );
+    machine::watch(&bar);
     bar.enable_steady_tick(Duration::from_millis(100));
 
     let probe = ffprobe::probe(&args.args.input);
+    args.args.resolve_encoder()?;
+    args.args.apply_sar(&probe);
+    args.args.apply_detelecine(&probe);
+    let input_is_image = probe.is_image;
     args.sample
-        .set_extension_from_input(&args.args.input, &probe);
-    run(args, probe.into(), bar).await?;
+        .set_extension_from_input(&args.args.input, &args.args.encoder, &probe);
+    if !args.chapters.is_empty() {
+        return sample_encode_chapters(args, probe, bar, cancel).await;
+    }
+    let resolution = probe.display_resolution();
+    let output = run(args.clone(), probe.into(), bar, cancel).await?;
+    if let Some(path) = &args.report {
+        report(&args, &output, resolution).write(path)?;
+    }
+
+    // encode how-to hint + predictions
+    eprintln!(
+        "\n{} {}\n",
+        style("Encode with:").dim(),
+        style(args.args.encode_hint(args.crf)).dim().italic(),
+    );
+    // stdout result
+    args.stdout_format.print_result(&output, input_is_image);
+    Ok(())
+}
+
+async fn sample_encode_chapters(
+    args: Args,
+    probe: Ffprobe,
+    bar: ProgressBar,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let mut results = vec![];
+    for (n, chapter) in ffprobe::probe_chapters(&args.args.input, &args.chapters)? {
+        let input = sample::copy_chapter(
+            &args.args.input,
+            n,
+            chapter,
+            args.sample.temp_dir.clone(),
+            probe.dynamic_hdr.any(),
+            None,
+        )
+        .await?;
+        let chapter_probe = ffprobe::probe(&input);
+        progress::println(&bar, style!("Chapter {n}").bold().to_string());
+        bar.reset();
+        let output = run(
+            Args {
+                args: args::Encode {
+                    input,
+                    ..args.args.clone()
+                },
+                chapters: vec![],
+                ..args.clone()
+            },
+            chapter_probe.into(),
+            bar.clone(),
+            cancel.clone(),
+        )
+        .await?;
+        results.push((n, output));
+    }
+    bar.finish_and_clear();
+    eprintln!(
+        "\n{} {}\n",
+        style("Encode with:").dim(),
+        style(args.args.encode_hint(args.crf)).dim().italic(),
+    );
+    match args.stdout_format {
+        StdoutFormat::Human => print_chapter_table(&results),
+        StdoutFormat::Json => machine::print_json(&chapters_json(&results)),
+    }
+    if let Some(path) = &args.summary {
+        let summary = match path.extension().is_some_and(|e| e == "json") {
+            true => chapters_json(&results).to_string(),
+            false => chapters_csv(&results),
+        };
+        std::fs::write(path, summary)
+            .with_context(|| format!("--summary: write {}", path.display()))?;
+    }
     Ok(())
 }
 
+fn report(args: &Args, output: &Output, resolution: Option<(u32, u32)>) -> Report {
+    let mut report = Report::new(format!("sample-encode {}", args.args.input.display()));
+    if output.has_vmaf() {
+        report.summary("vmaf", output.vmaf);
+        report.summary("vmaf min sample", output.vmaf_min);
+    }
+    let others = [
+        ("ssim", output.ssim.or(output.vmaf_ssim)),
+        ("xpsnr", output.xpsnr),
+        ("ssimulacra2", output.ssimulacra2),
+    ];
+    for (name, score) in others {
+        if let Some(score) = score {
+            report.summary(name, score);
+        }
+    }
+    report.summary("predicted size", HumanBytes(output.predicted_encode_size));
+    report.summary("encode percent", format!("{:.1}%", output.encode_percent));
+    report.summary(
+        "predicted time",
+        HumanDuration(output.predicted_encode_time),
+    );
+    report.summary("encode fps", format!("{:.1}", output.encode_fps));
+
+    report.setting("input", args.args.input.display());
+    report.setting("encoder", args.args.encoder.as_str());
+    report.setting("crf", TerseF32(args.crf));
+    if let Some(preset) = &args.args.preset {
+        report.setting("preset", preset);
+    }
+    if let Some(vf) = &args.args.vfilter {
+        report.setting("vfilter", vf);
+    }
+    if let Some(overlay) = &args.args.overlay {
+        report.setting("overlay", overlay);
+    }
+    report.setting("vmaf model", args.vmaf.model_label(resolution));
+    report.setting("vmaf statistic", args.vmaf.vmaf_statistic);
+    report.setting("encode with", args.args.encode_hint(args.crf));
+    report.series("vmaf (all samples)", output.vmaf_frames.clone());
+    report
+}
+
+/// Print an aligned per-chapter result table.
+fn print_chapter_table(results: &[(u32, Output)]) {
+    use console::{pad_str, Alignment};
+
+    let rows: Vec<[String; 5]> = results
+        .iter()
+        .map(|(n, out)| {
+            let score = match (out.ssim, out.xpsnr, out.ssimulacra2) {
+                (Some(ssim), ..) => style!("SSIM {ssim:.4}").bold(),
+                (_, Some(xpsnr), _) => style!("XPSNR {xpsnr:.2}").bold(),
+                (.., Some(s2)) => style!("SSIMULACRA2 {s2:.2}").bold(),
+                _ => match out.vmaf {
+                    v if v >= 95.0 => style!("VMAF {v:.2}").bold().green(),
+                    v if v < 80.0 => style!("VMAF {v:.2}").bold().red(),
+                    v => style!("VMAF {v:.2}").bold(),
+                },
+            };
+            let percent = match out.encode_percent.round() {
+                v if v < 80.0 => style!("{v}%").green(),
+                v if v >= 100.0 => style!("{v}%").red(),
+                v => style!("{v}%"),
+            };
+            [
+                n.to_string(),
+                score.to_string(),
+                HumanBytes(out.predicted_encode_size).to_string(),
+                percent.to_string(),
+                HumanDuration(out.predicted_encode_time).to_string(),
+            ]
+        })
+        .collect();
+
+    let header = ["chapter", "score", "size", "percent", "taking"];
+    let widths: Vec<_> = (0..header.len())
+        .map(|col| {
+            rows.iter()
+                .map(|r| console::measure_text_width(&r[col]))
+                .chain([header[col].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let line = |cells: [&str; 5]| {
+        let cells: Vec<_> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| pad_str(c, *w, Alignment::Left, None))
+            .collect();
+        cells.join("  ").trim_end().to_owned()
+    };
+    println!("{}", style(line(header)).dim());
+    for row in &rows {
+        println!("{}", line(row.each_ref().map(|c| c.as_str())));
+    }
+}
+
+fn chapters_json(results: &[(u32, Output)]) -> serde_json::Value {
+    results
+        .iter()
+        .map(|(n, out)| {
+            let mut json = out.to_json();
+            json["chapter"] = (*n).into();
+            json
+        })
+        .collect()
+}
+
+fn chapters_csv(results: &[(u32, Output)]) -> String {
+    let mut csv =
+        "chapter,vmaf,ssim,xpsnr,ssimulacra2,predicted_encode_size,encode_percent,predicted_encode_seconds\n"
+            .to_owned();
+    for (n, out) in results {
+        let ssim = out.ssim.map(|s| s.to_string()).unwrap_or_default();
+        let xpsnr = out.xpsnr.map(|s| s.to_string()).unwrap_or_default();
+        let ssimulacra2 = out.ssimulacra2.map(|s| s.to_string()).unwrap_or_default();
+        csv.push_str(&format!(
+            "{n},{},{ssim},{xpsnr},{ssimulacra2},{},{:.2},{}\n",
+            out.vmaf,
+            out.predicted_encode_size,
+            out.encode_percent,
+            out.predicted_encode_time.as_secs(),
+        ));
+    }
+    csv
+}
+
 pub async fn run(
     Args {
         args,
         crf,
         sample: sample_args,
         cache,
-        stdout_format,
-        vmaf,
+        stdout_format: _,
+        mut vmaf,
+        ssim,
+        xpsnr,
+        ssimulacra2,
+        with_ssim,
+        with_vmaf,
+        quiet,
+        chapters: _,
+        summary: _,
+        reuse_encodes,
+        report,
     }: Args,
     input_probe: Arc<Ffprobe>,
     bar: ProgressBar,
+    cancel: CancellationToken,
 ) -> anyhow::Result<Output> {
     let input = Arc::new(args.input.clone());
     let input_pixel_format = input_probe.pixel_format();
     let input_is_image = input_probe.is_image;
+    let println = |msg: String| {
+        if !quiet {
+            progress::println(&bar, msg);
+        }
+    };
     let input_len = fs::metadata(&*input).await?.len();
     let enc_args = args.to_encoder_args(crf, &input_probe)?;
     let duration = input_probe.duration.clone()?;
     let input_fps = input_probe.fps.clone()?;
     let samples = sample_args.sample_count(duration).max(1);
+    let runs = sample_args.samples_per_point.max(1);
     let keep = sample_args.keep;
-    let temp_dir = sample_args.temp_dir;
+    let vmaf_pix_fmt = vmaf.pix_fmt(Some(enc_args.pix_fmt), input_pixel_format);
+    println(style!("vmaf pixel format {vmaf_pix_fmt}").dim().to_string());
+    // previous versions always used the max of both formats, cache keys should only
+    // change when the format is now different
+    let legacy_vmaf_pix_fmt = enc_args
+        .pix_fmt
+        .max(input_pixel_format.unwrap_or(PixelFormat::Yuv444p10le));
+    let cache_vmaf_pix_fmt = (vmaf_pix_fmt != legacy_vmaf_pix_fmt).then_some(vmaf_pix_fmt);
 
     let (samples, sample_duration, full_pass) = {
         if input_is_image {
@@ -112,13 +409,138 @@ pub async fn run(
         }
     };
     let sample_duration_us = sample_duration.as_micros_u64();
-    bar.set_length(sample_duration_us * samples * 2);
+    let temp_dir = match sample_args.temp_backend {
+        TempBackend::Dir => sample_args.temp_dir,
+        TempBackend::Tmpfs => {
+            // room for sample copies & a few encodes of each
+            let sampled = match full_pass {
+                true => 1.0,
+                false => (sample_duration * samples as _).as_secs_f64() / duration.as_secs_f64(),
+            };
+            let needed = (input_len as f64 * sampled.min(1.0) * 3.0) as u64;
+            match temporary::tmpfs_dir(sample_args.temp_dir.as_deref(), needed) {
+                Some(tmpfs) => Some(tmpfs),
+                None => {
+                    // only warn once, e.g. for each crf-search attempt
+                    static WARN: std::sync::Once = std::sync::Once::new();
+                    WARN.call_once(|| {
+                        warn(
+                            &bar,
+                            &format!(
+                                "tmpfs unavailable or has less than {} free, using --temp-dir",
+                                HumanBytes(needed)
+                            ),
+                        )
+                    });
+                    sample_args.temp_dir
+                }
+            }
+        }
+    };
+    bar.set_length(sample_duration_us * samples * runs * 2);
+
+    // Open-GOP leading frames can be broken at stream copied sample starts,
+    // so pad samples by a gop & trim it when decoding
+    let pad_frames = match full_pass {
+        true => 0,
+        false => ffprobe::probe_open_gop(&input, cache).unwrap_or(0),
+    };
+    // ssimulacra2_rs compares the files as is, so cannot trim the padding
+    let pad_frames = match ssimulacra2 && pad_frames > 0 {
+        true => {
+            // only warn once, e.g. for each crf-search attempt
+            static WARN: std::sync::Once = std::sync::Once::new();
+            WARN.call_once(|| {
+                warn(
+                    &bar,
+                    "open-gop input, not padding samples for --ssimulacra2, \
+                     broken leading frames may lower scores",
+                )
+            });
+            0
+        }
+        false => pad_frames,
+    };
+    if pad_frames > 0 {
+        println(
+            style!("open-gop input, padding samples by {pad_frames} frames")
+                .dim()
+                .to_string(),
+        );
+    }
+    let trim = |vf: &Option<Cow<str>>| {
+        (pad_frames > 0).then(|| Cow::from(sample::trim_vfilter(pad_frames, vf.as_deref())))
+    };
+    let (trim_vfilter, trim_ref_vfilter) = (trim(&enc_args.vfilter), trim(&enc_args.ref_vfilter));
+    // loopback vmaf doesn't support analysing a differently filtered reference
+    let calc_vmaf = with_vmaf || !(ssim || xpsnr || ssimulacra2);
+    let loopback_vmaf = sample_args.loopback_vmaf
+        && !ssim
+        && !xpsnr
+        && !ssimulacra2
+        && !with_ssim
+        && enc_args.vfilter.is_none()
+        && trim_vfilter.is_none()
+        && enc_args.template.is_none();
+    if sample_args.loopback_vmaf && !loopback_vmaf {
+        // only warn once, e.g. for each crf-search attempt
+        static WARN: std::sync::Once = std::sync::Once::new();
+        WARN.call_once(|| {
+            warn(
+                &bar,
+                "--loopback-vmaf not supported with vfilters, templates or non-vmaf metrics, ignoring",
+            )
+        });
+    }
+    let enc_args = FfmpegEncodeArgs {
+        vfilter: trim_vfilter.or(enc_args.vfilter),
+        ref_vfilter: trim_ref_vfilter.or(enc_args.ref_vfilter),
+        ..enc_args
+    };
+    // ssimulacra2_rs compares the files as is, so cannot filter the reference
+    ensure!(
+        !ssimulacra2 || enc_args.vfilter.is_none(),
+        "--ssimulacra2 does not support --vfilter"
+    );
+    let encode_id = cache::encode_id(&enc_args);
 
     // Start creating copy samples async, this is IO bound & not cpu intensive
+    let sample_range = match full_pass || sample_args.include_credits {
+        true => None,
+        false => sample::body_range(&input, duration, cache)
+            .filter(|(start, end)| *end - *start > SAMPLE_SIZE * samples as _),
+    };
+    if let Some((start, end)) = sample_range {
+        // only print once, e.g. for each crf-search attempt
+        static INFO: std::sync::Once = std::sync::Once::new();
+        INFO.call_once(|| {
+            println(
+                style!(
+                    "excluding logos/credits, sampling {}-{}",
+                    humantime::format_duration(Duration::from_secs(start.as_secs())),
+                    humantime::format_duration(Duration::from_secs(end.as_secs())),
+                )
+                .dim()
+                .to_string(),
+            )
+        });
+    }
+    let (sample_offset, sample_range_duration) = match sample_range {
+        Some((start, end)) => (start, end - start),
+        None => (Duration::ZERO, duration),
+    };
+
     let (tx, mut sample_tasks) = tokio::sync::mpsc::unbounded_channel();
     let sample_temp = temp_dir.clone();
-    let sample_in = input.clone();
-    tokio::task::spawn_local(async move {
+    let dynamic_hdr = input_probe.dynamic_hdr.any();
+    let sample_in = match !full_pass && sample_args.reindex.is_needed(&input) {
+        true => {
+            bar.set_message("reindexing,");
+            Arc::new(sample::reindex(&input, temp_dir.clone(), dynamic_hdr).await?)
+        }
+        false => input.clone(),
+    };
+    cancel::spawn_local(&cancel, async move {
         if full_pass {
             // Use the entire video as a single sample
             let _ = tx.send((0, Ok((sample_in.clone(), input_len))));
@@ -128,9 +550,12 @@ pub async fn run(
                     sample_in.clone(),
                     sample_idx,
                     samples,
-                    duration,
+                    sample_offset,
+                    sample_range_duration,
                     input_fps,
+                    pad_frames,
                     sample_temp.clone(),
+                    dynamic_hdr,
                 )
                 .await;
                 if tx.send((sample_idx, sample)).is_err() {
@@ -141,19 +566,44 @@ pub async fn run(
     });
 
     let mut results = Vec::new();
+    let mut rerun = None;
     loop {
         bar.set_message("sampling,");
-        let (sample_idx, sample) = match sample_tasks.recv().await {
-            Some(s) => s,
-            None => break,
+        let (sample_idx, run, (sample, sample_size)) = match rerun.take() {
+            Some(rerun) => rerun,
+            None => match sample_tasks.recv().await {
+                Some((sample_idx, sample)) => (sample_idx, 0, sample?),
+                None => break,
+            },
         };
+        if run + 1 < runs {
+            rerun = Some((sample_idx, run + 1, (sample.clone(), sample_size)));
+        }
         let sample_n = sample_idx + 1;
-        match full_pass {
-            true => bar.set_prefix("Full pass"),
-            false => bar.set_prefix(format!("Sample {sample_n}/{samples}")),
+        match (full_pass, runs) {
+            (true, 1) => bar.set_prefix("Full pass"),
+            (true, _) => bar.set_prefix(format!("Full pass run {}/{runs}", run + 1)),
+            (false, 1) => bar.set_prefix(format!("Sample {sample_n}/{samples}")),
+            (false, _) => bar.set_prefix(format!(
+                "Sample {sample_n}/{samples} run {}/{runs}",
+                run + 1
+            )),
         };
-
-        let (sample, sample_size) = sample?;
+        // progress bar slot of this sample run
+        let slot = sample_idx * runs + run;
+        let reused_encode = reuse_encodes
+            .as_ref()
+            .map(|dir| {
+                dir.join(ffmpeg::encoded_sample_name(
+                    &sample,
+                    &enc_args.vcodec,
+                    enc_args.crf,
+                    enc_args.preset.as_deref(),
+                    &encode_id,
+                    sample_args.extension.as_deref().unwrap_or("mkv"),
+                ))
+            })
+            .filter(|e| e.is_file());
 
         // encode sample
         let result = match cache::cached_encode(
@@ -163,96 +613,251 @@ pub async fn run(
             input.extension(),
             input_len,
             full_pass,
+            input_probe.sar.and(input_probe.display_resolution()),
+            run,
             &enc_args,
             &vmaf,
+            cache_vmaf_pix_fmt,
+            ssim,
+            xpsnr,
+            ssimulacra2,
+            with_ssim,
+            with_vmaf,
         )
         .await
         {
             (Some(result), _) => {
-                bar.set_position(sample_n * sample_duration_us * 2);
-                bar.println(
+                bar.set_position((slot + 1) * sample_duration_us * 2);
+                println(
                     style!(
-                        "- Sample {sample_n} ({:.0}%) vmaf {:.2} (cache)",
+                        "- Sample {sample_n} ({:.0}%) {} (cache)",
                         100.0 * result.encoded_size as f32 / sample_size as f32,
-                        result.vmaf_score,
+                        result.score_label(),
                     )
                     .dim()
                     .to_string(),
                 );
                 result
             }
-            (None, key) => {
+            (None, key) if loopback_vmaf && reused_encode.is_none() => {
                 bar.set_message("encoding,");
                 let b = Instant::now();
-                let (encoded_sample, mut output) = ffmpeg::encode_sample(
+                let frame_log = (vmaf.vmaf_statistic != VmafStatistic::Mean || report.is_some())
+                    .then(|| {
+                        let mut log = temporary::process_dir(temp_dir.clone());
+                        log.push(naming::temp_file_name(&sample, "vmaf.json"));
+                        temporary::add(&log, TempKind::NotKeepable);
+                        log
+                    });
+                let lavfi_vmaf = match &frame_log {
+                    Some(log) => vmaf.with_frame_log(log),
+                    None => vmaf.clone(),
+                };
+                let mut output = ffmpeg::encode_sample_vmaf(
                     FfmpegEncodeArgs {
                         input: &sample,
                         ..enc_args.clone()
                     },
-                    temp_dir.clone(),
-                    sample_args.extension.as_deref().unwrap_or("mkv"),
+                    &lavfi_vmaf.ffmpeg_lavfi(input_probe.display_resolution(), vmaf_pix_fmt, None),
+                    &sample_args.runner,
                 )?;
-                while let Some(progress) = output.next().await {
-                    if let FfmpegOut::Progress { time, fps, .. } = progress? {
-                        bar.set_position(
-                            time.as_micros_u64() + sample_idx * sample_duration_us * 2,
-                        );
-                        if fps > 0.0 {
-                            bar.set_message(format!("enc {fps} fps,"));
-                        }
-                    }
-                }
-                let encode_time = b.elapsed();
-                let encoded_size = fs::metadata(&encoded_sample).await?.len();
-                let encoded_probe = ffprobe::probe(&encoded_sample);
-
-                // calculate vmaf
-                bar.set_message("vmaf running,");
-                let mut vmaf = vmaf::run(
-                    &sample,
-                    &encoded_sample,
-                    &vmaf.ffmpeg_lavfi(
-                        encoded_probe.resolution,
-                        enc_args
-                            .pix_fmt
-                            .max(input_pixel_format.unwrap_or(PixelFormat::Yuv444p10le)),
-                        args.vfilter.as_deref(),
-                    ),
-                )?;
-                let mut vmaf_score = -1.0;
-                while let Some(vmaf) = vmaf.next().await {
-                    match vmaf {
-                        VmafOut::Done(score) => {
-                            vmaf_score = score;
-                            break;
-                        }
-                        VmafOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
+                let (mut encoded_size, mut vmaf_score) = (0, -1.0);
+                while let Some(out) = output.next().await {
+                    match out? {
+                        LoopbackOut::Encoded(bytes) => encoded_size += bytes,
+                        LoopbackOut::Vmaf(score) => vmaf_score = score,
+                        LoopbackOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
+                            // single process, so progress covers both encode & vmaf
                             bar.set_position(
-                                sample_duration_us
-                                    // *24/fps adjusts for vmaf `-r 24`
-                                    + (time.as_micros_u64() as f64 * (24.0 / input_fps)).round() as u64
-                                    + sample_idx * sample_duration_us * 2,
+                                time.as_micros_u64() * 2 + slot * sample_duration_us * 2,
                             );
                             if fps > 0.0 {
-                                bar.set_message(format!("vmaf {fps} fps,"));
+                                bar.set_message(format!("enc+vmaf {fps} fps,"));
                             }
                         }
-                        VmafOut::Progress(_) => {}
-                        VmafOut::Err(e) => return Err(e),
+                        LoopbackOut::Progress(_) => {}
                     }
                 }
-
-                bar.println(
+                timings::record(Phase::SampleEncode, b.elapsed());
+                let (vmaf_score, vmaf_frames) =
+                    vmaf::score_statistic_frames(&vmaf, frame_log.as_deref(), vmaf_score)?;
+                let result = EncodeResult {
+                    vmaf_score,
+                    vmaf_frames,
+                    ssim_score: None,
+                    xpsnr_score: None,
+                    ssimulacra2_score: None,
+                    sample_size,
+                    encoded_size,
+                    encode_time: b.elapsed(),
+                    sample_duration,
+                    encoded_resolution: input_probe.resolution,
+                    from_cache: false,
+                };
+                println(
                     style!(
-                        "- Sample {sample_n} ({:.0}%) vmaf {vmaf_score:.2}",
-                        100.0 * encoded_size as f32 / sample_size as f32
+                        "- Sample {sample_n} ({:.0}%) {}",
+                        100.0 * encoded_size as f32 / sample_size as f32,
+                        result.score_label(),
                     )
                     .dim()
                     .to_string(),
                 );
+                if let Some(k) = key {
+                    cache::cache_result(k, &result).await?;
+                }
+                result
+            }
+            (None, key) => {
+                let reused = reused_encode.is_some();
+                let (encoded_sample, encode_time) = match reused_encode {
+                    Some(encoded_sample) => {
+                        bar.set_position(sample_duration_us + slot * sample_duration_us * 2);
+                        (encoded_sample, Duration::ZERO)
+                    }
+                    None => {
+                        bar.set_message("encoding,");
+                        let b = Instant::now();
+                        let (encoded_sample, mut output) = ffmpeg::encode_sample(
+                            FfmpegEncodeArgs {
+                                input: &sample,
+                                ..enc_args.clone()
+                            },
+                            temp_dir.clone(),
+                            &encode_id,
+                            sample_args.extension.as_deref().unwrap_or("mkv"),
+                            &sample_args.runner,
+                        )?;
+                        while let Some(progress) = output.next().await {
+                            if let FfmpegOut::Progress { time, fps, .. } = progress? {
+                                bar.set_position(
+                                    time.as_micros_u64() + slot * sample_duration_us * 2,
+                                );
+                                if fps > 0.0 {
+                                    bar.set_message(format!("enc {fps} fps,"));
+                                }
+                            }
+                        }
+                        (encoded_sample, b.elapsed())
+                    }
+                };
+                timings::record(Phase::SampleEncode, encode_time);
+                let encoded_size = fs::metadata(&encoded_sample).await?.len();
+                let encoded_probe = ffprobe::probe_with(&sample_args.runner, &encoded_sample);
+
+                let metric_timer = timings::start(Phase::Metric);
+                let metric_position = sample_duration_us + slot * sample_duration_us * 2;
+                // the cache key has the original vmaf args, so don't cache reduced retries
+                let mut vmaf_retried = false;
+                let (vmaf_score, vmaf_frames) = match calc_vmaf {
+                    true => {
+                        // calculate vmaf
+                        bar.set_message("vmaf running,");
+                        let frame_log = (vmaf.vmaf_statistic != VmafStatistic::Mean
+                            || report.is_some())
+                        .then(|| {
+                            let log = encoded_sample.with_extension("vmaf.json");
+                            temporary::add(&log, TempKind::NotKeepable);
+                            log
+                        });
+                        let mut vmaf_score = -1.0;
+                        'vmaf: loop {
+                            let lavfi_vmaf = match &frame_log {
+                                Some(log) => vmaf.with_frame_log(log),
+                                None => vmaf.clone(),
+                            };
+                            let mut vmaf_out = vmaf::run(
+                                &sample,
+                                &encoded_sample,
+                                &lavfi_vmaf.ffmpeg_lavfi(
+                                    encoded_probe.display_resolution(),
+                                    vmaf_pix_fmt,
+                                    enc_args.ref_vfilter.as_deref(),
+                                ),
+                                &sample_args.runner,
+                            )?;
+                            while let Some(next) = vmaf_out.next().await {
+                                match next {
+                                    VmafOut::Done(score) => {
+                                        vmaf_score = score;
+                                        break 'vmaf;
+                                    }
+                                    VmafOut::Progress(FfmpegOut::Progress {
+                                        time, fps, ..
+                                    }) => {
+                                        bar.set_position(
+                                        sample_duration_us
+                                            // *24/fps adjusts for vmaf `-r 24`
+                                            + (time.as_micros_u64() as f64 * (24.0 / input_fps)).round() as u64
+                                            + slot * sample_duration_us * 2,
+                                    );
+                                        if fps > 0.0 {
+                                            bar.set_message(format!("vmaf {fps} fps,"));
+                                        }
+                                    }
+                                    VmafOut::Progress(_) => {}
+                                    VmafOut::Err(e) => match vmaf.reduced_threads() {
+                                        Some(reduced) if vmaf::is_oom_error(&e) => {
+                                            warn(&bar, &vmaf::oom_retry_warning(&vmaf, &reduced));
+                                            vmaf = reduced;
+                                            vmaf_retried = true;
+                                            continue 'vmaf;
+                                        }
+                                        _ => return Err(e),
+                                    },
+                                }
+                            }
+                            break;
+                        }
+                        vmaf::score_statistic_frames(&vmaf, frame_log.as_deref(), vmaf_score)?
+                    }
+                    false => (0.0, vec![]),
+                };
+                let ssim_score = match ssim || with_ssim {
+                    true => Some(
+                        sample_ssim(
+                            &bar,
+                            &sample,
+                            &encoded_sample,
+                            vmaf_pix_fmt,
+                            enc_args.ref_vfilter.as_deref(),
+                            input_fps,
+                            metric_position,
+                        )
+                        .await?,
+                    ),
+                    false => None,
+                };
+                let xpsnr_score = match xpsnr {
+                    true => Some(
+                        sample_xpsnr(
+                            &bar,
+                            &sample,
+                            &encoded_sample,
+                            vmaf_pix_fmt,
+                            enc_args.ref_vfilter.as_deref(),
+                            metric_position,
+                        )
+                        .await?,
+                    ),
+                    false => None,
+                };
+                let ssimulacra2_score = match ssimulacra2 {
+                    true => {
+                        bar.set_message("ssimulacra2 running,");
+                        Some(ssimulacra2::score(&sample, &encoded_sample, input_is_image).await?)
+                    }
+                    false => None,
+                };
+
+                drop(metric_timer);
 
                 let result = EncodeResult {
                     vmaf_score,
+                    vmaf_frames,
+                    ssim_score,
+                    xpsnr_score,
+                    ssimulacra2_score,
                     sample_size,
                     encoded_size,
                     encode_time,
@@ -261,16 +866,26 @@ pub async fn run(
                         .ok()
                         .filter(|d| !d.is_zero())
                         .unwrap_or(sample_duration),
+                    encoded_resolution: encoded_probe.resolution,
                     from_cache: false,
                 };
 
-                if let Some(k) = key {
+                println(
+                    style!(
+                        "- Sample {sample_n} ({:.0}%) {}{}",
+                        100.0 * encoded_size as f32 / sample_size as f32,
+                        result.score_label(),
+                        if reused { " (reused)" } else { "" },
+                    )
+                    .dim()
+                    .to_string(),
+                );
+
+                if let Some(k) = key.filter(|_| !reused && !vmaf_retried) {
                     cache::cache_result(k, &result).await?;
                 }
 
-                // Early clean. Note: Avoid cleaning copy samples
-                temporary::clean(true).await;
-                if !keep {
+                if !keep && !reused {
                     let _ = tokio::fs::remove_file(encoded_sample).await;
                 }
 
@@ -278,66 +893,197 @@ pub async fn run(
             }
         };
 
+        // Early clean. Note: Avoid cleaning copy samples
+        temporary::clean(true).await;
         results.push(result);
     }
     bar.finish();
 
-    let output = Output {
-        vmaf: results.mean_vmaf(),
-        // Using file size * encode_percent can over-estimate. However, if it ends up less
-        // than the duration estimation it may turn out to be more accurate.
-        predicted_encode_size: results
+    // input video stream size excluding audio, e.g. large lossless audio tracks
+    let input_video_size = match input_is_image {
+        true => None,
+        false => ffprobe::probe_video_size(&sample_args.runner, &input, cache).filter(|s| *s > 0),
+    };
+    // Using file size * encode_percent can over-estimate. However, if it ends up less
+    // than the duration estimation it may turn out to be more accurate.
+    let predicted_size = |input_size| {
+        results
             .estimate_encode_size_by_duration(duration, full_pass)
-            .min(estimate_encode_size_by_file_percent(&results, &input, full_pass).await?),
+            .min(estimate_encode_size_by_file_percent(
+                &results, input_size, full_pass,
+            ))
+    };
+    let predicted_encode_size = predicted_size(input_len);
+    let vmaf_frames = results.vmaf_frames();
+    let output = Output {
+        vmaf: match vmaf.vmaf_statistic {
+            VmafStatistic::Mean => results.mean_vmaf(),
+            VmafStatistic::Min => results.min_vmaf(),
+            // pool all frames so percentiles cover the worst frames of every sample
+            stat => stat
+                .calculate(&vmaf_frames)
+                .unwrap_or_else(|| results.mean_vmaf()),
+        },
+        vmaf_frames,
+        predicted_encode_size,
         encode_percent: results.encoded_percent_size(),
+        video_encode_percent: input_video_size
+            .map(|size| predicted_size(size) as f64 * 100.0 / size as f64),
+        encode_percent_normalized: input_probe
+            .resolution
+            .and_then(|res| results.encoded_percent_size_normalized(res)),
+        encoded_resolution: results.iter().find_map(|r| r.encoded_resolution),
+        vmaf_min: results.min_vmaf(),
+        ssim: ssim.then(|| results.mean_ssim()),
+        xpsnr: xpsnr.then(|| results.mean_xpsnr()),
+        ssimulacra2: ssimulacra2.then(|| results.mean_ssimulacra2()),
+        vmaf_ssim: with_ssim.then(|| results.mean_ssim()),
+        with_vmaf,
         predicted_encode_time: results.estimate_encode_time(duration, full_pass),
+        encode_fps: results.encode_fps(input_fps),
+        // reused encodes are the same for every run, so have no run-to-run variation
+        vmaf_stddev: (runs > 1 && reuse_encodes.is_none())
+            .then(|| results.vmaf_run_stddev(runs as _)),
         from_cache: results.iter().all(|r| r.from_cache),
     };
 
-    if !bar.is_hidden() {
-        // encode how-to hint + predictions
-        eprintln!(
-            "\n{} {}\n",
-            style("Encode with:").dim(),
-            style(args.encode_hint(crf)).dim().italic(),
-        );
-        // stdout result
-        stdout_format.print_result(
-            output.vmaf,
-            output.predicted_encode_size,
-            output.encode_percent,
-            output.predicted_encode_time,
-            input_is_image,
-        );
+    Ok(output)
+}
+
+/// Calculate the SSIM "All" score of an encoded sample, updating `bar` progress from `position`.
+async fn sample_ssim(
+    bar: &ProgressBar,
+    sample: &Path,
+    encoded_sample: &Path,
+    pix_fmt: PixelFormat,
+    vfilter: Option<&str>,
+    input_fps: f64,
+    position: u64,
+) -> anyhow::Result<f32> {
+    bar.set_message("ssim running,");
+    let mut ssim_out = ssim::run(
+        sample,
+        encoded_sample,
+        &ssim::ffmpeg_lavfi(pix_fmt, vfilter),
+        Some(input_fps),
+    )?;
+    let mut ssim_score = None;
+    while let Some(next) = ssim_out.next().await {
+        match next {
+            SsimOut::Done(score) => ssim_score = Some(score),
+            SsimOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
+                bar.set_position(position + time.as_micros_u64());
+                if fps > 0.0 {
+                    bar.set_message(format!("ssim {fps} fps,"));
+                }
+            }
+            SsimOut::Progress(_) => {}
+            #[cfg(feature = "native-ssim")]
+            SsimOut::Err(e) if ssim::is_filter_missing_error(&e) => {
+                static WARN: std::sync::Once = std::sync::Once::new();
+                WARN.call_once(|| warn(bar, "ffmpeg ssim filter unavailable, using native ssim"));
+                bar.set_message("native ssim,");
+                ssim_score = Some(ssim::native::ssim_all(sample, encoded_sample, vfilter).await?);
+                break;
+            }
+            SsimOut::Err(e) => return Err(e),
+        }
     }
+    ssim_score.context("ffmpeg ssim score not found")
+}
 
-    Ok(output)
+/// Calculate the luma XPSNR score of an encoded sample, updating `bar` progress from `position`.
+async fn sample_xpsnr(
+    bar: &ProgressBar,
+    sample: &Path,
+    encoded_sample: &Path,
+    pix_fmt: PixelFormat,
+    vfilter: Option<&str>,
+    position: u64,
+) -> anyhow::Result<f32> {
+    bar.set_message("xpsnr running,");
+    let mut xpsnr_out = xpsnr::run(
+        sample,
+        encoded_sample,
+        &xpsnr::ffmpeg_lavfi(pix_fmt, vfilter),
+    )?;
+    let mut xpsnr_score = None;
+    while let Some(next) = xpsnr_out.next().await {
+        match next {
+            XpsnrOut::Done(score) => xpsnr_score = Some(score),
+            XpsnrOut::Progress(FfmpegOut::Progress { time, fps, .. }) => {
+                bar.set_position(position + time.as_micros_u64());
+                if fps > 0.0 {
+                    bar.set_message(format!("xpsnr {fps} fps,"));
+                }
+            }
+            XpsnrOut::Progress(_) => {}
+            XpsnrOut::Err(e) => return Err(e),
+        }
+    }
+    xpsnr_score.context("ffmpeg xpsnr score not found")
+}
+
+/// Print a warning above the progress bar, or to stderr if hidden.
+fn warn(bar: &ProgressBar, msg: &str) {
+    let msg = format!("{} {msg}", style("Warning:").yellow().bold());
+    match bar.is_hidden() {
+        true => eprintln!("{msg}"),
+        false => bar.println(msg),
+    }
 }
 
 /// Copy a sample from the input to the temp_dir (or input dir).
+///
+/// Samples are evenly spaced over the `duration` from `offset`.
+#[allow(clippy::too_many_arguments)]
 async fn sample(
     input: Arc<PathBuf>,
     sample_idx: u64,
     samples: u64,
+    offset: Duration,
     duration: Duration,
     fps: f64,
+    pad_frames: u32,
     temp_dir: Option<PathBuf>,
+    dynamic_hdr: bool,
 ) -> anyhow::Result<(Arc<PathBuf>, u64)> {
     let sample_n = sample_idx + 1;
 
-    let sample_start =
-        Duration::from_secs((duration.as_secs() - SAMPLE_SIZE_S * samples) / (samples + 1))
+    let sample_start = offset
+        + Duration::from_secs((duration.as_secs() - SAMPLE_SIZE_S * samples) / (samples + 1))
             * sample_n as _
-            + SAMPLE_SIZE * sample_idx as _;
+        + SAMPLE_SIZE * sample_idx as _;
     let sample_frames = (SAMPLE_SIZE_S as f64 * fps).round() as u32;
 
-    let sample = sample::copy(&input, sample_start, sample_frames, temp_dir).await?;
+    let mut attempt = 0;
+    let sample = loop {
+        match sample::copy(
+            &input,
+            sample_start,
+            sample_frames,
+            pad_frames,
+            fps,
+            temp_dir.clone(),
+            dynamic_hdr,
+        )
+        .await
+        {
+            Err(err) if attempt < process::IO_RETRIES && process::is_io_error(&err) => {
+                attempt += 1;
+                tokio::time::sleep(process::io_retry_backoff(attempt)).await;
+            }
+            result => break result?,
+        }
+    };
     let sample_size = fs::metadata(&sample).await?.len();
     ensure!(
         // ffmpeg copy may fail sucessfully and give us a small/empty output
         sample_size > 1024,
         "ffmpeg copy failed: encoded sample too small"
     );
+    // exclude trimmed padding from the size
+    let sample_size = sample_size * sample_frames as u64 / (sample_frames + pad_frames) as u64;
     Ok((sample.into(), sample_size))
 }
 
@@ -347,19 +1093,78 @@ pub struct EncodeResult {
     encoded_size: u64,
     vmaf_score: f32,
     encode_time: Duration,
+    /// Per-frame VMAF scores, if read from a frame log for a non-mean --vmaf-statistic.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vmaf_frames: Vec<f32>,
     /// Duration of the sample.
     ///
     /// This should be close to `SAMPLE_SIZE` but may deviate due to how samples are cut.
     sample_duration: Duration,
+    /// Resolution of the encoded sample, if known.
+    #[serde(default)]
+    encoded_resolution: Option<(u32, u32)>,
+    /// SSIM "All" score, calculated instead of vmaf with `--ssim`.
+    #[serde(default)]
+    ssim_score: Option<f32>,
+    /// Luma XPSNR score, calculated instead of vmaf with `--xpsnr`.
+    #[serde(default)]
+    xpsnr_score: Option<f32>,
+    /// SSIMULACRA2 score, calculated instead of vmaf with `--ssimulacra2`.
+    #[serde(default)]
+    ssimulacra2_score: Option<f32>,
     /// Result read from cache.
     from_cache: bool,
 }
 
+impl EncodeResult {
+    /// E.g. "vmaf 95.12", "ssim 0.9812" or multiple "vmaf 95.12 xpsnr 40.25".
+    fn score_label(&self) -> String {
+        let mut scores = vec![];
+        let others = (self.ssim_score, self.xpsnr_score, self.ssimulacra2_score);
+        // zero if not calculated alongside other metrics
+        if self.vmaf_score > 0.0 || others == (None, None, None) {
+            scores.push(format!("vmaf {:.2}", self.vmaf_score));
+        }
+        if let Some(ssim) = self.ssim_score {
+            scores.push(format!("ssim {ssim:.4}"));
+        }
+        if let Some(xpsnr) = self.xpsnr_score {
+            scores.push(format!("xpsnr {xpsnr:.2}"));
+        }
+        if let Some(s2) = self.ssimulacra2_score {
+            scores.push(format!("ssimulacra2 {s2:.2}"));
+        }
+        scores.join(" ")
+    }
+}
+
 trait EncodeResults {
     fn encoded_percent_size(&self) -> f64;
 
+    /// Encoded percent size adjusted for resolution changes, i.e. comparing bits-per-pixel.
+    ///
+    /// `None` if any encoded resolution is unknown.
+    fn encoded_percent_size_normalized(&self, input_resolution: (u32, u32)) -> Option<f64>;
+
     fn mean_vmaf(&self) -> f32;
 
+    /// Returns the lowest sample vmaf score.
+    fn min_vmaf(&self) -> f32;
+
+    /// Returns per-frame vmaf scores of all samples,
+    /// empty unless every sample has frame scores.
+    fn vmaf_frames(&self) -> Vec<f32>;
+
+    fn mean_ssim(&self) -> f32;
+
+    fn mean_xpsnr(&self) -> f32;
+
+    fn mean_ssimulacra2(&self) -> f32;
+
+    /// Returns the mean run-to-run VMAF standard deviation of each sample,
+    /// for results of consecutive `runs` of each sample.
+    fn vmaf_run_stddev(&self, runs: usize) -> f32;
+
     /// Return estimated encoded **video stream** size by multiplying sample size by duration.
     fn estimate_encode_size_by_duration(
         &self,
@@ -368,6 +1173,9 @@ trait EncodeResults {
     ) -> u64;
 
     fn estimate_encode_time(&self, input_duration: Duration, single_full_pass: bool) -> Duration;
+
+    /// Mean sample encoding frames per second.
+    fn encode_fps(&self, input_fps: f64) -> f64;
 }
 impl EncodeResults for Vec<EncodeResult> {
     fn encoded_percent_size(&self) -> f64 {
@@ -379,6 +1187,24 @@ impl EncodeResults for Vec<EncodeResult> {
         encoded * 100.0 / sample
     }
 
+    fn encoded_percent_size_normalized(&self, (in_w, in_h): (u32, u32)) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        let input_pixels = f64::from(in_w) * f64::from(in_h);
+        let mut encoded = 0.0;
+        for r in self {
+            let (w, h) = r.encoded_resolution?;
+            let encoded_pixels = f64::from(w) * f64::from(h);
+            if encoded_pixels <= 0.0 {
+                return None;
+            }
+            encoded += r.encoded_size as f64 * input_pixels / encoded_pixels;
+        }
+        let sample = self.iter().map(|r| r.sample_size).sum::<u64>() as f64;
+        Some(encoded * 100.0 / sample)
+    }
+
     fn mean_vmaf(&self) -> f32 {
         if self.is_empty() {
             return 0.0;
@@ -386,6 +1212,63 @@ impl EncodeResults for Vec<EncodeResult> {
         self.iter().map(|r| r.vmaf_score).sum::<f32>() / self.len() as f32
     }
 
+    fn min_vmaf(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.iter()
+            .map(|r| r.vmaf_score)
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    fn vmaf_frames(&self) -> Vec<f32> {
+        if self.iter().any(|r| r.vmaf_frames.is_empty()) {
+            return vec![];
+        }
+        self.iter().flat_map(|r| &r.vmaf_frames).copied().collect()
+    }
+
+    fn mean_ssim(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.iter().filter_map(|r| r.ssim_score).sum::<f32>() / self.len() as f32
+    }
+
+    fn mean_xpsnr(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.iter().filter_map(|r| r.xpsnr_score).sum::<f32>() / self.len() as f32
+    }
+
+    fn mean_ssimulacra2(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.iter().filter_map(|r| r.ssimulacra2_score).sum::<f32>() / self.len() as f32
+    }
+
+    fn vmaf_run_stddev(&self, runs: usize) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let chunks = self.chunks(runs.max(1));
+        let n = chunks.len();
+        let stddev_sum: f32 = chunks
+            .map(|runs| {
+                let mean = runs.iter().map(|r| r.vmaf_score).sum::<f32>() / runs.len() as f32;
+                let variance = runs
+                    .iter()
+                    .map(|r| (r.vmaf_score - mean).powi(2))
+                    .sum::<f32>()
+                    / runs.len() as f32;
+                variance.sqrt()
+            })
+            .sum();
+        stddev_sum / n as f32
+    }
+
     fn estimate_encode_size_by_duration(
         &self,
         input_duration: Duration,
@@ -405,6 +1288,18 @@ impl EncodeResults for Vec<EncodeResult> {
         (sample_encode_size * sample_factor).round() as _
     }
 
+    fn encode_fps(&self, input_fps: f64) -> f64 {
+        let encode_time = self.iter().map(|r| r.encode_time).sum::<Duration>();
+        if encode_time.is_zero() {
+            return 0.0;
+        }
+        let frames = self
+            .iter()
+            .map(|r| r.sample_duration.as_secs_f64() * input_fps)
+            .sum::<f64>();
+        frames / encode_time.as_secs_f64()
+    }
+
     fn estimate_encode_time(&self, input_duration: Duration, single_full_pass: bool) -> Duration {
         if self.is_empty() {
             return Duration::ZERO;
@@ -430,20 +1325,20 @@ impl EncodeResults for Vec<EncodeResult> {
 /// change to the input file size.
 ///
 /// This can over-estimate the larger the non-video proportion of the input.
-async fn estimate_encode_size_by_file_percent(
+fn estimate_encode_size_by_file_percent(
     results: &Vec<EncodeResult>,
-    input: &Path,
+    input_size: u64,
     single_full_pass: bool,
-) -> anyhow::Result<u64> {
+) -> u64 {
     if results.is_empty() {
-        return Ok(0);
+        return 0;
     }
     if single_full_pass {
-        return Ok(results[0].encoded_size);
+        return results[0].encoded_size;
     }
     let encode_proportion = results.encoded_percent_size() / 100.0;
 
-    Ok((fs::metadata(input).await?.len() as f64 * encode_proportion).round() as _)
+    (input_size as f64 * encode_proportion).round() as _
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -453,13 +1348,28 @@ pub enum StdoutFormat {
 }
 
 impl StdoutFormat {
-    fn print_result(self, vmaf: f32, size: u64, percent: f64, time: Duration, image: bool) {
+    fn print_result(self, output: &Output, image: bool) {
+        let Output {
+            vmaf,
+            ssim,
+            xpsnr,
+            ssimulacra2,
+            predicted_encode_size: size,
+            encode_percent: percent,
+            predicted_encode_time: time,
+            ..
+        } = *output;
         match self {
             Self::Human => {
-                let vmaf = match vmaf {
-                    v if v >= 95.0 => style(v).bold().green(),
-                    v if v < 80.0 => style(v).bold().red(),
-                    v => style(v).bold(),
+                let score = match (ssim, xpsnr, ssimulacra2) {
+                    (Some(ssim), ..) => format!("SSIM All {:.4}", style(ssim).bold()),
+                    (_, Some(xpsnr), _) => format!("XPSNR {:.2}", style(xpsnr).bold()),
+                    (.., Some(s2)) => format!("SSIMULACRA2 {:.2}", style(s2).bold()),
+                    _ => match vmaf {
+                        v if v >= 95.0 => format!("VMAF {:.2}", style(v).bold().green()),
+                        v if v < 80.0 => format!("VMAF {:.2}", style(v).bold().red()),
+                        v => format!("VMAF {:.2}", style(v).bold()),
+                    },
                 };
                 let percent = percent.round();
                 let size = match size {
@@ -477,19 +1387,21 @@ impl StdoutFormat {
                     true => "image",
                     false => "video stream",
                 };
+                let mut normalized = match output.resolution_normalized_percent() {
+                    Some(p) => style!(", {p:.0}% per-pixel").dim().to_string(),
+                    None => String::new(),
+                };
+                if let Some(p) = output.video_only_percent() {
+                    normalized += &style!(", {p:.0}% of input video").dim().to_string();
+                }
                 println!(
-                    "VMAF {vmaf:.2} predicted {enc_description} size {size} ({percent}) taking {time}"
+                    "{score} predicted {enc_description} size {size} ({percent}{normalized}) taking {time}"
                 );
+                if let Some(stddev) = output.vmaf_stddev {
+                    eprintln!("{}", style!("run-to-run VMAF stddev {stddev:.2}").dim());
+                }
             }
-            Self::Json => {
-                let json = serde_json::json!({
-                    "vmaf": vmaf,
-                    "predicted_encode_size": size,
-                    "predicted_encode_percent": percent,
-                    "predicted_encode_seconds": time.as_secs(),
-                });
-                println!("{}", serde_json::to_string(&json).unwrap());
-            }
+            Self::Json => machine::print_json(&output.to_json()),
         }
     }
 }
@@ -497,18 +1409,181 @@ impl StdoutFormat {
 /// Sample encode result.
 #[derive(Debug, Clone)]
 pub struct Output {
-    /// Sample mean VMAF score.
+    /// Sample mean VMAF score. Zero if another metric was calculated instead,
+    /// see [`Output::has_vmaf`].
     pub vmaf: f32,
+    /// Lowest sample VMAF score.
+    pub vmaf_min: f32,
+    /// Per-frame VMAF scores of all samples, with a non-mean --vmaf-statistic.
+    /// Empty if unavailable, e.g. older cached results.
+    pub vmaf_frames: Vec<f32>,
+    /// Sample mean SSIM "All" score, if calculated instead of VMAF.
+    pub ssim: Option<f32>,
+    /// Sample mean luma XPSNR score, if calculated instead of VMAF.
+    pub xpsnr: Option<f32>,
+    /// Sample mean SSIMULACRA2 score, if calculated instead of VMAF.
+    pub ssimulacra2: Option<f32>,
+    /// Sample mean SSIM "All" score, if calculated alongside VMAF.
+    pub vmaf_ssim: Option<f32>,
+    /// VMAF was also calculated alongside `ssim`, `xpsnr` or `ssimulacra2`.
+    pub with_vmaf: bool,
     /// Estimated full encoded **video stream** size.
     ///
     /// Encoded sample size multiplied by duration.
     pub predicted_encode_size: u64,
     /// Sample mean encoded percentage.
     pub encode_percent: f64,
+    /// Sample mean encoded percentage adjusted for resolution changes (e.g. crop/scale vfilters)
+    /// by comparing bits-per-pixel.
+    pub encode_percent_normalized: Option<f64>,
+    /// Resolution of the encoded samples, if known.
+    pub encoded_resolution: Option<(u32, u32)>,
+    /// Predicted encode size percentage of the input video stream size, excluding audio
+    /// & other streams. `None` if the input video stream size is unknown.
+    pub video_encode_percent: Option<f64>,
     /// Estimated full encode time.
     ///
     /// Sample encode time multiplied by duration.
     pub predicted_encode_time: Duration,
+    /// Sample encoding frames per second.
+    pub encode_fps: f64,
+    /// Mean run-to-run VMAF standard deviation, with `--samples-per-point` > 1.
+    pub vmaf_stddev: Option<f32>,
     /// All sample results were read from the cache.
     pub from_cache: bool,
 }
+
+impl Output {
+    /// Returns the resolution normalized encode percent if it differs from the raw percent,
+    /// i.e. the resolution was changed.
+    pub fn resolution_normalized_percent(&self) -> Option<f64> {
+        self.encode_percent_normalized
+            .filter(|p| (p - self.encode_percent).abs() >= 0.5)
+    }
+
+    /// Returns the video stream only encode percent if it differs from the raw percent,
+    /// e.g. when the input has large audio streams.
+    pub fn video_only_percent(&self) -> Option<f64> {
+        self.video_encode_percent
+            .filter(|p| (p - self.encode_percent).abs() >= 0.5)
+    }
+
+    /// Encode percent compared with `--max-encoded-percent`, using the video stream only
+    /// percent if `video_only` & known.
+    pub fn capped_percent(&self, video_only: bool) -> f64 {
+        match (video_only, self.video_encode_percent) {
+            (true, Some(p)) => p,
+            _ => self.encode_percent,
+        }
+    }
+
+    /// Returns true if VMAF was calculated, i.e. not replaced by another metric.
+    pub fn has_vmaf(&self) -> bool {
+        self.with_vmaf
+            || (self.ssim.is_none() && self.xpsnr.is_none() && self.ssimulacra2.is_none())
+    }
+
+    /// Json result, as printed with `--stdout-format json`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "vmaf": self.vmaf,
+            "predicted_encode_size": self.predicted_encode_size,
+            "predicted_encode_percent": self.encode_percent,
+            "predicted_encode_percent_normalized": self.encode_percent_normalized,
+            "predicted_encode_percent_video": self.video_encode_percent,
+            "predicted_encode_seconds": self.predicted_encode_time.as_secs(),
+        });
+        if !self.has_vmaf() {
+            json["vmaf"] = serde_json::Value::Null;
+        }
+        if let Some(ssim) = self.ssim {
+            json["ssim"] = ssim.into();
+        }
+        if let Some(xpsnr) = self.xpsnr {
+            json["xpsnr"] = xpsnr.into();
+        }
+        if let Some(ssimulacra2) = self.ssimulacra2 {
+            json["ssimulacra2"] = ssimulacra2.into();
+        }
+        if let Some(ssim) = self.vmaf_ssim {
+            json["ssim"] = ssim.into();
+        }
+        if let Some(stddev) = self.vmaf_stddev {
+            json["vmaf_stddev"] = stddev.into();
+        }
+        json
+    }
+}
+
+#[test]
+fn encoded_percent_size_normalized_downscale() {
+    let result = EncodeResult {
+        sample_size: 1000,
+        encoded_size: 250,
+        vmaf_score: 95.0,
+        vmaf_frames: vec![],
+        encode_time: Duration::from_secs(1),
+        sample_duration: SAMPLE_SIZE,
+        encoded_resolution: Some((1920, 1080)),
+        ssim_score: None,
+        xpsnr_score: None,
+        ssimulacra2_score: None,
+        from_cache: false,
+    };
+    let results = vec![result];
+    assert_eq!(results.encoded_percent_size(), 25.0);
+    // input 4x the pixels of the encoded sample
+    assert_eq!(
+        results.encoded_percent_size_normalized((3840, 2160)),
+        Some(100.0)
+    );
+}
+
+#[test]
+fn vmaf_run_stddev() {
+    let result = |vmaf_score| EncodeResult {
+        sample_size: 1000,
+        encoded_size: 250,
+        vmaf_score,
+        vmaf_frames: vec![],
+        encode_time: Duration::from_secs(1),
+        sample_duration: SAMPLE_SIZE,
+        encoded_resolution: None,
+        ssim_score: None,
+        xpsnr_score: None,
+        ssimulacra2_score: None,
+        from_cache: false,
+    };
+    // 2 samples, 2 runs each
+    let results = vec![result(94.0), result(96.0), result(90.0), result(90.0)];
+    assert_eq!(results.vmaf_run_stddev(2), 0.5);
+    assert_eq!(results.mean_vmaf(), 92.5);
+    assert_eq!(results.min_vmaf(), 90.0);
+}
+
+#[test]
+fn vmaf_frames_pooled() {
+    let result = |vmaf_frames: Vec<f32>| EncodeResult {
+        sample_size: 1000,
+        encoded_size: 250,
+        vmaf_score: 0.0,
+        vmaf_frames,
+        encode_time: Duration::from_secs(1),
+        sample_duration: SAMPLE_SIZE,
+        encoded_resolution: None,
+        ssim_score: None,
+        xpsnr_score: None,
+        ssimulacra2_score: None,
+        from_cache: false,
+    };
+    let results = vec![result(vec![95.0, 96.0]), result(vec![80.0, 97.0])];
+    assert_eq!(results.vmaf_frames(), [95.0, 96.0, 80.0, 97.0]);
+    assert_eq!(
+        VmafStatistic::Percentile(25).calculate(&results.vmaf_frames()),
+        Some(80.0)
+    );
+
+    // not all samples have frames, e.g. older cache entries
+    let results = vec![result(vec![95.0]), result(vec![])];
+    assert!(results.vmaf_frames().is_empty());
+}
@@ -1,10 +1,16 @@
 //! _sample-encode_ file system caching logic.
-use crate::{command::args::Vmaf, ffmpeg::FfmpegEncodeArgs};
-use anyhow::Context;
+use crate::{
+    command::args::{PixelFormat, Vmaf},
+    ffmpeg::FfmpegEncodeArgs,
+};
+use anyhow::{ensure, Context};
 use std::{
     ffi::OsStr,
+    fs::File,
     hash::Hash,
+    io::{BufRead, BufReader, BufWriter, Write},
     path::Path,
+    sync::OnceLock,
     time::{Duration, Instant},
 };
 
@@ -17,8 +23,16 @@ pub async fn cached_encode(
     input_extension: Option<&OsStr>,
     input_size: u64,
     full_pass: bool,
+    anamorphic_res: Option<(u32, u32)>,
+    run: u64,
     enc_args: &FfmpegEncodeArgs<'_>,
     vmaf_args: &Vmaf,
+    vmaf_pix_fmt: Option<PixelFormat>,
+    ssim: bool,
+    xpsnr: bool,
+    ssimulacra2: bool,
+    with_ssim: bool,
+    with_vmaf: bool,
 ) -> (Option<super::EncodeResult>, Option<Key>) {
     if !cache {
         return (None, None);
@@ -35,18 +49,31 @@ pub async fn cached_encode(
             input_size,
             full_pass,
         ),
+        anamorphic_res,
+        run,
         enc_args,
         vmaf_args,
+        vmaf_pix_fmt,
+        ssim,
+        xpsnr,
+        ssimulacra2,
+        with_ssim,
+        with_vmaf,
     );
 
     let key = Key(hash);
 
     let cached = tokio::task::spawn_blocking::<_, anyhow::Result<_>>(move || {
         let db = open_db()?;
-        Ok(match db.get(key.0.to_hex().as_bytes())? {
-            Some(data) => Some(serde_json::from_slice::<super::EncodeResult>(&data)?),
-            None => None,
-        })
+        let Some(data) = db.get(key.0.to_hex().as_bytes())? else {
+            return Ok(None);
+        };
+        let result = decode_entry(&data);
+        if result.is_none() {
+            // corrupt entry, remove so it is regenerated
+            db.remove(key.0.to_hex().as_bytes())?;
+        }
+        Ok(result)
     })
     .await
     .context("db.get task failed")
@@ -66,7 +93,7 @@ pub async fn cached_encode(
 }
 
 pub async fn cache_result(key: Key, result: &super::EncodeResult) -> anyhow::Result<()> {
-    let data = serde_json::to_vec(result)?;
+    let data = encode_entry(result)?;
     let insert = tokio::task::spawn_blocking(move || {
         let db = open_db()?;
         db.insert(key.0.to_hex().as_bytes(), data)?;
@@ -82,6 +109,104 @@ pub async fn cache_result(key: Key, result: &super::EncodeResult) -> anyhow::Res
     Ok(())
 }
 
+/// Serialize a result as json followed by its blake3 checksum.
+fn encode_entry(result: &super::EncodeResult) -> serde_json::Result<Vec<u8>> {
+    let mut data = serde_json::to_vec(result)?;
+    let checksum = blake3::hash(&data);
+    data.extend_from_slice(checksum.as_bytes());
+    Ok(data)
+}
+
+/// Deserialize & validate a cache entry.
+///
+/// Returns `None` if the checksum does not match or the result is nonsensical.
+/// Entries written before checksums were added are accepted if otherwise valid.
+fn decode_entry(data: &[u8]) -> Option<super::EncodeResult> {
+    let json = match data.split_at_checked(data.len().checked_sub(blake3::OUT_LEN)?) {
+        Some((json, checksum)) if blake3::hash(json).as_bytes() == checksum => json,
+        _ if data.last() == Some(&b'}') => data,
+        _ => return None,
+    };
+    let result: super::EncodeResult = serde_json::from_slice(json).ok()?;
+    is_valid(&result).then_some(result)
+}
+
+fn is_valid(result: &super::EncodeResult) -> bool {
+    result.vmaf_score.is_finite()
+        && (0.0..=100.0).contains(&result.vmaf_score)
+        && result.ssim_score.is_none_or(|s| (0.0..=1.0).contains(&s))
+        && result
+            .xpsnr_score
+            .is_none_or(|s| (0.0..=100.0).contains(&s))
+        && result
+            .ssimulacra2_score
+            .is_none_or(|s| s.is_finite() && s <= 100.0)
+        && result.sample_size > 0
+        && result.encoded_size > 0
+}
+
+/// Write all valid cache entries to `file` as json lines. Returns the number of entries written.
+pub fn export(file: &Path) -> anyhow::Result<usize> {
+    let db = open_db()?;
+    let mut out = BufWriter::new(File::create(file)?);
+    let mut count = 0;
+    for entry in db.iter() {
+        let (key, data) = entry?;
+        let (Ok(key), Some(result)) = (std::str::from_utf8(&key), decode_entry(&data)) else {
+            continue;
+        };
+        writeln!(out, "{}", export_line(key, &result)?)?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+/// Merge cache entries from an exported `file` into the cache, replacing any existing
+/// entries with the same key. Returns the number of entries imported.
+pub fn import(file: &Path) -> anyhow::Result<usize> {
+    let db = open_db()?;
+    let mut count = 0;
+    for (idx, line) in BufReader::new(File::open(file)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, result) =
+            parse_export_line(&line).with_context(|| format!("line {}", idx + 1))?;
+        db.insert(key.as_bytes(), encode_entry(&result)?)?;
+        count += 1;
+    }
+    db.flush()?;
+    Ok(count)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ExportEntry {
+    key: String,
+    result: super::EncodeResult,
+}
+
+fn export_line(key: &str, result: &super::EncodeResult) -> serde_json::Result<String> {
+    serde_json::to_string(&ExportEntry {
+        key: key.into(),
+        result: super::EncodeResult {
+            from_cache: false,
+            ..result.clone()
+        },
+    })
+}
+
+fn parse_export_line(line: &str) -> anyhow::Result<(String, super::EncodeResult)> {
+    let ExportEntry { key, result } = serde_json::from_str(line).context("invalid entry")?;
+    ensure!(
+        key.len() == blake3::OUT_LEN * 2 && key.bytes().all(|b| b.is_ascii_hexdigit()),
+        "invalid key"
+    );
+    ensure!(is_valid(&result), "invalid result");
+    Ok((key, result))
+}
+
 fn open_db() -> sled::Result<sled::Db> {
     const LOCK_MAX_WAIT: Duration = Duration::from_secs(2);
 
@@ -100,22 +225,133 @@ fn open_db() -> sled::Result<sled::Db> {
 #[derive(Debug, Clone, Copy)]
 pub struct Key(blake3::Hash);
 
+#[allow(clippy::too_many_arguments)]
 fn hash_encode(
     input_info: impl Hash,
+    anamorphic_res: Option<(u32, u32)>,
+    run: u64,
     enc_args: &FfmpegEncodeArgs<'_>,
     vmaf_args: &Vmaf,
+    vmaf_pix_fmt: Option<PixelFormat>,
+    ssim: bool,
+    xpsnr: bool,
+    ssimulacra2: bool,
+    with_ssim: bool,
+    with_vmaf: bool,
 ) -> blake3::Hash {
     let mut hasher = blake3::Hasher::new();
     let mut std_hasher = BlakeStdHasher(&mut hasher);
     input_info.hash(&mut std_hasher);
+    if let Some(res) = anamorphic_res {
+        // vmaf scaling uses the display resolution of non-square pixel inputs
+        ("display", res).hash(&mut std_hasher);
+    }
+    if run > 0 {
+        // --samples-per-point repeat runs are cached separately
+        ("run", run).hash(&mut std_hasher);
+    }
     enc_args.sample_encode_hash(&mut std_hasher);
     if !vmaf_args.is_default() {
         // avoid hashing if default for back compat
         vmaf_args.hash(&mut std_hasher);
     }
+    if let Some(pix_fmt) = vmaf_pix_fmt {
+        // only hashed if differing from the previous default for back compat
+        pix_fmt.hash(&mut std_hasher);
+    }
+    if ssim {
+        "ssim".hash(&mut std_hasher);
+    }
+    if xpsnr {
+        "xpsnr".hash(&mut std_hasher);
+    }
+    if ssimulacra2 {
+        "ssimulacra2".hash(&mut std_hasher);
+    }
+    if with_ssim {
+        "vmaf+ssim".hash(&mut std_hasher);
+    }
+    if with_vmaf {
+        "+vmaf".hash(&mut std_hasher);
+    }
+    if let Some(device) = encoder_device_id(&enc_args.vcodec) {
+        // hardware encoder output differs per device
+        device.hash(&mut std_hasher);
+    }
     hasher.finalize()
 }
 
+/// Short id of the encode settings part of the cache key, identifying sample encodes
+/// made with the same settings, e.g. for --reuse-encodes.
+pub fn encode_id(enc_args: &FfmpegEncodeArgs<'_>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    let mut std_hasher = BlakeStdHasher(&mut hasher);
+    enc_args.sample_encode_hash(&mut std_hasher);
+    if let Some(device) = encoder_device_id(&enc_args.vcodec) {
+        device.hash(&mut std_hasher);
+    }
+    hasher.finalize().to_hex()[..8].to_owned()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum HwEncoder {
+    Nvenc,
+    VideoToolbox,
+    /// vaapi & qsv, using dri render devices.
+    Dri,
+}
+
+impl HwEncoder {
+    fn from_vcodec(vcodec: &str) -> Option<Self> {
+        match vcodec.rsplit_once('_')?.1 {
+            "nvenc" => Some(Self::Nvenc),
+            "videotoolbox" => Some(Self::VideoToolbox),
+            "vaapi" | "qsv" => Some(Self::Dri),
+            _ => None,
+        }
+    }
+}
+
+/// Returns an identity of the hardware device(s) used by hardware encoders,
+/// e.g. the GPU model(s). `None` for software encoders or if unknown.
+fn encoder_device_id(vcodec: &str) -> Option<&'static str> {
+    static NVENC: OnceLock<Option<String>> = OnceLock::new();
+    static VIDEOTOOLBOX: OnceLock<Option<String>> = OnceLock::new();
+    static DRI: OnceLock<Option<String>> = OnceLock::new();
+
+    let command_output = |cmd: &str, args: &[&str]| {
+        let out = std::process::Command::new(cmd).args(args).output().ok()?;
+        let out = String::from_utf8_lossy(&out.stdout).trim().to_owned();
+        (!out.is_empty()).then_some(out)
+    };
+
+    match HwEncoder::from_vcodec(vcodec)? {
+        HwEncoder::Nvenc => NVENC.get_or_init(|| {
+            command_output(
+                "nvidia-smi",
+                &["--query-gpu=name,pci.device_id", "--format=csv,noheader"],
+            )
+        }),
+        HwEncoder::VideoToolbox => VIDEOTOOLBOX
+            .get_or_init(|| command_output("sysctl", &["-n", "machdep.cpu.brand_string"])),
+        HwEncoder::Dri => DRI.get_or_init(|| {
+            let mut ids: Vec<_> = std::fs::read_dir("/sys/class/drm")
+                .ok()?
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with("renderD"))
+                .filter_map(|e| {
+                    let vendor = std::fs::read_to_string(e.path().join("device/vendor")).ok()?;
+                    let device = std::fs::read_to_string(e.path().join("device/device")).ok()?;
+                    Some(format!("{}:{}", vendor.trim(), device.trim()))
+                })
+                .collect();
+            ids.sort();
+            (!ids.is_empty()).then(|| ids.join(","))
+        }),
+    }
+    .as_deref()
+}
+
 struct BlakeStdHasher<'a>(&'a mut blake3::Hasher);
 impl std::hash::Hasher for BlakeStdHasher<'_> {
     fn finish(&self) -> u64 {
@@ -127,3 +363,103 @@ impl std::hash::Hasher for BlakeStdHasher<'_> {
         self.0.update(bytes);
     }
 }
+
+#[test]
+fn entry_checksum() {
+    let result = super::EncodeResult {
+        sample_size: 5_000_000_000,
+        encoded_size: 1_200_000_000,
+        vmaf_score: 95.1,
+        vmaf_frames: vec![],
+        encode_time: Duration::from_secs(30),
+        sample_duration: Duration::from_secs(20),
+        encoded_resolution: Some((3840, 2160)),
+        ssim_score: None,
+        xpsnr_score: None,
+        ssimulacra2_score: None,
+        from_cache: false,
+    };
+    let mut data = encode_entry(&result).unwrap();
+    let decoded = decode_entry(&data).unwrap();
+    assert_eq!(decoded.sample_size, 5_000_000_000);
+    assert_eq!(decoded.vmaf_score, 95.1);
+
+    // legacy entry without checksum
+    let legacy = serde_json::to_vec(&result).unwrap();
+    assert!(decode_entry(&legacy).is_some());
+
+    // corruption
+    data[10] ^= 0xff;
+    assert!(decode_entry(&data).is_none());
+    assert!(decode_entry(&legacy[..legacy.len() - 5]).is_none());
+    assert!(decode_entry(b"").is_none());
+}
+
+#[test]
+fn export_import_line() {
+    let result = super::EncodeResult {
+        sample_size: 5_000_000,
+        encoded_size: 1_200_000,
+        vmaf_score: 95.1,
+        vmaf_frames: vec![],
+        encode_time: Duration::from_secs(30),
+        sample_duration: Duration::from_secs(20),
+        encoded_resolution: None,
+        ssim_score: None,
+        xpsnr_score: None,
+        ssimulacra2_score: None,
+        from_cache: true,
+    };
+    let key = blake3::hash(b"sample").to_hex();
+    let line = export_line(&key, &result).unwrap();
+    let (imported_key, imported) = parse_export_line(&line).unwrap();
+    assert_eq!(imported_key, key.as_str());
+    assert_eq!(imported.encoded_size, 1_200_000);
+    assert!(!imported.from_cache);
+
+    let bad_key = line.replace(key.as_str(), "../not-a-key");
+    assert!(parse_export_line(&bad_key).is_err());
+    assert!(parse_export_line("{}").is_err());
+}
+
+#[test]
+fn hw_encoder_from_vcodec() {
+    assert_eq!(HwEncoder::from_vcodec("av1_nvenc"), Some(HwEncoder::Nvenc));
+    assert_eq!(
+        HwEncoder::from_vcodec("hevc_videotoolbox"),
+        Some(HwEncoder::VideoToolbox)
+    );
+    assert_eq!(HwEncoder::from_vcodec("av1_qsv"), Some(HwEncoder::Dri));
+    assert_eq!(HwEncoder::from_vcodec("h264_vaapi"), Some(HwEncoder::Dri));
+    assert_eq!(HwEncoder::from_vcodec("libsvtav1"), None);
+    assert_eq!(HwEncoder::from_vcodec("libvpx-vp9"), None);
+    assert_eq!(encoder_device_id("libx265"), None);
+}
+
+#[test]
+fn encode_id_settings() {
+    let mut args = FfmpegEncodeArgs {
+        input: Path::new("vid.mkv"),
+        vcodec: "libx265".into(),
+        vfilter: None,
+        ref_vfilter: None,
+        pix_fmt: PixelFormat::Yuv420p10le,
+        crf: 28.0,
+        preset: Some("slow".into()),
+        output_args: vec![],
+        input_args: vec![],
+        hw_upload: None,
+        video_only: false,
+        template: None,
+    };
+    let id = encode_id(&args);
+    assert_eq!(id.len(), 8);
+
+    // input does not affect the encode settings
+    args.input = Path::new("other.mkv");
+    assert_eq!(encode_id(&args), id);
+
+    args.output_args.push(String::from("-x265-params").into());
+    args.output_args.push(String::from("aq-mode=3").into());
+    assert_ne!(encode_id(&args), id);
+}
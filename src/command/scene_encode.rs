@@ -0,0 +1,236 @@
+use crate::{
+    cancel::CancellationToken,
+    command::{args, crf_search, progress, SmallDuration, PROGRESS_CHARS},
+    console_ext::style,
+    ffmpeg, ffprobe,
+    float::TerseF32,
+    machine, naming,
+    process::FfmpegOut,
+    sample,
+    temporary::{self, TempKind},
+};
+use anyhow::Context;
+use clap::{Parser, ValueHint};
+use console::style;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio_stream::StreamExt;
+
+/// Per-scene adaptive encoding. Split the input video into scenes, crf-search & encode
+/// each scene at its own crf, then losslessly concatenate the scene encodes.
+///
+/// * Scenes are detected with the ffmpeg scene change score & split at the next keyframe.
+/// * Audio & subtitle streams & chapters are copied from the input.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    #[clap(flatten)]
+    pub search: crf_search::Args,
+
+    /// Output file, by default the same as input with `.av1` before the extension.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub existing: args::ExistingOutput,
+
+    /// Min ffmpeg scene change score 0-1 to split scenes at.
+    #[arg(long, default_value_t = 0.3)]
+    pub scene_threshold: f32,
+
+    /// Min scene duration, shorter scenes are merged into the previous scene.
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    pub min_scene_len: Duration,
+
+    /// Print the encode result to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn scene_encode(
+    Args {
+        mut search,
+        output,
+        existing,
+        scene_threshold,
+        min_scene_len,
+        json,
+    }: Args,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    const SPINNER_RUNNING: &str =
+        "{spinner:.cyan.bold} {prefix} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})";
+
+    search.quiet = true;
+    search.no_history_record = true;
+    let input = search.args.input.clone();
+    let input_probe = ffprobe::probe(&input);
+    search.args.resolve_encoder()?;
+    search.args.apply_sar(&input_probe);
+    search.args.apply_detelecine(&input_probe);
+    let duration = input_probe.duration.clone()?;
+    let output = output.unwrap_or_else(|| {
+        naming::default_output_name(&input, search.args.encoder.as_str(), false)
+    });
+    if !existing.check_output(&output)? {
+        return Ok(());
+    }
+    search.sample.set_extension_from_output(&output);
+    let degraded = search.vmaf_fallback();
+
+    let bar = ProgressBar::new(12).with_style(
+        ProgressStyle::default_bar()
+            .template(SPINNER_RUNNING)?
+            .progress_chars(PROGRESS_CHARS),
+    );
+    machine::watch(&bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    if let Some(warning) = &degraded {
+        progress::println(&bar, warning.clone());
+    }
+
+    bar.set_prefix("Detecting scenes");
+    bar.set_message("scene detection, ");
+    let cuts = merge_cuts(
+        &ffmpeg::detect_scenes(&input, scene_threshold).await?,
+        min_scene_len.as_secs_f64(),
+        duration.as_secs_f64(),
+    );
+    let (chunks, _) = sample::split_scenes(
+        &input,
+        &cuts,
+        search.sample.temp_dir.clone(),
+        input_probe.dynamic_hdr.any(),
+    )
+    .await?;
+    progress::println(
+        &bar,
+        style!("Encoding {} scenes", chunks.len()).dim().to_string(),
+    );
+
+    let ext = naming::pre_extension_name(search.args.encoder.as_str()).to_owned();
+    let mut encoded = vec![];
+    let mut scenes = vec![];
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let scene_n = idx + 1;
+        bar.set_prefix(format!("Scene {scene_n}/{}", chunks.len()));
+        bar.reset();
+
+        // search
+        let chunk_probe = Arc::new(ffprobe::probe(&chunk.path));
+        let mut chunk_search = search.clone();
+        chunk_search.args.input = chunk.path.clone();
+        let best = crf_search::run(&chunk_search, chunk_probe.clone(), bar.clone(), &cancel)
+            .await
+            .with_context(|| format!("scene {scene_n}"))?;
+
+        // encode
+        let chunk_output = chunk
+            .path
+            .with_file_name(naming::temp_file_name(&chunk.path, &format!("{ext}.mkv")));
+        temporary::add(&chunk_output, TempKind::Keepable);
+        let mut enc_args = chunk_search
+            .args
+            .to_encoder_args(best.crf(), &chunk_probe)?;
+        enc_args.video_only = true;
+        enc_args
+            .output_args
+            .extend(chunk_search.args.force_keyframes_args(&chunk_probe)?);
+        bar.reset();
+        bar.set_message("encoding, ");
+        if let Ok(d) = &chunk_probe.duration {
+            bar.set_length(chunk_search.args.output_duration(*d).as_micros_u64().max(1));
+        }
+        let mut enc = ffmpeg::encode(enc_args, &chunk_output, false, None, false, 0, &[], None)?;
+        while let Some(progress) = enc.next().await {
+            if let FfmpegOut::Progress { fps, time, .. } = progress? {
+                if fps > 0.0 {
+                    bar.set_message(format!("{fps} fps, "));
+                }
+                bar.set_position(time.as_micros_u64());
+            }
+        }
+
+        progress::println(
+            &bar,
+            style!(
+                "- Scene {scene_n} {:.1}s-{:.1}s crf {} {} ({:.0}%)",
+                chunk.start,
+                chunk.end,
+                TerseF32(best.crf()),
+                best.score_label(),
+                best.enc.encode_percent,
+            )
+            .dim()
+            .to_string(),
+        );
+        scenes.push(serde_json::json!({
+            "start": chunk.start,
+            "end": chunk.end,
+            "crf": best.crf(),
+            "score": best.score(),
+            "predicted_encode_percent": best.enc.encode_percent,
+        }));
+        encoded.push(chunk_output);
+        // clean sample encodes of this scene
+        temporary::clean(true).await;
+    }
+
+    // output is temporary until encoding has completed successfully
+    args::rename_existing(&output, &bar)?;
+    temporary::add(&output, TempKind::NotKeepable);
+    bar.set_prefix("Concatenating");
+    bar.set_message("concat, ");
+    ffmpeg::concat(
+        &encoded,
+        &input,
+        &output,
+        &[],
+        search.sample.temp_dir.clone(),
+    )
+    .await?;
+    bar.finish();
+    // successful encode, so don't delete it!
+    temporary::unadd(&output);
+
+    let output_size = tokio::fs::metadata(&output).await?.len();
+    let output_percent =
+        100.0 * output_size as f64 / tokio::fs::metadata(&input).await?.len() as f64;
+    if json {
+        machine::print_json(&serde_json::json!({
+            "output": output,
+            "size": output_size,
+            "encode_percent": output_percent,
+            "scenes": scenes,
+        }));
+    }
+    eprintln!(
+        "{} {} {}",
+        style("Encoded").dim(),
+        style(HumanBytes(output_size)).dim().bold(),
+        style!("({}%)", output_percent.round()).dim(),
+    );
+    Ok(())
+}
+
+/// Drop scene cuts less than `min_len` seconds after the previous cut or before the end.
+fn merge_cuts(cuts: &[f64], min_len: f64, duration: f64) -> Vec<f64> {
+    let mut merged = vec![];
+    let mut last = 0.0;
+    for &cut in cuts {
+        if cut - last >= min_len && duration - cut >= min_len {
+            merged.push(cut);
+            last = cut;
+        }
+    }
+    merged
+}
+
+#[test]
+fn merge_short_scenes() {
+    let cuts = [3.0, 12.5, 14.0, 30.0, 52.0, 58.0];
+    assert_eq!(merge_cuts(&cuts, 10.0, 60.0), [12.5, 30.0]);
+    assert_eq!(merge_cuts(&cuts, 0.0, 60.0), cuts);
+    assert!(merge_cuts(&cuts, 40.0, 60.0).is_empty());
+}
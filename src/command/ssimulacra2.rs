@@ -0,0 +1,65 @@
+use crate::{command::PROGRESS_CHARS, ffprobe, machine, ssimulacra2};
+use anyhow::ensure;
+use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{path::PathBuf, time::Duration};
+
+/// Full SSIMULACRA2 score calculation, distorted file vs reference file.
+/// Works with videos and images.
+///
+/// * Requires `ssimulacra2_rs` in PATH.
+/// * Distorted & reference must have the same resolution.
+#[derive(Parser)]
+#[clap(verbatim_doc_comment)]
+#[group(skip)]
+pub struct Args {
+    /// Reference video file.
+    #[arg(long)]
+    pub reference: PathBuf,
+
+    /// Re-encoded/distorted video file.
+    #[arg(long)]
+    pub distorted: PathBuf,
+
+    /// Verify the score is at least this value, exiting with an error otherwise.
+    #[arg(long)]
+    pub min_ssimulacra2: Option<f32>,
+
+    /// Print the score to stdout as json. Set by the global --json.
+    #[arg(skip)]
+    pub json: bool,
+}
+
+pub async fn ssimulacra2(
+    Args {
+        reference,
+        distorted,
+        min_ssimulacra2,
+        json,
+    }: Args,
+) -> anyhow::Result<()> {
+    let bar = ProgressBar::new(1).with_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg})")?
+            .progress_chars(PROGRESS_CHARS),
+    );
+    machine::watch(&bar);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar.set_message("ssimulacra2 running");
+
+    let image = ffprobe::probe(&reference).is_image;
+    let score = ssimulacra2::score(&reference, &distorted, image).await?;
+    bar.finish();
+
+    match json {
+        true => machine::print_json(&serde_json::json!({ "ssimulacra2": score })),
+        false => println!("{score}"),
+    }
+    if let Some(min_ssimulacra2) = min_ssimulacra2 {
+        ensure!(
+            score >= min_ssimulacra2,
+            "SSIMULACRA2 {score:.2} is below --min-ssimulacra2 {min_ssimulacra2}"
+        );
+    }
+    Ok(())
+}
@@ -1,16 +1,31 @@
+mod aligned;
+
 use crate::{
+    cancel::{self, CancellationToken},
     command::{
-        args::{self, PixelFormat},
-        PROGRESS_CHARS,
+        args::{self, VmafStatistic},
+        compare, progress, PROGRESS_CHARS,
     },
-    ffprobe,
+    console_ext::style,
+    ffprobe, machine, naming,
+    plot::{self, Plot},
     process::FfmpegOut,
+    report::Report,
+    sample,
+    stats::{frame_type_means, mean_confidence_95, percentile, Stats},
+    temporary::{self, TempKind},
     vmaf,
     vmaf::VmafOut,
 };
-use clap::Parser;
+use aligned::Aligned;
+use anyhow::{ensure, Context};
+use clap::{Parser, ValueHint};
+use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio_stream::StreamExt;
 
 /// Full VMAF score calculation, distorted file vs reference file.
@@ -20,6 +35,8 @@ use tokio_stream::StreamExt;
 /// * Auto sets `n_threads` to system threads.
 /// * Auto upscales lower resolution videos to the model.
 /// * Converts distorted & reference to appropriate format yuv streams before passing to vmaf.
+/// * Compares multiple distorted files, e.g. `--distorted old.x265.mkv new.av1.mkv`,
+///   printing per-file stats & differences to the first.
 #[derive(Parser)]
 #[clap(verbatim_doc_comment)]
 #[group(skip)]
@@ -33,12 +50,94 @@ pub struct Args {
     #[arg(long)]
     pub reference_vfilter: Option<String>,
 
-    /// Re-encoded/distorted video file.
-    #[arg(long)]
-    pub distorted: PathBuf,
+    /// Re-encoded/distorted video file(s).
+    ///
+    /// With multiple files each is scored with the same settings & a comparison table
+    /// of per-frame stats (as with `ab-av1 compare`) is printed.
+    #[arg(long, num_args = 1.., required = true)]
+    pub distorted: Vec<PathBuf>,
 
     #[clap(flatten)]
     pub vmaf: args::Vmaf,
+
+    /// Verify the score is at least this value, e.g. after a full encode,
+    /// exiting with an error otherwise.
+    ///
+    /// Consecutive windows are scored showing the running per-frame mean, min &
+    /// 5th percentile, which are also printed when done.
+    #[arg(long)]
+    pub min_vmaf: Option<f32>,
+
+    /// Abort early, with an error, once the score can no longer reach this value
+    /// even if all remaining frames scored 100, e.g. set to the --min-vmaf.
+    ///
+    /// Consecutive windows are scored, checking after each one.
+    #[arg(long, conflicts_with_all = ["verify_samples", "export_aligned"])]
+    pub abort_below: Option<f32>,
+
+    /// Estimate the score from this many evenly spaced 20s windows instead of
+    /// scoring every frame, e.g. to cheaply verify a long full encode.
+    ///
+    /// The estimate is the mean window score, printed with a 95% confidence interval.
+    /// With --min-vmaf a warning is printed if the interval reaches below it.
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["frame_types", "export_aligned"],
+    )]
+    pub verify_samples: Option<u64>,
+
+    /// Report the mean per-frame score of each distorted frame type (I, P, B).
+    ///
+    /// Useful to spot keyframe pumping, where keyframes score notably higher than
+    /// other frames, a common hardware encoder artifact.
+    #[arg(long)]
+    pub frame_types: bool,
+
+    /// Also calculate the libvmaf MS-SSIM feature, printing the per-frame
+    /// mean, min & 5th percentile values.
+    #[arg(long)]
+    pub ms_ssim: bool,
+
+    /// Write per-frame rows aligned with distorted frame info to this file, e.g. for ML analysis.
+    ///
+    /// Columns: frame, pts, frame_type, frame_size, vmaf & libvmaf features adm2, motion2,
+    /// vif_scale0-3 & ms_ssim (with --ms-ssim).
+    ///
+    /// Written as csv, or arrow ipc (".arrow", ".feather") & parquet (".parquet")
+    /// if built with the "aligned-export" feature.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub export_aligned: Option<PathBuf>,
+
+    /// Write a self-contained html report to this file, with an interactive
+    /// per-frame score graph, summary stats & settings.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub report: Option<PathBuf>,
+
+    /// Write a plot of per-frame scores to this ".svg" or ".png" file, with MS-SSIM
+    /// scaled ×100 & overlaid if --ms-ssim is set.
+    ///
+    /// With multiple --distorted files each score curve is plotted & the frame-aligned
+    /// differences to the first are plotted to a ".diff.svg"/".diff.png" file alongside.
+    #[arg(long, value_hint = ValueHint::FilePath, value_parser = plot::parse_path)]
+    pub plot: Option<PathBuf>,
+
+    /// Stdout result format `human`, `json` or `csv`. Set to json by the global --json.
+    ///
+    /// json & csv include per-frame score statistics: mean, harmonic mean, min, max & p5.
+    #[arg(long, value_enum, default_value_t = StdoutFormat::Human)]
+    pub stdout_format: StdoutFormat,
+
+    /// Don't draw progress or print warnings. Set by the global --quiet.
+    #[arg(skip)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StdoutFormat {
+    Human,
+    Json,
+    Csv,
 }
 
 pub async fn vmaf(
@@ -46,56 +145,594 @@ pub async fn vmaf(
         reference,
         reference_vfilter,
         distorted,
-        vmaf,
+        mut vmaf,
+        min_vmaf,
+        abort_below,
+        verify_samples,
+        frame_types,
+        ms_ssim,
+        export_aligned,
+        report,
+        plot,
+        stdout_format,
+        quiet,
     }: Args,
+    cancel: CancellationToken,
 ) -> anyhow::Result<()> {
+    if quiet {
+        progress::set_quiet();
+    }
+    let distorted = match &distorted[..] {
+        [distorted] => distorted.clone(),
+        _ => {
+            for (set, arg) in [
+                (min_vmaf.is_some(), "--min-vmaf"),
+                (abort_below.is_some(), "--abort-below"),
+                (verify_samples.is_some(), "--verify-samples"),
+                (frame_types, "--frame-types"),
+                (export_aligned.is_some(), "--export-aligned"),
+                (report.is_some(), "--report"),
+                (stdout_format == StdoutFormat::Csv, "--stdout-format csv"),
+            ] {
+                ensure!(!set, "{arg} requires a single --distorted file");
+            }
+            return compare_all(
+                &reference,
+                reference_vfilter.as_deref(),
+                &distorted,
+                vmaf,
+                ms_ssim,
+                plot.as_deref(),
+                stdout_format,
+                &cancel,
+            )
+            .await;
+        }
+    };
+
     let bar = ProgressBar::new(1).with_style(
         ProgressStyle::default_bar()
-            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}eta {eta})")?
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {wide_bar:.cyan/blue} ({msg}{prefix}eta {eta})")?
             .progress_chars(PROGRESS_CHARS)
     );
+    machine::watch(&bar);
     bar.enable_steady_tick(Duration::from_millis(100));
     bar.set_message("vmaf running, ");
 
     let dprobe = ffprobe::probe(&distorted);
-    let dpix_fmt = dprobe.pixel_format().unwrap_or(PixelFormat::Yuv444p10le);
     let rprobe = ffprobe::probe(&reference);
-    let rpix_fmt = rprobe.pixel_format().unwrap_or(PixelFormat::Yuv444p10le);
+    let pix_fmt = vmaf.pix_fmt(dprobe.pixel_format(), rprobe.pixel_format());
+    progress::println(
+        &bar,
+        style!("vmaf pixel format {pix_fmt}").dim().to_string(),
+    );
+    // with a target score consecutive windows to show running stats & allow aborting
+    let live = (min_vmaf.is_some() || abort_below.is_some())
+        && verify_samples.is_none()
+        && export_aligned.is_none()
+        && !dprobe.is_image;
+    // whole inputs, --verify-samples or consecutive windows
+    let windows: Vec<_> = match (verify_samples, &dprobe.duration) {
+        (Some(samples), _) => sample::ranges(dprobe.duration.clone()?, samples)
+            .into_iter()
+            .map(Some)
+            .collect(),
+        (None, Ok(duration)) if live => consecutive_windows(*duration)
+            .into_iter()
+            .map(Some)
+            .collect(),
+        _ => vec![None],
+    };
     let nframes = dprobe.nframes().or_else(|_| rprobe.nframes());
-    if let Ok(nframes) = nframes {
-        bar.set_length(nframes);
+    let track_frames = nframes.is_ok() && windows[0].is_none();
+    match (&nframes, windows[0]) {
+        (_, Some(_)) => bar.set_length(windows.len() as _),
+        (Ok(nframes), None) => bar.set_length(*nframes),
+        _ => {}
     }
 
-    let mut vmaf = vmaf::run(
-        &reference,
-        &distorted,
-        &vmaf.ffmpeg_lavfi(
-            dprobe.resolution,
-            dpix_fmt.max(rpix_fmt),
-            reference_vfilter.as_deref(),
-        ),
-    )?;
-    let mut vmaf_score = -1.0;
-    while let Some(vmaf) = vmaf.next().await {
-        match vmaf {
-            VmafOut::Done(score) => {
-                vmaf_score = score;
-                break;
+    let write_frame_log = vmaf.vmaf_statistic != VmafStatistic::Mean
+        || min_vmaf.is_some()
+        || abort_below.is_some()
+        || verify_samples.is_some()
+        || frame_types
+        || ms_ssim
+        || export_aligned.is_some()
+        || report.is_some()
+        || plot.is_some()
+        || stdout_format != StdoutFormat::Human;
+    let frame_logs: Vec<_> = (0..windows.len())
+        .map(|idx| {
+            write_frame_log.then(|| {
+                let suffix = match windows[idx] {
+                    Some(_) => format!("vmaf.window{idx}.json"),
+                    None => "vmaf.json".into(),
+                };
+                let mut log = temporary::process_dir(None);
+                log.push(naming::temp_file_name(&distorted, &suffix));
+                temporary::add(&log, TempKind::NotKeepable);
+                log
+            })
+        })
+        .collect();
+
+    if ms_ssim {
+        vmaf = vmaf.with_ms_ssim();
+    }
+
+    let mut window_scores = Vec::with_capacity(windows.len());
+    let mut scores = vec![];
+    // distorted frame number of each score, for --frame-types
+    let mut frame_nums = vec![];
+    let mut ms_ssim_scores = vec![];
+    for (idx, (window, frame_log)) in windows.iter().zip(&frame_logs).enumerate() {
+        let lavfi = |vmaf: &args::Vmaf| {
+            match frame_log {
+                Some(log) => vmaf.with_frame_log(log),
+                None => vmaf.clone(),
             }
-            VmafOut::Progress(FfmpegOut::Progress { frame, fps, .. }) => {
-                if fps > 0.0 {
-                    bar.set_message(format!("vmaf {fps} fps, "));
-                }
-                if nframes.is_ok() {
-                    bar.set_position(frame);
+            .ffmpeg_lavfi(
+                dprobe.display_resolution(),
+                pix_fmt,
+                reference_vfilter.as_deref(),
+            )
+        };
+        let mean = run(
+            &bar,
+            &mut vmaf,
+            (&reference, &distorted),
+            lavfi,
+            *window,
+            track_frames,
+            &cancel,
+        )
+        .await?;
+        window_scores.push(vmaf::score_statistic(&vmaf, frame_log.as_deref(), mean)?);
+        if let Some(log) = frame_log {
+            let window_scores = vmaf::read_frame_scores(log)?;
+            if frame_types {
+                frame_nums.extend(window_frame_nums(
+                    log,
+                    window_scores.len(),
+                    window.zip(dprobe.fps.as_ref().ok()),
+                )?);
+            }
+            scores.extend(window_scores);
+            if ms_ssim {
+                ms_ssim_scores.extend(vmaf::read_frame_metric(log, vmaf::MS_SSIM_FEATURE)?);
+            }
+        }
+        if window.is_some() {
+            bar.inc(1);
+        }
+        if live && window.is_some() {
+            if let Some(FrameStats { stats, p5, .. }) = FrameStats::from_scores(&scores) {
+                bar.set_prefix(format!(
+                    "mean {:.2}, min {:.2}, p5 {p5:.2}, ",
+                    stats.mean, stats.min
+                ));
+            }
+            let remaining = match &nframes {
+                Ok(nframes) => (*nframes as usize).saturating_sub(scores.len()),
+                // estimate assuming similar frame counts per window
+                Err(_) => scores.len() * (windows.len() - idx - 1) / (idx + 1),
+            };
+            if let Some(abort_below) = abort_below {
+                if let Some(best) = best_reachable(vmaf.vmaf_statistic, &scores, remaining)
+                    .filter(|best| *best < abort_below)
+                {
+                    bar.abandon();
+                    anyhow::bail!(
+                        "VMAF can reach at most {best:.2} after {} frames, \
+                         below --abort-below {abort_below}",
+                        scores.len()
+                    );
                 }
             }
-            VmafOut::Progress(FfmpegOut::StreamSizes { .. }) => {}
-            VmafOut::Err(e) => return Err(e),
         }
     }
     bar.finish();
 
-    println!("{vmaf_score}");
+    // only multiple --verify-samples windows have a confidence interval
+    let confidence = match live {
+        true => None,
+        false => mean_confidence_95(&window_scores),
+    };
+    let vmaf_score = match confidence {
+        Some((mean, _)) => mean,
+        None if live && windows[0].is_some() => vmaf
+            .vmaf_statistic
+            .calculate(&scores)
+            .context("vmaf frame logs have no scores")?,
+        None => window_scores[0],
+    };
+    let frame_stats = FrameStats::from_scores(&scores);
+    let type_means = match frame_types {
+        true => frame_type_means(
+            &scores,
+            &frame_nums,
+            &ffprobe::probe_frame_types(&distorted)?,
+        ),
+        false => vec![],
+    };
+    let ms_ssim = Stats::from_scores(&ms_ssim_scores);
+    if let (Some(path), [Some(log)]) = (&export_aligned, &frame_logs[..]) {
+        Aligned::read(log, ffprobe::probe_frames(&distorted)?)?.write(path)?;
+    }
+    if let Some(path) = &report {
+        let mut report = Report::new(format!("vmaf {}", distorted.display()));
+        report.summary("vmaf", vmaf_score);
+        if let Some((_, ci)) = confidence {
+            report.summary("windows", window_scores.len());
+            report.summary("95% CI", format!("{}-{}", vmaf_score - ci, vmaf_score + ci));
+        }
+        if let Some(FrameStats { stats, p5, frames }) = frame_stats {
+            report.summary("frames", frames);
+            report.summary("mean", stats.mean);
+            report.summary("harmonic mean", stats.harmonic_mean);
+            report.summary("min", stats.min);
+            report.summary("max", stats.max);
+            report.summary("p5", p5);
+        }
+        for (t, frames, mean) in &type_means {
+            report.summary(&format!("{t} frames mean"), format!("{mean:.2} ({frames})"));
+        }
+        if let Some(ms_ssim) = &ms_ssim {
+            report.summary("ms-ssim", ms_ssim.mean);
+        }
+        report.setting("reference", reference.display());
+        report.setting("distorted", distorted.display());
+        if let Some(vf) = &reference_vfilter {
+            report.setting("reference vfilter", vf);
+        }
+        report.setting("pixel format", pix_fmt);
+        report.setting("model", vmaf.model_label(dprobe.display_resolution()));
+        report.setting("statistic", vmaf.vmaf_statistic);
+        report.series("vmaf", scores.clone());
+        report.series("ms-ssim", ms_ssim_scores.clone());
+        report.write(path)?;
+    }
+    if let Some(path) = &plot {
+        let mut plot = Plot::new(match ms_ssim.is_some() {
+            true => "VMAF & MS-SSIM ×100",
+            false => "VMAF",
+        });
+        plot.series("vmaf", scores.clone());
+        // share the vmaf 0-100 y axis
+        plot.series(
+            "ms-ssim ×100",
+            ms_ssim_scores.iter().map(|s| s * 100.0).collect(),
+        );
+        plot.write(path)?;
+    }
+    match stdout_format {
+        StdoutFormat::Human => println!("{vmaf_score}"),
+        StdoutFormat::Csv => print!(
+            "{}",
+            csv(
+                vmaf_score,
+                frame_stats,
+                ms_ssim.map(|s| s.mean),
+                confidence.map(|(_, ci)| ci),
+            )
+        ),
+        StdoutFormat::Json => {
+            let mut out = serde_json::json!({ "vmaf": vmaf_score });
+            if let Some((_, ci)) = confidence {
+                out["windows"] = window_scores.clone().into();
+                out["ci95"] = serde_json::json!([vmaf_score - ci, vmaf_score + ci]);
+            }
+            if let Some(FrameStats { stats, p5, frames }) = frame_stats {
+                out["frames"] = frames.into();
+                out["stats"] = serde_json::json!({
+                    "mean": stats.mean,
+                    "harmonic_mean": stats.harmonic_mean,
+                    "min": stats.min,
+                    "max": stats.max,
+                    "p5": p5,
+                });
+            }
+            if frame_types {
+                let types: serde_json::Map<_, _> = type_means
+                    .iter()
+                    .map(|(t, frames, mean)| {
+                        let stats = serde_json::json!({ "frames": frames, "mean": mean });
+                        (t.to_string(), stats)
+                    })
+                    .collect();
+                out["frame_types"] = types.into();
+            }
+            if let Some(ms_ssim) = &ms_ssim {
+                out["ms_ssim"] = ms_ssim.mean.into();
+            }
+            machine::print_json(&out);
+        }
+    }
+    if let Some((_, ci)) = confidence.filter(|_| !quiet) {
+        eprintln!(
+            "{}",
+            style!(
+                "estimated from {} windows, 95% CI {:.2}-{:.2}",
+                window_scores.len(),
+                vmaf_score - ci,
+                vmaf_score + ci
+            )
+            .dim()
+        );
+    }
+    for (t, frames, mean) in &type_means {
+        eprintln!("{}", style!("{t} frames mean {mean:.2} ({frames})").dim());
+    }
+    if let Some(ms_ssim) = ms_ssim {
+        let p5 = percentile(&ms_ssim_scores, 5.0).unwrap_or(ms_ssim.min);
+        eprintln!(
+            "{}",
+            style!(
+                "MS-SSIM mean {:.4}, min {:.4}, p5 {p5:.4}",
+                ms_ssim.mean,
+                ms_ssim.min
+            )
+            .dim()
+        );
+    }
+
+    if let Some(min_vmaf) = min_vmaf {
+        if let Some(FrameStats { stats, p5, .. }) = frame_stats {
+            eprintln!(
+                "{}",
+                style!(
+                    "frames mean {:.2}, min {:.2}, p5 {p5:.2}",
+                    stats.mean,
+                    stats.min
+                )
+                .dim()
+            );
+        }
+        ensure!(
+            vmaf_score >= min_vmaf,
+            "VMAF {vmaf_score:.2} is below --min-vmaf {min_vmaf}"
+        );
+        if let Some((_, ci)) = confidence.filter(|(_, ci)| !quiet && vmaf_score - ci < min_vmaf) {
+            eprintln!(
+                "{} VMAF 95% CI lower bound {:.2} is below --min-vmaf {min_vmaf}, \
+                 consider more --verify-samples or a full score",
+                style("Warning:").yellow().bold(),
+                vmaf_score - ci,
+            );
+        }
+    }
     Ok(())
 }
+
+/// Score & compare multiple `distorted` files against the same `reference`.
+#[allow(clippy::too_many_arguments)]
+async fn compare_all(
+    reference: &Path,
+    reference_vfilter: Option<&str>,
+    distorted: &[PathBuf],
+    vmaf: args::Vmaf,
+    ms_ssim: bool,
+    plot: Option<&Path>,
+    stdout_format: StdoutFormat,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let results = compare::score_all(
+        reference,
+        reference_vfilter,
+        distorted,
+        vmaf,
+        ms_ssim,
+        cancel,
+    )
+    .await?;
+    if let Some(path) = plot {
+        compare::write_plots(path, &results)?;
+    }
+    match stdout_format {
+        StdoutFormat::Json => {
+            let first = results[0].score();
+            let files: Vec<_> = results
+                .iter()
+                .zip(distorted)
+                .map(|(scored, path)| {
+                    let mut json = scored.to_json();
+                    json["distorted"] = path.display().to_string().into();
+                    json["diff"] = serde_json::json!({ "vmaf": scored.score() - first });
+                    json
+                })
+                .collect();
+            machine::print_json(&serde_json::json!({ "distorted": files }));
+        }
+        _ => print!("{}", compare::table(&results)),
+    }
+    Ok(())
+}
+
+/// Run vmaf on `(reference, distorted)` or a window of them, retrying with fewer
+/// threads if libvmaf runs out of memory. Returns the libvmaf mean score.
+pub async fn run(
+    bar: &ProgressBar,
+    vmaf: &mut args::Vmaf,
+    (reference, distorted): (&Path, &Path),
+    lavfi: impl Fn(&args::Vmaf) -> String,
+    window: Option<(Duration, Duration)>,
+    track_frames: bool,
+    cancel: &CancellationToken,
+) -> anyhow::Result<f32> {
+    cancel::until(cancel, async {
+        'vmaf: loop {
+            let mut vmaf_out = vmaf::run_window(reference, distorted, &lavfi(vmaf), window)?;
+            while let Some(next) = vmaf_out.next().await {
+                match next {
+                    VmafOut::Done(score) => return Ok(score),
+                    VmafOut::Progress(FfmpegOut::Progress { frame, fps, .. }) => {
+                        if fps > 0.0 {
+                            bar.set_message(format!("vmaf {fps} fps, "));
+                        }
+                        if track_frames {
+                            bar.set_position(frame);
+                        }
+                    }
+                    VmafOut::Progress(FfmpegOut::StreamSizes { .. }) => {}
+                    VmafOut::Err(e) => match vmaf.reduced_threads() {
+                        Some(reduced) if vmaf::is_oom_error(&e) => {
+                            progress::println(
+                                bar,
+                                format!(
+                                    "{} {}",
+                                    style("Warning:").yellow().bold(),
+                                    vmaf::oom_retry_warning(vmaf, &reduced)
+                                ),
+                            );
+                            *vmaf = reduced;
+                            if track_frames {
+                                bar.set_position(0);
+                            }
+                            continue 'vmaf;
+                        }
+                        _ => return Err(e),
+                    },
+                }
+            }
+            return Ok(-1.0);
+        }
+    })
+    .await
+}
+
+/// Duration of each consecutive window scored with --min-vmaf or --abort-below.
+const LIVE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Consecutive [`LIVE_WINDOW`]s covering the whole `duration`. The last is open
+/// ended, up to a further window, in case the probed duration is short.
+fn consecutive_windows(duration: Duration) -> Vec<(Duration, Duration)> {
+    let count = duration.as_secs_f64() / LIVE_WINDOW.as_secs_f64();
+    let count = (count.ceil() as u32).max(1);
+    (0..count)
+        .map(|idx| {
+            let start = LIVE_WINDOW * idx;
+            match idx + 1 == count {
+                true => (start, duration.saturating_sub(start) + LIVE_WINDOW),
+                false => (start, LIVE_WINDOW),
+            }
+        })
+        .collect()
+}
+
+/// Distorted frame numbers of `len` scores in the libvmaf json `log`, from the
+/// per-window `frameNum`s offset by the window start frame.
+fn window_frame_nums(
+    log: &Path,
+    len: usize,
+    window_fps: Option<((Duration, Duration), &f64)>,
+) -> anyhow::Result<Vec<usize>> {
+    let offset = window_fps.map_or(0, |((start, _), fps)| {
+        (start.as_secs_f64() * fps).round() as usize
+    });
+    let nums = vmaf::read_frame_metric(log, "frameNum")?;
+    Ok(match nums.len() == len {
+        true => nums.into_iter().map(|n| offset + n as usize).collect(),
+        false => (offset..offset + len).collect(),
+    })
+}
+
+/// Returns the best final `statistic` score still reachable if all `remaining`
+/// frames scored 100.
+fn best_reachable(statistic: VmafStatistic, scores: &[f32], remaining: usize) -> Option<f32> {
+    let mut best = scores.to_vec();
+    best.resize(scores.len() + remaining, 100.0);
+    statistic.calculate(&best)
+}
+
+/// Per-frame vmaf score statistics.
+#[derive(Debug, Clone, Copy)]
+struct FrameStats {
+    stats: Stats,
+    p5: f32,
+    frames: usize,
+}
+
+impl FrameStats {
+    fn from_scores(scores: &[f32]) -> Option<Self> {
+        Some(Self {
+            stats: Stats::from_scores(scores)?,
+            p5: percentile(scores, 5.0)?,
+            frames: scores.len(),
+        })
+    }
+}
+
+/// Csv header & result row. `ci95` is the --verify-samples confidence interval half-width.
+fn csv(
+    vmaf: f32,
+    frame_stats: Option<FrameStats>,
+    ms_ssim: Option<f32>,
+    ci95: Option<f32>,
+) -> String {
+    let opt = |v: Option<f32>| v.map(|v| v.to_string()).unwrap_or_default();
+    let stats = frame_stats.map(|f| f.stats);
+    format!(
+        "vmaf,frames,mean,harmonic_mean,min,max,p5,ms_ssim,ci95_low,ci95_high\n\
+         {vmaf},{},{},{},{},{},{},{},{},{}\n",
+        frame_stats
+            .map(|f| f.frames.to_string())
+            .unwrap_or_default(),
+        opt(stats.map(|s| s.mean)),
+        opt(stats.map(|s| s.harmonic_mean)),
+        opt(stats.map(|s| s.min)),
+        opt(stats.map(|s| s.max)),
+        opt(frame_stats.map(|f| f.p5)),
+        opt(ms_ssim),
+        opt(ci95.map(|ci| vmaf - ci)),
+        opt(ci95.map(|ci| vmaf + ci)),
+    )
+}
+
+#[test]
+fn csv_output() {
+    let frame_stats = FrameStats::from_scores(&[90.0, 100.0]);
+    assert_eq!(
+        csv(95.0, frame_stats, None, None),
+        "vmaf,frames,mean,harmonic_mean,min,max,p5,ms_ssim,ci95_low,ci95_high\n\
+         95,2,95,94.739586,90,100,90,,,\n"
+    );
+    assert_eq!(
+        csv(95.5, None, Some(0.99), Some(0.5)),
+        "vmaf,frames,mean,harmonic_mean,min,max,p5,ms_ssim,ci95_low,ci95_high\n\
+         95.5,,,,,,,0.99,95,96\n"
+    );
+}
+
+#[test]
+fn live_windows() {
+    let secs = Duration::from_secs;
+    assert_eq!(consecutive_windows(secs(30)), [(secs(0), secs(90))]);
+    assert_eq!(
+        consecutive_windows(secs(150)),
+        [
+            (secs(0), secs(60)),
+            (secs(60), secs(60)),
+            (secs(120), secs(90))
+        ]
+    );
+}
+
+#[test]
+fn abort_below_reachable() {
+    let scores = [80.0; 100];
+    // all 100 remaining frames at 100 can only reach a mean of 90
+    assert_eq!(
+        best_reachable(VmafStatistic::Mean, &scores, 100),
+        Some(90.0)
+    );
+    assert_eq!(
+        best_reachable(VmafStatistic::Mean, &scores, 900),
+        Some(98.0)
+    );
+    // a low min can never recover
+    assert_eq!(
+        best_reachable(VmafStatistic::Min, &scores, 1000),
+        Some(80.0)
+    );
+    assert_eq!(best_reachable(VmafStatistic::Mean, &[], 0), None);
+}
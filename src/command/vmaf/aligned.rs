@@ -0,0 +1,222 @@
+//! vmaf `--export-aligned` per-frame rows, e.g. for ML analysis.
+use crate::{ffprobe::FrameInfo, vmaf};
+use anyhow::Context;
+use std::{fmt::Write, path::Path};
+
+/// libvmaf per-frame features exported alongside the vmaf score, (column, log feature).
+const FEATURES: &[(&str, &str)] = &[
+    ("adm2", "integer_adm2"),
+    ("motion2", "integer_motion2"),
+    ("vif_scale0", "integer_vif_scale0"),
+    ("vif_scale1", "integer_vif_scale1"),
+    ("vif_scale2", "integer_vif_scale2"),
+    ("vif_scale3", "integer_vif_scale3"),
+    ("ms_ssim", vmaf::MS_SSIM_FEATURE),
+];
+
+/// Per-frame scores aligned with distorted frame info.
+#[derive(Debug, Default)]
+pub struct Aligned {
+    /// libvmaf `frameNum` of each score, which skips frames with `n_subsample`.
+    frame_nums: Vec<usize>,
+    vmaf: Vec<f32>,
+    /// Feature columns with a value for every frame.
+    features: Vec<(&'static str, Vec<f32>)>,
+    frames: Vec<FrameInfo>,
+}
+
+impl Aligned {
+    /// Read per-frame scores from the libvmaf json `log`.
+    pub fn read(log: &Path, frames: Vec<FrameInfo>) -> anyhow::Result<Self> {
+        let vmaf = vmaf::read_frame_scores(log)?;
+        let frame_nums = vmaf::read_frame_metric(log, "frameNum")?;
+        let frame_nums = match frame_nums.len() == vmaf.len() {
+            true => frame_nums.into_iter().map(|n| n as usize).collect(),
+            false => (0..vmaf.len()).collect(),
+        };
+        let mut features = vec![];
+        for (column, feature) in FEATURES {
+            let values = vmaf::read_frame_metric(log, feature)?;
+            // features not calculated, or otherwise not aligned, are omitted
+            if !values.is_empty() && values.len() == vmaf.len() {
+                features.push((*column, values));
+            }
+        }
+        Ok(Self {
+            frame_nums,
+            vmaf,
+            features,
+            frames,
+        })
+    }
+
+    /// Distorted frame info of the `idx` score row.
+    fn frame(&self, idx: usize) -> Option<&FrameInfo> {
+        self.frames.get(self.frame_nums[idx])
+    }
+
+    /// Write to `path` as csv, or arrow ipc (".arrow", ".feather") & parquet (".parquet")
+    /// with the "aligned-export" feature.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match ext.as_deref() {
+            Some("arrow" | "feather") => self.write_arrow(path),
+            Some("parquet") => self.write_parquet(path),
+            _ => std::fs::write(path, self.csv()).map_err(Into::into),
+        }
+        .with_context(|| format!("write {}", path.display()))
+    }
+
+    /// Csv header & per-frame rows.
+    fn csv(&self) -> String {
+        let mut csv = String::from("frame,pts,frame_type,frame_size,vmaf");
+        for (column, _) in &self.features {
+            csv.push(',');
+            csv.push_str(column);
+        }
+        csv.push('\n');
+        for (idx, vmaf) in self.vmaf.iter().enumerate() {
+            let frame = self.frame(idx);
+            let pts = frame.and_then(|f| f.pts);
+            let _ = write!(
+                csv,
+                "{},{},{},{},{vmaf}",
+                self.frame_nums[idx],
+                pts.map(|p| p.to_string()).unwrap_or_default(),
+                frame
+                    .and_then(|f| f.pict_type)
+                    .map(String::from)
+                    .unwrap_or_default(),
+                frame
+                    .and_then(|f| f.size)
+                    .map(|s| s.to_string())
+                    .unwrap_or_default(),
+            );
+            for (_, values) in &self.features {
+                let _ = write!(csv, ",{}", values[idx]);
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(feature = "aligned-export")]
+impl Aligned {
+    fn record_batch(&self) -> anyhow::Result<arrow_array::RecordBatch> {
+        use arrow_array::{ArrayRef, Float32Array, Float64Array, StringArray, UInt64Array};
+        use arrow_schema::{DataType, Field, Schema};
+        use std::sync::Arc;
+
+        let rows = 0..self.vmaf.len();
+        let mut fields = vec![
+            Field::new("frame", DataType::UInt64, false),
+            Field::new("pts", DataType::Float64, true),
+            Field::new("frame_type", DataType::Utf8, true),
+            Field::new("frame_size", DataType::UInt64, true),
+            Field::new("vmaf", DataType::Float32, false),
+        ];
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from_iter_values(
+                self.frame_nums.iter().map(|n| *n as u64),
+            )),
+            Arc::new(Float64Array::from_iter(
+                rows.clone().map(|i| self.frame(i).and_then(|f| f.pts)),
+            )),
+            Arc::new(StringArray::from_iter(rows.clone().map(|i| {
+                self.frame(i).and_then(|f| f.pict_type).map(String::from)
+            }))),
+            Arc::new(UInt64Array::from_iter(
+                rows.map(|i| self.frame(i).and_then(|f| f.size)),
+            )),
+            Arc::new(Float32Array::from(self.vmaf.clone())),
+        ];
+        for (column, values) in &self.features {
+            fields.push(Field::new(*column, DataType::Float32, false));
+            columns.push(Arc::new(Float32Array::from(values.clone())));
+        }
+        Ok(arrow_array::RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    fn write_arrow(&self, path: &Path) -> anyhow::Result<()> {
+        let batch = self.record_batch()?;
+        let file = std::fs::File::create(path)?;
+        let mut writer = arrow_ipc::writer::FileWriter::try_new(file, &batch.schema())?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    fn write_parquet(&self, path: &Path) -> anyhow::Result<()> {
+        let batch = self.record_batch()?;
+        let file = std::fs::File::create(path)?;
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "aligned-export"))]
+impl Aligned {
+    fn write_arrow(&self, _: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("arrow export requires ab-av1 built with the \"aligned-export\" feature")
+    }
+
+    fn write_parquet(&self, _: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("parquet export requires ab-av1 built with the \"aligned-export\" feature")
+    }
+}
+
+#[cfg(test)]
+fn test_aligned() -> Aligned {
+    // n_subsample=2 scores
+    Aligned {
+        frame_nums: vec![0, 2, 4],
+        vmaf: vec![95.5, 90.25, 91.0],
+        features: vec![("ms_ssim", vec![0.99, 0.98, 0.985])],
+        frames: vec![
+            FrameInfo {
+                pts: Some(0.0),
+                pict_type: Some('I'),
+                size: Some(5000),
+            },
+            FrameInfo {
+                pts: Some(0.04),
+                pict_type: Some('B'),
+                size: Some(800),
+            },
+            FrameInfo {
+                pts: Some(0.08),
+                pict_type: Some('P'),
+                size: Some(1200),
+            },
+        ],
+    }
+}
+
+#[test]
+fn aligned_csv() {
+    assert_eq!(
+        test_aligned().csv(),
+        "frame,pts,frame_type,frame_size,vmaf,ms_ssim\n\
+         0,0,I,5000,95.5,0.99\n\
+         2,0.08,P,1200,90.25,0.98\n\
+         4,,,,91,0.985\n"
+    );
+}
+
+#[cfg(feature = "aligned-export")]
+#[test]
+fn aligned_record_batch() {
+    let batch = test_aligned().record_batch().unwrap();
+    assert_eq!(batch.num_rows(), 3);
+    assert_eq!(batch.num_columns(), 6);
+    assert_eq!(batch.column(1).null_count(), 1);
+}
@@ -0,0 +1,224 @@
+//! Encode energy usage estimation from cpu package power counters,
+//! RAPL on Linux or powermetrics on macOS.
+//!
+//! Measures the whole package, so includes other concurrent system activity.
+use crate::cancel::{self, CancellationToken};
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    rc::Rc,
+    time::Duration,
+};
+use tokio::{process::Command, task::JoinHandle};
+use tokio_process_stream::{Item, ProcessLineStream};
+use tokio_stream::StreamExt;
+
+const JOULES_PER_KWH: f64 = 3_600_000.0;
+/// RAPL counters can wrap within an hour, so must be sampled more often.
+const RAPL_INTERVAL: Duration = Duration::from_secs(10);
+const RAPL_DIR: &str = "/sys/class/powercap";
+const POWERMETRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Running energy meter, see [`Meter::start`].
+pub struct Meter {
+    joules: Rc<Cell<f64>>,
+    task: JoinHandle<Option<()>>,
+    rapl: Option<Rc<RefCell<Rapl>>>,
+}
+
+impl Meter {
+    /// Start metering energy usage.
+    ///
+    /// Returns `None` if unsupported or not permitted, e.g. RAPL `energy_uj` is usually root only.
+    pub fn start(cancel: &CancellationToken) -> Option<Self> {
+        let joules = Rc::new(Cell::new(0.0));
+        if cfg!(target_os = "macos") {
+            return Self::powermetrics(joules, cancel);
+        }
+
+        let rapl = Rc::new(RefCell::new(Rapl::open(Path::new(RAPL_DIR))?));
+        let task = cancel::spawn_local(cancel, {
+            let (rapl, joules) = (rapl.clone(), joules.clone());
+            async move {
+                loop {
+                    tokio::time::sleep(RAPL_INTERVAL).await;
+                    joules.set(joules.get() + rapl.borrow_mut().sample());
+                }
+            }
+        });
+        Some(Self {
+            joules,
+            task,
+            rapl: Some(rapl),
+        })
+    }
+
+    /// Sample `powermetrics` (requires root) cpu power every second.
+    fn powermetrics(joules: Rc<Cell<f64>>, cancel: &CancellationToken) -> Option<Self> {
+        let child = Command::new("powermetrics")
+            .args(["--samplers", "cpu_power", "-i"])
+            .arg(POWERMETRICS_INTERVAL.as_millis().to_string())
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut lines = ProcessLineStream::from(child);
+        let task = cancel::spawn_local(cancel, {
+            let joules = joules.clone();
+            async move {
+                while let Some(item) = lines.next().await {
+                    if let Item::Stdout(line) = item {
+                        if let Some(watts) = parse_powermetrics_watts(&line) {
+                            let sampled = watts * POWERMETRICS_INTERVAL.as_secs_f64();
+                            joules.set(joules.get() + sampled);
+                        }
+                    }
+                }
+            }
+        });
+        Some(Self {
+            joules,
+            task,
+            rapl: None,
+        })
+    }
+
+    /// Stop metering, returning the energy used in kWh if any was measured.
+    pub fn stop(self) -> Option<f64> {
+        self.task.abort();
+        let mut joules = self.joules.get();
+        if let Some(rapl) = &self.rapl {
+            joules += rapl.borrow_mut().sample();
+        }
+        (joules > 0.0).then(|| joules / JOULES_PER_KWH)
+    }
+}
+
+impl Drop for Meter {
+    fn drop(&mut self) {
+        // stop sampling, killing any powermetrics child, if not stopped e.g. when cancelled
+        self.task.abort();
+    }
+}
+
+/// Top level RAPL package domains, e.g. "intel-rapl:0".
+struct Rapl {
+    domains: Vec<RaplDomain>,
+}
+
+struct RaplDomain {
+    energy_uj: PathBuf,
+    max_uj: u64,
+    last_uj: u64,
+}
+
+impl Rapl {
+    fn open(dir: &Path) -> Option<Self> {
+        let domains: Vec<_> = fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                // sub-domains, e.g. "intel-rapl:0:0", are included in their package
+                name.starts_with("intel-rapl:") && name.matches(':').count() == 1
+            })
+            .filter_map(|entry| {
+                let path = entry.path();
+                let max_uj = read_uj(&path.join("max_energy_range_uj"))?;
+                let energy_uj = path.join("energy_uj");
+                let last_uj = read_uj(&energy_uj)?;
+                Some(RaplDomain {
+                    energy_uj,
+                    max_uj,
+                    last_uj,
+                })
+            })
+            .collect();
+        (!domains.is_empty()).then_some(Self { domains })
+    }
+
+    /// Joules used by all domains since the last sample.
+    fn sample(&mut self) -> f64 {
+        let mut uj = 0;
+        for domain in &mut self.domains {
+            if let Some(now) = read_uj(&domain.energy_uj) {
+                uj += counter_delta(domain.last_uj, now, domain.max_uj);
+                domain.last_uj = now;
+            }
+        }
+        uj as f64 / 1_000_000.0
+    }
+}
+
+fn read_uj(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Counter increase from `start` to `end`, which may have wrapped after `max` to 0.
+fn counter_delta(start: u64, end: u64, max: u64) -> u64 {
+    match end >= start {
+        true => end - start,
+        false => max.saturating_sub(start) + end + 1,
+    }
+}
+
+/// Parse powermetrics total power lines, e.g. Apple silicon
+/// "Combined Power (CPU + GPU + ANE): 1234 mW" or Intel
+/// "Intel energy model derived package power (CPUs+GT+SA): 5.67W".
+fn parse_powermetrics_watts(line: &str) -> Option<f64> {
+    if let Some(mw) = line.strip_prefix("Combined Power") {
+        let mw = mw.split_once(':')?.1.trim().strip_suffix("mW")?;
+        return Some(mw.trim().parse::<f64>().ok()? / 1000.0);
+    }
+    if line.contains("package power") {
+        let w = line.split_once(':')?.1.trim().strip_suffix('W')?;
+        return w.trim().parse().ok();
+    }
+    None
+}
+
+#[test]
+fn rapl_counter_delta() {
+    assert_eq!(counter_delta(100, 250, 1000), 150);
+    assert_eq!(counter_delta(900, 50, 1000), 151);
+    assert_eq!(counter_delta(1000, 0, 1000), 1);
+}
+
+#[test]
+fn rapl_domains() {
+    let dir = std::env::temp_dir().join(format!("ab-av1-rapl-test-{}", std::process::id()));
+    for (domain, uj) in [("intel-rapl:0", 5_000_000), ("intel-rapl:0:0", 1_000_000)] {
+        fs::create_dir_all(dir.join(domain)).unwrap();
+        fs::write(dir.join(domain).join("energy_uj"), format!("{uj}\n")).unwrap();
+        fs::write(
+            dir.join(domain).join("max_energy_range_uj"),
+            "262143328850\n",
+        )
+        .unwrap();
+    }
+    let mut rapl = Rapl::open(&dir).unwrap();
+    assert_eq!(rapl.domains.len(), 1);
+
+    fs::write(dir.join("intel-rapl:0/energy_uj"), "7500000").unwrap();
+    assert_eq!(rapl.sample(), 2.5);
+    assert_eq!(rapl.sample(), 0.0);
+    _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn powermetrics_watts() {
+    assert_eq!(
+        parse_powermetrics_watts("Combined Power (CPU + GPU + ANE): 1234 mW"),
+        Some(1.234)
+    );
+    assert_eq!(
+        parse_powermetrics_watts("Intel energy model derived package power (CPUs+GT+SA): 5.67W"),
+        Some(5.67)
+    );
+    assert_eq!(parse_powermetrics_watts("CPU Power: 800 mW"), None);
+}
@@ -0,0 +1,262 @@
+//! C api for embedding crf searches, e.g. in Python or Node wrappers, without
+//! managing ab-av1 subprocesses. See include/ab_av1.h.
+//!
+//! Build a shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! Searches run on a background thread, one at a time per process.
+use crate::{
+    cancel::{self, CancellationToken},
+    command::crf_search,
+    ffprobe,
+    machine::ExitCode,
+    temporary,
+};
+use clap::Parser;
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use serde_json::json;
+use std::{
+    ffi::{c_char, c_double, CStr, CString},
+    iter,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Set while a search thread is running.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// A crf search started by [`ab_av1_start_search`].
+pub struct Search {
+    bar: ProgressBar,
+    /// Result or error json, once done.
+    result: Arc<Mutex<Option<String>>>,
+    cancel: CancellationToken,
+}
+
+impl Search {
+    fn failed(message: impl Into<String>, code: u8) -> Self {
+        Self {
+            bar: ProgressBar::hidden(),
+            result: Arc::new(Mutex::new(Some(error_json(message.into(), code)))),
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Start a crf search in the background with crf-search cli `args`,
+/// e.g. "-i vid.mkv --preset 8 --min-vmaf 95".
+///
+/// Returns null if `args` is null or not utf8. Other failures, e.g. invalid args
+/// or another search already running, are reported by [`ab_av1_get_result_json`].
+///
+/// The returned search must be freed with [`ab_av1_free_search`].
+///
+/// # Safety
+/// `args` must be null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ab_av1_start_search(args: *const c_char) -> *mut Search {
+    if args.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(args) = CStr::from_ptr(args).to_str() else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(start(args)))
+}
+
+fn start(args: &str) -> Search {
+    let Some(args) = shlex::split(args) else {
+        return Search::failed("invalid args quoting", 2);
+    };
+    let args = iter::once("crf-search".to_owned()).chain(args);
+    let args = match crf_search::CommandArgs::try_parse_from(args) {
+        Ok(args) => args.search,
+        Err(err) => return Search::failed(err.to_string().trim(), 2),
+    };
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Search::failed("another search is running", ExitCode::Error as _);
+    }
+
+    let bar = ProgressBar::with_draw_target(Some(12), ProgressDrawTarget::hidden());
+    let result = Arc::new(Mutex::new(None));
+    let cancel = CancellationToken::new();
+    let spawned = thread::Builder::new().name("ab-av1-search".into()).spawn({
+        let (bar, result, cancel) = (bar.clone(), result.clone(), cancel.clone());
+        move || {
+            let json = catch_panic(|| run(args, bar, cancel));
+            RUNNING.store(false, Ordering::SeqCst);
+            *result.lock().unwrap() = Some(json);
+        }
+    });
+    if let Err(err) = spawned {
+        RUNNING.store(false, Ordering::SeqCst);
+        return Search::failed(format!("search thread: {err}"), ExitCode::Error as _);
+    }
+    Search {
+        bar,
+        result,
+        cancel,
+    }
+}
+
+/// Run the search to completion or cancellation, returning the result json.
+fn run(mut args: crf_search::Args, bar: ProgressBar, cancel: CancellationToken) -> String {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(rt) => rt,
+        Err(err) => return error_json(format!("tokio runtime: {err}"), ExitCode::Error as _),
+    };
+    let keep = args.sample.keep;
+    let result = tokio::task::LocalSet::new().block_on(&runtime, async {
+        let out = cancel::until(&cancel, search(&mut args, bar, &cancel)).await;
+        temporary::clean(keep).await;
+        out
+    });
+    match result {
+        Ok(result) => json!({ "result": result }).to_string(),
+        Err(err) => {
+            let message = format!("{err:#}");
+            error_json(message, ExitCode::of(&Err(err)) as _)
+        }
+    }
+}
+
+async fn search(
+    args: &mut crf_search::Args,
+    bar: ProgressBar,
+    cancel: &CancellationToken,
+) -> anyhow::Result<serde_json::Value> {
+    let probe = Arc::new(ffprobe::probe(&args.args.input));
+    args.args.resolve_encoder()?;
+    args.args.apply_sar(&probe);
+    args.args.apply_detelecine(&probe);
+    args.sample
+        .set_extension_from_input(&args.args.input, &args.args.encoder, &probe);
+    let best = crf_search::run(args, probe.clone(), bar, cancel).await?;
+    let mut json = best.enc.to_json();
+    json["crf"] = best.crf().into();
+    if let Some(rendition) = crf_search::rendition(&args.args, &best, &probe) {
+        json["rendition"] = rendition.to_json();
+    }
+    Ok(json)
+}
+
+/// Call `f` returning its json, or a failed search error json if it panics
+/// so a panicking search still finishes & frees the running slot.
+fn catch_panic(f: impl FnOnce() -> String) -> String {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+            (Some(msg), _) => msg,
+            (_, Some(msg)) => msg.as_str(),
+            _ => "unknown panic",
+        };
+        error_json(format!("search panicked: {message}"), ExitCode::Error as _)
+    })
+}
+
+/// Error json, `exit_code` matching the cli's, see [`ExitCode`].
+fn error_json(message: String, exit_code: u8) -> String {
+    json!({ "error": message, "exit_code": exit_code }).to_string()
+}
+
+/// Returns the search progress fraction 0-1, 1 once done.
+///
+/// # Safety
+/// `search` must be a live pointer returned by [`ab_av1_start_search`].
+#[no_mangle]
+pub unsafe extern "C" fn ab_av1_poll_progress(search: *const Search) -> c_double {
+    let search = &*search;
+    if search.result.lock().unwrap().is_some() {
+        return 1.0;
+    }
+    match search.bar.length().unwrap_or(0) {
+        0 => 0.0,
+        len => (search.bar.position() as f64 / len as f64).min(1.0),
+    }
+}
+
+/// Returns the result json once done, otherwise null.
+///
+/// * success: `{"result": {"crf", "vmaf", "predicted_encode_size", ...}}`
+///   as crf-search `--json`.
+/// * failure: `{"error", "exit_code"}` with the cli exit code,
+///   e.g. 3 no suitable crf found, 130 cancelled.
+///
+/// A returned string must be freed with [`ab_av1_free_string`].
+///
+/// # Safety
+/// `search` must be a live pointer returned by [`ab_av1_start_search`].
+#[no_mangle]
+pub unsafe extern "C" fn ab_av1_get_result_json(search: *const Search) -> *mut c_char {
+    let search = &*search;
+    match &*search.result.lock().unwrap() {
+        Some(json) => CString::new(json.as_str()).map_or(ptr::null_mut(), CString::into_raw),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Cancel a running search, killing any ffmpeg processes. The search then
+/// finishes shortly after with an `exit_code` 130 error result.
+///
+/// # Safety
+/// `search` must be a live pointer returned by [`ab_av1_start_search`].
+#[no_mangle]
+pub unsafe extern "C" fn ab_av1_cancel(search: *const Search) {
+    (*search).cancel.cancel();
+}
+
+/// Free a search, cancelling it if still running.
+///
+/// # Safety
+/// `search` must be null or a pointer returned by [`ab_av1_start_search`],
+/// not used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn ab_av1_free_search(search: *mut Search) {
+    if !search.is_null() {
+        drop(Box::from_raw(search));
+    }
+}
+
+/// Free a string returned by [`ab_av1_get_result_json`].
+///
+/// # Safety
+/// `s` must be null or a pointer returned by [`ab_av1_get_result_json`],
+/// not used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn ab_av1_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[test]
+fn start_invalid_args() {
+    let search = start("--min-vmaf 95");
+    let result = search.result.lock().unwrap().clone().unwrap();
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["exit_code"], 2);
+    assert!(result["error"].as_str().unwrap().contains("--input"));
+}
+
+#[test]
+fn search_panic_error_json() {
+    let json = catch_panic(|| panic!("boom {}", 1));
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["exit_code"], ExitCode::Error as u8);
+    assert_eq!(json["error"], "search panicked: boom 1");
+
+    assert_eq!(catch_panic(|| "{}".into()), "{}");
+}
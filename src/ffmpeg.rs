@@ -1,33 +1,46 @@
 //! ffmpeg encoding logic
+pub mod capabilities;
+
 use crate::{
-    command::args::PixelFormat,
+    command::args::{AlsoEncode, PixelFormat},
     float::TerseF32,
-    process::{CommandExt, FfmpegOut},
+    naming,
+    process::{exit_ok_stderr, Chunks, CommandExt, FfmpegOut, ProcessRunner, SystemRunner},
     temporary::{self, TempKind},
 };
-use anyhow::Context;
+use anyhow::{ensure, Context};
 use std::{
+    borrow::Cow,
     collections::HashSet,
+    ffi::OsString,
     hash::{Hash, Hasher},
     path::{Path, PathBuf},
     process::Stdio,
     sync::{Arc, OnceLock},
 };
 use tokio::process::Command;
-use tokio_stream::Stream;
+use tokio_process_stream::Item;
+use tokio_stream::{Stream, StreamExt};
 
 /// Exposed ffmpeg encoding args.
 #[derive(Debug, Clone)]
 pub struct FfmpegEncodeArgs<'a> {
     pub input: &'a Path,
     pub vcodec: Arc<str>,
-    pub vfilter: Option<&'a str>,
+    pub vfilter: Option<Cow<'a, str>>,
+    /// Metric reference vfilter, `vfilter` without any --overlay unless --overlay-reference.
+    pub ref_vfilter: Option<Cow<'a, str>>,
     pub pix_fmt: PixelFormat,
     pub crf: f32,
     pub preset: Option<Arc<str>>,
     pub output_args: Vec<Arc<String>>,
     pub input_args: Vec<Arc<String>>,
+    /// Hardware frame upload vfilter applied after `vfilter`, e.g. "format=nv12,hwupload"
+    /// for vaapi. `-pix_fmt` is not set as the format is converted before uploading.
+    pub hw_upload: Option<&'static str>,
     pub video_only: bool,
+    /// User `--ffmpeg-template` replacing the built-in command.
+    pub template: Option<Arc<str>>,
 }
 
 impl FfmpegEncodeArgs<'_> {
@@ -37,11 +50,7 @@ impl FfmpegEncodeArgs<'_> {
         // hashing svt-av1 version means new encoder releases will avoid old cache data
         if &*self.vcodec == "libsvtav1" {
             let svtav1_verion = SVT_AV1_V.get_or_init(|| {
-                use std::process::Command;
-                match Command::new("SvtAv1EncApp").arg("--version").output() {
-                    Ok(out) => out.stdout,
-                    _ => <_>::default(),
-                }
+                capabilities::tool_output("SvtAv1EncApp", &["--version"]).unwrap_or_default()
             });
             svtav1_verion.hash(state);
         }
@@ -49,89 +58,250 @@ impl FfmpegEncodeArgs<'_> {
         // input not relevant to sample encoding
         self.vcodec.hash(state);
         self.vfilter.hash(state);
+        if self.ref_vfilter != self.vfilter {
+            self.ref_vfilter.hash(state);
+        }
         self.pix_fmt.hash(state);
         self.crf.to_bits().hash(state);
         self.preset.hash(state);
         self.output_args.hash(state);
         self.input_args.hash(state);
+        if let Some(template) = &self.template {
+            template.hash(state);
+        }
     }
 }
 
+/// Encoded sample file name, e.g. "vid.sample1+48f.av1.crf32.1a2b3c4d.mkv".
+///
+/// `encode_id` identifies the full encode settings, so files only match encodes
+/// with the same settings.
+pub fn encoded_sample_name(
+    sample: &Path,
+    vcodec: &str,
+    crf: f32,
+    preset: Option<&str>,
+    encode_id: &str,
+    dest_ext: &str,
+) -> OsString {
+    let pre = naming::pre_extension_name(vcodec);
+    let crf_str = format!("{}", TerseF32(crf)).replace('.', "_");
+    let suffix = match preset {
+        Some(p) => format!("{pre}.crf{crf_str}.{p}.{encode_id}.{dest_ext}"),
+        None => format!("{pre}.crf{crf_str}.{encode_id}.{dest_ext}"),
+    };
+    naming::temp_file_name(sample, &suffix)
+}
+
 /// Encode a sample.
 pub fn encode_sample(
     FfmpegEncodeArgs {
         input,
         vcodec,
         vfilter,
+        ref_vfilter: _,
         pix_fmt,
         crf,
         preset,
         output_args,
         input_args,
+        hw_upload,
         video_only: _,
+        template,
     }: FfmpegEncodeArgs,
     temp_dir: Option<PathBuf>,
+    encode_id: &str,
     dest_ext: &str,
+    runner: &impl ProcessRunner,
 ) -> anyhow::Result<(PathBuf, impl Stream<Item = anyhow::Result<FfmpegOut>>)> {
-    let pre = pre_extension_name(&vcodec);
-    let crf_str = format!("{}", TerseF32(crf)).replace('.', "_");
-    let dest_file_name = match &preset {
-        Some(p) => input.with_extension(format!("{pre}.crf{crf_str}.{p}.{dest_ext}")),
-        None => input.with_extension(format!("{pre}.crf{crf_str}.{dest_ext}")),
-    };
-    let dest_file_name = dest_file_name.file_name().unwrap();
     let mut dest = temporary::process_dir(temp_dir);
-    dest.push(dest_file_name);
+    dest.push(encoded_sample_name(
+        input,
+        &vcodec,
+        crf,
+        preset.as_deref(),
+        encode_id,
+        dest_ext,
+    ));
 
     temporary::add(&dest, TempKind::Keepable);
 
-    let enc = Command::new("ffmpeg")
-        .kill_on_drop(true)
-        .arg("-y")
+    let mut cmd = match template {
+        Some(template) => template_command(&template, input, &dest, crf, vfilter.as_deref())?,
+        None => {
+            let mut cmd = Command::new("ffmpeg");
+            cmd.arg("-y")
+                .args(input_args.iter().map(|a| &**a))
+                .arg2("-i", input)
+                .arg2("-c:v", &*vcodec)
+                .args(output_args.iter().map(|a| &**a))
+                .arg2_if(!vcodec.crf_in_output_args(), vcodec.crf_arg(), crf)
+                .arg2_if(hw_upload.is_none(), "-pix_fmt", pix_fmt.as_str())
+                .arg2_opt(vcodec.preset_arg(), preset)
+                .arg2_opt("-vf", upload_vfilter(vfilter.as_deref(), hw_upload))
+                .arg("-an")
+                .arg(&dest);
+            cmd
+        }
+    };
+
+    let enc = runner
+        .spawn(
+            cmd.kill_on_drop(true)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped()),
+        )
+        .context("ffmpeg encode_sample")?;
+
+    let stream = FfmpegOut::stream(enc, "ffmpeg encode_sample");
+    Ok((dest, stream))
+}
+
+/// Encode a sample & calculate its VMAF score in a single ffmpeg process using a
+/// loopback decoder (requires ffmpeg >= 7.1), avoiding writing the encoded sample to disk.
+///
+/// `vmaf_lavfi` should use `[0:v]` as the distorted & `[1:v]` as the reference input,
+/// as for separate vmaf runs.
+///
+/// The encoded sample is muxed as matroska to stdout & counted, not stored.
+pub fn encode_sample_vmaf(
+    FfmpegEncodeArgs {
+        input,
+        vcodec,
+        vfilter,
+        ref_vfilter: _,
+        pix_fmt,
+        crf,
+        preset,
+        output_args,
+        input_args,
+        hw_upload,
+        video_only: _,
+        template: _,
+    }: FfmpegEncodeArgs,
+    vmaf_lavfi: &str,
+    runner: &impl ProcessRunner,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<LoopbackOut>>> {
+    let lavfi = format!(
+        "{}[vmaf]",
+        vmaf_lavfi
+            .replace("[0:v]", "[dec:0]")
+            .replace("[1:v]", "[0:v]")
+    );
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
         .args(input_args.iter().map(|a| &**a))
         .arg2("-i", input)
+        .arg2("-map", "0:v:0")
         .arg2("-c:v", &*vcodec)
         .args(output_args.iter().map(|a| &**a))
-        .arg2(vcodec.crf_arg(), crf)
-        .arg2("-pix_fmt", pix_fmt.as_str())
+        .arg2_if(!vcodec.crf_in_output_args(), vcodec.crf_arg(), crf)
+        .arg2_if(hw_upload.is_none(), "-pix_fmt", pix_fmt.as_str())
         .arg2_opt(vcodec.preset_arg(), preset)
-        .arg2_opt("-vf", vfilter)
-        .arg("-an")
-        .arg(&dest)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("ffmpeg encode_sample")?;
+        .arg2_opt("-vf", upload_vfilter(vfilter.as_deref(), hw_upload))
+        .arg2("-f", "matroska")
+        .arg("pipe:1")
+        .arg2("-dec", "0:0")
+        .arg2("-filter_complex", lavfi)
+        .arg2("-map", "[vmaf]")
+        .arg2("-f", "null")
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let enc = runner
+        .spawn(&mut cmd)
+        .context("ffmpeg encode_sample_vmaf")?;
 
-    let stream = FfmpegOut::stream(enc, "ffmpeg encode_sample");
-    Ok((dest, stream))
+    let mut chunks = Chunks::default();
+    Ok(enc.filter_map(move |item| match item {
+        Item::Stdout(chunk) => Some(Ok(LoopbackOut::Encoded(chunk.len() as u64))),
+        Item::Stderr(chunk) => {
+            chunks.push(&chunk);
+            let line = chunks.last_line();
+            if let Some(score) = crate::vmaf::parse_score(line) {
+                return Some(Ok(LoopbackOut::Vmaf(score)));
+            }
+            FfmpegOut::try_parse(line).map(|p| Ok(LoopbackOut::Progress(p)))
+        }
+        Item::Done(code) => match exit_ok_stderr("ffmpeg encode_sample_vmaf", code, &chunks) {
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        },
+    }))
+}
+
+#[derive(Debug)]
+pub enum LoopbackOut {
+    Progress(FfmpegOut),
+    /// Encoded bytes output.
+    Encoded(u64),
+    Vmaf(f32),
 }
 
 /// Encode to output.
+///
+/// With `also_encode` a second output is encoded in the same ffmpeg process.
+#[allow(clippy::too_many_arguments)]
 pub fn encode(
+    args: FfmpegEncodeArgs,
+    output: &Path,
+    has_audio: bool,
+    audio_codec: Option<&str>,
+    downmix_to_stereo: bool,
+    opus_bitrate: u32,
+    mux_flags: &[(Arc<str>, Arc<str>)],
+    also_encode: Option<(&AlsoEncode, &Path)>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<FfmpegOut>>> {
+    let mut cmd = encode_command(
+        args,
+        output,
+        has_audio,
+        audio_codec,
+        downmix_to_stereo,
+        opus_bitrate,
+        mux_flags,
+        also_encode,
+    )?;
+    let enc = SystemRunner
+        .spawn(
+            cmd.kill_on_drop(true)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped()),
+        )
+        .context("ffmpeg encode")?;
+
+    Ok(FfmpegOut::stream(enc, "ffmpeg encode"))
+}
+
+/// Command to encode to output, see [`encode`].
+#[allow(clippy::too_many_arguments)]
+pub fn encode_command(
     FfmpegEncodeArgs {
         input,
         vcodec,
         vfilter,
+        ref_vfilter: _,
         pix_fmt,
         crf,
         preset,
         output_args,
         input_args,
+        hw_upload,
         video_only,
+        template,
     }: FfmpegEncodeArgs,
     output: &Path,
     has_audio: bool,
     audio_codec: Option<&str>,
     downmix_to_stereo: bool,
-) -> anyhow::Result<impl Stream<Item = anyhow::Result<FfmpegOut>>> {
+    opus_bitrate: u32,
+    mux_flags: &[(Arc<str>, Arc<str>)],
+    also_encode: Option<(&AlsoEncode, &Path)>,
+) -> anyhow::Result<Command> {
     let oargs: HashSet<_> = output_args.iter().map(|a| a.as_str()).collect();
-    let output_ext = output.extension().and_then(|e| e.to_str());
-
-    let add_faststart = output_ext == Some("mp4") && !oargs.contains("-movflags");
-    let add_cues_to_front =
-        matches!(output_ext, Some("mkv") | Some("webm")) && !oargs.contains("-cues_to_front");
+    let mux_args = mux_args(output, mux_flags, &oargs)?;
 
     let audio_codec = audio_codec.unwrap_or(if downmix_to_stereo && has_audio {
         "libopus"
@@ -139,62 +309,410 @@ pub fn encode(
         "copy"
     });
 
-    let set_ba_128k = audio_codec == "libopus" && !oargs.contains("-b:a");
+    let set_opus_ba = audio_codec == "libopus" && !oargs.contains("-b:a");
     let downmix_to_stereo = downmix_to_stereo && !oargs.contains("-ac");
     let map = match video_only {
         true => "0:v:0",
         false => "0",
     };
 
-    let enc = Command::new("ffmpeg")
+    Ok(match template {
+        Some(template) => {
+            ensure!(
+                also_encode.is_none(),
+                "--also-encode cannot be used with --ffmpeg-template"
+            );
+            template_command(&template, input, output, crf, vfilter.as_deref())?
+        }
+        None => {
+            let mut cmd = Command::new("ffmpeg");
+            cmd.args(input_args.iter().map(|a| &**a))
+                .arg("-y")
+                .arg2("-i", input)
+                .arg2("-map", map)
+                .arg2("-c:v", "copy")
+                .arg2("-c:v:0", &*vcodec)
+                .args(output_args.iter().map(|a| &**a))
+                .arg2_if(!vcodec.crf_in_output_args(), vcodec.crf_arg(), crf)
+                .arg2_if(hw_upload.is_none(), "-pix_fmt", pix_fmt.as_str())
+                .arg2_opt(vcodec.preset_arg(), preset)
+                .arg2_opt("-vf", upload_vfilter(vfilter.as_deref(), hw_upload))
+                .arg2("-c:s", "copy")
+                .arg2("-c:a", audio_codec)
+                .arg2_if(downmix_to_stereo, "-ac", 2)
+                .arg2_if(set_opus_ba, "-b:a", opus_bitrate)
+                .args(mux_args)
+                .arg(output);
+            if let Some((also, also_output)) = also_encode {
+                cmd.arg2("-map", map)
+                    .arg2("-c:v", "copy")
+                    .arg2("-c:v:0", &*also.vcodec)
+                    .args(
+                        also.args
+                            .iter()
+                            .flat_map(|(k, v)| [format!("-{k}"), v.to_string()]),
+                    )
+                    .arg2_opt("-vf", vfilter.as_deref())
+                    .arg2("-c:s", "copy")
+                    .arg2("-c:a", audio_codec)
+                    .arg2_if(downmix_to_stereo, "-ac", 2)
+                    .arg2_if(set_opus_ba, "-b:a", opus_bitrate)
+                    .arg(also_output);
+            }
+            cmd
+        }
+    })
+}
+
+/// Returns `vfilter` followed by the `hw_upload` vfilter, if any.
+fn upload_vfilter(vfilter: Option<&str>, hw_upload: Option<&str>) -> Option<String> {
+    match (vfilter, hw_upload) {
+        (Some(vf), Some(upload)) => Some(format!("{vf},{upload}")),
+        (vf, upload) => vf.or(upload).map(String::from),
+    }
+}
+
+/// Returns the libopus `-b:a` bitrate for audio with `channels` output channels.
+///
+/// Without `per_channel` uses 48k per channel, with a minimum of 128k.
+pub fn opus_bitrate(channels: Option<i64>, per_channel: Option<u32>) -> u32 {
+    const DEFAULT_PER_CHANNEL: u32 = 48_000;
+    const DEFAULT_MIN: u32 = 128_000;
+
+    let channels = channels
+        .and_then(|c| u32::try_from(c).ok())
+        .unwrap_or(2)
+        .max(1);
+    match per_channel {
+        Some(per_channel) => per_channel.saturating_mul(channels),
+        None => DEFAULT_PER_CHANNEL
+            .saturating_mul(channels)
+            .max(DEFAULT_MIN),
+    }
+}
+
+/// Returns whether ffmpeg has the named filter, e.g. "libvmaf".
+///
+/// Returns `None` if the ffmpeg filters cannot be listed.
+pub fn has_filter(name: &str) -> Option<bool> {
+    static FILTERS: OnceLock<Option<String>> = OnceLock::new();
+    let filters = FILTERS.get_or_init(|| {
+        let out = capabilities::tool_output("ffmpeg", &["-hide_banner", "-filters"])?;
+        Some(String::from_utf8_lossy(&out).into())
+    });
+    let filters = filters.as_deref()?;
+    Some(
+        filters
+            .lines()
+            .any(|l| l.split_whitespace().nth(1) == Some(name)),
+    )
+}
+
+/// Supported muxer flags: (name, output extensions, default value).
+pub const MUX_FLAGS: &[(&str, &[&str], Option<&str>)] = &[
+    ("movflags", &["mp4", "mov", "m4v"], Some("+faststart")),
+    ("frag_duration", &["mp4", "mov", "m4v"], None),
+    ("cues_to_front", &["mkv", "webm"], Some("y")),
+    ("reserve_index_space", &["mkv", "webm"], None),
+    ("live", &["mkv", "webm"], None),
+    ("dash", &["webm"], None),
+    ("dash_track_number", &["webm"], None),
+];
+
+/// Returns muxer args for the output container: user `mux_flags` + container defaults.
+///
+/// Errors if a flag is not supported by the output container.
+pub fn mux_args(
+    output: &Path,
+    mux_flags: &[(Arc<str>, Arc<str>)],
+    output_args: &HashSet<&str>,
+) -> anyhow::Result<Vec<String>> {
+    let ext = output.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mut args = vec![];
+
+    for (key, val) in mux_flags {
+        let supported = MUX_FLAGS
+            .iter()
+            .any(|(k, exts, _)| **key == **k && exts.contains(&ext));
+        ensure!(
+            supported,
+            "--mux-flag {key} is not supported for .{ext} output"
+        );
+        args.extend([format!("-{key}"), val.to_string()]);
+    }
+
+    for (key, exts, default) in MUX_FLAGS {
+        let flag = format!("-{key}");
+        if let Some(default) = default {
+            if exts.contains(&ext) && !args.contains(&flag) && !output_args.contains(flag.as_str())
+            {
+                args.extend([flag, default.to_string()]);
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Detect scene changes in the input video using the ffmpeg `scene` score,
+/// returning the scene start times, excluding zero.
+///
+/// `threshold` is the min scene change score 0-1, e.g. 0.3.
+pub async fn detect_scenes(input: &Path, threshold: f32) -> anyhow::Result<Vec<f64>> {
+    let out = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg2("-i", input)
+        .arg2("-map", "0:v:0")
+        .arg2("-vf", format!("select='gt(scene,{threshold})',showinfo"))
+        .arg2("-f", "null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg scene detection")?;
+    crate::process::ensure_success("ffmpeg scene detection", &out)?;
+    Ok(String::from_utf8_lossy(&out.stderr)
+        .lines()
+        .filter_map(parse_showinfo_pts_time)
+        .filter(|t| *t > 0.0)
+        .collect())
+}
+
+/// Parse the `pts_time` of a showinfo filter frame line.
+fn parse_showinfo_pts_time(line: &str) -> Option<f64> {
+    if !line.contains("Parsed_showinfo") {
+        return None;
+    }
+    let idx = line.find("pts_time:")?;
+    line[idx + "pts_time:".len()..]
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Fast low-res sped up proxy encode of the `input` video stream, see [`proxy_vfilter`].
+pub fn proxy(
+    input: &Path,
+    output: &Path,
+    height: u32,
+    speed: u32,
+    timecode: bool,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<FfmpegOut>>> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-y")
+        .arg2("-i", input)
+        .arg2("-map", "0:v:0")
+        .arg2("-vf", proxy_vfilter(height, speed, timecode))
+        .arg2("-c:v", "libsvtav1")
+        .arg2("-preset", "12")
+        .arg2("-crf", "40")
+        .arg2("-pix_fmt", "yuv420p")
+        .arg("-an")
+        .arg(output)
         .kill_on_drop(true)
-        .args(input_args.iter().map(|a| &**a))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let enc = SystemRunner.spawn(&mut cmd).context("ffmpeg proxy")?;
+    Ok(FfmpegOut::stream(enc, "ffmpeg proxy"))
+}
+
+/// Proxy video filter keeping every `speed`th frame at the original frame rate,
+/// scaled to `height`, optionally burning in the original timecode.
+fn proxy_vfilter(height: u32, speed: u32, timecode: bool) -> String {
+    let mut vf = String::new();
+    if timecode {
+        // before speeding up, so the original timestamp is shown
+        vf.push_str(
+            "drawtext=text='%{pts\\:hms}':x=8:y=8:fontsize=h/20:fontcolor=white\
+             :box=1:boxcolor=black@0.6:boxborderw=4,",
+        );
+    }
+    vf.push_str(&format!("scale=-2:{height}"));
+    if speed > 1 {
+        vf.push_str(&format!(",framestep={speed},setpts=PTS/{speed}"));
+    }
+    vf
+}
+
+/// Losslessly concatenate video `chunks` into `output`, copying audio & subtitle
+/// streams, chapters & metadata from the original `input`.
+pub async fn concat(
+    chunks: &[PathBuf],
+    input: &Path,
+    output: &Path,
+    mux_flags: &[(Arc<str>, Arc<str>)],
+    temp_dir: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut list_file = temporary::process_dir(temp_dir);
+    list_file.push(naming::temp_file_name(input, "concat.txt"));
+    temporary::add(&list_file, TempKind::NotKeepable);
+    let list: String = chunks
+        .iter()
+        .map(|c| concat_list_entry(c))
+        .collect::<anyhow::Result<_>>()?;
+    tokio::fs::write(&list_file, list)
+        .await
+        .with_context(|| format!("write {}", list_file.display()))?;
+
+    let out = Command::new("ffmpeg")
         .arg("-y")
+        .arg2("-f", "concat")
+        .arg2("-safe", "0")
+        .arg2("-i", &list_file)
         .arg2("-i", input)
-        .arg2("-map", map)
-        .arg2("-c:v", "copy")
-        .arg2("-c:v:0", &*vcodec)
-        .args(output_args.iter().map(|a| &**a))
-        .arg2(vcodec.crf_arg(), crf)
-        .arg2("-pix_fmt", pix_fmt.as_str())
-        .arg2_opt(vcodec.preset_arg(), preset)
-        .arg2_opt("-vf", vfilter)
-        .arg2("-c:s", "copy")
-        .arg2("-c:a", audio_codec)
-        .arg2_if(downmix_to_stereo, "-ac", 2)
-        .arg2_if(set_ba_128k, "-b:a", "128k")
-        .arg2_if(add_faststart, "-movflags", "+faststart")
-        .arg2_if(add_cues_to_front, "-cues_to_front", "y")
+        .arg2("-map", "0:v")
+        .arg2("-map", "1:a?")
+        .arg2("-map", "1:s?")
+        .arg2("-map_chapters", "1")
+        .arg2("-map_metadata", "1")
+        .arg2("-c", "copy")
+        .args(mux_args(output, mux_flags, &<_>::default())?)
         .arg(output)
         .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("ffmpeg encode")?;
+        .output()
+        .await
+        .context("ffmpeg concat")?;
+    crate::process::ensure_success("ffmpeg concat", &out)
+}
 
-    Ok(FfmpegOut::stream(enc, "ffmpeg encode"))
+/// Concat demuxer `file` line, quoting the absolute chunk path.
+fn concat_list_entry(chunk: &Path) -> anyhow::Result<String> {
+    let path = std::path::absolute(chunk)?;
+    let path = path.to_string_lossy().replace('\'', r"'\''");
+    Ok(format!("file '{path}'\n"))
+}
+
+#[test]
+fn showinfo_pts_time() {
+    let line = "[Parsed_showinfo_1 @ 0x5581] n:   2 pts: 258048 pts_time:10.752  duration:   1024 \
+                duration_time:0.0426667 fmt:yuv420p10le";
+    assert_eq!(parse_showinfo_pts_time(line), Some(10.752));
+    assert_eq!(
+        parse_showinfo_pts_time("frame=  100 fps=0.0 q=-0.0 size=N/A time=00:00:04.17"),
+        None
+    );
 }
 
-pub fn pre_extension_name(vcodec: &str) -> &str {
-    match vcodec.strip_prefix("lib").filter(|s| !s.is_empty()) {
-        Some("svtav1") => "av1",
-        Some("vpx-vp9") => "vp9",
-        Some(suffix) => suffix,
-        _ => vcodec,
+#[test]
+fn concat_entry_quoting() {
+    assert_eq!(
+        concat_list_entry(Path::new("/tmp/it's.scene001.av1.mkv")).unwrap(),
+        "file '/tmp/it'\\''s.scene001.av1.mkv'\n"
+    );
+}
+
+#[test]
+fn mux_args_defaults() {
+    let none = HashSet::new();
+    let args = mux_args(Path::new("vid.mp4"), &[], &none).unwrap();
+    assert_eq!(args, ["-movflags", "+faststart"]);
+
+    let args = mux_args(Path::new("vid.mkv"), &[], &none).unwrap();
+    assert_eq!(args, ["-cues_to_front", "y"]);
+
+    let flags = [("cues_to_front".into(), "n".into())];
+    let args = mux_args(Path::new("vid.webm"), &flags, &none).unwrap();
+    assert_eq!(args, ["-cues_to_front", "n"]);
+
+    let flags = [("movflags".into(), "+faststart".into())];
+    assert!(mux_args(Path::new("vid.mkv"), &flags, &none).is_err());
+}
+
+/// Build a command from a user `--ffmpeg-template` substituting placeholders.
+fn template_command(
+    template: &str,
+    input: &Path,
+    output: &Path,
+    crf: f32,
+    vfilter: Option<&str>,
+) -> anyhow::Result<Command> {
+    let args = shlex::split(template).context("invalid --ffmpeg-template")?;
+    let (program, args) = args.split_first().context("empty --ffmpeg-template")?;
+
+    let mut cmd = Command::new(program);
+    for arg in args {
+        cmd.arg(fill_template(arg, input, output, crf, vfilter));
     }
+    Ok(cmd)
 }
 
-trait VCodecSpecific {
+/// Replace template placeholders in a single pass, so substituted values,
+/// e.g. paths containing "{output}", are not themselves replaced.
+fn fill_template(
+    arg: &str,
+    input: &Path,
+    output: &Path,
+    crf: f32,
+    vfilter: Option<&str>,
+) -> String {
+    let mut filled = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder = rest.find('}').and_then(|end| {
+            let value: Cow<str> = match &rest[1..end] {
+                "input" => input.to_string_lossy(),
+                "output" => output.to_string_lossy(),
+                "quality" => TerseF32(crf).to_string().into(),
+                "vfilter" => vfilter.unwrap_or("null").into(),
+                _ => return None,
+            };
+            Some((value, end + 1))
+        });
+        match placeholder {
+            Some((value, len)) => {
+                filled.push_str(&value);
+                rest = &rest[len..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[test]
+fn fill_template_placeholders() {
+    let arg = fill_template(
+        "{input}|{output}|{quality}|{vfilter}",
+        Path::new("in.mkv"),
+        Path::new("out.mkv"),
+        32.5,
+        None,
+    );
+    assert_eq!(arg, "in.mkv|out.mkv|32.5|null");
+
+    // values aren't substituted again & unknown braces are kept
+    let arg = fill_template(
+        "{input} {output} {x}{",
+        Path::new("{output}.mkv"),
+        Path::new("out.mkv"),
+        30.0,
+        None,
+    );
+    assert_eq!(arg, "{output}.mkv out.mkv {x}{");
+}
+
+pub trait VCodecSpecific {
     /// Arg to use preset values with, normally `-preset`.
     fn preset_arg(&self) -> &str;
     /// Arg to use crf values with, normally `-crf`.
     fn crf_arg(&self) -> &str;
+    /// Returns `true` if the crf arg is instead set in the output args, as it needs mapping
+    /// or depends on rate control, e.g. videotoolbox `-q:v`.
+    fn crf_in_output_args(&self) -> bool;
 }
 impl VCodecSpecific for Arc<str> {
     fn preset_arg(&self) -> &str {
         match &**self {
             "libaom-av1" | "libvpx-vp9" => "-cpu-used",
             "librav1e" => "-speed",
+            vc if vc.ends_with("_amf") => "-quality",
             _ => "-preset",
         }
     }
@@ -207,8 +725,52 @@ impl VCodecSpecific for Arc<str> {
             "-cq"
         } else if self.ends_with("_qsv") {
             "-global_quality"
+        } else if self.ends_with("_amf") {
+            "-qp_i"
+        } else if self.ends_with("_videotoolbox") {
+            "-q:v"
         } else {
             "-crf"
         }
     }
+
+    fn crf_in_output_args(&self) -> bool {
+        self.ends_with("_videotoolbox")
+    }
+}
+
+#[test]
+fn hw_upload_vfilter() {
+    let upload = Some("format=nv12,hwupload");
+    assert_eq!(
+        upload_vfilter(Some("scale=1280:-2"), upload).as_deref(),
+        Some("scale=1280:-2,format=nv12,hwupload")
+    );
+    assert_eq!(upload_vfilter(None, upload).as_deref(), upload);
+    assert_eq!(
+        upload_vfilter(Some("fps=24"), None).as_deref(),
+        Some("fps=24")
+    );
+    assert_eq!(upload_vfilter(None, None), None);
+}
+
+#[test]
+fn proxy_filter() {
+    assert_eq!(
+        proxy_vfilter(480, 4, false),
+        "scale=-2:480,framestep=4,setpts=PTS/4"
+    );
+    assert_eq!(proxy_vfilter(360, 1, false), "scale=-2:360");
+    assert!(proxy_vfilter(480, 4, true)
+        .starts_with("drawtext=text='%{pts\\:hms}':x=8:y=8:fontsize=h/20:fontcolor=white:box=1"));
+}
+
+#[test]
+fn opus_bitrate_channel_scaling() {
+    assert_eq!(opus_bitrate(Some(2), None), 128_000);
+    assert_eq!(opus_bitrate(None, None), 128_000);
+    assert_eq!(opus_bitrate(Some(6), None), 288_000);
+    assert_eq!(opus_bitrate(Some(8), None), 384_000);
+    assert_eq!(opus_bitrate(Some(2), Some(48_000)), 96_000);
+    assert_eq!(opus_bitrate(Some(8), Some(64_000)), 512_000);
 }
@@ -0,0 +1,94 @@
+//! Persistent cache of tool capability probes, e.g. `ffmpeg -filters`, avoiding
+//! the startup latency of running them every time, notably for network-mounted binaries.
+use crate::cache_db;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+/// Returns the stdout of a successful `tool` run with `args`, cached while the
+/// tool binary is unchanged.
+///
+/// Failed runs are not cached. Cache errors are ignored, just running the tool.
+pub fn tool_output(tool: &str, args: &[&str]) -> Option<Vec<u8>> {
+    let run = || {
+        Command::new(tool)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| out.stdout)
+    };
+    let Some(key) = find_in_path(tool).and_then(|bin| key(&bin, args)) else {
+        return run();
+    };
+    let db = cache_db::open("capability-cache");
+    let cached = db
+        .as_ref()
+        .ok()
+        .and_then(|db| db.get(key.as_bytes()).ok().flatten());
+    if let Some(stdout) = cached {
+        return Some(stdout.to_vec());
+    }
+
+    let stdout = run()?;
+    if let Ok(db) = db {
+        _ = db
+            .insert(key.as_bytes(), stdout.as_slice())
+            .and_then(|_| db.flush());
+    }
+    Some(stdout)
+}
+
+/// Resolve the `tool` binary using the PATH env var.
+fn find_in_path(tool: &str) -> Option<PathBuf> {
+    let name = format!("{tool}{}", std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|bin| bin.is_file())
+}
+
+/// Key using the binary path, size & modified time rather than hashing the
+/// entire binary, which would be slow for network-mounted binaries.
+fn key(bin: &Path, args: &[&str]) -> Option<String> {
+    let bin = std::fs::canonicalize(bin).ok()?;
+    let meta = std::fs::metadata(&bin).ok()?;
+    let modified = meta
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?;
+    Some(key_from(&bin, meta.len(), modified, args))
+}
+
+fn key_from(bin: &Path, size: u64, modified: Duration, args: &[&str]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher
+        .update(bin.as_os_str().as_encoded_bytes())
+        .update(&size.to_le_bytes())
+        .update(&modified.as_nanos().to_le_bytes());
+    for arg in args {
+        hasher.update(arg.as_bytes()).update(&[0]);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+#[test]
+fn capability_key() {
+    let (secs, bin) = (Duration::from_secs, Path::new("/usr/bin/ffmpeg"));
+    let key = key_from(bin, 1000, secs(5), &["-filters"]);
+    assert_eq!(key, key_from(bin, 1000, secs(5), &["-filters"]));
+    // any change to the binary or args invalidates
+    assert_ne!(key, key_from(bin, 1001, secs(5), &["-filters"]));
+    assert_ne!(key, key_from(bin, 1000, secs(6), &["-filters"]));
+    assert_ne!(
+        key,
+        key_from(Path::new("/opt/ffmpeg"), 1000, secs(5), &["-filters"])
+    );
+    assert_ne!(key, key_from(bin, 1000, secs(5), &["-encoders"]));
+    assert_ne!(
+        key_from(bin, 1000, secs(5), &["-a", "b"]),
+        key_from(bin, 1000, secs(5), &["-ab"])
+    );
+}
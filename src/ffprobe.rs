@@ -1,5 +1,11 @@
 //! ffprobe logic
-use crate::command::args::PixelFormat;
+pub mod cache;
+
+use crate::{
+    command::args::PixelFormat,
+    process::{ProcessRunner, SystemRunner},
+    timings::{self, Phase},
+};
 use anyhow::{anyhow, Context};
 use std::{fmt, fs::File, io::Read, path::Path, time::Duration};
 
@@ -13,8 +19,28 @@ pub struct Ffprobe {
     /// Video frame rate.
     pub fps: Result<f64, ProbeError>,
     pub resolution: Option<(u32, u32)>,
+    /// Video sample aspect ratio, if non-square, e.g. 64:45 for anamorphic PAL DVDs.
+    pub sar: Option<(u32, u32)>,
     pub is_image: bool,
     pub pix_fmt: Option<String>,
+    /// Dynamic HDR metadata present in the video stream.
+    pub dynamic_hdr: DynamicHdr,
+}
+
+/// Dynamic HDR metadata types.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DynamicHdr {
+    pub dolby_vision: bool,
+    pub hdr10_plus: bool,
+    /// The video stream is HEVC, which dovi_tool & hdr10plus_tool can extract metadata from.
+    pub hevc: bool,
+}
+
+impl DynamicHdr {
+    /// Returns `true` if any dynamic HDR metadata is present.
+    pub fn any(&self) -> bool {
+        self.dolby_vision || self.hdr10_plus
+    }
 }
 
 impl Ffprobe {
@@ -23,6 +49,18 @@ impl Ffprobe {
         PixelFormat::try_from(pf).ok()
     }
 
+    /// Resolution with square pixels, i.e. the width scaled by the sample aspect ratio.
+    pub fn display_resolution(&self) -> Option<(u32, u32)> {
+        let (w, h) = self.resolution?;
+        Some(match self.sar {
+            Some((num, den)) => {
+                let w = (f64::from(w) * f64::from(num) / f64::from(den) / 2.0).round() as u32 * 2;
+                (w, h)
+            }
+            None => (w, h),
+        })
+    }
+
     pub fn nframes(&self) -> Result<u64, ProbeError> {
         match (&self.fps, &self.duration) {
             (Ok(fps), Ok(duration)) => {
@@ -40,19 +78,41 @@ impl Ffprobe {
 
 /// Try to ffprobe the given input.
 pub fn probe(input: &Path) -> Ffprobe {
+    probe_with(&SystemRunner, input)
+}
+
+/// [`probe`] running ffprobe with `runner`.
+pub fn probe_with(runner: &impl ProcessRunner, input: &Path) -> Ffprobe {
+    let _timer = timings::start(Phase::Probe);
     let is_image = is_image(input).unwrap_or(false);
 
-    let probe = match ffprobe::ffprobe(input) {
+    let probe = ffprobe_stdout(
+        runner,
+        "ffprobe",
+        &[
+            "-v",
+            "quiet",
+            "-show_format",
+            "-show_streams",
+            "-print_format",
+            "json",
+        ],
+        input,
+    )
+    .and_then(|out| Ok(serde_json::from_slice::<ffprobe::FfProbe>(&out)?));
+    let probe = match probe {
         Ok(p) => p,
         Err(err) => {
             return Ffprobe {
-                duration: Err(ProbeError(format!("ffprobe: {err}"))),
-                fps: Err(ProbeError(format!("ffprobe: {err}"))),
+                duration: Err(ProbeError(format!("ffprobe: {err:#}"))),
+                fps: Err(ProbeError(format!("ffprobe: {err:#}"))),
                 has_audio: true,
                 max_audio_channels: None,
                 resolution: None,
+                sar: None,
                 is_image: false,
                 pix_fmt: None,
+                dynamic_hdr: DynamicHdr::default(),
             }
         }
     };
@@ -80,23 +140,397 @@ pub fn probe(input: &Path) -> Ffprobe {
             Some((w, h))
         });
 
+    let sar = probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type.as_deref() == Some("video"))
+        .find_map(|s| parse_sar(s.sample_aspect_ratio.as_deref()?));
+
+    let dolby_vision = probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type.as_deref() == Some("video"))
+        .flat_map(|s| &s.side_data_list)
+        .any(|sd| sd.side_data_type.contains("DOVI"));
+
+    let hevc = probe
+        .streams
+        .iter()
+        .find(|s| s.codec_type.as_deref() == Some("video"))
+        .is_some_and(|s| s.codec_name.as_deref() == Some("hevc"));
+
     let pix_fmt = probe
         .streams
         .into_iter()
         .filter(|s| s.codec_type.as_deref() == Some("video"))
         .find_map(|s| s.pix_fmt);
 
+    // HDR10+ requires high bit depth, avoid probing frames otherwise
+    let hdr10_plus = !is_image
+        && pix_fmt.as_deref().is_some_and(|p| p.contains("10"))
+        && probe_hdr10_plus(runner, input);
+
     Ffprobe {
         duration: duration.map_err(ProbeError::from),
         fps: fps.map_err(ProbeError::from),
         has_audio,
         max_audio_channels,
         resolution,
+        sar,
         is_image,
         pix_fmt,
+        dynamic_hdr: DynamicHdr {
+            dolby_vision,
+            hdr10_plus,
+            hevc,
+        },
     }
 }
 
+/// Parse a non-square sample aspect ratio, e.g. "64:45".
+fn parse_sar(sar: &str) -> Option<(u32, u32)> {
+    let (num, den) = sar.split_once(':')?;
+    let (num, den) = (num.parse().ok()?, den.parse().ok()?);
+    (num > 0 && den > 0 && num != den).then_some((num, den))
+}
+
+/// Probe the first video frame for HDR10+ dynamic metadata.
+fn probe_hdr10_plus(runner: &impl ProcessRunner, input: &Path) -> bool {
+    let out = runner.output(
+        std::process::Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-select_streams",
+                "v:0",
+                "-read_intervals",
+                "%+#1",
+            ])
+            .args(["-show_entries", "frame=side_data_list", "-of", "json"])
+            .arg(input),
+    );
+    match out {
+        Ok(out) => String::from_utf8_lossy(&out.stdout).contains("SMPTE2094-40"),
+        Err(_) => false,
+    }
+}
+
+/// Probe the start of the video for open-GOP, returning the GOP length in frames if so.
+///
+/// Open-GOP is detected by leading pictures, i.e. packets following a non-initial keyframe
+/// in decode order with an earlier presentation timestamp.
+///
+/// If `cache` is set previous results for the same input are reused.
+pub fn probe_open_gop(input: &Path, cache: bool) -> Option<u32> {
+    match cache {
+        true => cache::get_or_insert_with(input, "open-gop", || {
+            probe_open_gop_packets(&SystemRunner, input)
+        }),
+        false => probe_open_gop_packets(&SystemRunner, input),
+    }
+}
+
+fn probe_open_gop_packets(runner: &impl ProcessRunner, input: &Path) -> Option<u32> {
+    let _timer = timings::start(Phase::Probe);
+    let out = runner
+        .output(
+            std::process::Command::new("ffprobe")
+                .args(["-v", "error", "-select_streams", "v:0"])
+                .args(["-read_intervals", "%+#1000"])
+                .args(["-show_entries", "packet=pts,flags", "-of", "csv=p=0"])
+                .arg(input),
+        )
+        .ok()?;
+    parse_open_gop(&String::from_utf8_lossy(&out.stdout))
+}
+
+fn parse_open_gop(packets_csv: &str) -> Option<u32> {
+    let packets: Vec<(i64, bool)> = packets_csv
+        .lines()
+        .filter_map(|l| {
+            let (pts, flags) = l.trim().split_once(',')?;
+            Some((pts.parse().ok()?, flags.starts_with('K')))
+        })
+        .collect();
+    let keyframes: Vec<usize> = (0..packets.len()).filter(|i| packets[*i].1).collect();
+
+    let gop = u32::try_from(keyframes.get(1)? - keyframes[0]).ok()?;
+    let open = keyframes.iter().skip(1).any(|&k| {
+        packets[k + 1..]
+            .iter()
+            .take_while(|(_, key)| !key)
+            .any(|(pts, _)| *pts < packets[k].0)
+    });
+    open.then_some(gop)
+}
+
+/// A chapter time range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Probe chapter metadata returning the `selected` 1-based chapters.
+pub fn probe_chapters(input: &Path, selected: &[u32]) -> anyhow::Result<Vec<(u32, Chapter)>> {
+    let chapters = probe_all_chapters(input)?;
+    anyhow::ensure!(!chapters.is_empty(), "input has no chapters");
+
+    selected
+        .iter()
+        .map(|&n| {
+            let chapter = n
+                .checked_sub(1)
+                .and_then(|idx| chapters.get(idx as usize))
+                .with_context(|| {
+                    format!(
+                        "chapter {n} not found, input has {} chapters",
+                        chapters.len()
+                    )
+                })?;
+            Ok((n, *chapter))
+        })
+        .collect()
+}
+
+/// Probe all chapter metadata, in order.
+pub fn probe_all_chapters(input: &Path) -> anyhow::Result<Vec<Chapter>> {
+    let out = ffprobe_stdout(
+        &SystemRunner,
+        "ffprobe chapters",
+        &["-v", "error", "-show_chapters", "-of", "json"],
+        input,
+    )?;
+    parse_chapters(&out)
+}
+
+/// Probe the picture type, e.g. 'I', 'P' or 'B', of each video frame.
+pub fn probe_frame_types(video: &Path) -> anyhow::Result<Vec<char>> {
+    let out = ffprobe_stdout(
+        &SystemRunner,
+        "ffprobe frame types",
+        &[
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "frame=pict_type",
+            "-of",
+            "csv=p=0",
+        ],
+        video,
+    )?;
+    Ok(parse_frame_types(&out))
+}
+
+/// Per-frame video info.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInfo {
+    /// Presentation time in seconds.
+    pub pts: Option<f64>,
+    /// Picture type, e.g. 'I', 'P' or 'B'.
+    pub pict_type: Option<char>,
+    /// Compressed frame size in bytes.
+    pub size: Option<u64>,
+}
+
+/// Probe the size in bytes of the first video stream, excluding audio & other streams.
+///
+/// Uses mkv stream statistics tags, or the stream bitrate & duration.
+/// `None` if neither are available. If `cache` is set previous results are reused.
+pub fn probe_video_size(runner: &impl ProcessRunner, input: &Path, cache: bool) -> Option<u64> {
+    match cache {
+        true => {
+            cache::get_or_insert_with(input, "video-size", || probe_video_size_with(runner, input))
+        }
+        false => probe_video_size_with(runner, input),
+    }
+}
+
+fn probe_video_size_with(runner: &impl ProcessRunner, input: &Path) -> Option<u64> {
+    let _timer = timings::start(Phase::Probe);
+    let out = ffprobe_stdout(
+        runner,
+        "ffprobe video size",
+        &[
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=bit_rate:stream_tags:format=duration",
+            "-of",
+            "json",
+        ],
+        input,
+    )
+    .ok()?;
+    parse_video_size(&out)
+}
+
+fn parse_video_size(json: &[u8]) -> Option<u64> {
+    #[derive(serde::Deserialize)]
+    struct Probe {
+        #[serde(default)]
+        streams: Vec<Stream>,
+        format: Option<Format>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Stream {
+        bit_rate: Option<String>,
+        #[serde(default)]
+        tags: std::collections::HashMap<String, String>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Format {
+        duration: Option<String>,
+    }
+
+    let probe: Probe = serde_json::from_slice(json).ok()?;
+    let stream = probe.streams.first()?;
+    // mkv statistics tags, e.g. "NUMBER_OF_BYTES-eng"
+    let tagged = stream
+        .tags
+        .iter()
+        .find(|(k, _)| k.starts_with("NUMBER_OF_BYTES"))
+        .and_then(|(_, v)| v.parse().ok());
+    tagged.or_else(|| {
+        let bit_rate: f64 = stream.bit_rate.as_ref()?.parse().ok()?;
+        let duration: f64 = probe.format?.duration?.parse().ok()?;
+        Some((bit_rate * duration / 8.0).round() as u64)
+    })
+}
+
+/// Probe the presentation time, picture type & compressed size of each video frame.
+pub fn probe_frames(video: &Path) -> anyhow::Result<Vec<FrameInfo>> {
+    probe_frames_with(&SystemRunner, video)
+}
+
+fn probe_frames_with(runner: &impl ProcessRunner, video: &Path) -> anyhow::Result<Vec<FrameInfo>> {
+    let out = ffprobe_stdout(
+        runner,
+        "ffprobe frames",
+        &[
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "frame=pts_time,pkt_size,pict_type",
+            "-of",
+            "json",
+        ],
+        video,
+    )?;
+    parse_frames(&out)
+}
+
+/// Probe the decode timestamp of each video packet, `None` if unset.
+pub fn probe_packet_dts(video: &Path) -> anyhow::Result<Vec<Option<f64>>> {
+    let out = ffprobe_stdout(
+        &SystemRunner,
+        "ffprobe packets",
+        &[
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "packet=dts_time",
+            "-of",
+            "csv=p=0",
+        ],
+        video,
+    )?;
+    Ok(String::from_utf8_lossy(&out)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.trim().trim_end_matches(',').parse().ok())
+        .collect())
+}
+
+/// Run ffprobe with `args` then the `input`, returning stdout if successful.
+fn ffprobe_stdout(
+    runner: &impl ProcessRunner,
+    name: &str,
+    args: &[&str],
+    input: &Path,
+) -> anyhow::Result<Vec<u8>> {
+    let out = runner
+        .output(std::process::Command::new("ffprobe").args(args).arg(input))
+        .with_context(|| name.to_owned())?;
+    anyhow::ensure!(
+        out.status.success(),
+        "{name}: {}",
+        String::from_utf8_lossy(&out.stderr).trim()
+    );
+    Ok(out.stdout)
+}
+
+fn parse_frames(json: &[u8]) -> anyhow::Result<Vec<FrameInfo>> {
+    #[derive(serde::Deserialize)]
+    struct Frames {
+        #[serde(default)]
+        frames: Vec<RawFrame>,
+    }
+    #[derive(serde::Deserialize)]
+    struct RawFrame {
+        pts_time: Option<String>,
+        pkt_size: Option<String>,
+        pict_type: Option<String>,
+    }
+
+    Ok(serde_json::from_slice::<Frames>(json)
+        .context("invalid ffprobe frames json")?
+        .frames
+        .into_iter()
+        .map(|f| FrameInfo {
+            pts: f.pts_time.and_then(|t| t.parse().ok()),
+            pict_type: f.pict_type.and_then(|t| t.chars().next()),
+            size: f.pkt_size.and_then(|s| s.parse().ok()),
+        })
+        .collect())
+}
+
+fn parse_frame_types(csv: &[u8]) -> Vec<char> {
+    String::from_utf8_lossy(csv)
+        .lines()
+        .filter_map(|l| l.trim().trim_end_matches(',').chars().next())
+        .collect()
+}
+
+fn parse_chapters(json: &[u8]) -> anyhow::Result<Vec<Chapter>> {
+    #[derive(serde::Deserialize)]
+    struct Chapters {
+        #[serde(default)]
+        chapters: Vec<RawChapter>,
+    }
+    #[derive(serde::Deserialize)]
+    struct RawChapter {
+        start_time: String,
+        end_time: String,
+    }
+
+    let secs = |s: &str| {
+        s.parse::<f64>()
+            .ok()
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            .with_context(|| format!("invalid ffprobe chapter time: {s:?}"))
+    };
+    serde_json::from_slice::<Chapters>(json)
+        .context("invalid ffprobe chapters json")?
+        .chapters
+        .iter()
+        .map(|c| {
+            Ok(Chapter {
+                start: secs(&c.start_time)?,
+                end: secs(&c.end_time)?,
+            })
+        })
+        .collect()
+}
+
 fn is_image(path: &Path) -> anyhow::Result<bool> {
     let file = File::open(path)?;
     let mut file_header = Vec::with_capacity(8192);
@@ -162,3 +596,155 @@ impl From<anyhow::Error> for ProbeError {
 }
 
 impl std::error::Error for ProbeError {}
+
+#[test]
+fn parse_chapters_json() {
+    let json = br#"{
+        "chapters": [
+            {"id": 0, "time_base": "1/1000", "start": 0, "start_time": "0.000000", "end": 90500, "end_time": "90.500000", "tags": {"title": "Intro"}},
+            {"id": 1, "time_base": "1/1000", "start": 90500, "start_time": "90.500000", "end": 300000, "end_time": "300.000000"}
+        ]
+    }"#;
+    assert_eq!(
+        parse_chapters(json).unwrap(),
+        vec![
+            Chapter {
+                start: Duration::ZERO,
+                end: Duration::from_millis(90_500),
+            },
+            Chapter {
+                start: Duration::from_millis(90_500),
+                end: Duration::from_secs(300),
+            },
+        ]
+    );
+    assert_eq!(parse_chapters(b"{}").unwrap(), vec![]);
+}
+
+#[test]
+fn parse_open_gop_leading_pictures() {
+    // closed: I P B B | I P B B (B frames reference only within the gop)
+    let closed = "0,K__\n3,___\n1,___\n2,___\n4,K__\n7,___\n5,___\n6,___\n";
+    assert_eq!(parse_open_gop(closed), None);
+
+    // open: leading B frames after the 2nd keyframe display before it
+    let open = "0,K__\n3,___\n1,___\n2,___\n6,K__\n4,___\n5,___\n9,___\n";
+    assert_eq!(parse_open_gop(open), Some(4));
+
+    assert_eq!(parse_open_gop(""), None);
+}
+
+#[test]
+fn sar_display_resolution() {
+    assert_eq!(parse_sar("64:45"), Some((64, 45)));
+    assert_eq!(parse_sar("1:1"), None);
+    assert_eq!(parse_sar("0:1"), None);
+    assert_eq!(parse_sar("N/A"), None);
+
+    let probe = Ffprobe {
+        duration: Ok(Duration::from_secs(60)),
+        has_audio: false,
+        max_audio_channels: None,
+        fps: Ok(25.0),
+        resolution: Some((720, 576)),
+        sar: Some((64, 45)),
+        is_image: false,
+        pix_fmt: None,
+        dynamic_hdr: <_>::default(),
+    };
+    assert_eq!(probe.display_resolution(), Some((1024, 576)));
+}
+
+#[test]
+fn frames_json() {
+    let json = br#"{
+        "frames": [
+            { "pts_time": "0.000000", "pkt_size": "51234", "pict_type": "I" },
+            { "pts_time": "0.041708", "pkt_size": "1320", "pict_type": "B" },
+            { "pict_type": "P" }
+        ]
+    }"#;
+    assert_eq!(
+        parse_frames(json).unwrap(),
+        [
+            FrameInfo {
+                pts: Some(0.0),
+                pict_type: Some('I'),
+                size: Some(51234)
+            },
+            FrameInfo {
+                pts: Some(0.041708),
+                pict_type: Some('B'),
+                size: Some(1320)
+            },
+            FrameInfo {
+                pts: None,
+                pict_type: Some('P'),
+                size: None
+            },
+        ]
+    );
+}
+
+#[test]
+fn frame_types_csv() {
+    assert_eq!(
+        parse_frame_types(b"I\nB\r\nB,\n\nP\n"),
+        ['I', 'B', 'B', 'P']
+    );
+}
+
+#[test]
+fn probe_with_canned_output() {
+    use crate::process::CannedRunner;
+
+    let runner = CannedRunner::default()
+        .with(
+            "ffprobe",
+            "0,K__\n3,___\n1,___\n2,___\n4,K__\n7,___\n5,___\n6,___\n",
+            "",
+        )
+        .with(
+            "ffprobe",
+            "0,K__\n3,___\n1,___\n2,___\n6,K__\n4,___\n5,___\n",
+            "",
+        )
+        .with(
+            "ffprobe",
+            r#"{"frames": [{"pts_time": "0.041708", "pkt_size": "5000", "pict_type": "I"}]}"#,
+            "",
+        );
+    let input = Path::new("vid.mkv");
+    assert_eq!(probe_open_gop_packets(&runner, input), None);
+    assert_eq!(probe_open_gop_packets(&runner, input), Some(4));
+    let frames = probe_frames_with(&runner, input).unwrap();
+    assert_eq!(frames[0].pict_type, Some('I'));
+
+    {
+        let runs = runner.runs.borrow();
+        assert_eq!(runs.len(), 3);
+        assert!(runs[2].contains(&"frame=pts_time,pkt_size,pict_type".to_owned()));
+        assert_eq!(runs[2].last().unwrap(), "vid.mkv");
+    }
+    // no more canned output
+    assert!(probe_frames_with(&runner, input).is_err());
+}
+
+#[test]
+fn video_size_json() {
+    let mkv = br#"{
+        "programs": [],
+        "streams": [{ "tags": { "BPS-eng": "5000000", "NUMBER_OF_BYTES-eng": "37500000" } }],
+        "format": { "duration": "60.000000" }
+    }"#;
+    assert_eq!(parse_video_size(mkv), Some(37_500_000));
+
+    let mp4 = br#"{
+        "streams": [{ "bit_rate": "4000000", "tags": { "language": "und" } }],
+        "format": { "duration": "60.000000" }
+    }"#;
+    assert_eq!(parse_video_size(mp4), Some(30_000_000));
+
+    let unknown = br#"{ "streams": [{}], "format": { "duration": "60.000000" } }"#;
+    assert_eq!(parse_video_size(unknown), None);
+}
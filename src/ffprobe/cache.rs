@@ -0,0 +1,89 @@
+//! Persistent cache of input analysis results, e.g. open-GOP detection, so
+//! repeated searches & batch re-runs over the same input reuse previous analysis.
+use crate::cache_db;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// Return the cached `analysis` result for `input` or run `analyse` & cache the result.
+///
+/// Cache errors are ignored, just running `analyse`.
+pub fn get_or_insert_with<T: Serialize + DeserializeOwned>(
+    input: &Path,
+    analysis: &str,
+    analyse: impl FnOnce() -> T,
+) -> T {
+    let Some(key) = key(input, analysis) else {
+        return analyse();
+    };
+    let db = cache_db::open("input-analysis-cache");
+    let cached = db
+        .as_ref()
+        .ok()
+        .and_then(|db| db.get(key.as_bytes()).ok().flatten())
+        .and_then(|data| serde_json::from_slice(&data).ok());
+    if let Some(result) = cached {
+        return result;
+    }
+
+    let result = analyse();
+    if let Ok(db) = db {
+        if let Ok(data) = serde_json::to_vec(&result) {
+            _ = db.insert(key.as_bytes(), data).and_then(|_| db.flush());
+        }
+    }
+    result
+}
+
+/// Key using the canonical input path, size & modified time rather than hashing the
+/// entire file, which would be too slow.
+fn key(input: &Path, analysis: &str) -> Option<String> {
+    let input = std::fs::canonicalize(input).ok()?;
+    let meta = std::fs::metadata(&input).ok()?;
+    let modified = meta
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?;
+    Some(key_from(&input, meta.len(), modified, analysis))
+}
+
+fn key_from(input: &Path, size: u64, modified: Duration, analysis: &str) -> String {
+    let hash = blake3::Hasher::new()
+        .update(input.as_os_str().as_encoded_bytes())
+        .update(&size.to_le_bytes())
+        .update(&modified.as_nanos().to_le_bytes())
+        .finalize();
+    format!("{analysis}:{}", hash.to_hex())
+}
+
+#[test]
+fn analysis_key() {
+    let (secs, path) = (Duration::from_secs, Path::new);
+    let key = key_from(path("/videos/vid.mkv"), 1000, secs(5), "open-gop");
+    assert!(key.starts_with("open-gop:"), "{key}");
+    assert_eq!(
+        key,
+        key_from(path("/videos/vid.mkv"), 1000, secs(5), "open-gop")
+    );
+    // any change to the input invalidates
+    assert_ne!(
+        key,
+        key_from(path("/videos/vid.mkv"), 1001, secs(5), "open-gop")
+    );
+    assert_ne!(
+        key,
+        key_from(path("/videos/vid.mkv"), 1000, secs(6), "open-gop")
+    );
+    assert_ne!(
+        key,
+        key_from(path("/videos/vid.mp4"), 1000, secs(5), "open-gop")
+    );
+    // same named files in other dirs are different inputs
+    assert_ne!(
+        key,
+        key_from(path("/other/vid.mkv"), 1000, secs(5), "open-gop")
+    );
+}
@@ -0,0 +1,377 @@
+//! Dynamic HDR (Dolby Vision & HDR10+) metadata passthrough.
+//!
+//! For HEVC input & output metadata is extracted from the input with dovi_tool &
+//! hdr10plus_tool and re-injected into the encoded output, as both tools work on HEVC
+//! bitstreams. Dolby Vision profile 7 is converted to single layer profile 8.1.
+//!
+//! With libsvtav1 & libaom-av1 Dolby Vision RPUs are instead coded by the encoder,
+//! requiring ffmpeg >= 7.1. HDR10+ is not preserved.
+use crate::{
+    command::args::{self, vfilter},
+    ffmpeg,
+    ffprobe::{DynamicHdr, Ffprobe},
+    naming,
+    process::{ensure_success, CommandExt},
+    temporary::{self, TempKind},
+};
+use anyhow::Context;
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+};
+use tokio::process::Command;
+
+/// Encoder output args coding Dolby Vision RPUs from the input frames.
+const ENCODER_DOVI_ARGS: &[&str] = &["-dolbyvision", "1"];
+
+/// Dynamic HDR metadata extracted from the input, see [`extract`].
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// Dolby Vision RPU binary.
+    rpu: Option<PathBuf>,
+    /// HDR10+ json metadata.
+    hdr10_plus: Option<PathBuf>,
+    /// Dolby Vision is coded by the encoder, see [`Passthrough::Encoder`].
+    encoder_dovi: bool,
+}
+
+impl Metadata {
+    /// Metadata coded by the encoder, see [`Passthrough::Encoder`].
+    pub fn encoder() -> Self {
+        Self {
+            encoder_dovi: true,
+            ..<_>::default()
+        }
+    }
+
+    /// Encoder output args to pass metadata through.
+    pub fn encoder_args(&self) -> impl Iterator<Item = Arc<String>> {
+        let args = if self.encoder_dovi {
+            ENCODER_DOVI_ARGS
+        } else {
+            &[]
+        };
+        args.iter().map(|a| Arc::new(a.to_string()))
+    }
+
+    /// Returns `true` if extracted metadata must be [`inject`]ed into the output.
+    pub fn needs_inject(&self) -> bool {
+        self.rpu.is_some() || self.hdr10_plus.is_some()
+    }
+}
+
+/// How dynamic HDR metadata is passed through to the encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Passthrough {
+    /// Extract with dovi_tool & hdr10plus_tool, see [`extract`] & [`inject`].
+    Tools,
+    /// Dolby Vision RPUs are coded by the av1 encoder, see [`Metadata::encoder`].
+    Encoder,
+}
+
+/// Returns how dynamic HDR metadata can be passed through to the encode,
+/// or why it can't.
+pub fn passthrough(args: &args::Encode, probe: &Ffprobe) -> Result<Passthrough, &'static str> {
+    passthrough_with(args, probe, || dv_profile(&args.input))
+}
+
+fn passthrough_with(
+    args: &args::Encode,
+    probe: &Ffprobe,
+    dv_profile: impl FnOnce() -> Option<u8>,
+) -> Result<Passthrough, &'static str> {
+    let fps = probe.fps.as_ref().ok().copied();
+    if fps.is_none() {
+        return Err("requires a known input frame rate");
+    }
+    // metadata is per frame, so frames must map 1:1
+    if let Some(vf) = &args.vfilter {
+        if vfilter::output_fps(vf, fps).is_some() || vfilter::time_scale(vf) != 1.0 {
+            return Err("not supported with frame rate changing vfilters");
+        }
+    }
+
+    let vcodec = args.encoder.as_str();
+    if hevc_encoder(vcodec) {
+        return match probe.dynamic_hdr.hevc {
+            true => Ok(Passthrough::Tools),
+            false => Err("requires hevc input with an hevc encoder"),
+        };
+    }
+    if !matches!(vcodec, "libsvtav1" | "libaom-av1") {
+        return Err("requires an hevc, libsvtav1 or libaom-av1 encoder");
+    }
+    if !probe.dynamic_hdr.dolby_vision {
+        return Err("of HDR10+ requires an hevc encoder");
+    }
+    if dv_profile() == Some(7) {
+        // dual layer, the enhancement layer can't be coded by av1 encoders
+        return Err("of Dolby Vision profile 7 requires an hevc encoder");
+    }
+    Ok(Passthrough::Encoder)
+}
+
+/// Returns `true` if `vcodec` encodes HEVC, e.g. "libx265" or "hevc_nvenc".
+fn hevc_encoder(vcodec: &str) -> bool {
+    vcodec.contains("265") || vcodec.contains("hevc")
+}
+
+/// Probe the input Dolby Vision profile, e.g. 7 for dual layer UHD Blu-ray.
+fn dv_profile(input: &Path) -> Option<u8> {
+    let out = std::process::Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args([
+            "-show_entries",
+            "stream_side_data=dv_profile",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(input)
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .find_map(|l| l.trim().trim_matches(',').parse().ok())
+}
+
+/// Extract the `dynamic_hdr` metadata of the `input` video stream to temp files.
+pub async fn extract(
+    input: &Path,
+    dynamic_hdr: DynamicHdr,
+    temp_dir: Option<PathBuf>,
+) -> anyhow::Result<Metadata> {
+    let dir = temporary::process_dir(temp_dir);
+    let annexb = dir.join(naming::temp_file_name(input, "hdr.hevc"));
+    to_annexb(input, &annexb).await?;
+
+    let mut metadata = Metadata::default();
+    if dynamic_hdr.dolby_vision {
+        let rpu = dir.join(naming::temp_file_name(input, "rpu.bin"));
+        temporary::add(&rpu, TempKind::NotKeepable);
+        run(
+            "dovi_tool extract-rpu",
+            // mode 2: convert to profile 8.1, e.g. dual layer profile 7 would otherwise
+            // be injected into the single layer output producing an invalid stream
+            Command::new("dovi_tool")
+                .arg2("-m", "2")
+                .arg("extract-rpu")
+                .arg2("-i", &annexb)
+                .arg2("-o", &rpu),
+        )
+        .await?;
+        metadata.rpu = Some(rpu);
+    }
+    if dynamic_hdr.hdr10_plus {
+        let json = dir.join(naming::temp_file_name(input, "hdr10plus.json"));
+        temporary::add(&json, TempKind::NotKeepable);
+        run(
+            "hdr10plus_tool extract",
+            Command::new("hdr10plus_tool")
+                .arg("extract")
+                .arg2("-i", &annexb)
+                .arg2("-o", &json),
+        )
+        .await?;
+        metadata.hdr10_plus = Some(json);
+    }
+    // the bitstream copy is as large as the input, so don't wait for temp cleanup
+    _ = tokio::fs::remove_file(&annexb).await;
+    Ok(metadata)
+}
+
+/// Inject `metadata` into the encoded `output` video stream, remuxing in place.
+pub async fn inject(
+    output: &Path,
+    metadata: &Metadata,
+    fps: f64,
+    mux_flags: &[(Arc<str>, Arc<str>)],
+    temp_dir: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let dir = temporary::process_dir(temp_dir);
+    let mut video = dir.join(naming::temp_file_name(output, "hevc"));
+    to_annexb(output, &video).await?;
+
+    if let Some(rpu) = &metadata.rpu {
+        let injected = dir.join(naming::temp_file_name(output, "dv.hevc"));
+        temporary::add(&injected, TempKind::NotKeepable);
+        run(
+            "dovi_tool inject-rpu",
+            Command::new("dovi_tool")
+                .arg("inject-rpu")
+                .arg2("-i", &video)
+                .arg2("--rpu-in", rpu)
+                .arg2("-o", &injected),
+        )
+        .await?;
+        _ = tokio::fs::remove_file(&video).await;
+        video = injected;
+    }
+    if let Some(json) = &metadata.hdr10_plus {
+        let injected = dir.join(naming::temp_file_name(output, "hdr10plus.hevc"));
+        temporary::add(&injected, TempKind::NotKeepable);
+        run(
+            "hdr10plus_tool inject",
+            Command::new("hdr10plus_tool")
+                .arg("inject")
+                .arg2("-i", &video)
+                .arg2("-j", json)
+                .arg2("-o", &injected),
+        )
+        .await?;
+        _ = tokio::fs::remove_file(&video).await;
+        video = injected;
+    }
+
+    // replace the output video stream, keeping all other streams
+    let ext = output.extension().and_then(|e| e.to_str()).unwrap_or("mkv");
+    let remuxed = output.with_file_name(naming::temp_file_name(output, &format!("hdr.{ext}")));
+    temporary::add(&remuxed, TempKind::NotKeepable);
+    run(
+        "ffmpeg hdr remux",
+        Command::new("ffmpeg")
+            .arg("-y")
+            .arg2("-r", fps.to_string())
+            .arg2("-i", &video)
+            .arg2("-i", output)
+            .arg2("-map", "0:v:0")
+            .arg2("-map", "1")
+            .arg2("-map", "-1:v:0")
+            .arg2("-c", "copy")
+            .arg2("-map_metadata", "1")
+            // Dolby Vision configuration records are only muxed with "unofficial" compliance
+            .arg2("-strict", "unofficial")
+            .args(ffmpeg::mux_args(output, mux_flags, &<_>::default())?)
+            .arg(&remuxed),
+    )
+    .await?;
+    _ = tokio::fs::remove_file(&video).await;
+    tokio::fs::rename(&remuxed, output)
+        .await
+        .with_context(|| format!("rename {}", remuxed.display()))?;
+    temporary::unadd(&remuxed);
+    Ok(())
+}
+
+/// Copy the first video stream of `input` to an annex b HEVC bitstream, as the tools require.
+async fn to_annexb(input: &Path, dest: &Path) -> anyhow::Result<()> {
+    temporary::add(dest, TempKind::NotKeepable);
+    run(
+        "ffmpeg hevc bitstream",
+        Command::new("ffmpeg")
+            .arg("-y")
+            .arg2("-i", input)
+            .arg2("-map", "0:v:0")
+            .arg2("-c:v", "copy")
+            .arg2("-bsf:v", "hevc_mp4toannexb")
+            .arg2("-f", "hevc")
+            .arg(dest),
+    )
+    .await
+}
+
+async fn run(name: &'static str, cmd: &mut Command) -> anyhow::Result<()> {
+    let out = cmd
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context(name)?;
+    ensure_success(name, &out)
+}
+
+#[cfg(test)]
+fn test_probe(dynamic_hdr: DynamicHdr) -> Ffprobe {
+    Ffprobe {
+        duration: Ok(std::time::Duration::from_secs(60)),
+        has_audio: true,
+        max_audio_channels: None,
+        fps: Ok(24.0),
+        resolution: Some((3840, 2160)),
+        sar: None,
+        is_image: false,
+        pix_fmt: Some("yuv420p10le".into()),
+        dynamic_hdr,
+    }
+}
+
+#[test]
+fn passthrough_support() {
+    use clap::Parser;
+    let enc = |args: &[&str]| {
+        args::Encode::try_parse_from(["ab-av1", "-i", "vid.mkv"].iter().chain(args)).unwrap()
+    };
+    let dv_hevc = test_probe(DynamicHdr {
+        dolby_vision: true,
+        hdr10_plus: false,
+        hevc: true,
+    });
+    let hdr10_plus_hevc = test_probe(DynamicHdr {
+        dolby_vision: false,
+        hdr10_plus: true,
+        hevc: true,
+    });
+    let dv_h264 = test_probe(DynamicHdr {
+        dolby_vision: true,
+        hdr10_plus: false,
+        hevc: false,
+    });
+    let p8 = || Some(8);
+    let p7 = || Some(7);
+
+    // hevc tools
+    let x265 = enc(&["-e", "libx265"]);
+    assert_eq!(
+        passthrough_with(&x265, &dv_hevc, p7),
+        Ok(Passthrough::Tools)
+    );
+    assert_eq!(
+        passthrough_with(&x265, &hdr10_plus_hevc, p8),
+        Ok(Passthrough::Tools)
+    );
+    assert!(passthrough_with(&x265, &dv_h264, p8).is_err());
+
+    // av1 encoder dolby vision
+    let svt = enc(&[]);
+    assert_eq!(
+        passthrough_with(&svt, &dv_hevc, p8),
+        Ok(Passthrough::Encoder)
+    );
+    assert_eq!(
+        passthrough_with(&enc(&["-e", "libaom-av1"]), &dv_h264, p8),
+        Ok(Passthrough::Encoder)
+    );
+    assert!(passthrough_with(&svt, &dv_hevc, p7).is_err());
+    assert!(passthrough_with(&svt, &hdr10_plus_hevc, p8).is_err());
+
+    // unsupported encoders
+    assert!(passthrough_with(&enc(&["-e", "libx264"]), &dv_hevc, p8).is_err());
+    assert!(passthrough_with(&enc(&["-e", "av1_nvenc"]), &dv_hevc, p8).is_err());
+
+    // frames must map 1:1
+    assert!(passthrough_with(
+        &enc(&["-e", "libx265", "--vfilter", "fps=30"]),
+        &dv_hevc,
+        p8
+    )
+    .is_err());
+    assert!(passthrough_with(&enc(&["--vfilter", "setpts=0.5*PTS"]), &dv_hevc, p8).is_err());
+    assert_eq!(
+        passthrough_with(&enc(&["--vfilter", "scale=1920:-2"]), &dv_hevc, p8),
+        Ok(Passthrough::Encoder)
+    );
+    let unknown_fps = Ffprobe {
+        fps: Err(crate::ffprobe::ProbeError::from(anyhow::anyhow!("no fps"))),
+        ..test_probe(dv_hevc.dynamic_hdr)
+    };
+    assert!(passthrough_with(&x265, &unknown_fps, p8).is_err());
+}
+
+#[test]
+fn hevc_encoders() {
+    assert!(hevc_encoder("libx265"));
+    assert!(hevc_encoder("hevc_nvenc"));
+    assert!(hevc_encoder("hevc_qsv"));
+    assert!(!hevc_encoder("libsvtav1"));
+    assert!(!hevc_encoder("av1_nvenc"));
+}
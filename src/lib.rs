@@ -0,0 +1,38 @@
+//! ab-av1 core used by the `ab-av1` binary.
+//!
+//! The rust api is internal & unstable. Embedders should use the C api, see `ffi`
+//! with the "ffi" feature.
+mod audio;
+mod cache_db;
+pub mod cancel;
+pub mod command;
+mod console_ext;
+mod energy;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod ffmpeg;
+mod ffprobe;
+mod float;
+mod hdr;
+mod live_vmaf;
+pub mod machine;
+mod naming;
+mod plot;
+mod preview;
+mod process;
+mod psnr;
+mod rendition;
+mod report;
+mod sample;
+mod ssim;
+mod ssimulacra2;
+mod stats;
+pub mod temporary;
+pub mod timings;
+mod vmaf;
+mod xpsnr;
+
+use std::time::Duration;
+
+const SAMPLE_SIZE_S: u64 = 20;
+const SAMPLE_SIZE: Duration = Duration::from_secs(SAMPLE_SIZE_S);
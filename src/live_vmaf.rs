@@ -0,0 +1,175 @@
+//! Rolling vmaf estimate of an in-progress encode, scoring windows of the output
+//! as they are written. See `--live-vmaf`.
+use crate::{
+    cancel::{self, CancellationToken},
+    command::{args, progress},
+    ffprobe, vmaf,
+};
+use console::style;
+use indicatif::{HumanDuration, ProgressBar};
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// Output time between the starts of scored windows.
+const INTERVAL: Duration = Duration::from_secs(60);
+/// Duration of each scored window.
+const WINDOW: Duration = Duration::from_secs(5);
+/// Encoded output time that may not be written yet, e.g. buffered by the muxer.
+const LAG: Duration = Duration::from_secs(15);
+/// Windows averaged in the rolling estimate.
+const ROLLING: usize = 5;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Running live vmaf scoring, see [`LiveVmaf::start`].
+pub struct LiveVmaf {
+    encoded: Rc<Cell<Duration>>,
+    estimate: Rc<Cell<Option<f32>>>,
+    task: JoinHandle<Option<()>>,
+}
+
+impl LiveVmaf {
+    /// Start scoring windows of the `output` against the `input` as they are encoded,
+    /// see [`LiveVmaf::progress`].
+    ///
+    /// Uses a single vmaf thread, unless set by `--vmaf n_threads`, so the encode is
+    /// barely slowed. Prints a warning if the estimate drops below `min_vmaf`.
+    pub fn start(
+        bar: ProgressBar,
+        (input, output): (PathBuf, PathBuf),
+        vmaf: args::Vmaf,
+        ref_vfilter: Option<String>,
+        min_vmaf: Option<f32>,
+        cancel: &CancellationToken,
+    ) -> Self {
+        let vmaf = args::Vmaf {
+            vmaf_threads: Some(1),
+            ..vmaf
+        };
+        let encoded = Rc::new(Cell::new(Duration::ZERO));
+        let estimate = Rc::new(Cell::new(None));
+        let task = cancel::spawn_local(cancel, {
+            let (encoded, estimate) = (encoded.clone(), estimate.clone());
+            async move {
+                let rprobe = ffprobe::probe(&input);
+                let mut lavfi = None;
+                let mut scores = VecDeque::with_capacity(ROLLING);
+                let mut warned = false;
+                let mut next = Duration::ZERO;
+                loop {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    let Some(start) = next_window(next, encoded.get()) else {
+                        continue;
+                    };
+                    // probe once the output has been partially written
+                    let lavfi = lavfi.get_or_insert_with(|| {
+                        let dprobe = ffprobe::probe(&output);
+                        vmaf.ffmpeg_lavfi(
+                            dprobe.display_resolution(),
+                            vmaf.pix_fmt(dprobe.pixel_format(), rprobe.pixel_format()),
+                            ref_vfilter.as_deref(),
+                        )
+                    });
+                    match vmaf::window_score(&input, &output, lavfi, (start, WINDOW)).await {
+                        Ok(Some(score)) => {
+                            if scores.len() == ROLLING {
+                                scores.pop_front();
+                            }
+                            scores.push_back(score);
+                            let rolling = scores.iter().sum::<f32>() / scores.len() as f32;
+                            estimate.set(Some(rolling));
+                            if let Some(min) = min_vmaf.filter(|min| rolling < *min && !warned) {
+                                warned = true;
+                                progress::println(
+                                    &bar,
+                                    format!(
+                                        "{} live vmaf ~{rolling:.1} at {} is below the {min} target",
+                                        style("Warning:").yellow().bold(),
+                                        HumanDuration(start),
+                                    ),
+                                );
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            progress::println(
+                                &bar,
+                                format!(
+                                    "{} live vmaf failed, stopping: {err}",
+                                    style("Warning:").yellow().bold(),
+                                ),
+                            );
+                            return;
+                        }
+                    }
+                    next = start + INTERVAL;
+                }
+            }
+        });
+        Self {
+            encoded,
+            estimate,
+            task,
+        }
+    }
+
+    /// Update the encoded output time.
+    pub fn progress(&self, time: Duration) {
+        self.encoded.set(time);
+    }
+
+    /// Rolling mean vmaf of the most recently scored windows, if any.
+    pub fn estimate(&self) -> Option<f32> {
+        self.estimate.get()
+    }
+}
+
+impl Drop for LiveVmaf {
+    fn drop(&mut self) {
+        // stop scoring, killing any vmaf child
+        self.task.abort();
+    }
+}
+
+/// Returns the reason `--live-vmaf` can't score the `output`, if any.
+///
+/// Windows are read from the output while it's being written, which requires
+/// a streamable container. Timestamps must also match the input.
+pub fn unsupported(output: &Path, args: &args::Encode) -> Option<&'static str> {
+    let ext = output.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if !matches!(ext.to_ascii_lowercase().as_str(), "mkv" | "webm") {
+        return Some("requires mkv or webm output");
+    }
+    if args.output_duration(INTERVAL) != INTERVAL {
+        return Some("not supported with timestamp changing vfilters");
+    }
+    None
+}
+
+/// Returns the start of the latest window at or after `next` that has been written
+/// by `encoded` output time, skipping windows if scoring falls behind.
+fn next_window(next: Duration, encoded: Duration) -> Option<Duration> {
+    let written = encoded.checked_sub(WINDOW + LAG)?;
+    if written < next {
+        return None;
+    }
+    let skip = ((written - next).as_secs_f64() / INTERVAL.as_secs_f64()) as u32;
+    Some(next + INTERVAL * skip)
+}
+
+#[test]
+fn live_vmaf_next_window() {
+    let secs = Duration::from_secs;
+    assert_eq!(next_window(secs(0), secs(10)), None);
+    assert_eq!(next_window(secs(0), secs(20)), Some(secs(0)));
+    assert_eq!(next_window(secs(60), secs(70)), None);
+    assert_eq!(next_window(secs(60), secs(80)), Some(secs(60)));
+    // scoring fell behind, skip to the latest written window
+    assert_eq!(next_window(secs(60), secs(200)), Some(secs(180)));
+    assert_eq!(next_window(secs(60), secs(199)), Some(secs(120)));
+}
@@ -0,0 +1,206 @@
+//! `--machine` mode, json lines events on stdout for GUI frontends.
+//!
+//! Each stdout line is a json object with `schema` & `event` fields:
+//! * `start`: `{"version"}` ab-av1 version.
+//! * `progress`: `{"position", "length", "fraction", "message", "elapsed_secs", "eta_secs"}`.
+//! * `result`: `{"result"}` the command's `--json` result.
+//! * `error`: `{"message"}`.
+//! * `done`: `{"exit_code"}` the process exit code, see [`ExitCode`].
+use crate::command::{crf_search, progress};
+use indicatif::{FormattedDuration, HumanDuration, ProgressBar, ProgressDrawTarget};
+use serde_json::json;
+use std::{
+    fmt,
+    io::{IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Event schema version, incremented on breaking changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Non-terminal stderr status line interval.
+const STATUS_INTERVAL: Duration = Duration::from_secs(15);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stable process exit codes. Invalid cli args exit with 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Ok = 0,
+    Error = 1,
+    /// The search failed to find a crf satisfying the score & size constraints.
+    NoGoodCrf = 3,
+    /// Interrupted by ctrl-c.
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    pub fn of(result: &anyhow::Result<()>) -> Self {
+        let Err(err) = result else {
+            return Self::Ok;
+        };
+        if err.downcast_ref::<Interrupted>().is_some() {
+            return Self::Interrupted;
+        }
+        match err.downcast_ref::<crf_search::Error>() {
+            Some(crf_search::Error::NoGoodCrf { .. }) => Self::NoGoodCrf,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// Ctrl-c error.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "ctrl_c".fmt(f)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    emit("start", json!({ "version": env!("CARGO_PKG_VERSION") }));
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Print a `--json` result, as a `result` event in machine mode.
+pub fn print_json(result: &serde_json::Value) {
+    match enabled() {
+        true => emit("result", json!({ "result": result })),
+        false => println!("{result}"),
+    }
+}
+
+/// In machine mode hide `bar` & periodically emit its progress as events,
+/// until finished or dropped.
+///
+/// Otherwise hide `bar` in [`progress::quiet`] mode or, if stderr is not a terminal,
+/// hide `bar` & periodically print single line status updates to stderr when
+/// progress has changed.
+pub fn watch(bar: &ProgressBar) {
+    if enabled() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+        every(bar, PROGRESS_INTERVAL, |bar| {
+            emit("progress", progress_fields(bar))
+        });
+    } else if progress::quiet() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+    } else if !std::io::stderr().is_terminal() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+        let mut last = None;
+        every(bar, STATUS_INTERVAL, move |bar| {
+            let progress = Some((bar.position(), bar.message()));
+            if progress != last {
+                eprintln!("[{}] {}", FormattedDuration(bar.elapsed()), status(bar));
+                last = progress;
+            }
+        });
+    }
+}
+
+/// Call `f` with `bar` every `interval` until finished or dropped.
+fn every(bar: &ProgressBar, interval: Duration, mut f: impl FnMut(&ProgressBar) + 'static) {
+    let bar = bar.downgrade();
+    tokio::task::spawn_local(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let Some(bar) = bar.upgrade() else { break };
+            if bar.is_finished() {
+                break;
+            }
+            f(&bar);
+        }
+    });
+}
+
+/// Single line status, e.g. "42% crf 32 VMAF 95.12, eta 3 minutes".
+fn status(bar: &ProgressBar) -> String {
+    let percent = match bar.length().unwrap_or(0) {
+        0 => 0,
+        len => (bar.position() * 100 / len).min(100),
+    };
+    let message = bar.message();
+    let message = message.trim_end_matches([',', ' ']);
+    match bar.position() {
+        0 => format!("{percent}% {message}"),
+        _ => format!("{percent}% {message}, eta {}", HumanDuration(bar.eta())),
+    }
+}
+
+fn progress_fields(bar: &ProgressBar) -> serde_json::Value {
+    let length = bar.length().unwrap_or(0);
+    let fraction = match length {
+        0 => 0.0,
+        len => (bar.position() as f64 / len as f64).min(1.0),
+    };
+    json!({
+        "position": bar.position(),
+        "length": length,
+        "fraction": fraction,
+        "message": bar.message().trim_end_matches([',', ' ']),
+        "elapsed_secs": bar.elapsed().as_secs(),
+        "eta_secs": bar.eta().as_secs(),
+    })
+}
+
+/// Emit the final `error` & `done` events and exit with a stable exit code.
+pub fn exit(result: anyhow::Result<()>) -> ! {
+    let code = ExitCode::of(&result);
+    if let Err(err) = result {
+        emit("error", json!({ "message": format!("{err:#}") }));
+    }
+    emit("done", json!({ "exit_code": code as u8 }));
+    std::process::exit(code as _);
+}
+
+fn emit(event: &str, fields: serde_json::Value) {
+    let line = event_json(event, fields);
+    let mut stdout = std::io::stdout().lock();
+    _ = writeln!(stdout, "{line}").and_then(|_| stdout.flush());
+}
+
+fn event_json(event: &str, mut fields: serde_json::Value) -> serde_json::Value {
+    fields["schema"] = SCHEMA_VERSION.into();
+    fields["event"] = event.into();
+    fields
+}
+
+#[test]
+fn event_schema() {
+    let event = event_json("done", json!({ "exit_code": 3 }));
+    assert_eq!(
+        event,
+        json!({ "schema": 1, "event": "done", "exit_code": 3 })
+    );
+}
+
+#[test]
+fn status_line() {
+    let bar = ProgressBar::hidden().with_message("crf 32 VMAF 95.12, ");
+    bar.set_length(200);
+    assert_eq!(status(&bar), "0% crf 32 VMAF 95.12");
+    bar.set_position(84);
+    assert!(status(&bar).starts_with("42% crf 32 VMAF 95.12, eta "));
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(ExitCode::of(&Ok(())), ExitCode::Ok);
+    assert_eq!(
+        ExitCode::of(&Err(Interrupted.into())),
+        ExitCode::Interrupted
+    );
+    assert_eq!(
+        ExitCode::of(&Err(anyhow::anyhow!("ffmpeg failed"))),
+        ExitCode::Error
+    );
+}
@@ -1,62 +1,227 @@
-mod command;
-mod console_ext;
-mod ffmpeg;
-mod ffprobe;
-mod float;
-mod process;
-mod sample;
-mod temporary;
-mod vmaf;
-
-use anyhow::anyhow;
-use clap::Parser;
+use ab_av1::{cancel::CancellationToken, command, machine, temporary, timings};
+use anyhow::Context;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use futures::FutureExt;
-use std::time::Duration;
 use tokio::signal;
 
-const SAMPLE_SIZE_S: u64 = 20;
-const SAMPLE_SIZE: Duration = Duration::from_secs(SAMPLE_SIZE_S);
-
 #[derive(Parser)]
 #[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Don't print per-sample or per-crf-attempt lines.
+    #[arg(long, short, global = true)]
+    quiet: bool,
+
+    /// Print results to stdout as json.
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print json lines progress, result & error events to stdout for frontends,
+    /// exiting with stable exit codes: 0 success, 1 error, 2 invalid args,
+    /// 3 no suitable crf found, 130 interrupted. Implies --json & --quiet.
+    #[arg(long, global = true)]
+    machine: bool,
+
+    /// Print a breakdown of time spent in each phase, e.g. sample encodes & metric scoring,
+    /// when done.
+    #[arg(long, global = true)]
+    timings: bool,
+
+    /// Disable coloured/styled console output.
+    #[arg(long, global = true, env = "NO_COLOR", value_parser = clap::builder::FalseyValueParser::new())]
+    no_color: bool,
+}
+
+#[derive(Subcommand)]
 enum Command {
     SampleEncode(command::sample_encode::Args),
     Vmaf(command::vmaf::Args),
+    Psnr(command::psnr::Args),
+    Ssimulacra2(command::ssimulacra2::Args),
+    Compare(command::compare::Args),
     Encode(command::encode::Args),
-    CrfSearch(command::crf_search::Args),
+    CrfSearch(command::crf_search::CommandArgs),
     AutoEncode(command::auto_encode::Args),
+    SceneEncode(command::scene_encode::Args),
+    QualitySearch(command::quality_search::Args),
+    Proxy(command::proxy::Args),
+    AudioSearch(command::audio_search::Args),
+    Doctor(command::doctor::Args),
+    Detect(command::detect::Args),
+    Cache(command::cache::Args),
+    Config(command::config::Args),
+    Debug(command::debug::Args),
     PrintCompletions(command::print_completions::Args),
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
-    let action = Command::parse();
+    let Cli {
+        command: mut action,
+        quiet,
+        json,
+        machine,
+        timings,
+        no_color,
+    } = Cli::parse();
+    let start = std::time::Instant::now();
+    if no_color {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    if machine {
+        machine::enable();
+    }
+    let sidecar = action.apply_sidecar();
+    if machine && sidecar.is_err() {
+        machine::exit(sidecar);
+    }
+    sidecar?;
+    action.set_output(quiet || machine, json || machine);
 
     let keep = action.keep_temp_files();
 
     let local = tokio::task::LocalSet::new();
+    let cancel = CancellationToken::new();
 
     let command = local.run_until(match action {
-        Command::SampleEncode(args) => command::sample_encode(args).boxed_local(),
-        Command::Vmaf(args) => command::vmaf(args).boxed_local(),
-        Command::Encode(args) => command::encode(args).boxed_local(),
-        Command::CrfSearch(args) => command::crf_search(args).boxed_local(),
-        Command::AutoEncode(args) => command::auto_encode(args).boxed_local(),
-        Command::PrintCompletions(args) => return command::print_completions(args),
+        Command::SampleEncode(args) => command::sample_encode(args, cancel.clone()).boxed_local(),
+        Command::Vmaf(args) => command::vmaf(args, cancel.clone()).boxed_local(),
+        Command::Psnr(args) => command::psnr(args).boxed_local(),
+        Command::Ssimulacra2(args) => command::ssimulacra2(args).boxed_local(),
+        Command::Compare(args) => command::compare(args, cancel.clone()).boxed_local(),
+        Command::Encode(args) => command::encode(args, cancel.clone()).boxed_local(),
+        Command::CrfSearch(args) => command::crf_search(args, cancel.clone()).boxed_local(),
+        Command::AutoEncode(args) => command::auto_encode(args, cancel.clone()).boxed_local(),
+        Command::SceneEncode(args) => command::scene_encode(args, cancel.clone()).boxed_local(),
+        Command::QualitySearch(args) => command::quality_search(args, cancel.clone()).boxed_local(),
+        Command::Proxy(args) => command::proxy(args, cancel.clone()).boxed_local(),
+        Command::AudioSearch(args) => command::audio_search(args, cancel.clone()).boxed_local(),
+        Command::Doctor(args) => return command::doctor(args),
+        Command::Detect(args) => return command::detect(args),
+        Command::Cache(args) => return command::cache(args),
+        Command::Config(args) => return command::config(args),
+        Command::Debug(args) => return command::debug(args),
+        Command::PrintCompletions(args) => return command::print_completions(args, Cli::command()),
     });
 
     let out = tokio::select! {
         r = command => r,
-        _ = signal::ctrl_c() => Err(anyhow!("ctrl_c")),
+        _ = signal::ctrl_c() => {
+            cancel.cancel();
+            Err(machine::Interrupted.into())
+        }
     };
 
     // Final cleanup. Samples are already deleted (if wished by the user) during `command::sample_encode::run`.
     temporary::clean(keep).await;
 
+    if timings {
+        eprint!("{}", timings::summary(start.elapsed()));
+    }
+
+    if machine {
+        machine::exit(out);
+    }
     out
 }
 
 impl Command {
+    /// Apply the global output flags to the command args.
+    fn set_output(&mut self, quiet: bool, json: bool) {
+        use command::{crf_search, sample_encode};
+
+        match self {
+            Self::SampleEncode(args) => {
+                args.quiet |= quiet;
+                if json {
+                    args.stdout_format = sample_encode::StdoutFormat::Json;
+                }
+            }
+            Self::CrfSearch(args) => {
+                args.search.quiet |= quiet;
+                if json {
+                    args.stdout_format = crf_search::StdoutFormat::Json;
+                }
+            }
+            Self::Vmaf(args) => {
+                args.quiet |= quiet;
+                if json {
+                    args.stdout_format = command::vmaf::StdoutFormat::Json;
+                }
+            }
+            Self::Psnr(args) => args.json = json,
+            Self::Ssimulacra2(args) => args.json = json,
+            Self::Compare(args) => args.json = json,
+            Self::Encode(args) => {
+                args.quiet |= quiet;
+                args.json = json;
+            }
+            Self::AutoEncode(args) => args.json = json,
+            Self::SceneEncode(args) => args.json = json,
+            Self::Detect(args) => args.json = json,
+            Self::Proxy(args) => args.json = json,
+            Self::AudioSearch(args) => {
+                args.quiet |= quiet;
+                args.json = json;
+            }
+            Self::QualitySearch(args) => {
+                args.search.quiet |= quiet;
+                args.json = json;
+            }
+            Self::Doctor(_)
+            | Self::Cache(_)
+            | Self::Config(_)
+            | Self::Debug(_)
+            | Self::PrintCompletions(_) => {}
+        }
+    }
+
+    /// Override args with those in the input's sidecar file, if any.
+    fn apply_sidecar(&mut self) -> anyhow::Result<()> {
+        use command::args::sidecar_args;
+
+        let input = match self {
+            Self::SampleEncode(args) => &args.args.input,
+            Self::CrfSearch(args) => &args.search.args.input,
+            Self::Encode(args) => &args.args.input,
+            Self::AutoEncode(args) => &args.search.args.input,
+            Self::SceneEncode(args) => &args.search.args.input,
+            Self::QualitySearch(args) => &args.search.args.input,
+            Self::Config(args) => match &args.action {
+                command::config::Action::Show(args) => &args.args.input,
+            },
+            Self::Vmaf(_)
+            | Self::Psnr(_)
+            | Self::Ssimulacra2(_)
+            | Self::Compare(_)
+            | Self::Proxy(_)
+            | Self::AudioSearch(_)
+            | Self::Doctor(_)
+            | Self::Detect(_)
+            | Self::Cache(_)
+            | Self::Debug(_)
+            | Self::PrintCompletions(_) => return Ok(()),
+        };
+        let Some((path, sidecar)) = sidecar_args(input)? else {
+            return Ok(());
+        };
+        // reparse the full command line so the sidecar only overrides what it sets
+        let args = sidecar.merge(std::env::args_os());
+        let matches = Cli::command()
+            .args_override_self(true)
+            .try_get_matches_from(args)
+            .with_context(|| format!("invalid {}", path.display()))?;
+        *self = Cli::from_arg_matches(&matches)?.command;
+        eprintln!(
+            "{}",
+            console::style(format!("Using overrides from {}", path.display())).dim()
+        );
+        Ok(())
+    }
+
     /// This decides what commands will keep temp files.
     ///
     /// # Important
@@ -66,8 +231,10 @@ impl Command {
     fn keep_temp_files(&self) -> bool {
         match self {
             Self::SampleEncode(args) => args.sample.keep,
-            Self::CrfSearch(args) => args.sample.keep,
+            Self::CrfSearch(args) => args.search.sample.keep,
             Self::AutoEncode(args) => args.search.sample.keep,
+            Self::SceneEncode(args) => args.search.sample.keep,
+            Self::QualitySearch(args) => args.search.sample.keep,
             _ => false,
         }
     }
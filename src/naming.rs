@@ -0,0 +1,110 @@
+//! Output & temporary file naming logic.
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// Short encoder name used as a pre-extension, e.g. "libsvtav1" -> "av1".
+pub fn pre_extension_name(vcodec: &str) -> &str {
+    match vcodec.strip_prefix("lib").filter(|s| !s.is_empty()) {
+        Some("svtav1") => "av1",
+        Some("vpx-vp9") => "vp9",
+        Some(suffix) => suffix,
+        _ => vcodec,
+    }
+}
+
+/// Default output extension for the encoder & input.
+///
+/// * image -> "avif"
+/// * vid.mp4 -> "mp4"
+/// * vid.webm + av1/vp9 encoder -> "webm"
+/// * otherwise -> "mkv"
+pub fn default_output_ext(input: &Path, vcodec: &str, is_image: bool) -> &'static str {
+    let webm_compatible = vcodec.contains("av1") || vcodec.contains("vp9");
+    if is_image {
+        return "avif";
+    }
+    match input.extension().and_then(|e| e.to_str()) {
+        Some("mp4") => "mp4",
+        Some("webm") if webm_compatible => "webm",
+        _ => "mkv",
+    }
+}
+
+/// E.g. vid.mkv -> "vid.av1.mkv"
+pub fn default_output_name(input: &Path, vcodec: &str, is_image: bool) -> PathBuf {
+    let pre = pre_extension_name(vcodec);
+    let ext = default_output_ext(input, vcodec, is_image);
+    input.with_extension(format!("{pre}.{ext}"))
+}
+
+/// Temporary file name derived from the input file name, e.g. ("vid.mp4", "sample1.mkv")
+/// -> "vid.sample1.mkv".
+///
+/// Inputs without a usable file name use "input" as the stem.
+pub fn temp_file_name(input: &Path, suffix: &str) -> OsString {
+    let mut name = input
+        .file_stem()
+        .filter(|s| !s.is_empty())
+        .unwrap_or("input".as_ref())
+        .to_owned();
+    name.push(".");
+    name.push(suffix);
+    name
+}
+
+/// Numbered alternative name, e.g. ("vid.av1.mkv", 2) -> "vid.av1.2.mkv".
+pub fn numbered_name(path: &Path, n: u32) -> PathBuf {
+    match path.extension() {
+        Some(ext) => {
+            let mut ext_n = OsString::from(format!("{n}."));
+            ext_n.push(ext);
+            path.with_extension(ext_n)
+        }
+        None => path.with_extension(n.to_string()),
+    }
+}
+
+#[test]
+fn default_output_names() {
+    let out = |input: &str, vcodec, image| default_output_name(Path::new(input), vcodec, image);
+    assert_eq!(out("vid.mp4", "libsvtav1", false), Path::new("vid.av1.mp4"));
+    assert_eq!(
+        out("vid.webm", "libvpx-vp9", false),
+        Path::new("vid.vp9.webm")
+    );
+    assert_eq!(out("vid.webm", "libx265", false), Path::new("vid.x265.mkv"));
+    assert_eq!(out("vid", "libsvtav1", false), Path::new("vid.av1.mkv"));
+    assert_eq!(out("pic.png", "libsvtav1", true), Path::new("pic.av1.avif"));
+    assert_eq!(out("pic.png", "libx264", true), Path::new("pic.x264.avif"));
+}
+
+#[test]
+fn temp_file_names() {
+    assert_eq!(
+        temp_file_name(Path::new("dir/vid.mp4"), "sample1+48f.mkv"),
+        "vid.sample1+48f.mkv"
+    );
+    assert_eq!(
+        temp_file_name(Path::new("vid"), "vmaf.json"),
+        "vid.vmaf.json"
+    );
+    assert_eq!(
+        temp_file_name(Path::new("/"), "vmaf.json"),
+        "input.vmaf.json"
+    );
+    assert_eq!(
+        temp_file_name(Path::new(".."), "vmaf.json"),
+        "input.vmaf.json"
+    );
+}
+
+#[test]
+fn numbered_names() {
+    assert_eq!(
+        numbered_name(Path::new("/vids/vid.av1.mkv"), 2),
+        Path::new("/vids/vid.av1.2.mkv")
+    );
+    assert_eq!(numbered_name(Path::new("vid"), 1), Path::new("vid.1"));
+}
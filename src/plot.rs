@@ -0,0 +1,325 @@
+//! Static per-frame score plots, see `--plot`.
+mod png;
+
+use crate::report::escape;
+use anyhow::{bail, Context};
+use png::{Anchor, Canvas};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Series colors, shared with html report graphs.
+pub const COLORS: [&str; 5] = ["#4fc3f7", "#ffb74d", "#81c784", "#e57373", "#ba68c8"];
+
+const WIDTH: f32 = 1200.0;
+const HEIGHT: f32 = 400.0;
+/// Left, right, top & bottom margins around the plot area.
+const MARGIN: (f32, f32, f32, f32) = (70.0, 20.0, 30.0, 40.0);
+/// Series with more points are reduced to per-bucket min & max values.
+const MAX_BUCKETS: usize = 1200;
+
+/// A line plot of one or more per-frame score series against frame number,
+/// with axes autoscaled to the data.
+#[derive(Debug, Default)]
+pub struct Plot {
+    /// Y axis label, e.g. "VMAF".
+    metric: String,
+    series: Vec<(String, Vec<f32>)>,
+}
+
+impl Plot {
+    pub fn new(metric: impl Into<String>) -> Self {
+        Self {
+            metric: metric.into(),
+            ..<_>::default()
+        }
+    }
+
+    /// Add per-frame scores to plot. Empty scores are ignored.
+    pub fn series(&mut self, name: &str, scores: Vec<f32>) {
+        if !scores.is_empty() {
+            self.series.push((name.into(), scores));
+        }
+    }
+
+    /// `(x, y)` axes autoscaled to the series.
+    fn axes(&self) -> (Axis, Axis) {
+        let frames = self.series.iter().map(|(_, s)| s.len()).max().unwrap_or(1);
+        let (min, max) = self
+            .series
+            .iter()
+            .flat_map(|(_, s)| s)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(*v), hi.max(*v))
+            });
+        (
+            Axis::autoscale(0.0, frames.saturating_sub(1) as f32),
+            Axis::autoscale(min, max),
+        )
+    }
+
+    pub fn to_svg(&self) -> String {
+        let (left, right, top, bottom) = MARGIN;
+        let (w, h) = (WIDTH - left - right, HEIGHT - top - bottom);
+        let (x_axis, y_axis) = self.axes();
+        let x = |f: f32| left + (f - x_axis.min) / (x_axis.max - x_axis.min) * w;
+        let y = |v: f32| top + (1.0 - (v - y_axis.min) / (y_axis.max - y_axis.min)) * h;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+             viewBox=\"0 0 {WIDTH} {HEIGHT}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"#1a1a1a\"/>\n"
+        );
+        for v in y_axis.ticks() {
+            let _ = writeln!(
+                svg,
+                "<line x1=\"{left}\" x2=\"{}\" y1=\"{ty:.1}\" y2=\"{ty:.1}\" stroke=\"#444\"/>\
+                 <text x=\"{}\" y=\"{:.1}\" fill=\"#999\" text-anchor=\"end\">{}</text>",
+                left + w,
+                left - 6.0,
+                y(v) + 4.0,
+                y_axis.label(v),
+                ty = y(v),
+            );
+        }
+        for f in x_axis.ticks() {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{}\" fill=\"#999\" text-anchor=\"middle\">{}</text>",
+                x(f),
+                top + h + 18.0,
+                x_axis.label(f),
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{}\" fill=\"#999\" text-anchor=\"middle\">frame</text>\
+             <text transform=\"translate(14 {:.1}) rotate(-90)\" fill=\"#999\" \
+             text-anchor=\"middle\">{}</text>",
+            left + w / 2.0,
+            HEIGHT - 4.0,
+            top + h / 2.0,
+            escape(&self.metric),
+        );
+        for (idx, (name, scores)) in self.series.iter().enumerate() {
+            let color = COLORS[idx % COLORS.len()];
+            let points = bucket_points(scores)
+                .map(|(f, v)| format!("{:.1},{:.1}", x(f), y(v)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                svg,
+                "<polyline fill=\"none\" stroke=\"{color}\" points=\"{points}\"/>\
+                 <text x=\"{}\" y=\"18\" fill=\"{color}\">{}</text>",
+                left + idx as f32 * 150.0,
+                escape(name),
+            );
+        }
+        svg += "</svg>\n";
+        svg
+    }
+
+    /// Png rendering of the same layout as [`Plot::to_svg`].
+    pub fn to_png(&self) -> Vec<u8> {
+        let (left, right, top, bottom) = MARGIN;
+        let (w, h) = (WIDTH - left - right, HEIGHT - top - bottom);
+        let (x_axis, y_axis) = self.axes();
+        let x = |f: f32| left + (f - x_axis.min) / (x_axis.max - x_axis.min) * w;
+        let y = |v: f32| top + (1.0 - (v - y_axis.min) / (y_axis.max - y_axis.min)) * h;
+        let (grid, label) = (rgb("#444"), rgb("#999"));
+
+        let mut canvas = Canvas::new(WIDTH as _, HEIGHT as _, rgb("#1a1a1a"));
+        for v in y_axis.ticks() {
+            canvas.line((left, y(v)), (left + w, y(v)), grid);
+            canvas.text(
+                (left - 6.0, y(v) + 4.0),
+                &y_axis.label(v),
+                Anchor::End,
+                label,
+            );
+        }
+        for f in x_axis.ticks() {
+            let pos = (x(f), top + h + 18.0);
+            canvas.text(pos, &x_axis.label(f), Anchor::Middle, label);
+        }
+        canvas.text(
+            (left + w / 2.0, HEIGHT - 4.0),
+            "frame",
+            Anchor::Middle,
+            label,
+        );
+        let pos = (14.0, top + h / 2.0);
+        canvas.text(pos, &self.metric, Anchor::Vertical, label);
+        for (idx, (name, scores)) in self.series.iter().enumerate() {
+            let color = rgb(COLORS[idx % COLORS.len()]);
+            let points: Vec<_> = bucket_points(scores).map(|(f, v)| (x(f), y(v))).collect();
+            canvas.polyline(&points, color);
+            canvas.text(
+                (left + idx as f32 * 150.0, 18.0),
+                name,
+                Anchor::Start,
+                color,
+            );
+        }
+        canvas.to_png()
+    }
+
+    /// Write the plot, in a format according to the `path` extension, see [`parse_path`].
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let plot = match Format::of(path)? {
+            Format::Svg => self.to_svg().into_bytes(),
+            Format::Png => self.to_png(),
+        };
+        std::fs::write(path, plot).with_context(|| format!("--plot: write {}", path.display()))
+    }
+}
+
+/// Plot output formats.
+enum Format {
+    Svg,
+    Png,
+}
+
+impl Format {
+    fn of(path: &Path) -> anyhow::Result<Self> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "svg" => Ok(Self::Svg),
+            "png" => Ok(Self::Png),
+            _ => bail!("unsupported extension '{ext}', use .svg or .png"),
+        }
+    }
+}
+
+/// `--plot` path value parser, checking the format is supported before any scoring.
+pub fn parse_path(path: &str) -> anyhow::Result<PathBuf> {
+    let path = PathBuf::from(path);
+    Format::of(&path)?;
+    Ok(path)
+}
+
+/// `(frame, score)` points of `scores`, reduced to each bucket's min & max if there are
+/// more than [`MAX_BUCKETS`] so dips remain visible.
+fn bucket_points(scores: &[f32]) -> impl Iterator<Item = (f32, f32)> + '_ {
+    let size = scores.len().div_ceil(MAX_BUCKETS).max(1);
+    scores
+        .chunks(size)
+        .enumerate()
+        .flat_map(move |(idx, chunk)| {
+            let start = (idx * size) as f32;
+            match chunk {
+                [v] => vec![(start, *v)],
+                _ => {
+                    let (lo, hi) = chunk.iter().enumerate().fold(
+                        ((0, f32::INFINITY), (0, f32::NEG_INFINITY)),
+                        |(lo, hi), (i, v)| {
+                            (
+                                if *v < lo.1 { (i, *v) } else { lo },
+                                if *v > hi.1 { (i, *v) } else { hi },
+                            )
+                        },
+                    );
+                    let mut points = vec![lo, hi];
+                    points.sort_by_key(|(i, _)| *i);
+                    points
+                        .into_iter()
+                        .map(|(i, v)| (start + i as f32, v))
+                        .collect()
+                }
+            }
+        })
+}
+
+/// An axis range extended to "nice" tick steps, e.g. 1, 2 or 5 times a power of 10.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Axis {
+    min: f32,
+    max: f32,
+    step: f32,
+}
+
+impl Axis {
+    fn autoscale(min: f32, max: f32) -> Self {
+        let (min, max) = match (min, max) {
+            (min, max) if min > max => (0.0, 1.0),
+            (min, max) if min == max => (min - 1.0, max + 1.0),
+            range => range,
+        };
+        let rough = (max - min) / 5.0;
+        let mag = 10_f32.powf(rough.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|m| m * mag)
+            .find(|s| *s >= rough)
+            .unwrap_or(10.0 * mag);
+        Self {
+            min: (min / step).floor() * step,
+            max: (max / step).ceil() * step,
+            step,
+        }
+    }
+
+    fn ticks(self) -> impl Iterator<Item = f32> {
+        let n = ((self.max - self.min) / self.step).round() as u32;
+        (0..=n).map(move |i| self.min + i as f32 * self.step)
+    }
+
+    fn label(self, v: f32) -> String {
+        let decimals = (-self.step.log10().floor()).max(0.0) as usize;
+        format!("{v:.decimals$}")
+    }
+}
+
+/// `#rgb` or `#rrggbb` color components.
+fn rgb(hex: &str) -> [u8; 3] {
+    let hex = hex.trim_start_matches('#');
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).unwrap_or(0);
+    match hex.len() {
+        3 => [digit(0) * 17, digit(1) * 17, digit(2) * 17],
+        _ => [0, 2, 4].map(|i| digit(i) * 16 + digit(i + 1)),
+    }
+}
+
+#[test]
+fn axis_autoscale() {
+    let axis = Axis::autoscale(93.2, 99.1);
+    assert_eq!((axis.min, axis.max, axis.step), (92.0, 100.0, 2.0));
+    assert_eq!(axis.ticks().count(), 5);
+    assert_eq!(axis.label(94.0), "94");
+
+    let axis = Axis::autoscale(0.981, 0.9962);
+    assert!((axis.step - 0.005).abs() < 1e-6, "{axis:?}");
+    assert_eq!(axis.label(0.985), "0.985");
+
+    let axis = Axis::autoscale(95.0, 95.0);
+    assert_eq!((axis.min, axis.max), (94.0, 96.0));
+}
+
+#[test]
+fn plot_svg() {
+    let mut plot = Plot::new("VMAF & SSIM");
+    plot.series("vmaf", vec![95.0, 96.0, 94.0]);
+    plot.series("empty", vec![]);
+    let svg = plot.to_svg();
+    assert!(svg.contains("VMAF &amp; SSIM"));
+    assert_eq!(svg.matches("<polyline").count(), 1);
+    assert!(!svg.contains("empty"));
+
+    let long: Vec<f32> = (0..10_000)
+        .map(|f| if f == 5000 { 10.0 } else { 95.0 })
+        .collect();
+    assert!(bucket_points(&long).count() <= 2 * MAX_BUCKETS);
+    assert!(bucket_points(&long).any(|(f, v)| f == 5000.0 && v == 10.0));
+
+    assert!(parse_path("vmaf.SVG").is_ok());
+    assert!(parse_path("vmaf.png").is_ok());
+    assert!(parse_path("vmaf.jpg").is_err());
+
+    assert_eq!(rgb("#1a1a1a"), [0x1a, 0x1a, 0x1a]);
+    assert_eq!(rgb("#999"), [0x99, 0x99, 0x99]);
+    let png = plot.to_png();
+    assert!(png.starts_with(b"\x89PNG"));
+    // uncompressed, so at least the raw rgb rows with filter bytes
+    assert!(png.len() > (WIDTH as usize * 3 + 1) * HEIGHT as usize);
+}
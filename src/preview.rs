@@ -0,0 +1,243 @@
+//! Live encode preview http server.
+//!
+//! Serves a periodically updated jpeg of a recently encoded frame, decoded from the
+//! partially written output, plus progress json.
+use anyhow::Context;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a new preview frame is decoded from the output.
+const FRAME_INTERVAL: Duration = Duration::from_secs(15);
+/// Seek this far behind the encoded position, as the tail may not be flushed yet.
+const FRAME_LAG: Duration = Duration::from_secs(5);
+
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>ab-av1 preview</title></head>
+<body style="background:#222;color:#ddd;font-family:sans-serif">
+<pre id="progress"></pre><img id="frame" style="max-width:100%">
+<script>
+async function update() {
+  try {
+    const p = await (await fetch("/progress.json")).json();
+    document.getElementById("progress").textContent = JSON.stringify(p, null, 2);
+    if (p.frame) document.getElementById("frame").src = "/frame.jpg?" + p.frame;
+  } catch (e) {}
+}
+update();
+setInterval(update, 2000);
+</script></body></html>"#;
+
+#[derive(Debug, Default)]
+struct State {
+    output: PathBuf,
+    time: Duration,
+    duration: Option<Duration>,
+    fps: f32,
+    /// Latest jpeg & the encoded time it was taken from.
+    frame: Option<(Vec<u8>, Duration)>,
+}
+
+/// Running preview server, stops when dropped.
+#[derive(Debug)]
+pub struct Preview {
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Preview {
+    /// Serve a preview of `output` on `addr`.
+    pub fn serve(
+        addr: SocketAddr,
+        output: &Path,
+        duration: Option<Duration>,
+    ) -> anyhow::Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("preview server bind {addr}"))?;
+        listener.set_nonblocking(true)?;
+
+        let state = Arc::new(Mutex::new(State {
+            output: output.to_owned(),
+            duration,
+            ..<_>::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (s, stop_flag) = (state.clone(), stop.clone());
+        thread::spawn(move || accept_loop(listener, &s, &stop_flag));
+        let (s, stop_flag) = (state.clone(), stop.clone());
+        thread::spawn(move || frame_loop(&s, &stop_flag));
+
+        Ok(Self { state, stop })
+    }
+
+    /// Update the encode progress.
+    pub fn progress(&self, time: Duration, fps: f32) {
+        let mut state = self.state.lock().unwrap();
+        state.time = time;
+        if fps > 0.0 {
+            state.fps = fps;
+        }
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn accept_loop(listener: TcpListener, state: &Mutex<State>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // a broken client connection shouldn't affect the encode
+                _ = handle(stream, state);
+            }
+            Err(_) => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+fn frame_loop(state: &Mutex<State>, stop: &AtomicBool) {
+    let mut last = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(200));
+        if last.elapsed() < FRAME_INTERVAL {
+            continue;
+        }
+        last = Instant::now();
+
+        let (output, time) = {
+            let state = state.lock().unwrap();
+            (state.output.clone(), state.time)
+        };
+        let Some(at) = time.checked_sub(FRAME_LAG) else {
+            continue;
+        };
+        // best effort, e.g. mp4 output isn't readable until finished
+        if let Some(jpeg) = decode_frame(&output, at) {
+            state.lock().unwrap().frame = Some((jpeg, at));
+        }
+    }
+}
+
+/// Decode a single frame of `video` at `at` into jpeg data.
+fn decode_frame(video: &Path, at: Duration) -> Option<Vec<u8>> {
+    let out = Command::new("ffmpeg")
+        .arg("-nostdin")
+        .args(["-loglevel", "error"])
+        .arg("-ss")
+        .arg(format!("{:.3}", at.as_secs_f64()))
+        .arg("-i")
+        .arg(video)
+        .args(["-map", "0:v:0", "-frames:v", "1"])
+        .args(["-c:v", "mjpeg", "-q:v", "4", "-f", "image2pipe", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    (out.status.success() && !out.stdout.is_empty()).then_some(out.stdout)
+}
+
+fn handle(stream: TcpStream, state: &Mutex<State>) -> anyhow::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let (status, content_type, body) = response(&request_line, &state.lock().unwrap());
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+fn response(request_line: &str, state: &State) -> (&'static str, &'static str, Vec<u8>) {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next().unwrap_or("/"));
+    if method != Some("GET") {
+        return (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"method not allowed".to_vec(),
+        );
+    }
+    match path.split('?').next() {
+        Some("/") => ("200 OK", "text/html; charset=utf-8", INDEX_HTML.into()),
+        Some("/progress.json") => ("200 OK", "application/json", progress_json(state).into()),
+        Some("/frame.jpg") => match &state.frame {
+            Some((jpeg, _)) => ("200 OK", "image/jpeg", jpeg.clone()),
+            None => (
+                "503 Service Unavailable",
+                "text/plain",
+                b"no frame yet".to_vec(),
+            ),
+        },
+        _ => ("404 Not Found", "text/plain", b"not found".to_vec()),
+    }
+}
+
+fn progress_json(state: &State) -> String {
+    let percent = state
+        .duration
+        .filter(|d| !d.is_zero())
+        .map(|d| (100.0 * state.time.as_secs_f64() / d.as_secs_f64()).min(100.0));
+    serde_json::json!({
+        "output": state.output.display().to_string(),
+        "time": state.time.as_secs_f64(),
+        "duration": state.duration.map(|d| d.as_secs_f64()),
+        "percent": percent,
+        "fps": state.fps,
+        // changes whenever a new frame is available
+        "frame": state.frame.as_ref().map(|(_, at)| at.as_millis() as u64),
+    })
+    .to_string()
+}
+
+#[test]
+fn preview_responses() {
+    let mut state = State {
+        output: "vid.av1.mkv".into(),
+        time: Duration::from_secs(30),
+        duration: Some(Duration::from_secs(120)),
+        ..<_>::default()
+    };
+    let (status, content_type, _) = response("GET / HTTP/1.1\r\n", &state);
+    assert_eq!(
+        (status, content_type),
+        ("200 OK", "text/html; charset=utf-8")
+    );
+
+    let (status, ..) = response("GET /frame.jpg?1 HTTP/1.1\r\n", &state);
+    assert_eq!(status, "503 Service Unavailable");
+    state.frame = Some((vec![0xff, 0xd8], Duration::from_secs(25)));
+    let (status, _, body) = response("GET /frame.jpg?25000 HTTP/1.1\r\n", &state);
+    assert_eq!((status, body), ("200 OK", vec![0xff, 0xd8]));
+
+    let (_, _, body) = response("GET /progress.json HTTP/1.1\r\n", &state);
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["percent"], 25.0);
+    assert_eq!(json["frame"], 25000);
+
+    let (status, ..) = response("POST / HTTP/1.1\r\n", &state);
+    assert_eq!(status, "405 Method Not Allowed");
+    let (status, ..) = response("GET /nope HTTP/1.1\r\n", &state);
+    assert_eq!(status, "404 Not Found");
+}
@@ -2,39 +2,205 @@ use anyhow::{anyhow, ensure};
 use std::{
     borrow::Cow,
     ffi::OsStr,
-    io,
+    fmt, io,
+    pin::Pin,
     process::{ExitStatus, Output},
     sync::Arc,
     time::Duration,
 };
 use time::macros::format_description;
-use tokio::process::Child;
 use tokio_process_stream::{Item, ProcessChunkStream};
 use tokio_stream::{Stream, StreamExt};
 
+/// Stdout & stderr chunks of a spawned process, ending with its exit status.
+pub type ChunkStream = Pin<Box<dyn Stream<Item = Item<Vec<u8>>>>>;
+
+/// Runs external processes, e.g. ffprobe & ffmpeg, capturing their output.
+///
+/// Allows tests to inject canned process output, see `CannedRunner`.
+pub trait ProcessRunner {
+    /// Run `cmd` to completion.
+    fn output(&self, cmd: &mut std::process::Command) -> io::Result<Output>;
+
+    /// Spawn `cmd`, e.g. a long running ffmpeg encode, streaming its output.
+    fn spawn(&self, cmd: &mut tokio::process::Command) -> io::Result<ChunkStream>;
+}
+
+/// Runs real processes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl ProcessRunner for SystemRunner {
+    fn output(&self, cmd: &mut std::process::Command) -> io::Result<Output> {
+        cmd.output()
+    }
+
+    fn spawn(&self, cmd: &mut tokio::process::Command) -> io::Result<ChunkStream> {
+        let chunks = ProcessChunkStream::from(cmd.spawn()?).map(|item| match item {
+            Item::Stdout(chunk) => Item::Stdout(chunk.to_vec()),
+            Item::Stderr(chunk) => Item::Stderr(chunk.to_vec()),
+            Item::Done(code) => Item::Done(code),
+        });
+        Ok(Box::pin(chunks))
+    }
+}
+
+/// Shared [`ProcessRunner`] carried in args, [`SystemRunner`] by default.
+#[derive(Clone)]
+pub struct Runner(Arc<dyn ProcessRunner + Send + Sync>);
+
+impl Runner {
+    pub fn new(runner: impl ProcessRunner + Send + Sync + 'static) -> Self {
+        Self(Arc::new(runner))
+    }
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new(SystemRunner)
+    }
+}
+
+impl fmt::Debug for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Runner")
+    }
+}
+
+impl ProcessRunner for Runner {
+    fn output(&self, cmd: &mut std::process::Command) -> io::Result<Output> {
+        self.0.output(cmd)
+    }
+
+    fn spawn(&self, cmd: &mut tokio::process::Command) -> io::Result<ChunkStream> {
+        self.0.spawn(cmd)
+    }
+}
+
+/// Test runner returning canned successful stdout & stderr for each program,
+/// recording the args of each run.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct CannedRunner {
+    outputs: Vec<(&'static str, &'static str, &'static str)>,
+    pub runs: std::cell::RefCell<Vec<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl CannedRunner {
+    /// Return `stdout` & `stderr` for runs of `program`, in order if added multiple times.
+    pub fn with(
+        mut self,
+        program: &'static str,
+        stdout: &'static str,
+        stderr: &'static str,
+    ) -> Self {
+        self.outputs.push((program, stdout, stderr));
+        self
+    }
+}
+
+#[cfg(test)]
+impl ProcessRunner for CannedRunner {
+    fn output(&self, cmd: &mut std::process::Command) -> io::Result<Output> {
+        self.canned(cmd.get_program(), cmd.get_args())
+    }
+
+    fn spawn(&self, cmd: &mut tokio::process::Command) -> io::Result<ChunkStream> {
+        let cmd = cmd.as_std();
+        let out = self.canned(cmd.get_program(), cmd.get_args())?;
+        let chunks = [
+            Item::Stdout(out.stdout),
+            Item::Stderr(out.stderr),
+            Item::Done(Ok(out.status)),
+        ];
+        Ok(Box::pin(tokio_stream::iter(chunks)))
+    }
+}
+
+#[cfg(test)]
+impl CannedRunner {
+    fn canned<'a>(
+        &self,
+        program: &OsStr,
+        args: impl Iterator<Item = &'a OsStr>,
+    ) -> io::Result<Output> {
+        let program = program.to_string_lossy().into_owned();
+        let mut runs = self.runs.borrow_mut();
+        let nth = runs
+            .iter()
+            .filter(|run| run.first() == Some(&program))
+            .count();
+        runs.push(
+            std::iter::once(program.clone())
+                .chain(args.map(|a| a.to_string_lossy().into_owned()))
+                .collect(),
+        );
+        let (_, stdout, stderr) = self
+            .outputs
+            .iter()
+            .filter(|(p, ..)| *p == program)
+            .nth(nth)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, program))?;
+        Ok(Output {
+            status: ExitStatus::default(),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        })
+    }
+}
+
 pub fn ensure_success(name: &'static str, out: &Output) -> anyhow::Result<()> {
     ensure!(
         out.status.success(),
         "{name} exit code {}\n---stderr---\n{}\n------------",
-        out.status
-            .code()
-            .map(|c| c.to_string())
-            .unwrap_or_else(|| "None".into()),
+        exit_code(out.status),
         String::from_utf8_lossy(&out.stderr).trim(),
     );
     Ok(())
 }
 
+/// Exit code for error messages, or "None (signal N)" if terminated by a signal.
+fn exit_code(status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return code.to_string();
+    }
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return format!("None (signal {signal})");
+    }
+    "None".into()
+}
+
+/// Max retries of an operation failing with a transient I/O error, see [`is_io_error`].
+pub const IO_RETRIES: u32 = 3;
+
+/// Returns true if the error looks like a transient I/O failure,
+/// e.g. reading an input on a network filesystem.
+pub fn is_io_error(err: &anyhow::Error) -> bool {
+    let err = format!("{err:#}");
+    [
+        "Input/output error",
+        "I/O error",
+        "os error 5)",
+        "Stale file handle",
+        "Connection reset",
+        "Connection timed out",
+        "Resource temporarily unavailable",
+    ]
+    .iter()
+    .any(|e| err.contains(e))
+}
+
+/// Delay before I/O retry `attempt` (1-based): 2s, 4s, 8s, ... max 60s.
+pub fn io_retry_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2_u64.saturating_pow(attempt).min(60))
+}
+
 /// Convert exit code result into simple result.
 pub fn exit_ok(name: &'static str, done: io::Result<ExitStatus>) -> anyhow::Result<()> {
     let code = done?;
-    ensure!(
-        code.success(),
-        "{name} exit code {}",
-        code.code()
-            .map(|c| c.to_string())
-            .unwrap_or_else(|| "None".into())
-    );
+    ensure!(code.success(), "{name} exit code {}", exit_code(code));
     Ok(())
 }
 
@@ -96,11 +262,11 @@ impl FfmpegOut {
     }
 
     pub fn stream(
-        child: Child,
+        process: ChunkStream,
         name: &'static str,
     ) -> impl Stream<Item = anyhow::Result<FfmpegOut>> {
         let mut chunks = Chunks::default();
-        ProcessChunkStream::from(child).filter_map(move |item| match item {
+        process.filter_map(move |item| match item {
             Item::Stderr(chunk) => {
                 chunks.push(&chunk);
                 FfmpegOut::try_parse(chunks.last_line()).map(Ok)
@@ -224,9 +390,6 @@ fn parse_ffmpeg_stream_sizes() {
 }
 
 pub trait CommandExt {
-    /// Adds an argument if `condition` otherwise noop.
-    fn arg_if(&mut self, condition: bool, a: impl ArgString) -> &mut Self;
-
     /// Adds two arguments.
     fn arg2(&mut self, a: impl ArgString, b: impl ArgString) -> &mut Self;
 
@@ -237,13 +400,6 @@ pub trait CommandExt {
     fn arg2_if(&mut self, condition: bool, a: impl ArgString, b: impl ArgString) -> &mut Self;
 }
 impl CommandExt for tokio::process::Command {
-    fn arg_if(&mut self, c: bool, arg: impl ArgString) -> &mut Self {
-        match c {
-            true => self.arg(arg.arg_string()),
-            false => self,
-        }
-    }
-
     fn arg2(&mut self, a: impl ArgString, b: impl ArgString) -> &mut Self {
         self.arg(a.arg_string()).arg(b.arg_string())
     }
@@ -303,3 +459,17 @@ impl ArgString for Arc<str> {
         Cow::Borrowed((**self).as_ref())
     }
 }
+
+#[test]
+fn io_errors() {
+    assert!(is_io_error(&anyhow!(
+        "ffmpeg encode exit code 1\n---stderr---\nvid.mkv: Input/output error\n------------"
+    )));
+    assert!(is_io_error(&anyhow::Error::from(
+        io::Error::from_raw_os_error(5)
+    )));
+    assert!(!is_io_error(&anyhow!("ffmpeg encode exit code 1")));
+    assert_eq!(io_retry_backoff(1), Duration::from_secs(2));
+    assert_eq!(io_retry_backoff(3), Duration::from_secs(8));
+    assert_eq!(io_retry_backoff(10), Duration::from_secs(60));
+}
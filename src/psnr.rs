@@ -0,0 +1,146 @@
+//! psnr logic
+use crate::{
+    command::args::PixelFormat,
+    process::{exit_ok_stderr, Chunks, CommandExt, FfmpegOut},
+};
+use anyhow::Context;
+use std::path::Path;
+use tokio::process::Command;
+use tokio_process_stream::{Item, ProcessChunkStream};
+use tokio_stream::{Stream, StreamExt};
+
+/// Per-frame psnr value used for identical frames, which ffmpeg reports as "inf".
+pub const MAX_FRAME_PSNR: f32 = 100.0;
+
+/// Calculate the average PSNR score of distorted vs reference.
+pub fn run(
+    reference: &Path,
+    distorted: &Path,
+    filter_complex: &str,
+) -> anyhow::Result<impl Stream<Item = PsnrOut>> {
+    let psnr: ProcessChunkStream = Command::new("ffmpeg")
+        .kill_on_drop(true)
+        .arg2("-i", distorted)
+        .arg2("-i", reference)
+        .arg2("-filter_complex", filter_complex)
+        .arg2("-f", "null")
+        .arg("-")
+        .try_into()
+        .context("ffmpeg psnr")?;
+
+    let mut chunks = Chunks::default();
+    let psnr = psnr.filter_map(move |item| match item {
+        Item::Stderr(chunk) => PsnrOut::try_from_chunk(&chunk, &mut chunks),
+        Item::Stdout(_) => None,
+        Item::Done(code) => PsnrOut::ignore_ok(exit_ok_stderr("ffmpeg psnr", code, &chunks)),
+    });
+
+    Ok(psnr)
+}
+
+/// Returns ffmpeg `filter_complex`/`lavfi` value for calculating psnr,
+/// writing per-frame stats to `stats_file`.
+///
+/// The distorted stream is scaled to the reference resolution, if different, using
+/// the reference input of `scale` (ffmpeg >= 7.1).
+pub fn ffmpeg_lavfi(pix_fmt: PixelFormat, ref_vfilter: Option<&str>, stats_file: &Path) -> String {
+    let ref_vf = match ref_vfilter {
+        None => String::new(),
+        Some(vf) if vf.ends_with(',') => vf.into(),
+        Some(vf) => format!("{vf},"),
+    };
+    let stats_file = stats_file
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace(':', "\\\\:");
+    format!(
+        "[0:v]format={pix_fmt},setpts=PTS-STARTPTS[dis];\
+         [1:v]format={pix_fmt},{ref_vf}setpts=PTS-STARTPTS,split[ref][sref];\
+         [dis][ref]scale=rw:rh:flags=bicubic[sdis];\
+         [sdis][sref]psnr=stats_file={stats_file}"
+    )
+}
+
+/// Read per-frame average psnr values from a psnr filter `stats_file`.
+pub fn read_frame_scores(stats_file: &Path) -> anyhow::Result<Vec<f32>> {
+    let stats = std::fs::read_to_string(stats_file)
+        .with_context(|| format!("read {}", stats_file.display()))?;
+    Ok(parse_frame_scores(&stats))
+}
+
+/// Parse `psnr_avg` of each stats line, e.g.
+/// "n:1 mse_avg:0.52 mse_y:0.61 mse_u:0.33 mse_v:0.30 psnr_avg:50.97 psnr_y:50.26 ..."
+fn parse_frame_scores(stats: &str) -> Vec<f32> {
+    stats
+        .lines()
+        .filter_map(|line| {
+            let avg = line
+                .split_whitespace()
+                .find_map(|w| w.strip_prefix("psnr_avg:"))?;
+            match avg {
+                "inf" => Some(MAX_FRAME_PSNR),
+                avg => avg.parse::<f32>().ok().map(|s| s.min(MAX_FRAME_PSNR)),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum PsnrOut {
+    Progress(FfmpegOut),
+    Done(f32),
+    Err(anyhow::Error),
+}
+
+impl PsnrOut {
+    fn ignore_ok<T>(result: anyhow::Result<T>) -> Option<Self> {
+        match result {
+            Ok(_) => None,
+            Err(err) => Some(Self::Err(err)),
+        }
+    }
+
+    fn try_from_chunk(chunk: &[u8], chunks: &mut Chunks) -> Option<Self> {
+        chunks.push(chunk);
+        let line = chunks.last_line();
+
+        if let Some(score) = parse_psnr_average(line) {
+            return Some(Self::Done(score));
+        }
+        if let Some(progress) = FfmpegOut::try_parse(line) {
+            return Some(Self::Progress(progress));
+        }
+        None
+    }
+}
+
+/// Parse the "average" score from ffmpeg psnr filter output.
+fn parse_psnr_average(line: &str) -> Option<f32> {
+    let idx = line.find("PSNR ")?;
+    let avg = line[idx..]
+        .split_whitespace()
+        .find_map(|w| w.strip_prefix("average:"))?;
+    match avg {
+        "inf" => Some(MAX_FRAME_PSNR),
+        avg => avg.parse().ok(),
+    }
+}
+
+#[test]
+fn parse_psnr_average_line() {
+    let line = "[Parsed_psnr_5 @ 0x5581] PSNR y:41.253 u:45.912 v:46.112 average:42.401 min:38.110 max:48.002";
+    assert_eq!(parse_psnr_average(line), Some(42.401));
+    assert_eq!(parse_psnr_average("frame=  100 fps=50"), None);
+}
+
+#[test]
+fn parse_psnr_stats_file() {
+    let stats = "n:1 mse_avg:0.52 mse_y:0.61 mse_u:0.33 mse_v:0.30 psnr_avg:50.97 psnr_y:50.26\n\
+                 n:2 mse_avg:0.00 mse_y:0.00 mse_u:0.00 mse_v:0.00 psnr_avg:inf psnr_y:inf\n\
+                 garbage\n\
+                 n:3 mse_avg:3.20 mse_y:3.90 mse_u:1.20 mse_v:1.10 psnr_avg:43.08 psnr_y:42.22\n";
+    assert_eq!(
+        parse_frame_scores(stats),
+        vec![50.97, MAX_FRAME_PSNR, 43.08]
+    );
+}
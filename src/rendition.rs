@@ -0,0 +1,244 @@
+//! Rendition descriptions for packaging scripts, e.g. building HLS/DASH manifests.
+use crate::command::args::PixelFormat;
+use std::time::Duration;
+
+/// A video rendition in the field names commonly used by packaging scripts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    /// RFC6381 codecs string, e.g. "av01.0.08M.10". `None` for unknown codecs.
+    pub codecs: Option<String>,
+    /// Video stream bits per second.
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub frame_rate: Option<f64>,
+}
+
+impl Rendition {
+    /// Rendition of a `vcodec` encode of `size` bytes over `duration`.
+    pub fn new(
+        vcodec: &str,
+        pix_fmt: PixelFormat,
+        resolution: Option<(u32, u32)>,
+        frame_rate: Option<f64>,
+        size: u64,
+        duration: Duration,
+    ) -> Self {
+        let bandwidth = match duration.as_secs_f64() {
+            secs if secs > 0.0 => (size as f64 * 8.0 / secs).round() as u64,
+            _ => 0,
+        };
+        Self {
+            codecs: resolution.and_then(|res| codecs(vcodec, pix_fmt, res, frame_rate)),
+            bandwidth,
+            resolution,
+            frame_rate,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "codecs": self.codecs,
+            "bandwidth": self.bandwidth,
+            "resolution": self.resolution.map(|(w, h)| format!("{w}x{h}")),
+            "frame_rate": self.frame_rate,
+        })
+    }
+}
+
+/// Returns the RFC6381 codecs string of a `vcodec` encode, e.g. "avc1.640028".
+///
+/// Levels are the lowest allowing the resolution & frame rate (as encoders select
+/// by default), ignoring bitrate limits. The main tier & 4:2:0 chroma are assumed
+/// except for yuv444p10le.
+pub fn codecs(
+    vcodec: &str,
+    pix_fmt: PixelFormat,
+    (w, h): (u32, u32),
+    fps: Option<f64>,
+) -> Option<String> {
+    let bit_depth = match pix_fmt {
+        PixelFormat::Yuv420p => 8,
+        PixelFormat::Yuv420p10le | PixelFormat::Yuv444p10le => 10,
+    };
+    let yuv444 = pix_fmt == PixelFormat::Yuv444p10le;
+    let pixels = u64::from(w) * u64::from(h);
+    let rate = (pixels as f64 * fps.unwrap_or(30.0)) as u64;
+    let fits = |(max_pixels, max_rate, max_w, max_h): (u64, u64, u32, u32)| {
+        pixels <= max_pixels && rate <= max_rate && w <= max_w && h <= max_h
+    };
+
+    if vcodec.contains("av1") {
+        // seq_level_idx, max pic size, max display rate, max h & v size
+        const LEVELS: [(u8, (u64, u64, u32, u32)); 12] = [
+            (0, (147456, 4423680, 2048, 1152)),
+            (1, (278784, 8363520, 2816, 1584)),
+            (4, (665856, 19975680, 4352, 2448)),
+            (5, (1065024, 31950720, 5504, 3096)),
+            (8, (2359296, 70778880, 6144, 3456)),
+            (9, (2359296, 141557760, 6144, 3456)),
+            (12, (8912896, 267386880, 8192, 4352)),
+            (13, (8912896, 534773760, 8192, 4352)),
+            (14, (8912896, 1069547520, 8192, 4352)),
+            (16, (35651584, 1069547520, 16384, 8704)),
+            (17, (35651584, 2139095040, 16384, 8704)),
+            (18, (35651584, 4278190080, 16384, 8704)),
+        ];
+        // 31: maximum parameters
+        let level = LEVELS
+            .iter()
+            .find(|(_, l)| fits(*l))
+            .map_or(31, |(l, _)| *l);
+        let profile = u8::from(yuv444);
+        return Some(format!("av01.{profile}.{level:02}M.{bit_depth:02}"));
+    }
+    if vcodec.contains("vp9") {
+        // level, max luma picture size & sample rate
+        const LEVELS: [(u8, u64, u64); 14] = [
+            (10, 36864, 829440),
+            (11, 73728, 2764800),
+            (20, 122880, 4608000),
+            (21, 245760, 9216000),
+            (30, 552960, 20736000),
+            (31, 983040, 36864000),
+            (40, 2228224, 83558400),
+            (41, 2228224, 160432128),
+            (50, 8912896, 311951360),
+            (51, 8912896, 588251136),
+            (52, 8912896, 1176502272),
+            (60, 35651584, 1176502272),
+            (61, 35651584, 2353004544),
+            (62, 35651584, 4706009088),
+        ];
+        let (level, ..) = LEVELS
+            .iter()
+            .find(|(_, p, r)| fits((*p, *r, u32::MAX, u32::MAX)))?;
+        let profile = match (yuv444, bit_depth) {
+            (false, 8) => 0,
+            (true, 8) => 1,
+            (false, _) => 2,
+            (true, _) => 3,
+        };
+        return Some(format!("vp09.{profile:02}.{level}.{bit_depth:02}"));
+    }
+    if vcodec.contains("264") {
+        // level_idc, max frame size & processing rate in 16x16 macroblocks
+        const LEVELS: [(u8, u64, u64); 19] = [
+            (10, 99, 1485),
+            (11, 396, 3000),
+            (12, 396, 6000),
+            (13, 396, 11880),
+            (20, 396, 11880),
+            (21, 792, 19800),
+            (22, 1620, 20250),
+            (30, 1620, 40500),
+            (31, 3600, 108000),
+            (32, 5120, 216000),
+            (40, 8192, 245760),
+            (41, 8192, 245760),
+            (42, 8704, 522240),
+            (50, 22080, 589824),
+            (51, 36864, 983040),
+            (52, 36864, 2073600),
+            (60, 139264, 4177920),
+            (61, 139264, 8355840),
+            (62, 139264, 16711680),
+        ];
+        let mbs = u64::from(w.div_ceil(16)) * u64::from(h.div_ceil(16));
+        let mbps = (mbs as f64 * fps.unwrap_or(30.0)) as u64;
+        let (level, ..) = LEVELS
+            .iter()
+            .find(|(_, fs, ps)| mbs <= *fs && mbps <= *ps)?;
+        // High, High 10 & High 4:4:4 Predictive profile_idc
+        let profile = match (yuv444, bit_depth) {
+            (true, _) => 244,
+            (false, 8) => 100,
+            (false, _) => 110,
+        };
+        return Some(format!("avc1.{profile:02x}00{level:02x}"));
+    }
+    if vcodec.contains("265") || vcodec.contains("hevc") {
+        // level_idc, max luma picture size & sample rate
+        const LEVELS: [(u8, u64, u64); 13] = [
+            (30, 36864, 552960),
+            (60, 122880, 3686400),
+            (63, 245760, 7372800),
+            (90, 552960, 16588800),
+            (93, 983040, 33177600),
+            (120, 2228224, 66846720),
+            (123, 2228224, 133693440),
+            (150, 8912896, 267386880),
+            (153, 8912896, 534773760),
+            (156, 8912896, 1069547520),
+            (180, 35651584, 1069547520),
+            (183, 35651584, 2139095040),
+            (186, 35651584, 4278190080),
+        ];
+        let (level, ..) = LEVELS
+            .iter()
+            .find(|(_, p, r)| fits((*p, *r, u32::MAX, u32::MAX)))?;
+        // Main, Main 10 & format range extensions profile with compatibility flags
+        let profile = match (yuv444, bit_depth) {
+            (true, _) => "4.10",
+            (false, 8) => "1.6",
+            (false, _) => "2.4",
+        };
+        return Some(format!("hvc1.{profile}.L{level}.90"));
+    }
+    None
+}
+
+#[test]
+fn rfc6381_codecs() {
+    use PixelFormat::*;
+    let fhd = (1920, 1080);
+    assert_eq!(
+        codecs("libsvtav1", Yuv420p10le, fhd, Some(24.0)).as_deref(),
+        Some("av01.0.08M.10")
+    );
+    assert_eq!(
+        codecs("libsvtav1", Yuv420p, (3840, 2160), Some(60.0)).as_deref(),
+        Some("av01.0.13M.08")
+    );
+    assert_eq!(
+        codecs("libaom-av1", Yuv444p10le, (1280, 720), Some(30.0)).as_deref(),
+        Some("av01.1.05M.10")
+    );
+    assert_eq!(
+        codecs("libx264", Yuv420p, fhd, Some(24.0)).as_deref(),
+        Some("avc1.640028")
+    );
+    assert_eq!(
+        codecs("libx264", Yuv420p, fhd, Some(60.0)).as_deref(),
+        Some("avc1.64002a")
+    );
+    assert_eq!(
+        codecs("libx265", Yuv420p10le, fhd, Some(24.0)).as_deref(),
+        Some("hvc1.2.4.L120.90")
+    );
+    assert_eq!(
+        codecs("libvpx-vp9", Yuv420p, fhd, Some(30.0)).as_deref(),
+        Some("vp09.00.40.08")
+    );
+    assert_eq!(codecs("mpeg4", Yuv420p, fhd, Some(30.0)), None);
+}
+
+#[test]
+fn rendition_json() {
+    let rendition = Rendition::new(
+        "libsvtav1",
+        PixelFormat::Yuv420p10le,
+        Some((1920, 1080)),
+        Some(24.0),
+        15_000_000,
+        Duration::from_secs(60),
+    );
+    assert_eq!(
+        rendition.to_json(),
+        serde_json::json!({
+            "codecs": "av01.0.08M.10",
+            "bandwidth": 2_000_000,
+            "resolution": "1920x1080",
+            "frame_rate": 24.0,
+        })
+    );
+}
@@ -0,0 +1,195 @@
+//! Self-contained html reports with interactive per-frame score graphs, see `--report`.
+use crate::plot;
+use anyhow::Context;
+use std::{fmt::Display, path::Path};
+
+const STYLE: &str = "body{background:#222;color:#ddd;font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse}td{padding:2px 12px 2px 0}td:first-child{color:#999}\
+canvas{background:#1a1a1a;width:100%;max-width:1200px}\
+#legend label{margin-right:1em}#tip{color:#999;height:1.2em}";
+
+const SCRIPT: &str = r##"
+const canvas = document.getElementById("chart");
+const ctx = canvas.getContext("2d");
+const tip = document.getElementById("tip");
+const shown = SERIES.map(() => true);
+const pad = 40;
+let hover = null;
+
+function bounds() {
+  let min = Infinity, max = -Infinity, frames = 1;
+  SERIES.forEach((s, i) => {
+    if (!shown[i]) return;
+    frames = Math.max(frames, s.scores.length);
+    for (const v of s.scores) { min = Math.min(min, v); max = Math.max(max, v); }
+  });
+  if (min > max) { min = 0; max = 1; }
+  if (min == max) { min -= 1; max += 1; }
+  return { min, max, frames };
+}
+
+function draw() {
+  const { min, max, frames } = bounds();
+  const w = canvas.width - pad * 2, h = canvas.height - pad * 2;
+  const x = f => pad + f / Math.max(frames - 1, 1) * w;
+  const y = v => pad + (1 - (v - min) / (max - min)) * h;
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  ctx.strokeStyle = "#444";
+  ctx.fillStyle = "#999";
+  ctx.font = "12px sans-serif";
+  for (let i = 0; i <= 4; i++) {
+    const v = min + (max - min) * i / 4;
+    ctx.beginPath(); ctx.moveTo(pad, y(v)); ctx.lineTo(pad + w, y(v)); ctx.stroke();
+    ctx.fillText(v.toFixed(2), 2, y(v) + 4);
+  }
+  SERIES.forEach((s, i) => {
+    if (!shown[i]) return;
+    ctx.strokeStyle = colors[i % colors.length];
+    ctx.beginPath();
+    s.scores.forEach((v, f) => f ? ctx.lineTo(x(f), y(v)) : ctx.moveTo(x(f), y(v)));
+    ctx.stroke();
+  });
+  if (hover != null) {
+    ctx.strokeStyle = "#888";
+    ctx.beginPath(); ctx.moveTo(x(hover), pad); ctx.lineTo(x(hover), pad + h); ctx.stroke();
+    tip.textContent = "frame " + hover + ": " + SERIES
+      .filter((s, i) => shown[i] && hover < s.scores.length)
+      .map(s => s.name + " " + s.scores[hover].toFixed(3)).join(", ");
+  }
+}
+
+canvas.addEventListener("mousemove", e => {
+  const { frames } = bounds();
+  const r = canvas.getBoundingClientRect();
+  const cx = (e.clientX - r.left) * canvas.width / r.width;
+  const f = Math.round((cx - pad) / (canvas.width - pad * 2) * (frames - 1));
+  hover = Math.min(Math.max(f, 0), frames - 1);
+  draw();
+});
+canvas.addEventListener("mouseleave", () => { hover = null; tip.textContent = ""; draw(); });
+
+const legend = document.getElementById("legend");
+SERIES.forEach((s, i) => {
+  const label = document.createElement("label");
+  const check = document.createElement("input");
+  check.type = "checkbox";
+  check.checked = true;
+  check.onchange = () => { shown[i] = check.checked; draw(); };
+  label.style.color = colors[i % colors.length];
+  label.append(check, s.name);
+  legend.append(label);
+});
+draw();
+"##;
+
+/// A report of summary stats, per-frame scores & the settings that produced them.
+#[derive(Debug, Default)]
+pub struct Report {
+    title: String,
+    summary: Vec<(String, String)>,
+    settings: Vec<(String, String)>,
+    series: Vec<(String, Vec<f32>)>,
+}
+
+impl Report {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..<_>::default()
+        }
+    }
+
+    /// Add a summary stat row, e.g. "vmaf", 95.1.
+    pub fn summary(&mut self, name: &str, value: impl Display) {
+        self.summary.push((name.into(), value.to_string()));
+    }
+
+    /// Add a settings row, e.g. "encoder", "libsvtav1".
+    pub fn setting(&mut self, name: &str, value: impl Display) {
+        self.settings.push((name.into(), value.to_string()));
+    }
+
+    /// Add per-frame scores to graph. Empty scores are ignored.
+    pub fn series(&mut self, name: &str, scores: Vec<f32>) {
+        if !scores.is_empty() {
+            self.series.push((name.into(), scores));
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        let title = escape(&self.title);
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+             <style>{STYLE}</style></head>\n<body><h1>{title}</h1>\n"
+        );
+        html += &table("Summary", &self.summary);
+        if self.series.is_empty() {
+            html += "<p>No per-frame scores available.</p>\n";
+        } else {
+            let series: Vec<_> = self
+                .series
+                .iter()
+                .map(|(name, scores)| serde_json::json!({ "name": name, "scores": scores }))
+                .collect();
+            // avoid "</script>" in data ending the script early
+            let series = serde_json::to_string(&series)
+                .unwrap_or_default()
+                .replace("</", "<\\/");
+            html += "<h2>Per-frame scores</h2><div id=\"legend\"></div>\
+                     <canvas id=\"chart\" width=\"1200\" height=\"400\"></canvas>\
+                     <div id=\"tip\"></div>\n";
+            let colors = serde_json::to_string(&plot::COLORS).unwrap_or_default();
+            html += &format!(
+                "<script>const SERIES = {series};\nconst colors = {colors};{SCRIPT}</script>\n"
+            );
+        }
+        html += &table("Settings", &self.settings);
+        html += "</body></html>\n";
+        html
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_html())
+            .with_context(|| format!("--report: write {}", path.display()))
+    }
+}
+
+fn table(heading: &str, rows: &[(String, String)]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let mut html = format!("<h2>{heading}</h2><table>\n");
+    for (name, value) in rows {
+        html += &format!(
+            "<tr><td>{}</td><td>{}</td></tr>\n",
+            escape(name),
+            escape(value)
+        );
+    }
+    html += "</table>\n";
+    html
+}
+
+/// Escape text for html & svg.
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn report_html() {
+    let mut report = Report::new("vmaf <vid.mkv>");
+    report.summary("vmaf", 95.5);
+    report.setting("vfilter", "scale=1280:-1");
+    report.series("vmaf", vec![95.0, 96.0]);
+    report.series("empty", vec![]);
+
+    let html = report.to_html();
+    assert!(html.contains("<title>vmaf &lt;vid.mkv&gt;</title>"));
+    assert!(html.contains("<tr><td>vmaf</td><td>95.5</td></tr>"));
+    assert!(html.contains("<tr><td>vfilter</td><td>scale=1280:-1</td></tr>"));
+    assert!(html.contains(r#"const SERIES = [{"name":"vmaf","scores":[95.0,96.0]}];"#));
+    assert!(!html.contains("empty"));
+}
@@ -1,7 +1,11 @@
 //! ffmpeg logic
 use crate::{
-    process::{ensure_success, CommandExt},
+    ffprobe::{self, Chapter},
+    naming,
+    process::{ensure_success, CommandExt, FfmpegOut, ProcessRunner, SystemRunner},
     temporary::{self, TempKind},
+    timings::{self, Phase},
+    SAMPLE_SIZE,
 };
 use anyhow::Context;
 use std::{
@@ -14,21 +18,32 @@ use tokio::process::Command;
 /// Create a 20s sample from `sample_start`.
 ///
 /// Fast as this uses `-c:v copy`.
+///
+/// If `dynamic_hdr` Dolby Vision/HDR10+ side data is preserved so samples match the input.
+///
+/// `pad_frames` extra frames are copied before the sample start, these should be trimmed
+/// after decoding. Used for open-GOP inputs where leading frames may be broken.
 pub async fn copy(
     input: &Path,
     sample_start: Duration,
     frames: u32,
+    pad_frames: u32,
+    fps: f64,
     temp_dir: Option<PathBuf>,
+    dynamic_hdr: bool,
 ) -> anyhow::Result<PathBuf> {
+    let _timer = timings::start(Phase::SampleExtract);
     let mut dest = temporary::process_dir(temp_dir);
     // Always using mkv for the samples works better than, e.g. using mp4 for mp4s
     // see https://github.com/alexheretic/ab-av1/issues/82#issuecomment-1337306325
-    dest.push(
-        input
-            .with_extension(format!("sample{}+{frames}f.mkv", sample_start.as_secs()))
-            .file_name()
-            .unwrap(),
-    );
+    let name = match pad_frames {
+        0 => format!("sample{}+{frames}f.mkv", sample_start.as_secs()),
+        pad => format!("sample{}+{frames}f.pad{pad}.mkv", sample_start.as_secs()),
+    };
+    dest.push(naming::temp_file_name(input, &name));
+    let sample_start =
+        sample_start.saturating_sub(Duration::from_secs_f64(pad_frames as f64 / fps));
+    let frames = frames + pad_frames;
     if dest.exists() {
         return Ok(dest);
     }
@@ -38,10 +53,11 @@ pub async fn copy(
     // See https://github.com/alexheretic/ab-av1/issues/36#issuecomment-1146634936
     let mut out = Command::new("ffmpeg")
         .arg("-y")
-        .arg2("-ss", sample_start.as_secs().to_string())
+        .arg2("-ss", sample_start.as_secs_f64().to_string())
         .arg2("-i", input)
         .arg2("-frames:v", frames)
         .arg2("-c:v", "copy")
+        .args(hdr_args(dynamic_hdr))
         .arg("-an")
         .arg("-sn")
         .arg(&dest)
@@ -58,10 +74,11 @@ pub async fn copy(
             .arg("-y")
             // try +genpts workaround
             .arg2("-fflags", "+genpts")
-            .arg2("-ss", sample_start.as_secs().to_string())
+            .arg2("-ss", sample_start.as_secs_f64().to_string())
             .arg2("-i", input)
             .arg2("-frames:v", frames)
             .arg2("-c:v", "copy")
+            .args(hdr_args(dynamic_hdr))
             .arg("-an")
             .arg("-sn")
             .arg(&dest)
@@ -71,6 +88,616 @@ pub async fn copy(
             .context("ffmpeg copy")?;
     }
 
-    ensure_success("ffmpeg copy", &out)?;
+    if let Err(err) = ensure_success("ffmpeg copy", &out) {
+        // don't reuse a partial sample on retry
+        _ = tokio::fs::remove_file(&dest).await;
+        return Err(err);
+    }
     Ok(dest)
 }
+
+/// Remux the input video stream to a temporary mkv, preserving timestamps.
+///
+/// Inputs with poor or broken indexes (e.g. avi, some mpeg-ts) seek inaccurately,
+/// the mkv cues of the remux allow accurate sample seeking.
+pub async fn reindex(
+    input: &Path,
+    temp_dir: Option<PathBuf>,
+    dynamic_hdr: bool,
+) -> anyhow::Result<PathBuf> {
+    let _timer = timings::start(Phase::SampleExtract);
+    let mut dest = temporary::process_dir(temp_dir);
+    dest.push(naming::temp_file_name(input, "reindex.mkv"));
+    if dest.exists() {
+        return Ok(dest);
+    }
+    temporary::add(&dest, TempKind::Keepable);
+
+    let out = Command::new("ffmpeg")
+        .arg("-y")
+        .arg2("-fflags", "+genpts")
+        .arg2("-i", input)
+        .arg("-copyts")
+        .arg2("-map", "0:v:0")
+        .arg2("-c:v", "copy")
+        .args(hdr_args(dynamic_hdr))
+        .arg(&dest)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg reindex")?;
+
+    if let Err(err) = ensure_success("ffmpeg reindex", &out) {
+        _ = tokio::fs::remove_file(&dest).await;
+        return Err(err);
+    }
+    Ok(dest)
+}
+
+/// Returns a vfilter trimming `pad_frames` copied by [`copy`], followed by `vfilter`.
+pub fn trim_vfilter(pad_frames: u32, vfilter: Option<&str>) -> String {
+    match vfilter {
+        Some(vf) => format!("trim=start_frame={pad_frames},setpts=PTS-STARTPTS,{vf}"),
+        None => format!("trim=start_frame={pad_frames},setpts=PTS-STARTPTS"),
+    }
+}
+
+/// Copy a single chapter of the input, including video & audio streams.
+///
+/// Fast as this uses `-c copy`, so the start will snap to the nearest preceding keyframe.
+///
+/// Subtitles are also copied if `subtitles_for` an output in the same container family
+/// as the input, as e.g. mov_text cannot be muxed into mkv & image subtitles into mp4.
+pub async fn copy_chapter(
+    input: &Path,
+    chapter_n: u32,
+    chapter: Chapter,
+    temp_dir: Option<PathBuf>,
+    dynamic_hdr: bool,
+    subtitles_for: Option<&Path>,
+) -> anyhow::Result<PathBuf> {
+    let _timer = timings::start(Phase::SampleExtract);
+    let family = container_family(input);
+    let subtitles =
+        family.is_some() && subtitles_for.is_some_and(|out| container_family(out) == family);
+    let ext = family.unwrap_or("mkv");
+    let mut dest = temporary::process_dir(temp_dir);
+    dest.push(naming::temp_file_name(
+        input,
+        &format!(
+            "chapter{chapter_n}{}.{ext}",
+            if subtitles { ".subs" } else { "" }
+        ),
+    ));
+    if dest.exists() {
+        return Ok(dest);
+    }
+    temporary::add(&dest, TempKind::Keepable);
+
+    let out = Command::new("ffmpeg")
+        .arg("-y")
+        .arg2("-ss", chapter.start.as_secs_f64().to_string())
+        .arg2("-i", input)
+        .arg2(
+            "-t",
+            (chapter.end.saturating_sub(chapter.start))
+                .as_secs_f64()
+                .to_string(),
+        )
+        .arg2("-map", "0:v:0")
+        .arg2("-map", "0:a?")
+        .arg2_if(subtitles, "-map", "0:s?")
+        .arg2("-c", "copy")
+        .arg2("-map_chapters", "-1")
+        .args(hdr_args(dynamic_hdr))
+        .arg(&dest)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg copy chapter")?;
+
+    ensure_success("ffmpeg copy chapter", &out)?;
+    Ok(dest)
+}
+
+/// A scene chunk of the input video stream, see [`split_scenes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneChunk {
+    pub path: PathBuf,
+    /// Start time in the input, seconds.
+    pub start: f64,
+    /// End time in the input, seconds.
+    pub end: f64,
+}
+
+/// Split the input video stream into chunks starting at each of the `cuts` times (seconds).
+///
+/// Fast & lossless as this uses `-c copy`, so cuts snap to the next keyframe.
+///
+/// Also returns the total frames copied, i.e. the input video frame count, if reported.
+pub async fn split_scenes(
+    input: &Path,
+    cuts: &[f64],
+    temp_dir: Option<PathBuf>,
+    dynamic_hdr: bool,
+) -> anyhow::Result<(Vec<SceneChunk>, Option<u64>)> {
+    let _timer = timings::start(Phase::SampleExtract);
+    let dir = temporary::process_dir(temp_dir);
+    let list = dir.join(naming::temp_file_name(input, "scenes.csv"));
+    temporary::add(&list, TempKind::NotKeepable);
+    let pattern = dir.join(naming::temp_file_name(input, "scene%03d.mkv"));
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-y")
+        .arg2("-i", input)
+        .arg2("-map", "0:v:0")
+        .arg2("-c", "copy")
+        .args(hdr_args(dynamic_hdr))
+        .arg2("-f", "segment")
+        .arg2("-reset_timestamps", "1")
+        .arg2("-segment_list", &list)
+        .arg2("-segment_list_type", "csv");
+    if !cuts.is_empty() {
+        let times: Vec<_> = cuts.iter().map(|t| format!("{t:.3}")).collect();
+        cmd.arg2("-segment_times", times.join(","));
+    }
+    let out = cmd
+        .arg(&pattern)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("ffmpeg split scenes")?;
+
+    let list_csv = tokio::fs::read_to_string(&list).await.unwrap_or_default();
+    let chunks = parse_segment_list(&list_csv, &dir);
+    for chunk in &chunks {
+        temporary::add(&chunk.path, TempKind::Keepable);
+    }
+    ensure_success("ffmpeg split scenes", &out)?;
+    anyhow::ensure!(!chunks.is_empty(), "ffmpeg split scenes: no chunks written");
+    Ok((chunks, copied_frames(&out.stderr)))
+}
+
+/// The final ffmpeg progress frame count in `stderr`.
+fn copied_frames(stderr: &[u8]) -> Option<u64> {
+    String::from_utf8_lossy(stderr)
+        .rsplit(['\r', '\n'])
+        .find_map(|line| match FfmpegOut::try_parse(line.trim())? {
+            FfmpegOut::Progress { frame, .. } => Some(frame),
+            _ => None,
+        })
+}
+
+/// Parse the segment muxer csv list, "name,start,end" lines.
+fn parse_segment_list(csv: &str, dir: &Path) -> Vec<SceneChunk> {
+    csv.lines()
+        .filter_map(|line| {
+            let mut cols = line.trim().rsplitn(3, ',');
+            let end = cols.next()?.parse().ok()?;
+            let start = cols.next()?.parse().ok()?;
+            let name = cols.next()?.trim_matches('"');
+            Some(SceneChunk {
+                path: dir.join(name),
+                start,
+                end,
+            })
+        })
+        .collect()
+}
+
+/// Leading seconds scanned for studio logos.
+const LOGO_SCAN_S: f64 = 120.0;
+/// Trailing seconds scanned for credits.
+const CREDITS_SCAN_S: f64 = 600.0;
+
+/// Returns the input time range excluding detected leading studio logos & trailing
+/// credits, or `None` if neither were detected.
+///
+/// Logos & credits are detected as mostly black or frozen (low-motion) frames in the
+/// first 2 & last 10 minutes. Sampling them skews quality & size results relative
+/// to the main body of a film.
+pub fn body_range(input: &Path, duration: Duration, cache: bool) -> Option<(Duration, Duration)> {
+    let duration = duration.as_secs_f64();
+    // shorter inputs, e.g. clips & episodes sans credits, are sampled as is
+    if duration < 2.0 * (LOGO_SCAN_S + CREDITS_SCAN_S) {
+        return None;
+    }
+    let detect = || detect_credit_like(&SystemRunner, input, duration);
+    let intervals = match cache {
+        true => ffprobe::cache::get_or_insert_with(input, "credit-like", detect),
+        false => detect(),
+    }?;
+    let (start, end) = body_from_intervals(intervals, duration)?;
+    Some((Duration::from_secs_f64(start), Duration::from_secs_f64(end)))
+}
+
+/// Detect black or frozen frame intervals (seconds) in the leading & trailing scan windows.
+fn detect_credit_like(
+    runner: &impl ProcessRunner,
+    input: &Path,
+    duration: f64,
+) -> Option<Vec<(f64, f64)>> {
+    let _timer = timings::start(Phase::Probe);
+    let detect = |seek: &[&str], offset: f64, window: f64| {
+        let out = runner
+            .output(
+                std::process::Command::new("ffmpeg")
+                    .arg("-hide_banner")
+                    .args(seek)
+                    .arg("-i")
+                    .arg(input)
+                    .args(["-map", "0:v:0", "-f", "null"])
+                    .args([
+                        "-vf",
+                        "blackdetect=d=1:pic_th=0.85,freezedetect=n=0.003:d=2",
+                    ])
+                    .arg("-")
+                    .stdin(Stdio::null()),
+            )
+            .ok()
+            .filter(|out| out.status.success())?;
+        let intervals = parse_credit_like(&String::from_utf8_lossy(&out.stderr), window);
+        Some(
+            intervals
+                .into_iter()
+                .map(move |(s, e)| (s + offset, e + offset)),
+        )
+    };
+    let head = detect(&["-t", &LOGO_SCAN_S.to_string()], 0.0, LOGO_SCAN_S)?;
+    let tail = detect(
+        &["-sseof", &format!("-{CREDITS_SCAN_S}")],
+        duration - CREDITS_SCAN_S,
+        CREDITS_SCAN_S,
+    )?;
+    Some(head.chain(tail).collect())
+}
+
+/// Parse blackdetect & freezedetect intervals from ffmpeg stderr.
+/// A freeze lasting until the end of the `window` has no logged end.
+fn parse_credit_like(stderr: &str, window: f64) -> Vec<(f64, f64)> {
+    let value = |line: &str, key: &str| -> Option<f64> {
+        let idx = line.find(key)?;
+        line[idx + key.len()..]
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    let mut intervals = vec![];
+    let mut freeze_start = None;
+    for line in stderr.lines() {
+        if line.contains("[blackdetect") {
+            if let (Some(start), Some(end)) =
+                (value(line, "black_start:"), value(line, "black_end:"))
+            {
+                intervals.push((start, end));
+            }
+        } else if line.contains("[freezedetect") {
+            if let Some(start) = value(line, "freeze_start:") {
+                freeze_start = Some(start);
+            } else if let Some(end) = value(line, "freeze_end:") {
+                if let Some(start) = freeze_start.take() {
+                    intervals.push((start, end));
+                }
+            }
+        }
+    }
+    if let Some(start) = freeze_start {
+        intervals.push((start, window));
+    }
+    intervals
+}
+
+/// Calculate the (start, end) body range from black/frozen `intervals`.
+///
+/// * Start after the last interval beginning in the logo scan window.
+/// * End at the earliest interval in the credits scan window from which at least
+///   60% of the remaining duration is black/frozen.
+fn body_from_intervals(mut intervals: Vec<(f64, f64)>, duration: f64) -> Option<(f64, f64)> {
+    const MIN_CREDITS_S: f64 = 30.0;
+
+    intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // merge overlapping black & frozen intervals
+    let mut merged: Vec<(f64, f64)> = vec![];
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let start = merged
+        .iter()
+        .filter(|(s, _)| *s < LOGO_SCAN_S)
+        .map(|(_, e)| *e)
+        .fold(0.0, f64::max);
+    let end = merged
+        .iter()
+        .map(|(s, _)| *s)
+        .filter(|s| *s >= duration - CREDITS_SCAN_S && duration - s >= MIN_CREDITS_S)
+        .find(|&from| {
+            let covered: f64 = merged
+                .iter()
+                .map(|(s, e)| (e.min(duration) - s.max(from)).max(0.0))
+                .sum();
+            covered / (duration - from) >= 0.6
+        })
+        .unwrap_or(duration);
+
+    // ignore unlikely detections removing most of the input
+    (end - start >= duration / 2.0 && (start > 0.0 || end < duration)).then_some((start, end))
+}
+
+/// Args to preserve dynamic HDR side data when stream copying.
+/// Container family, by extension, of containers able to stream copy each others
+/// subtitles. `None` if unknown.
+fn container_family(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "mkv" => Some("mkv"),
+        "mp4" | "m4v" | "mov" => Some("mp4"),
+        _ => None,
+    }
+}
+
+fn hdr_args(dynamic_hdr: bool) -> &'static [&'static str] {
+    match dynamic_hdr {
+        // Dolby Vision configuration records are only muxed with "unofficial" compliance.
+        // HDR10+ & DV RPU data is carried in the bitstream so survives the copy.
+        true => &["-map_metadata", "0", "-strict", "unofficial"],
+        false => &[],
+    }
+}
+
+/// Samples scanned for telecine.
+const TELECINE_SAMPLES: u64 = 3;
+
+/// Returns whether the input is likely telecined (3:2 pulldown), e.g. film DVDs
+/// stored as 29.97fps interlaced video with 2 in 5 frames combed.
+///
+/// Detected by the ffmpeg idet filter over a few samples. Results are cached.
+pub fn telecined(input: &Path, duration: Duration) -> bool {
+    let detect = || {
+        let _timer = timings::start(Phase::Probe);
+        ranges(duration, TELECINE_SAMPLES)
+            .into_iter()
+            .try_fold(Idet::default(), |mut idet, range| {
+                idet.add(detect_idet(&SystemRunner, input, range)?);
+                Some(idet)
+            })
+            .map(|idet| idet.telecined())
+    };
+    ffprobe::cache::get_or_insert_with(input, "telecine", detect).unwrap_or(false)
+}
+
+fn detect_idet(
+    runner: &impl ProcessRunner,
+    input: &Path,
+    (offset, duration): (Duration, Duration),
+) -> Option<Idet> {
+    let out = runner
+        .output(
+            std::process::Command::new("ffmpeg")
+                .arg("-hide_banner")
+                .args(["-ss", &offset.as_secs_f32().to_string()])
+                .args(["-t", &duration.as_secs_f32().to_string()])
+                .arg("-i")
+                .arg(input)
+                .args(["-map", "0:v:0", "-vf", "idet", "-f", "null", "-"])
+                .stdin(Stdio::null()),
+        )
+        .ok()
+        .filter(|out| out.status.success())?;
+    Idet::parse(&String::from_utf8_lossy(&out.stderr))
+}
+
+/// ffmpeg idet filter frame counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Idet {
+    /// Multi frame detection interlaced (tff + bff) & progressive frames.
+    interlaced: u64,
+    progressive: u64,
+    /// Frames with a top or bottom field repeated from the previous frame & all frames.
+    repeated: u64,
+    frames: u64,
+}
+
+impl Idet {
+    /// Parse the idet summary lines from ffmpeg stderr.
+    fn parse(stderr: &str) -> Option<Self> {
+        let count = |line: &str, key: &str| -> Option<u64> {
+            let idx = line.find(key)?;
+            line[idx + key.len()..]
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+        };
+        let multi = stderr
+            .lines()
+            .find(|l| l.contains("Multi frame detection:"))?;
+        let repeated = stderr.lines().find(|l| l.contains("Repeated Fields:"))?;
+        let (top, bottom) = (count(repeated, "Top:")?, count(repeated, "Bottom:")?);
+        Some(Self {
+            interlaced: count(multi, "TFF:")? + count(multi, "BFF:")?,
+            progressive: count(multi, "Progressive:")?,
+            repeated: top + bottom,
+            frames: count(repeated, "Neither:")? + top + bottom,
+        })
+    }
+
+    fn add(&mut self, other: Self) {
+        self.interlaced += other.interlaced;
+        self.progressive += other.progressive;
+        self.repeated += other.repeated;
+        self.frames += other.frames;
+    }
+
+    /// Pulldown repeats 2 fields every 5 frames & combs 2 of 5 frames, unlike
+    /// progressive (neither) or true interlaced content (no repeats, all combed).
+    fn telecined(self) -> bool {
+        let ratio = |n: u64, total: u64| match total {
+            0 => 0.0,
+            _ => n as f64 / total as f64,
+        };
+        let combed = ratio(self.interlaced, self.interlaced + self.progressive);
+        ratio(self.repeated, self.frames) >= 0.15 || (0.25..=0.55).contains(&combed)
+    }
+}
+
+/// Evenly spaced `(offset, duration)` sample ranges, or the whole input if too short.
+pub fn ranges(duration: Duration, samples: u64) -> Vec<(Duration, Duration)> {
+    let sampled = SAMPLE_SIZE * samples as u32;
+    if sampled >= duration {
+        return vec![(Duration::ZERO, duration)];
+    }
+    let gap = (duration - sampled) / (samples as u32 + 1);
+    (0..samples as u32)
+        .map(|idx| (gap * (idx + 1) + SAMPLE_SIZE * idx, SAMPLE_SIZE))
+        .collect()
+}
+
+#[test]
+fn segment_list_csv() {
+    let csv = "vid.scene000.mkv,0.000000,10.760000\nvid.scene001.mkv,10.760000,25.025000\n";
+    let dir = Path::new("/tmp/ab-av1");
+    assert_eq!(
+        parse_segment_list(csv, dir),
+        [
+            SceneChunk {
+                path: dir.join("vid.scene000.mkv"),
+                start: 0.0,
+                end: 10.76,
+            },
+            SceneChunk {
+                path: dir.join("vid.scene001.mkv"),
+                start: 10.76,
+                end: 25.025,
+            },
+        ]
+    );
+}
+
+#[test]
+fn split_copied_frames() {
+    let stderr = b"[segment @ 0x55] Opening 'vid.scene000.mkv' for writing\n\
+        frame=  240 fps=0.0 q=-1.0 size=N/A time=00:00:10.00 bitrate=N/A speed= 20x\r\
+        frame= 1438 fps=0.0 q=-1.0 Lsize=N/A time=00:00:59.95 bitrate=N/A speed=  25x\n\
+        video:51234kB audio:0kB subtitle:0kB other streams:0kB global headers:0kB muxing overhead: unknown\n";
+    assert_eq!(copied_frames(stderr), Some(1438));
+    assert_eq!(copied_frames(b"error"), None);
+}
+
+#[test]
+fn credit_like_stderr() {
+    let stderr = "\
+[blackdetect @ 0x5581] black_start:0 black_end:1.5 black_duration:1.5
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_start: 2.5
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_duration: 4
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_end: 6.5
+frame= 2880 fps=480 q=-0.0 size=N/A time=00:02:00.00 bitrate=N/A speed=  20x
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_start: 110
+";
+    assert_eq!(
+        parse_credit_like(stderr, 120.0),
+        [(0.0, 1.5), (2.5, 6.5), (110.0, 120.0)]
+    );
+}
+
+#[test]
+fn body_excludes_logos_credits() {
+    let duration = 6000.0;
+    let intervals = vec![
+        (0.0, 1.5),
+        (1.0, 8.0),
+        // dark scene after the logo scan window is not excluded
+        (150.0, 160.0),
+        // brief black before the credits
+        (5500.0, 5502.0),
+        (5700.0, 5900.0),
+        (5890.0, 5990.0),
+    ];
+    assert_eq!(
+        body_from_intervals(intervals, duration),
+        Some((8.0, 5700.0))
+    );
+    assert_eq!(body_from_intervals(vec![(5500.0, 5502.0)], duration), None);
+    assert_eq!(body_from_intervals(vec![], duration), None);
+}
+
+#[test]
+fn detect_credits_canned_output() {
+    use crate::process::CannedRunner;
+
+    let runner = CannedRunner::default()
+        .with(
+            "ffmpeg",
+            "",
+            "[blackdetect @ 0x1] black_start:0 black_end:6.5 black_duration:6.5\n",
+        )
+        .with(
+            "ffmpeg",
+            "",
+            "[freezedetect @ 0x2] lavfi.freezedetect.freeze_start: 400\n",
+        );
+    let intervals = detect_credit_like(&runner, Path::new("film.mkv"), 6000.0).unwrap();
+    // tail intervals are offset to input time
+    assert_eq!(intervals, [(0.0, 6.5), (5800.0, 6000.0)]);
+    assert_eq!(body_from_intervals(intervals, 6000.0), Some((6.5, 5800.0)));
+    let runs = runner.runs.borrow();
+    assert_eq!(runs[0][1..4], ["-hide_banner", "-t", "120"]);
+    assert_eq!(runs[1][1..4], ["-hide_banner", "-sseof", "-600"]);
+}
+
+#[test]
+fn sample_ranges() {
+    let secs = Duration::from_secs;
+    assert_eq!(
+        ranges(secs(100), 2),
+        [(secs(20), SAMPLE_SIZE), (secs(60), SAMPLE_SIZE)]
+    );
+    assert_eq!(ranges(secs(30), 2), [(Duration::ZERO, secs(30))]);
+}
+
+#[test]
+fn idet_telecine() {
+    let telecine = "\
+[Parsed_idet_0 @ 0x1] Repeated Fields: Neither:   359 Top:   120 Bottom:   121
+[Parsed_idet_0 @ 0x1] Single frame detection: TFF:   150 BFF:     0 Progressive:   390 Undetermined:    60
+[Parsed_idet_0 @ 0x1] Multi frame detection: TFF:   238 BFF:     0 Progressive:   360 Undetermined:     2
+";
+    let idet = Idet::parse(telecine).unwrap();
+    assert_eq!(
+        idet,
+        Idet {
+            interlaced: 238,
+            progressive: 360,
+            repeated: 241,
+            frames: 600,
+        }
+    );
+    assert!(idet.telecined());
+
+    let interlaced = "\
+[Parsed_idet_0 @ 0x1] Repeated Fields: Neither:   598 Top:     1 Bottom:     1
+[Parsed_idet_0 @ 0x1] Multi frame detection: TFF:   590 BFF:     0 Progressive:     8 Undetermined:     2
+";
+    assert!(!Idet::parse(interlaced).unwrap().telecined());
+
+    let progressive = "\
+[Parsed_idet_0 @ 0x1] Repeated Fields: Neither:   600 Top:     0 Bottom:     0
+[Parsed_idet_0 @ 0x1] Multi frame detection: TFF:     3 BFF:     0 Progressive:   597 Undetermined:     0
+";
+    assert!(!Idet::parse(progressive).unwrap().telecined());
+    assert_eq!(Idet::parse(""), None);
+}
+
+#[test]
+fn subtitle_container_family() {
+    let family = |p: &str| container_family(Path::new(p));
+    assert_eq!(family("in.mkv"), Some("mkv"));
+    assert_eq!(family("in.MP4"), Some("mp4"));
+    assert_eq!(family("out.mov"), family("in.m4v"));
+    assert_ne!(family("in.mkv"), family("out.mp4"));
+    assert_eq!(family("in.ts"), None);
+}
@@ -0,0 +1,142 @@
+//! ssim logic
+#[cfg(feature = "native-ssim")]
+pub mod native;
+
+use crate::{
+    command::args::PixelFormat,
+    process::{exit_ok_stderr, Chunks, CommandExt, FfmpegOut},
+};
+use anyhow::Context;
+use std::path::Path;
+use tokio::process::Command;
+use tokio_process_stream::{Item, ProcessChunkStream};
+use tokio_stream::{Stream, StreamExt};
+
+/// Calculate the SSIM "All" score of distorted vs reference.
+///
+/// `fps`, typically the reference frame rate, is forced on both inputs so frames
+/// are compared in lockstep. If `None` input timestamps are used.
+pub fn run(
+    reference: &Path,
+    distorted: &Path,
+    filter_complex: &str,
+    fps: Option<f64>,
+) -> anyhow::Result<impl Stream<Item = SsimOut>> {
+    let ssim: ProcessChunkStream = command(reference, distorted, filter_complex, fps)
+        .try_into()
+        .context("ffmpeg ssim")?;
+
+    let mut chunks = Chunks::default();
+    let ssim = ssim.filter_map(move |item| match item {
+        Item::Stderr(chunk) => SsimOut::try_from_chunk(&chunk, &mut chunks),
+        Item::Stdout(_) => None,
+        Item::Done(code) => SsimOut::ignore_ok(exit_ok_stderr("ffmpeg ssim", code, &chunks)),
+    });
+
+    Ok(ssim)
+}
+
+fn command(reference: &Path, distorted: &Path, filter_complex: &str, fps: Option<f64>) -> Command {
+    let fps = fps
+        .filter(|f| f.is_finite() && *f > 0.0)
+        .map(|f| f.to_string());
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .arg2_opt("-r", fps.as_ref())
+        .arg2("-i", distorted)
+        .arg2_opt("-r", fps.as_ref())
+        .arg2("-i", reference)
+        .arg2("-filter_complex", filter_complex)
+        .arg2("-f", "null")
+        .arg("-");
+    cmd
+}
+
+/// Returns true if the error indicates ffmpeg was built without the ssim filter.
+#[cfg(feature = "native-ssim")]
+pub fn is_filter_missing_error(err: &anyhow::Error) -> bool {
+    format!("{err:#}").contains("No such filter: 'ssim'")
+}
+
+/// Returns ffmpeg `filter_complex`/`lavfi` value for calculating ssim.
+///
+/// The distorted stream is scaled to the reference resolution, if different, using
+/// the reference input of `scale` (ffmpeg >= 7.1).
+pub fn ffmpeg_lavfi(pix_fmt: PixelFormat, ref_vfilter: Option<&str>) -> String {
+    let ref_vf = match ref_vfilter {
+        None => String::new(),
+        Some(vf) if vf.ends_with(',') => vf.into(),
+        Some(vf) => format!("{vf},"),
+    };
+    format!(
+        "[0:v]format={pix_fmt},setpts=PTS-STARTPTS[dis];\
+         [1:v]format={pix_fmt},{ref_vf}setpts=PTS-STARTPTS,split[ref][sref];\
+         [dis][ref]scale=rw:rh:flags=bicubic[sdis];[sdis][sref]ssim"
+    )
+}
+
+#[derive(Debug)]
+pub enum SsimOut {
+    Progress(FfmpegOut),
+    Done(f32),
+    Err(anyhow::Error),
+}
+
+impl SsimOut {
+    fn ignore_ok<T>(result: anyhow::Result<T>) -> Option<Self> {
+        match result {
+            Ok(_) => None,
+            Err(err) => Some(Self::Err(err)),
+        }
+    }
+
+    fn try_from_chunk(chunk: &[u8], chunks: &mut Chunks) -> Option<Self> {
+        chunks.push(chunk);
+        let line = chunks.last_line();
+
+        if let Some(score) = parse_ssim_all(line) {
+            return Some(Self::Done(score));
+        }
+        if let Some(progress) = FfmpegOut::try_parse(line) {
+            return Some(Self::Progress(progress));
+        }
+        None
+    }
+}
+
+/// Parse the "All" score from ffmpeg ssim filter output.
+fn parse_ssim_all(line: &str) -> Option<f32> {
+    let idx = line.find("SSIM ")?;
+    let all = line[idx..]
+        .split_whitespace()
+        .find_map(|w| w.strip_prefix("All:"))?;
+    all.parse().ok()
+}
+
+#[test]
+fn parse_ssim_all_line() {
+    let line = "[Parsed_ssim_5 @ 0x55d1] SSIM Y:0.990360 (20.159) U:0.995 (23.1) V:0.996 (24.0) All:0.992307 (21.139)";
+    assert_eq!(parse_ssim_all(line), Some(0.992307));
+    assert_eq!(parse_ssim_all("frame=  100 fps=50"), None);
+}
+
+#[test]
+fn ssim_command_rate() {
+    let args = |fps| {
+        let cmd = command(Path::new("ref.mkv"), Path::new("dis.mkv"), "ssim", fps);
+        cmd.as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(
+        args(Some(59.94)),
+        "-r 59.94 -i dis.mkv -r 59.94 -i ref.mkv -filter_complex ssim -f null -"
+    );
+    assert_eq!(
+        args(None),
+        "-i dis.mkv -i ref.mkv -filter_complex ssim -f null -"
+    );
+    assert_eq!(args(Some(0.0)), args(None));
+}
@@ -0,0 +1,226 @@
+//! In-process SSIM calculation for ffmpeg builds without the ssim filter.
+//!
+//! Frames are decoded to yuv420p yuv4mpeg via ffmpeg pipes & compared
+//! using the same 8x8 window (stride 4) approach as the ffmpeg ssim filter.
+use anyhow::{ensure, Context};
+use std::{
+    io::{BufRead, BufReader, ErrorKind},
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+};
+
+const C1: f64 = 0.01 * 0.01 * 255.0 * 255.0 * 64.0;
+const C2: f64 = 0.03 * 0.03 * 255.0 * 255.0 * 64.0 * 63.0;
+
+/// Calculate the mean SSIM "All" score of distorted vs reference.
+///
+/// The distorted is scaled to the reference resolution, after `ref_vfilter`,
+/// the same as [`super::ffmpeg_lavfi`].
+pub async fn ssim_all(
+    reference: &Path,
+    distorted: &Path,
+    ref_vfilter: Option<&str>,
+) -> anyhow::Result<f32> {
+    let reference = reference.to_owned();
+    let distorted = distorted.to_owned();
+    let ref_vfilter = ref_vfilter.map(|vf| vf.trim_end_matches(',').to_owned());
+    tokio::task::spawn_blocking(move || run_blocking(&reference, &distorted, ref_vfilter)).await?
+}
+
+fn run_blocking(
+    reference: &Path,
+    distorted: &Path,
+    ref_vfilter: Option<String>,
+) -> anyhow::Result<f32> {
+    let mut ref_dec = decode_y4m(reference, ref_vfilter.as_deref().unwrap_or("null"))?;
+    let mut ref_out = BufReader::new(ref_dec.stdout.take().unwrap());
+    let (width, height) = read_line(&mut ref_out)?
+        .as_deref()
+        .and_then(parse_y4m_resolution)
+        .context("native ssim: unknown reference resolution")?;
+
+    let scale = format!("scale={width}:{height}:flags=bicubic");
+    let mut dis_dec = decode_y4m(distorted, &scale)?;
+    let mut dis_out = BufReader::new(dis_dec.stdout.take().unwrap());
+    ensure!(
+        read_line(&mut dis_out)?.is_some(),
+        "native ssim: no distorted frames decoded"
+    );
+
+    let (w, h) = (width as usize, height as usize);
+    let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+    let frame_len = w * h + 2 * cw * ch;
+    let mut ref_frame = vec![0; frame_len];
+    let mut dis_frame = vec![0; frame_len];
+
+    let (mut sum, mut frames) = (0.0, 0_u64);
+    loop {
+        let ref_read = read_frame(&mut ref_out, &mut ref_frame)?;
+        let dis_read = read_frame(&mut dis_out, &mut dis_frame)?;
+        if !ref_read || !dis_read {
+            break;
+        }
+        sum += frame_ssim_all(&ref_frame, &dis_frame, w, h);
+        frames += 1;
+    }
+    _ = ref_dec.kill();
+    _ = dis_dec.kill();
+    _ = ref_dec.wait();
+    _ = dis_dec.wait();
+
+    ensure!(frames > 0, "native ssim: no frames decoded");
+    Ok((sum / frames as f64) as f32)
+}
+
+/// Spawn ffmpeg decoding `input` with `vfilter` to yuv420p yuv4mpeg on stdout.
+fn decode_y4m(input: &Path, vfilter: &str) -> anyhow::Result<Child> {
+    Command::new("ffmpeg")
+        .arg("-nostdin")
+        .args(["-loglevel", "error"])
+        .arg("-i")
+        .arg(input)
+        .args(["-map", "0:v:0", "-vf", vfilter])
+        .args(["-pix_fmt", "yuv420p", "-f", "yuv4mpegpipe", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("ffmpeg yuv4mpeg decode")
+}
+
+/// Parse the width & height from a yuv4mpeg stream header,
+/// e.g. "YUV4MPEG2 W1920 H1080 F24:1 Ip A1:1 C420jpeg".
+fn parse_y4m_resolution(header: &[u8]) -> Option<(u32, u32)> {
+    let header = std::str::from_utf8(header).ok()?;
+    let mut params = header.strip_prefix("YUV4MPEG2 ")?.split_whitespace();
+    let w = params
+        .clone()
+        .find_map(|p| p.strip_prefix('W'))?
+        .parse()
+        .ok()?;
+    let h = params.find_map(|p| p.strip_prefix('H'))?.parse().ok()?;
+    Some((w, h))
+}
+
+/// Read a header line without the trailing newline, `None` at the end of the stream.
+fn read_line(out: &mut impl BufRead) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut line = vec![];
+    out.read_until(b'\n', &mut line)
+        .context("ffmpeg yuv4mpeg read")?;
+    if line.pop() != Some(b'\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+/// Read a whole "FRAME" of data, returns `false` at the end of the stream.
+fn read_frame(out: &mut impl BufRead, frame: &mut [u8]) -> anyhow::Result<bool> {
+    match read_line(out)? {
+        Some(line) if line.starts_with(b"FRAME") => {}
+        Some(_) => anyhow::bail!("native ssim: invalid yuv4mpeg frame header"),
+        None => return Ok(false),
+    }
+    match out.read_exact(frame) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e).context("ffmpeg yuv4mpeg read"),
+    }
+}
+
+/// SSIM "All" of a yuv420p frame, planes weighted by size.
+fn frame_ssim_all(a: &[u8], b: &[u8], w: usize, h: usize) -> f64 {
+    let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+    let (y_len, c_len) = (w * h, cw * ch);
+    let planes = [(0, w, h), (y_len, cw, ch), (y_len + c_len, cw, ch)];
+
+    let total = (y_len + 2 * c_len) as f64;
+    planes
+        .iter()
+        .map(|&(start, pw, ph)| {
+            let len = pw * ph;
+            let ssim = plane_ssim(&a[start..start + len], &b[start..start + len], pw, ph);
+            ssim * len as f64 / total
+        })
+        .sum()
+}
+
+/// Mean SSIM of 8x8 windows with a stride of 4, window rows split across threads.
+fn plane_ssim(a: &[u8], b: &[u8], w: usize, h: usize) -> f64 {
+    if w < 8 || h < 8 {
+        return match a == b {
+            true => 1.0,
+            false => window_ssim(a, b, w, 0, 0, w, h),
+        };
+    }
+    let rows: Vec<_> = (0..=h - 8).step_by(4).collect();
+    let cols = (w - 8) / 4 + 1;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = rows.len().div_ceil(threads).max(1);
+
+    let sum: f64 = thread::scope(|s| {
+        let handles: Vec<_> = rows
+            .chunks(chunk)
+            .map(|rows| {
+                s.spawn(move || {
+                    rows.iter()
+                        .flat_map(|&y| (0..=w - 8).step_by(4).map(move |x| (x, y)))
+                        .map(|(x, y)| window_ssim(a, b, w, x, y, 8, 8))
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+    sum / (rows.len() * cols) as f64
+}
+
+fn window_ssim(a: &[u8], b: &[u8], stride: usize, x: usize, y: usize, w: usize, h: usize) -> f64 {
+    let (mut s1, mut s2, mut ss, mut s12) = (0_u64, 0_u64, 0_u64, 0_u64);
+    for row in y..y + h {
+        let idx = row * stride + x;
+        for (&pa, &pb) in a[idx..idx + w].iter().zip(&b[idx..idx + w]) {
+            let (pa, pb) = (u64::from(pa), u64::from(pb));
+            s1 += pa;
+            s2 += pb;
+            ss += pa * pa + pb * pb;
+            s12 += pa * pb;
+        }
+    }
+    // normalize sums to a 64 pixel window to match the constants
+    let n = (w * h) as f64;
+    let norm = 64.0 / n;
+    let (s1, s2) = (s1 as f64 * norm, s2 as f64 * norm);
+    let (ss, s12) = (ss as f64 * norm, s12 as f64 * norm);
+
+    let vars = ss * 64.0 - s1 * s1 - s2 * s2;
+    let covar = s12 * 64.0 - s1 * s2;
+    (2.0 * s1 * s2 + C1) * (2.0 * covar + C2) / ((s1 * s1 + s2 * s2 + C1) * (vars + C2))
+}
+
+#[test]
+fn plane_ssim_identical_and_distorted() {
+    let (w, h) = (32, 24);
+    let a: Vec<u8> = (0..w * h).map(|i| ((i * 7) % 251) as u8).collect();
+    assert!((plane_ssim(&a, &a, w, h) - 1.0).abs() < 1e-9);
+
+    let b: Vec<u8> = a.iter().map(|p| p.saturating_add(20) / 2).collect();
+    let ssim = plane_ssim(&a, &b, w, h);
+    assert!(ssim > 0.0 && ssim < 0.99, "{ssim}");
+}
+
+#[test]
+fn y4m_resolution() {
+    assert_eq!(
+        parse_y4m_resolution(b"YUV4MPEG2 W1350 H1080 F25:1 Ip A1:1 C420jpeg XYSCSS=420JPEG"),
+        Some((1350, 1080))
+    );
+    assert_eq!(parse_y4m_resolution(b"YUV4MPEG2 W1350 F25:1"), None);
+    assert_eq!(parse_y4m_resolution(b"FRAME"), None);
+
+    let mut stream = &b"FRAME\nabcdFRAME\nab"[..];
+    let mut frame = [0; 4];
+    assert!(read_frame(&mut stream, &mut frame).unwrap());
+    assert_eq!(&frame, b"abcd");
+    assert!(!read_frame(&mut stream, &mut frame).unwrap());
+}
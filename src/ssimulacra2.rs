@@ -0,0 +1,57 @@
+//! ssimulacra2 logic, using the external `ssimulacra2_rs` tool.
+use anyhow::{ensure, Context};
+use std::path::Path;
+use tokio::process::Command;
+
+/// Calculate the mean SSIMULACRA2 score of distorted vs reference.
+///
+/// Requires `ssimulacra2_rs` in PATH. Videos are compared frame by frame, images
+/// use the `image` mode.
+pub async fn score(reference: &Path, distorted: &Path, image: bool) -> anyhow::Result<f32> {
+    let out = Command::new("ssimulacra2_rs")
+        .kill_on_drop(true)
+        .arg(if image { "image" } else { "video" })
+        .arg(reference)
+        .arg(distorted)
+        .output()
+        .await
+        .context("ssimulacra2_rs")?;
+    ensure!(
+        out.status.success(),
+        "ssimulacra2_rs exit code {:?}\n---stderr---\n{}\n------------",
+        out.status.code(),
+        String::from_utf8_lossy(&out.stderr).trim(),
+    );
+    parse_score(&String::from_utf8_lossy(&out.stdout)).context("ssimulacra2_rs score not found")
+}
+
+/// Parse the video "Mean: 78.2316" or image "Score: 78.2316" output line.
+fn parse_score(stdout: &str) -> Option<f32> {
+    stdout.lines().find_map(|line| {
+        let line = line.trim();
+        line.strip_prefix("Mean:")
+            .or_else(|| line.strip_prefix("Score:"))?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+#[test]
+fn parse_ssimulacra2_video_out() {
+    const OUT: &str = "\
+Video Score for 240 frames
+Mean: 78.23164
+Median: 78.50231822
+Std Dev: 2.41238512
+5th Percentile: 73.12005518
+95th Percentile: 81.77283715
+";
+    assert_eq!(parse_score(OUT), Some(78.23164));
+}
+
+#[test]
+fn parse_ssimulacra2_image_out() {
+    assert_eq!(parse_score("Score: 84.72104\n"), Some(84.72104));
+    assert_eq!(parse_score("error: no frames\n"), None);
+}
@@ -0,0 +1,152 @@
+//! Per-frame score statistics logic.
+
+/// Summary statistics of per-frame scores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub mean: f32,
+    /// Harmonic mean, calculated in the same way as libvmaf `harmonic_mean` pooling.
+    pub harmonic_mean: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Stats {
+    /// Returns `None` if `scores` is empty.
+    pub fn from_scores(scores: &[f32]) -> Option<Self> {
+        if scores.is_empty() {
+            return None;
+        }
+        let n = scores.len() as f64;
+        let mean = scores.iter().map(|s| f64::from(*s)).sum::<f64>() / n;
+        // libvmaf style harmonic mean, shifted by 1 to handle zero scores
+        let harmonic_mean = n / scores
+            .iter()
+            .map(|s| 1.0 / (f64::from(*s) + 1.0))
+            .sum::<f64>()
+            - 1.0;
+        Some(Self {
+            mean: mean as _,
+            harmonic_mean: harmonic_mean as _,
+            min: scores.iter().copied().fold(f32::INFINITY, f32::min),
+            max: scores.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+        })
+    }
+}
+
+/// Returns the nearest-rank `p`th percentile `[0, 100]` of the scores.
+pub fn percentile(scores: &[f32], p: f32) -> Option<f32> {
+    if scores.is_empty() {
+        return None;
+    }
+    let mut sorted = scores.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let rank = ((p.clamp(0.0, 100.0) / 100.0) * sorted.len() as f32).ceil() as usize;
+    Some(sorted[rank.saturating_sub(1).min(sorted.len() - 1)])
+}
+
+/// Mean score & frame count per frame type, e.g. 'I', 'P', 'B', in order of first appearance.
+///
+/// Each score's frame type is looked up by its frame number, e.g. libvmaf `frameNum`
+/// which skips frames with `n_subsample`.
+pub fn frame_type_means(
+    scores: &[f32],
+    frame_nums: &[usize],
+    frame_types: &[char],
+) -> Vec<(char, usize, f32)> {
+    let mut sums: Vec<(char, usize, f64)> = vec![];
+    let typed = scores
+        .iter()
+        .zip(frame_nums)
+        .filter_map(|(score, n)| Some((score, frame_types.get(*n)?)));
+    for (score, frame_type) in typed {
+        match sums.iter_mut().find(|(t, ..)| t == frame_type) {
+            Some((_, n, sum)) => {
+                *n += 1;
+                *sum += f64::from(*score);
+            }
+            None => sums.push((*frame_type, 1, f64::from(*score))),
+        }
+    }
+    sums.into_iter()
+        .map(|(t, n, sum)| (t, n, (sum / n as f64) as f32))
+        .collect()
+}
+
+/// Returns the mean & 95% confidence interval half-width of the mean of `scores`,
+/// treated as a small random sample using the t-distribution.
+///
+/// Returns `None` with fewer than 2 scores.
+pub fn mean_confidence_95(scores: &[f32]) -> Option<(f32, f32)> {
+    /// Two-sided 95% t critical values for 1..=30 degrees of freedom.
+    const T95: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    if scores.len() < 2 {
+        return None;
+    }
+    let n = scores.len() as f64;
+    let mean = scores.iter().map(|s| f64::from(*s)).sum::<f64>() / n;
+    let variance = scores
+        .iter()
+        .map(|s| (f64::from(*s) - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    let t = T95.get(scores.len() - 2).copied().unwrap_or(1.96);
+    Some((mean as _, (t * (variance / n).sqrt()) as _))
+}
+
+#[test]
+fn stats_from_scores() {
+    let stats = Stats::from_scores(&[90.0, 95.0, 100.0]).unwrap();
+    assert_eq!(stats.mean, 95.0);
+    assert_eq!(stats.min, 90.0);
+    assert_eq!(stats.max, 100.0);
+    assert!(stats.harmonic_mean < stats.mean, "{stats:?}");
+    assert!(Stats::from_scores(&[]).is_none());
+}
+
+#[test]
+fn percentile_nearest_rank() {
+    let scores: Vec<f32> = (1..=100).map(|s| s as f32).collect();
+    assert_eq!(percentile(&scores, 1.0), Some(1.0));
+    assert_eq!(percentile(&scores, 5.0), Some(5.0));
+    assert_eq!(percentile(&scores, 50.0), Some(50.0));
+    assert_eq!(percentile(&scores, 0.0), Some(1.0));
+    assert_eq!(percentile(&scores, 100.0), Some(100.0));
+}
+
+#[test]
+fn mean_confidence() {
+    let (mean, half_width) = mean_confidence_95(&[94.0, 95.0, 96.0]).unwrap();
+    assert_eq!(mean, 95.0);
+    // t(2) * sd(1) / sqrt(3)
+    assert!((half_width - 2.484).abs() < 0.001, "{half_width}");
+
+    let (_, half_width) = mean_confidence_95(&[95.0, 95.0]).unwrap();
+    assert_eq!(half_width, 0.0);
+    assert!(mean_confidence_95(&[95.0]).is_none());
+}
+
+#[test]
+fn frame_type_mean_scores() {
+    let scores = [98.0, 90.0, 92.0, 96.0, 94.0];
+    let types = ['I', 'B', 'B', 'P', 'B'];
+    assert_eq!(
+        frame_type_means(&scores, &[0, 1, 2, 3, 4], &types),
+        vec![('I', 1, 98.0), ('B', 3, 92.0), ('P', 1, 96.0)]
+    );
+}
+
+#[test]
+fn frame_type_means_subsampled() {
+    let frame_types = ['I', 'B', 'B', 'P', 'B', 'B', 'I', 'B'];
+    // n_subsample=3 scores of frames 0, 3 & 6
+    let means = frame_type_means(&[99.0, 94.0, 97.0], &[0, 3, 6], &frame_types);
+    assert_eq!(means, [('I', 2, 98.0), ('P', 1, 94.0)]);
+
+    // frame numbers beyond the probed types are ignored
+    let means = frame_type_means(&[95.0, 90.0], &[7, 8], &frame_types);
+    assert_eq!(means, [('B', 1, 95.0)]);
+}
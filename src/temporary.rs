@@ -95,3 +95,53 @@ pub fn process_dir(conf_parent: Option<PathBuf>) -> PathBuf {
 
     temp_dir
 }
+
+/// Returns the tmpfs directory if available with at least `min_free` bytes free.
+///
+/// Decided once per configured `temp_dir`, so later runs, e.g. crf-search attempts,
+/// keep using the same directory even if tmpfs free space changes.
+pub fn tmpfs_dir(temp_dir: Option<&Path>, min_free: u64) -> Option<PathBuf> {
+    static DECIDED: OnceLock<Mutex<HashMap<Option<PathBuf>, Option<PathBuf>>>> = OnceLock::new();
+    DECIDED
+        .get_or_init(<_>::default)
+        .lock()
+        .unwrap()
+        .entry(temp_dir.map(Path::to_owned))
+        .or_insert_with(|| available_tmpfs(min_free))
+        .clone()
+}
+
+fn available_tmpfs(min_free: u64) -> Option<PathBuf> {
+    let dir = Path::new("/dev/shm");
+    if !dir.is_dir() {
+        return None;
+    }
+    (available_space(dir)? >= min_free).then(|| dir.to_owned())
+}
+
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // safety: valid nul terminated path & statvfs struct pointer
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)] // field types vary by platform
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_: &Path) -> Option<u64> {
+    None
+}
+
+#[test]
+fn tmpfs_decided_once() {
+    let temp_dir = Path::new("/tmp/ab-av1-tmpfs-decided-once-test");
+    assert_eq!(tmpfs_dir(Some(temp_dir), u64::MAX), None);
+    // enough space now, but the earlier decision stands
+    assert_eq!(tmpfs_dir(Some(temp_dir), 0), None);
+}
@@ -0,0 +1,112 @@
+//! Per-run phase timings, printed with `--timings`.
+use std::{
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+static TIMINGS: Mutex<[Duration; Phase::ALL.len()]> =
+    Mutex::new([Duration::ZERO; Phase::ALL.len()]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// ffprobe calls.
+    Probe,
+    /// Copying samples out of the input.
+    SampleExtract,
+    /// Encoding samples.
+    SampleEncode,
+    /// Vmaf/ssim/xpsnr scoring of encoded samples.
+    Metric,
+    /// Full output encoding.
+    Encode,
+}
+
+impl Phase {
+    const ALL: [Self; 5] = [
+        Self::Probe,
+        Self::SampleExtract,
+        Self::SampleEncode,
+        Self::Metric,
+        Self::Encode,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Probe => "probing",
+            Self::SampleExtract => "sample extraction",
+            Self::SampleEncode => "sample encodes",
+            Self::Metric => "metric scoring",
+            Self::Encode => "final encode",
+        }
+    }
+}
+
+/// Add `elapsed` to the `phase` total.
+pub fn record(phase: Phase, elapsed: Duration) {
+    if let Ok(mut timings) = TIMINGS.lock() {
+        timings[phase as usize] += elapsed;
+    }
+}
+
+/// Start timing `phase`, recorded when the returned timer is dropped.
+pub fn start(phase: Phase) -> Timer {
+    Timer(phase, Instant::now())
+}
+
+#[must_use]
+pub struct Timer(Phase, Instant);
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        record(self.0, self.1.elapsed());
+    }
+}
+
+/// Phase breakdown table of the run so far, where `total` is the whole run duration.
+pub fn summary(total: Duration) -> String {
+    let timings = TIMINGS.lock().map(|t| *t).unwrap_or_default();
+    format_summary(&timings, total)
+}
+
+fn format_summary(timings: &[Duration; Phase::ALL.len()], total: Duration) -> String {
+    let percent = |d: Duration| match total.is_zero() {
+        true => 0.0,
+        false => 100.0 * d.as_secs_f64() / total.as_secs_f64(),
+    };
+    let mut out = String::new();
+    let mut row = |label: &str, d: Duration| {
+        _ = writeln!(
+            out,
+            "{label:<18} {:>9.1}s {:>5.1}%",
+            d.as_secs_f64(),
+            percent(d)
+        );
+    };
+    for phase in Phase::ALL {
+        row(phase.label(), timings[phase as usize]);
+    }
+    // phases may overlap, e.g. probing during sample encodes
+    let phases: Duration = timings.iter().sum();
+    row("other", total.saturating_sub(phases));
+    row("total", total);
+    out
+}
+
+#[test]
+fn summary_breakdown() {
+    let timings = [
+        Duration::from_secs(1),
+        Duration::from_secs(4),
+        Duration::from_secs(30),
+        Duration::from_secs(15),
+        Duration::ZERO,
+    ];
+    let summary = format_summary(&timings, Duration::from_secs(100));
+    let lines: Vec<_> = summary.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], "probing                  1.0s   1.0%");
+    assert_eq!(lines[2], "sample encodes          30.0s  30.0%");
+    assert_eq!(lines[5], "other                   50.0s  50.0%");
+    assert_eq!(lines[6], "total                  100.0s 100.0%");
+}
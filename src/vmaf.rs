@@ -1,29 +1,60 @@
 //! vmaf logic
-use crate::process::{exit_ok_stderr, Chunks, CommandExt, FfmpegOut};
+use crate::process::{exit_ok_stderr, Chunks, CommandExt, FfmpegOut, ProcessRunner, SystemRunner};
 use anyhow::Context;
-use std::path::Path;
+use std::{path::Path, process::Stdio, time::Duration};
 use tokio::process::Command;
-use tokio_process_stream::{Item, ProcessChunkStream};
+use tokio_process_stream::Item;
 use tokio_stream::{Stream, StreamExt};
 
+/// libvmaf MS-SSIM feature & per-frame metric name.
+pub const MS_SSIM_FEATURE: &str = "float_ms_ssim";
+
 /// Calculate VMAF score by converting the original first to yuv.
 /// This can produce more accurate results than testing directly from original source.
 pub fn run(
     reference: &Path,
     distorted: &Path,
     filter_complex: &str,
+    runner: &impl ProcessRunner,
+) -> anyhow::Result<impl Stream<Item = VmafOut>> {
+    run_window_with(runner, reference, distorted, filter_complex, None)
+}
+
+/// [`run`] limited to the `(offset, duration)` window of both inputs, or the whole inputs if `None`.
+pub fn run_window(
+    reference: &Path,
+    distorted: &Path,
+    filter_complex: &str,
+    window: Option<(Duration, Duration)>,
+) -> anyhow::Result<impl Stream<Item = VmafOut>> {
+    run_window_with(&SystemRunner, reference, distorted, filter_complex, window)
+}
+
+fn run_window_with(
+    runner: &impl ProcessRunner,
+    reference: &Path,
+    distorted: &Path,
+    filter_complex: &str,
+    window: Option<(Duration, Duration)>,
 ) -> anyhow::Result<impl Stream<Item = VmafOut>> {
-    let vmaf: ProcessChunkStream = Command::new("ffmpeg")
-        .kill_on_drop(true)
+    let ss = window.map(|(offset, _)| offset.as_secs_f32());
+    let t = window.map(|(_, duration)| duration.as_secs_f32());
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
         .arg2("-r", "24")
+        .arg2_opt("-ss", ss)
+        .arg2_opt("-t", t)
         .arg2("-i", distorted)
         .arg2("-r", "24")
+        .arg2_opt("-ss", ss)
+        .arg2_opt("-t", t)
         .arg2("-i", reference)
         .arg2("-filter_complex", filter_complex)
         .arg2("-f", "null")
         .arg("-")
-        .try_into()
-        .context("ffmpeg vmaf")?;
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let vmaf = runner.spawn(&mut cmd).context("ffmpeg vmaf")?;
 
     let mut chunks = Chunks::default();
     let vmaf = vmaf.filter_map(move |item| match item {
@@ -35,6 +66,136 @@ pub fn run(
     Ok(vmaf)
 }
 
+/// Score the `(offset, duration)` window of `distorted` against `reference`.
+///
+/// Returns `None` if vmaf finished without a score, e.g. an empty window.
+pub async fn window_score(
+    reference: &Path,
+    distorted: &Path,
+    filter_complex: &str,
+    window: (Duration, Duration),
+) -> anyhow::Result<Option<f32>> {
+    let mut vmaf = run_window(reference, distorted, filter_complex, Some(window))?;
+    while let Some(next) = vmaf.next().await {
+        match next {
+            VmafOut::Done(score) => return Ok(Some(score)),
+            VmafOut::Err(err) => return Err(err),
+            VmafOut::Progress(_) => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Returns `true` if the vmaf error looks like libvmaf ran out of memory, i.e. an
+/// allocation error in stderr or ffmpeg was SIGKILLed, e.g. by the OOM killer.
+///
+/// Other signals, like Ctrl-C or SIGTERM, are not treated as out of memory.
+pub fn is_oom_error(err: &anyhow::Error) -> bool {
+    let err = format!("{err:#}");
+    err.contains("Cannot allocate memory")
+        || err.contains("Out of memory")
+        || err.contains("out of memory")
+        || err.contains("exit code None (signal 9)")
+}
+
+/// Returns the configured statistic of per-frame scores read from the json `frame_log`,
+/// or the libvmaf `mean` if no log was written.
+pub fn score_statistic(
+    vmaf: &crate::command::args::Vmaf,
+    frame_log: Option<&Path>,
+    mean: f32,
+) -> anyhow::Result<f32> {
+    score_statistic_frames(vmaf, frame_log, mean).map(|(score, _)| score)
+}
+
+/// Returns [`score_statistic`] & the per-frame scores, empty if no log was written.
+pub fn score_statistic_frames(
+    vmaf: &crate::command::args::Vmaf,
+    frame_log: Option<&Path>,
+    mean: f32,
+) -> anyhow::Result<(f32, Vec<f32>)> {
+    let Some(frame_log) = frame_log else {
+        return Ok((mean, vec![]));
+    };
+    let scores = read_frame_scores(frame_log)?;
+    let score = vmaf
+        .vmaf_statistic
+        .calculate(&scores)
+        .context("vmaf frame log has no scores")?;
+    Ok((score, scores))
+}
+
+/// Read per-frame vmaf scores from a libvmaf json log.
+///
+/// The log is read line by line so memory use doesn't grow with the log size,
+/// which can be large for multi-hour inputs. Irregular lines are ignored.
+pub fn read_frame_scores(log: &Path) -> anyhow::Result<Vec<f32>> {
+    read_frame_metric(log, "vmaf")
+}
+
+/// Read per-frame values of a `metric`, e.g. [`MS_SSIM_FEATURE`], from a libvmaf json log.
+pub fn read_frame_metric(log: &Path, metric: &str) -> anyhow::Result<Vec<f32>> {
+    let file = std::fs::File::open(log).with_context(|| format!("read {}", log.display()))?;
+    parse_frame_metric(std::io::BufReader::new(file), metric)
+        .with_context(|| format!("read {}", log.display()))
+}
+
+fn parse_frame_metric(log: impl std::io::BufRead, metric: &str) -> anyhow::Result<Vec<f32>> {
+    let key = format!("\"{metric}\":");
+    let key = key.as_str();
+
+    let mut scores = vec![];
+    for line in log.split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        // per-frame scores end where the pooled/aggregate sections start
+        if line.contains("\"pooled_metrics\"") || line.contains("\"aggregate_metrics\"") {
+            break;
+        }
+        let scores_in_line = line.match_indices(key).filter_map(|(idx, _)| {
+            let value = line[idx + key.len()..].trim_start();
+            let end = value
+                .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+                .unwrap_or(value.len());
+            value[..end].parse::<f32>().ok().filter(|s| s.is_finite())
+        });
+        scores.extend(scores_in_line);
+    }
+    Ok(scores)
+}
+
+/// Warning for an out of memory retry with `reduced` args, noting if frame subsampling
+/// changed from `vmaf` as that affects the score.
+pub fn oom_retry_warning(
+    vmaf: &crate::command::args::Vmaf,
+    reduced: &crate::command::args::Vmaf,
+) -> String {
+    let mut warning = format!(
+        "vmaf ran out of memory, retrying with {}",
+        reduced.vmaf_args.join(":")
+    );
+    let subsample = |v: &crate::command::args::Vmaf| {
+        v.vmaf_args
+            .iter()
+            .find_map(|a| a.strip_prefix("n_subsample=")?.parse::<usize>().ok())
+            .unwrap_or(1)
+    };
+    if subsample(vmaf) != subsample(reduced) {
+        warning += &format!(
+            ", n_subsample changed from {} to {} which may affect the score",
+            subsample(vmaf),
+            subsample(reduced)
+        );
+    }
+    warning
+}
+
+/// Parse the libvmaf "VMAF score: x" stderr output line.
+pub fn parse_score(line: &str) -> Option<f32> {
+    let idx = line.find("VMAF score: ")?;
+    line[idx + "VMAF score: ".len()..].trim().parse().ok()
+}
+
 #[derive(Debug)]
 pub enum VmafOut {
     Progress(FfmpegOut),
@@ -54,10 +215,8 @@ impl VmafOut {
         chunks.push(chunk);
         let line = chunks.last_line();
 
-        if let Some(idx) = line.find("VMAF score: ") {
-            return Some(Self::Done(
-                line[idx + "VMAF score: ".len()..].trim().parse().ok()?,
-            ));
+        if let Some(score) = parse_score(line) {
+            return Some(Self::Done(score));
         }
         if let Some(progress) = FfmpegOut::try_parse(line) {
             return Some(Self::Progress(progress));
@@ -65,3 +224,75 @@ impl VmafOut {
         None
     }
 }
+
+#[test]
+fn parse_json_frame_scores() {
+    let json = br#"{
+  "version": "2.3.1",
+  "frames": [
+    {
+      "frameNum": 0,
+      "metrics": {
+        "integer_motion": 0.000000,
+        "vmaf": 97.250000
+      }
+    },
+    garbage \xff line
+    {
+      "frameNum": 1,
+      "metrics": {
+        "integer_motion": 1.200000,
+        "vmaf": 93.500000
+      }
+    },
+    {"frameNum": 2, "metrics": {"integer_motion": 1.1, "vmaf": 95}}
+  ],
+  "pooled_metrics": {
+    "vmaf": {
+      "min": 93.500000,
+      "mean": 95.375000
+    }
+  }
+}"#;
+    assert_eq!(
+        parse_frame_metric(&json[..], "vmaf").unwrap(),
+        vec![97.25, 93.5, 95.0]
+    );
+}
+
+#[test]
+fn parse_json_frame_ms_ssim() {
+    let json = br#"{
+  "frames": [
+    {"frameNum": 0, "metrics": {"float_ms_ssim": 0.991200, "vmaf": 97.25}},
+    {"frameNum": 1, "metrics": {"float_ms_ssim": 0.985000, "vmaf": 93.5}}
+  ],
+  "pooled_metrics": {
+    "float_ms_ssim": {"min": 0.985, "mean": 0.9881}
+  }
+}"#;
+    assert_eq!(
+        parse_frame_metric(&json[..], MS_SSIM_FEATURE).unwrap(),
+        vec![0.9912, 0.985]
+    );
+}
+
+#[test]
+fn oom_error() {
+    use anyhow::anyhow;
+
+    assert!(is_oom_error(&anyhow!(
+        "ffmpeg vmaf exit code 1\n---stderr---\nlibvmaf ERROR: Cannot allocate memory"
+    )));
+    assert!(is_oom_error(&anyhow!(
+        "ffmpeg vmaf exit code None (signal 9)"
+    )));
+    // Ctrl-C, SIGTERM
+    assert!(!is_oom_error(&anyhow!(
+        "ffmpeg vmaf exit code None (signal 2)"
+    )));
+    assert!(!is_oom_error(&anyhow!(
+        "ffmpeg vmaf exit code None (signal 15)"
+    )));
+    assert!(!is_oom_error(&anyhow!("ffmpeg vmaf exit code None")));
+}
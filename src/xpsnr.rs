@@ -0,0 +1,106 @@
+//! xpsnr logic
+use crate::{
+    command::args::PixelFormat,
+    process::{exit_ok_stderr, Chunks, CommandExt, FfmpegOut},
+    psnr::MAX_FRAME_PSNR,
+};
+use anyhow::Context;
+use std::path::Path;
+use tokio::process::Command;
+use tokio_process_stream::{Item, ProcessChunkStream};
+use tokio_stream::{Stream, StreamExt};
+
+/// Calculate the average luma XPSNR score of distorted vs reference.
+///
+/// Requires ffmpeg >= 7.1 built with the xpsnr filter.
+pub fn run(
+    reference: &Path,
+    distorted: &Path,
+    filter_complex: &str,
+) -> anyhow::Result<impl Stream<Item = XpsnrOut>> {
+    let xpsnr: ProcessChunkStream = Command::new("ffmpeg")
+        .kill_on_drop(true)
+        .arg2("-i", distorted)
+        .arg2("-i", reference)
+        .arg2("-filter_complex", filter_complex)
+        .arg2("-f", "null")
+        .arg("-")
+        .try_into()
+        .context("ffmpeg xpsnr")?;
+
+    let mut chunks = Chunks::default();
+    let xpsnr = xpsnr.filter_map(move |item| match item {
+        Item::Stderr(chunk) => XpsnrOut::try_from_chunk(&chunk, &mut chunks),
+        Item::Stdout(_) => None,
+        Item::Done(code) => XpsnrOut::ignore_ok(exit_ok_stderr("ffmpeg xpsnr", code, &chunks)),
+    });
+
+    Ok(xpsnr)
+}
+
+/// Returns ffmpeg `filter_complex`/`lavfi` value for calculating xpsnr.
+///
+/// The distorted stream is scaled to the reference resolution, if different, using
+/// the reference input of `scale`.
+pub fn ffmpeg_lavfi(pix_fmt: PixelFormat, ref_vfilter: Option<&str>) -> String {
+    let ref_vf = match ref_vfilter {
+        None => String::new(),
+        Some(vf) if vf.ends_with(',') => vf.into(),
+        Some(vf) => format!("{vf},"),
+    };
+    format!(
+        "[0:v]format={pix_fmt},setpts=PTS-STARTPTS[dis];\
+         [1:v]format={pix_fmt},{ref_vf}setpts=PTS-STARTPTS,split[ref][sref];\
+         [dis][ref]scale=rw:rh:flags=bicubic[sdis];[sdis][sref]xpsnr"
+    )
+}
+
+#[derive(Debug)]
+pub enum XpsnrOut {
+    Progress(FfmpegOut),
+    Done(f32),
+    Err(anyhow::Error),
+}
+
+impl XpsnrOut {
+    fn ignore_ok<T>(result: anyhow::Result<T>) -> Option<Self> {
+        match result {
+            Ok(_) => None,
+            Err(err) => Some(Self::Err(err)),
+        }
+    }
+
+    fn try_from_chunk(chunk: &[u8], chunks: &mut Chunks) -> Option<Self> {
+        chunks.push(chunk);
+        let line = chunks.last_line();
+
+        if let Some(score) = parse_xpsnr_luma(line) {
+            return Some(Self::Done(score));
+        }
+        if let Some(progress) = FfmpegOut::try_parse(line) {
+            return Some(Self::Progress(progress));
+        }
+        None
+    }
+}
+
+/// Parse the average luma score from ffmpeg xpsnr filter output.
+fn parse_xpsnr_luma(line: &str) -> Option<f32> {
+    let idx = line.find("XPSNR average")?;
+    let mut words = line[idx..].split_whitespace();
+    words.find(|w| *w == "y:")?;
+    match words.next()? {
+        "inf" => Some(MAX_FRAME_PSNR),
+        y => y.parse::<f32>().ok().map(|s| s.min(MAX_FRAME_PSNR)),
+    }
+}
+
+#[test]
+fn parse_xpsnr_luma_line() {
+    let line =
+        "[Parsed_xpsnr_3 @ 0x55d1] XPSNR average, 240 frames  y: 41.3672  u: 44.1045  v: 45.0331";
+    assert_eq!(parse_xpsnr_luma(line), Some(41.3672));
+    let identical = "[Parsed_xpsnr_3 @ 0x55d1] XPSNR average, 24 frames  y: inf  u: inf  v: inf";
+    assert_eq!(parse_xpsnr_luma(identical), Some(MAX_FRAME_PSNR));
+    assert_eq!(parse_xpsnr_luma("frame=  100 fps=50"), None);
+}